@@ -0,0 +1,12 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    // The gRPC service is generated from its definition, with the protoc bundled with the build dependencies
+    // so that building needs nothing installed.
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/consensus.proto");
+        let protoc = protoc_bin_vendored::protoc_bin_path().expect("protoc should be bundled for this platform");
+        std::env::set_var("PROTOC", protoc);
+        tonic_prost_build::compile_protos("proto/consensus.proto").expect("the gRPC service definition should compile");
+    }
+}
@@ -0,0 +1,775 @@
+//! The [LlmActor], which answers, evaluates and refines on behalf of one persona.
+
+use std::{any::Any, borrow::Cow, collections::{HashMap, HashSet}, future::Future, panic::AssertUnwindSafe, sync::Arc, time::Instant};
+
+use actix::prelude::*;
+use async_trait::async_trait;
+use futures::{future::join_all, FutureExt};
+use tracing::{debug, error, field, info_span, warn, Instrument, Span};
+
+use crate::{
+    config::ActorConfig,
+    constraints::AnswerConstraints,
+    coordinator::Coordinator,
+    error::ConsensusError,
+    messages::{ActorFailed, ActorRole, AnswerEvaluation, AnswerQuestion, AnswerRefinement, AnswerToken, CancelCalls, CandidateVote, ChairDecision, DraftAnswer, EvaluateAnswer, Ping, ProviderFailed, QuestionId, QuestionRouted, RefineAnswer, RefinementSuggestion, RouteQuestion, SuggestRefinement, Summarize, SynthesizeAnswer, UsageReport, UseTools, VoteOnCandidates},
+    parsing::{ask_for_evaluation, keep_to_constraints, Caller},
+    personas::Persona,
+    prompts::{PromptActor, PromptCandidate, PromptCodeRun, PromptData, PromptEvaluation, PromptSuggestion, PromptVersion, Prompts, Template},
+    provider::{CompletionRequest, CredentialsConfig, GenerationParams, Image, Limiter, LlmProvider, ProviderError, ResponseCache, RetryPolicy, ToolRound, ToolSpec},
+    result::Evaluation,
+    sandbox::CodeRun,
+    search::{self, FoundResult, WebSearch},
+    strategy::{EvaluationMode, Sampling, Stage},
+    tools::Tool,
+};
+
+/// How many times a model may call tools in answering one request, after which the call fails.
+const MAX_TOOL_ROUNDS: usize = 5;
+
+// LLM actor that interacts with LLM API
+pub struct LlmActor {
+    name: String,
+    persona: Persona,
+    role: ActorRole,
+    provider: Arc<dyn LlmProvider>,
+    /// The provider the actor evaluates, votes and routes with, if not its own.
+    evaluator: Option<Arc<dyn LlmProvider>>,
+    /// The provider the actor evaluates with when a question's deadline is near, if it has a fast model.
+    fast_evaluator: Option<Arc<dyn LlmProvider>>,
+    /// Sampling settings sent with every request the actor makes.
+    params: GenerationParams,
+    prompts: Arc<Prompts>,
+    /// The web search a fact checker checks answers' claims with, if it has one.
+    search: Option<WebSearch>,
+    /// The tools the actor's models may call while answering and evaluating.
+    tools: Vec<Tool>,
+    /// Provider calls in progress, by question, so that cancelling a question can abort them.
+    calls: HashMap<QuestionId, HashMap<u64, SpawnHandle>>,
+    /// Identifies the next provider call.
+    next_call: u64,
+}
+
+impl LlmActor {
+    pub fn new(name: String, persona: Persona, provider: Arc<dyn LlmProvider>) -> Self {
+        LlmActor { name, persona, role: ActorRole::Member, provider, evaluator: None, fast_evaluator: None, params: GenerationParams::default(), prompts: Prompts::built_in(), search: None, tools: Vec::new(), calls: HashMap::new(), next_call: 0 }
+    }
+
+    /// Sets the sampling settings the actor requests, such as a low temperature for a strict evaluator.
+    pub fn with_params(mut self, params: GenerationParams) -> Self {
+        self.params = params;
+        self
+    }
+
+    /// Sets the part the actor plays on the panel.
+    pub fn with_role(mut self, role: ActorRole) -> Self {
+        self.role = role;
+        self
+    }
+
+    /// Sets the provider the actor evaluates answers, votes on candidates and routes questions with, such as a
+    /// cheaper model than the one it writes answers with.
+    pub fn with_evaluator(mut self, evaluator: Arc<dyn LlmProvider>) -> Self {
+        self.evaluator = Some(evaluator);
+        self
+    }
+
+    /// Sets the provider the actor evaluates answers with when a question's deadline is near, such as a smaller and
+    /// faster model than the one it usually evaluates with.
+    pub fn with_fast_evaluator(mut self, evaluator: Arc<dyn LlmProvider>) -> Self {
+        self.fast_evaluator = Some(evaluator);
+        self
+    }
+
+    /// Sets the templates the actor renders its prompts from, in place of the built-in ones.
+    pub fn with_prompts(mut self, prompts: Arc<Prompts>) -> Self {
+        self.prompts = prompts;
+        self
+    }
+
+    /// Sets the web search the actor checks answers' claims with, if it is the panel's fact checker.
+    pub fn with_search(mut self, search: WebSearch) -> Self {
+        self.search = Some(search);
+        self
+    }
+
+    /// Sets the tools the actor's models may call while answering and evaluating, which the [Coordinator] runs.
+    pub fn with_tools(mut self, tools: Vec<Tool>) -> Self {
+        self.tools = tools;
+        self
+    }
+
+    /// Builds an actor from its config entry, creating the provider it talks to, any it evaluates with, in a hurry or
+    /// not, and any they fail over to, with the given keys, retry policy, response cache and limiter.
+    pub fn from_config(config: &ActorConfig, credentials: &CredentialsConfig, retry: &RetryPolicy, cache: Option<&Arc<ResponseCache>>, limiter: Option<&Arc<Limiter>>) -> Result<Self, ProviderError> {
+        let provider = config.provider.build_with_fallback(&config.fallback, credentials, retry, cache, limiter)?;
+        let actor = LlmActor::new(config.name.clone(), config.persona.clone(), provider)
+            .with_params(config.provider.params)
+            .with_role(config.role.unwrap_or_default())
+            .with_tools(config.tools.clone());
+        let actor = match &config.evaluation_model {
+            Some(model) => actor.with_evaluator(config.provider.with_model(model).build_with_fallback(&config.fallback, credentials, retry, cache, limiter)?),
+            None => actor,
+        };
+        let actor = match &config.fast_model {
+            Some(model) => actor.with_fast_evaluator(config.provider.with_model(model).build_with_fallback(&config.fallback, credentials, retry, cache, limiter)?),
+            None => actor,
+        };
+        if !actor.tools.is_empty() && !actor.provider.supports_tools() {
+            warn!("{}'s provider ({}) cannot call tools, so {} answers without them.", actor.name, config.provider.describe(), actor.name);
+        }
+        Ok(actor)
+    }
+
+    /// The knowledge domain the actor answers from.
+    pub fn domain(&self) -> &str {
+        &self.persona.domain
+    }
+
+    /// The part the actor plays on the panel.
+    pub fn role(&self) -> ActorRole {
+        self.role
+    }
+
+    /// The tools the actor's models may call.
+    pub fn tools(&self) -> &[Tool] {
+        &self.tools
+    }
+
+    /// The template variables describing the actor, to which each stage adds its own.
+    fn prompt_data(&self) -> PromptData {
+        PromptData::actor(&self.name, &self.persona, self.role)
+    }
+
+    /// The provider the actor evaluates with, and whether it is one of its own rather than the one it answers with.
+    fn evaluator(&self) -> (Arc<dyn LlmProvider>, bool) {
+        match &self.evaluator {
+            Some(evaluator) => (evaluator.clone(), true),
+            None => (self.provider.clone(), false),
+        }
+    }
+
+    /// The provider the actor evaluates with when the question's deadline is near: its fast model's if it has one,
+    /// or the one it always evaluates with.
+    fn hurried_evaluator(&self) -> (Arc<dyn LlmProvider>, bool) {
+        match &self.fast_evaluator {
+            Some(evaluator) => (evaluator.clone(), true),
+            None => self.evaluator(),
+        }
+    }
+
+    /// The actor's tools as the provider's model is given them, or none if it cannot call tools.
+    fn tool_specs(&self, provider: &dyn LlmProvider) -> Vec<ToolSpec> {
+        if !provider.supports_tools() {
+            return Vec::new();
+        }
+        self.tools.iter().map(|tool| tool.spec()).collect()
+    }
+
+    /// The images attached to a question that the provider's model can be shown, and how many of them it cannot.
+    fn shown_images(&self, provider: &dyn LlmProvider, images: &[Image]) -> (Vec<Image>, usize) {
+        let shown = images.len().min(provider.max_images());
+        if shown < images.len() {
+            debug!("{} is not shown {} of the question's {} images, which its model cannot see.", self.name, images.len() - shown, images.len());
+        }
+        (images[..shown].to_vec(), images.len() - shown)
+    }
+
+    /// System prompt describing the actor's persona, used when it evaluates or refines an answer.
+    fn persona(&self) -> String {
+        self.prompts.render(Template::Persona, &self.prompt_data())
+    }
+
+    /// Runs the work for a question, including its provider calls, until it finishes or [CancelCalls] aborts it.
+    /// If the work panics, the actor stops, so that its supervisor restarts it.
+    ///
+    /// The work is traced in a span of its own under `parent`, the span of the round it belongs to, which records
+    /// how long it took. Evaluations and votes also record their verdict on it.
+    fn spawn_call(&mut self, ctx: &mut Context<Self>, question_id: QuestionId, parent: &Span, stage: &'static str, call: impl Future<Output = ()> + 'static) {
+        let id = self.next_call;
+        self.next_call += 1;
+        let span = info_span!(parent: parent, "call", actor = %self.name, stage, latency_ms = field::Empty, verdict = field::Empty);
+        let started = Instant::now();
+        let call = async move {
+            call.await;
+            Span::current().record("latency_ms", started.elapsed().as_millis() as u64);
+        };
+        let handle = ctx.spawn(AssertUnwindSafe(call.instrument(span)).catch_unwind().into_actor(self).map(move |result, actor, ctx| {
+            if let Err(panic) = result {
+                // The call is left in flight, for restarting to report.
+                error!("{}'s {} call for question {} panicked, restarting it: {}", actor.name, stage, question_id, panic_message(panic.as_ref()));
+                ctx.stop();
+                return;
+            }
+            if let Some(calls) = actor.calls.get_mut(&question_id) {
+                calls.remove(&id);
+                if calls.is_empty() {
+                    actor.calls.remove(&question_id);
+                }
+            }
+        }));
+        self.calls.entry(question_id).or_default().insert(id, handle);
+    }
+}
+
+impl Actor for LlmActor {
+    type Context = Context<Self>;
+}
+
+impl Supervised for LlmActor {
+    /// Tells the [Coordinator] about every question whose calls the restart aborted, so that their work is sent
+    /// again instead of waited on.
+    fn restarting(&mut self, _ctx: &mut Self::Context) {
+        for question_id in self.calls.drain().map(|(question_id, _)| question_id) {
+            Coordinator::from_registry().do_send(ActorFailed { name: self.name.clone(), question_id });
+        }
+    }
+}
+
+/// The message a panic was raised with, if it was raised with one.
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    panic.downcast_ref::<&str>().copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("no message")
+}
+
+// LLM Actor Message Handlers
+impl Handler<RouteQuestion> for LlmActor {
+    type Result = bool;
+
+    fn handle(&mut self, msg: RouteQuestion, ctx: &mut Self::Context) -> Self::Result {
+        debug!("LLM actor {} received RouteQuestion for question {}: {}", self.name, msg.question_id, msg.question);
+
+        let panel = msg.panel.iter().map(|(name, domain)| PromptActor { name: name.clone(), domain: domain.clone() }).collect();
+        let prompt = self.prompts.render(Template::Route, &PromptData { question: msg.question.clone(), panel, ..self.prompt_data() });
+        let request = CompletionRequest { system: None, prompt, images: Vec::new(), params: self.params, template: Template::Route, tools: Vec::new(), tool_rounds: Vec::new() };
+        let names: Vec<String> = msg.panel.into_iter().map(|(name, _)| name).collect();
+        let name = self.name.clone();
+        let question_id = msg.question_id;
+        let (provider, evaluation_model) = self.evaluator();
+        let execution = async move {
+            // Routing only saves the panel from a poorly suited draft, so failing to route is not worth failing the question.
+            let choice = match complete(provider.as_ref(), &request, question_id, &name, evaluation_model).await {
+                Ok(result) => {
+                    let choice = parse_route(&result, &names);
+                    if choice.is_none() {
+                        error!("Unexpected response from RouteQuestion: {}", result);
+                    }
+                    choice
+                },
+                Err(e) => {
+                    error!("{} failed to complete RouteQuestion for question {}: {}", name, question_id, e);
+                    None
+                },
+            };
+            Span::current().record("verdict", choice.as_deref());
+            Coordinator::from_registry().do_send(QuestionRouted { question_id, name, choice });
+        };
+
+        self.spawn_call(ctx, question_id, &msg.span, "route", execution);
+        true
+    }
+}
+
+impl Handler<DraftAnswer> for LlmActor {
+    type Result = bool;
+
+    fn handle(&mut self, msg: DraftAnswer, ctx: &mut Self::Context) -> Self::Result {
+        debug!("LLM actor {} received DraftAnswer for question {}: {}", self.name, msg.question_id, msg.question);
+
+        let (images, unseen_images) = self.shown_images(self.provider.as_ref(), &msg.images);
+        let data = PromptData {
+            question: msg.question.clone(),
+            context: msg.context.clone(),
+            documents: msg.documents.clone(),
+            unseen_images,
+            constraints: msg.constraints.describe(),
+            preamble: self.prompts.preamble(&self.persona),
+            instructions: msg.instructions.clone().unwrap_or_default(),
+            ..self.prompt_data()
+        };
+        let prompt = self.prompts.render(Template::Draft, &data);
+        let request = CompletionRequest { system: None, prompt, images, params: self.params, template: Template::Draft, tools: self.tool_specs(self.provider.as_ref()), tool_rounds: Vec::new() };
+        let name = self.name.clone();
+        let question_id = msg.question_id;
+        let provider = self.provider.clone();
+        let execution = async move {
+            let answer = match msg.sampling {
+                Some(sampling) => sample_answer(provider.as_ref(), request, sampling, &msg.constraints, question_id, &name).await,
+                None => write_answer(provider.as_ref(), request, &msg.constraints, question_id, &name, Stage::Drafting).await,
+            };
+            let answer = match answer {
+                Ok(answer) => answer,
+                Err(e) => return report_failure(question_id, name, "DraftAnswer", e),
+            };
+            Coordinator::from_registry().do_send(AnswerQuestion { question_id, name, answer });
+        };
+
+        self.spawn_call(ctx, question_id, &msg.span, "draft", execution);
+        true
+    }
+}
+
+impl Handler<VoteOnCandidates> for LlmActor {
+    type Result = bool;
+
+    fn handle(&mut self, msg: VoteOnCandidates, ctx: &mut Self::Context) -> Self::Result {
+        let candidates = msg.candidates.iter()
+            .enumerate()
+            .map(|(index, candidate)| PromptCandidate { number: index + 1, text: candidate.clone() })
+            .collect();
+        let data = PromptData { question: msg.question.clone(), candidates, ..self.prompt_data() };
+        let prompt = self.prompts.render(Template::Vote, &data);
+        let request = CompletionRequest { system: Some(self.persona()), prompt, images: Vec::new(), params: self.params, template: Template::Vote, tools: Vec::new(), tool_rounds: Vec::new() };
+
+        let name = self.name.clone();
+        let question_id = msg.question_id;
+        let count = msg.candidates.len();
+        let (provider, evaluation_model) = self.evaluator();
+        let execution = async move {
+            let result = match complete(provider.as_ref(), &request, question_id, &name, evaluation_model).await {
+                Ok(result) => result,
+                Err(e) => return report_failure(question_id, name, "VoteOnCandidates", e),
+            };
+            let (verdict, reasoning) = split_verdict(&result);
+            let choice = leading_number(&verdict)
+                .and_then(|number| number.checked_sub(1))
+                .filter(|index| *index < count);
+            if choice.is_none() {
+                error!("Unexpected response from VoteOnCandidates: {}", result);
+            }
+            Span::current().record("verdict", choice.map(|index| index + 1));
+            Coordinator::from_registry().do_send(CandidateVote { question_id, name, choice, reasoning });
+        };
+
+        self.spawn_call(ctx, question_id, &msg.span, "vote", execution);
+        true
+    }
+}
+
+impl Handler<EvaluateAnswer> for LlmActor {
+    type Result = bool;
+
+    fn handle(&mut self, msg: EvaluateAnswer, ctx: &mut Self::Context) -> Self::Result {
+        let name = self.name.clone();
+        let question_id = msg.question_id;
+        let round = msg.round;
+        let (provider, evaluation_model) = if msg.hurry { self.hurried_evaluator() } else { self.evaluator() };
+        let (images, unseen_images) = self.shown_images(provider.as_ref(), &msg.images);
+        let data = PromptData {
+            question: msg.question.clone(),
+            answer: msg.answer.clone(),
+            documents: msg.documents.clone(),
+            unseen_images,
+            peer_evaluations: msg.peer_evaluations.iter().map(peer_evaluation).collect(),
+            citations: msg.citations,
+            code_runs: msg.code_runs.iter().map(code_run).collect(),
+            ..self.prompt_data()
+        };
+        let template = match msg.mode {
+            EvaluationMode::Binary => Template::BinaryEvaluation,
+            EvaluationMode::Scored { .. } => Template::ScoredEvaluation,
+        };
+        let system = self.persona();
+        // A fact checker first asks what to search for, with the same persona and model it evaluates with.
+        let fact_check = self.search.clone().filter(|_| self.role == ActorRole::FactChecker).map(|search| {
+            let data = PromptData { max_queries: search.max_queries(), ..data.clone() };
+            let prompt = self.prompts.render(Template::FactCheck, &data);
+            (search, CompletionRequest { system: Some(system.clone()), prompt, images: Vec::new(), params: self.params, template: Template::FactCheck, tools: Vec::new(), tool_rounds: Vec::new() })
+        });
+        let tools = self.tool_specs(provider.as_ref());
+        let prompts = self.prompts.clone();
+        let params = self.params;
+        let execution = async move {
+            let mut data = data;
+            if let Some((search, request)) = fact_check {
+                data.search_results = search_claims(provider.as_ref(), &request, &search, question_id, &name, evaluation_model).await;
+            }
+            let prompt = prompts.render(template, &data);
+            let request = CompletionRequest { system: Some(system), prompt, images, params, template, tools, tool_rounds: Vec::new() };
+            let mut caller = ActorCall { question_id, name: &name, evaluation: evaluation_model };
+            let asked = ask_for_evaluation(provider.as_ref(), request, msg.mode, &name, &mut caller).await;
+            let (evaluation, score, reasoning) = match asked {
+                Ok(evaluation) => evaluation,
+                Err(e) => return fail_question(question_id, name, e),
+            };
+            Span::current().record("verdict", field::debug(evaluation));
+            Coordinator::from_registry().do_send(AnswerEvaluation { question_id, round, name, evaluation, score, reasoning });
+        };
+
+        self.spawn_call(ctx, question_id, &msg.span, "evaluate", execution);
+        true
+    }
+}
+
+/// Asks the fact checker what to search for with the `fact_check` request, and runs the searches. If it cannot be
+/// asked, the fact checker evaluates without searching.
+async fn search_claims(provider: &dyn LlmProvider, request: &CompletionRequest, search: &WebSearch, question_id: QuestionId, name: &str, evaluation: bool) -> Vec<FoundResult> {
+    let response = match complete(provider, request, question_id, name, evaluation).await {
+        Ok(response) => response,
+        Err(e) => {
+            warn!("{} could not say what to search for, so it checks the answer without searching: {}", name, e);
+            return Vec::new();
+        },
+    };
+    let queries = search::parse_queries(&response, search.max_queries());
+    debug!("{} searches for {:?} to check the answer.", name, queries);
+    search.search_all(&queries).await
+}
+
+/// Calls the provider, reporting the tokens each call used, and any fallback that answered, to the [Coordinator]
+/// as it succeeds. While the model calls the request's tools instead of responding, the Coordinator runs them and
+/// the model is asked again with what they returned, up to [MAX_TOOL_ROUNDS] times. `evaluation` is set if the
+/// provider is the actor's evaluation model.
+async fn complete(provider: &dyn LlmProvider, request: &CompletionRequest, question_id: QuestionId, name: &str, evaluation: bool) -> Result<String, ProviderError> {
+    let coordinator = Coordinator::from_registry();
+    let mut request = Cow::Borrowed(request);
+    for _ in 0..=MAX_TOOL_ROUNDS {
+        let started = Instant::now();
+        let completion = provider.complete(&request).await?;
+        let latency = started.elapsed();
+        coordinator.do_send(UsageReport { question_id, name: name.to_string(), usage: completion.usage, backend: completion.backend, evaluation, template: request.template, latency });
+        if completion.tool_calls.is_empty() {
+            return Ok(completion.text);
+        }
+        let outputs = coordinator.send(UseTools { question_id, name: name.to_string(), calls: completion.tool_calls.clone() }).await
+            .map_err(|e| ProviderError::InvalidResponse(format!("the tools it called could not be run: {}", e)))?;
+        request.to_mut().tool_rounds.push(ToolRound { text: completion.text, calls: completion.tool_calls, outputs });
+    }
+    Err(ProviderError::InvalidResponse(format!("the model was still calling tools after {} rounds of them", MAX_TOOL_ROUNDS)))
+}
+
+/// Calls an actor's provider for a question as [complete] does.
+struct ActorCall<'a> {
+    question_id: QuestionId,
+    name: &'a str,
+    /// Whether the provider is the actor's evaluation model.
+    evaluation: bool,
+}
+
+#[async_trait]
+impl Caller for ActorCall<'_> {
+    async fn complete(&mut self, provider: &dyn LlmProvider, request: &CompletionRequest) -> Result<String, ProviderError> {
+        complete(provider, request, self.question_id, self.name, self.evaluation).await
+    }
+}
+
+/// Like [complete], but also sends the [Coordinator] each piece of the response as it arrives, for answers
+/// that can be shown while they are written.
+async fn stream(provider: &dyn LlmProvider, request: &CompletionRequest, question_id: QuestionId, name: &str, stage: Stage) -> Result<String, ProviderError> {
+    let coordinator = Coordinator::from_registry();
+    // Tool calls are not read from streamed responses, so an answer that may call tools is sent whole.
+    if !request.tools.is_empty() {
+        let answer = complete(provider, request, question_id, name, false).await?;
+        coordinator.do_send(AnswerToken { question_id, name: name.to_string(), stage, token: answer.clone() });
+        return Ok(answer);
+    }
+    let on_token = |token: &str| coordinator.do_send(AnswerToken { question_id, name: name.to_string(), stage, token: token.to_string() });
+    let started = Instant::now();
+    let completion = provider.stream(request, &on_token).await?;
+    let latency = started.elapsed();
+    coordinator.do_send(UsageReport { question_id, name: name.to_string(), usage: completion.usage, backend: completion.backend, evaluation: false, template: request.template, latency });
+    Ok(completion.text)
+}
+
+/// Streams an answer, then holds it to the constraints.
+async fn write_answer(provider: &dyn LlmProvider, request: CompletionRequest, constraints: &AnswerConstraints, question_id: QuestionId, name: &str, stage: Stage) -> Result<String, ProviderError> {
+    let answer = stream(provider, &request, question_id, name, stage).await?;
+    keep_to_constraints(provider, request, answer, constraints, name, &mut ActorCall { question_id, name, evaluation: false }).await
+}
+
+/// Writes `sampling.samples` answers at once at the sampling temperature, and keeps the one that agrees most with
+/// the others, preferring those that keep to the constraints. It is then held to them like a streamed answer.
+/// Samples whose calls failed are left out, unless every one did.
+async fn sample_answer(provider: &dyn LlmProvider, request: CompletionRequest, sampling: Sampling, constraints: &AnswerConstraints, question_id: QuestionId, name: &str) -> Result<String, ProviderError> {
+    let sampled = CompletionRequest { params: GenerationParams { temperature: Some(sampling.temperature), ..request.params }, ..request.clone() };
+    let calls = (0..sampling.samples).map(|_| complete(provider, &sampled, question_id, name, false));
+    let mut samples = Vec::with_capacity(sampling.samples);
+    let mut failure = None;
+    for result in join_all(calls).await {
+        match result {
+            Ok(sample) => samples.push(sample),
+            Err(e) => failure = Some(e),
+        }
+    }
+    if samples.is_empty() {
+        // No failure either only if no samples were asked for.
+        return Err(failure.unwrap_or(ProviderError::EmptyResponse));
+    }
+    let keeping: Vec<&str> = samples.iter().map(String::as_str).filter(|sample| constraints.check(sample).is_ok()).collect();
+    let pool = if keeping.is_empty() { samples.iter().map(String::as_str).collect() } else { keeping };
+    let chosen = most_representative(&pool);
+    debug!("{} wrote {} samples, of which {} kept to the constraints. Drafting sample {} of those.", name, samples.len(), pool.len(), chosen + 1);
+    let answer = pool[chosen].to_string();
+    Coordinator::from_registry().do_send(AnswerToken { question_id, name: name.to_string(), stage: Stage::Drafting, token: answer.clone() });
+    keep_to_constraints(provider, request, answer, constraints, name, &mut ActorCall { question_id, name, evaluation: false }).await
+}
+
+/// The index of the sample that agrees most with the others: the one sharing the largest share of words with
+/// them on average.
+fn most_representative(samples: &[&str]) -> usize {
+    let words: Vec<HashSet<String>> = samples.iter()
+        .map(|sample| sample.split(|c: char| !c.is_alphanumeric()).filter(|word| !word.is_empty()).map(str::to_lowercase).collect())
+        .collect();
+    let agreement = |index: usize| -> f64 {
+        words.iter()
+            .enumerate()
+            .filter(|(other, _)| *other != index)
+            .map(|(_, other)| {
+                let union = words[index].union(other).count();
+                if union == 0 { 1.0 } else { words[index].intersection(other).count() as f64 / union as f64 }
+            })
+            .sum()
+    };
+    (0..samples.len()).rev().max_by(|a, b| agreement(*a).total_cmp(&agreement(*b))).unwrap_or(0)
+}
+
+/// Tells the [Coordinator] that a provider call failed for good, so that the question fails instead of hanging.
+fn report_failure(question_id: QuestionId, name: String, call: &'static str, error: ProviderError) {
+    fail_question(question_id, name.clone(), ConsensusError::Provider { actor: name, call, error });
+}
+
+/// Tells the [Coordinator] that the actor cannot go on with the question, so that the question fails.
+fn fail_question(question_id: QuestionId, name: String, error: ConsensusError) {
+    error!("Question {} failed: {}", question_id, error);
+    Coordinator::from_registry().do_send(ProviderFailed { question_id, name, error });
+}
+
+/// Describes another actor's previous evaluation for debate mode.
+fn peer_evaluation(evaluation: &Evaluation) -> PromptEvaluation {
+    let verdict = match evaluation.score {
+        Some(score) => format!("score {}", score),
+        None => format!("{:?}", evaluation.feedback),
+    };
+    PromptEvaluation { actor: evaluation.actor.clone(), verdict, reasoning: evaluation.reasoning.clone() }
+}
+
+/// Describes how running one of the answer's code blocks went, for the evaluation prompts.
+fn code_run(run: &CodeRun) -> PromptCodeRun {
+    PromptCodeRun { block: run.block, language: run.language.clone(), outcome: run.outcome(), output: run.output.clone() }
+}
+
+/// Splits a response into its first non-empty line and the reasoning that follows it.
+fn split_verdict(result: &str) -> (String, String) {
+    let mut result_parts: Vec<&str> = result.split("\n")
+        .filter(|s| !(*s).is_empty())
+        .collect();
+    if result_parts.is_empty() {
+        return (String::new(), String::new());
+    }
+    let verdict = result_parts[0].replace(" ", "");
+    let reasoning = result_parts.split_off(1).join("\n\n");
+    (verdict, reasoning)
+}
+
+/// Parses the first run of digits in a verdict line, such as the 7 in "Score: 7".
+fn leading_number(verdict: &str) -> Option<usize> {
+    let digits: String = verdict.chars()
+        .skip_while(|c| !c.is_ascii_digit())
+        .take_while(|c| c.is_ascii_digit())
+        .collect();
+    digits.parse().ok()
+}
+
+/// Finds the panel member a routing response names: the first line if it is exactly a name, otherwise the
+/// longest name mentioned as a whole word anywhere in the response.
+fn parse_route(response: &str, names: &[String]) -> Option<String> {
+    let first_line = response.lines().map(str::trim).find(|line| !line.is_empty()).unwrap_or_default();
+    let first_line = first_line.trim_matches(|c: char| !c.is_alphanumeric());
+    if let Some(name) = names.iter().find(|name| name.eq_ignore_ascii_case(first_line)) {
+        return Some(name.clone());
+    }
+    let response = response.to_lowercase();
+    names.iter()
+        .filter(|name| mentions(&response, &name.to_lowercase()))
+        .max_by_key(|name| name.len())
+        .cloned()
+}
+
+/// Whether `word` appears in `text` other than as part of a longer word.
+fn mentions(text: &str, word: &str) -> bool {
+    text.match_indices(word).any(|(start, _)| {
+        let before = text[..start].chars().next_back();
+        let after = text[start + word.len()..].chars().next();
+        !before.is_some_and(char::is_alphanumeric) && !after.is_some_and(char::is_alphanumeric)
+    })
+}
+
+impl Handler<RefineAnswer> for LlmActor {
+    type Result = bool;
+
+    fn handle(&mut self, msg: RefineAnswer, ctx: &mut Self::Context) -> Self::Result {
+        let (images, unseen_images) = self.shown_images(self.provider.as_ref(), &msg.images);
+        let data = PromptData {
+            question: msg.question,
+            answer: msg.answer,
+            reasoning: msg.reasoning,
+            veto: msg.veto.unwrap_or_default(),
+            guidance: msg.guidance.unwrap_or_default(),
+            documents: msg.documents,
+            unseen_images,
+            constraints: msg.constraints.describe(),
+            ..self.prompt_data()
+        };
+        let prompt = self.prompts.render(Template::Refine, &data);
+        let request = CompletionRequest { system: Some(self.persona()), prompt, images, params: self.params, template: Template::Refine, tools: self.tool_specs(self.provider.as_ref()), tool_rounds: Vec::new() };
+
+        let name = self.name.clone();
+        let question_id = msg.question_id;
+        let provider = self.provider.clone();
+        let execution = async move {
+            let answer = match write_answer(provider.as_ref(), request, &msg.constraints, question_id, &name, Stage::Refining).await {
+                Ok(answer) => answer,
+                Err(e) => return report_failure(question_id, name, "RefineAnswer", e),
+            };
+            Coordinator::from_registry().do_send(AnswerRefinement { question_id, name, answer });
+        };
+
+        self.spawn_call(ctx, question_id, &msg.span, "refine", execution);
+        true
+    }
+}
+
+impl Handler<SuggestRefinement> for LlmActor {
+    type Result = bool;
+
+    fn handle(&mut self, msg: SuggestRefinement, ctx: &mut Self::Context) -> Self::Result {
+        let data = PromptData { question: msg.question, answer: msg.answer, reasoning: msg.reasoning, ..self.prompt_data() };
+        let prompt = self.prompts.render(Template::Suggest, &data);
+        let request = CompletionRequest { system: Some(self.persona()), prompt, images: Vec::new(), params: self.params, template: Template::Suggest, tools: Vec::new(), tool_rounds: Vec::new() };
+
+        let name = self.name.clone();
+        let question_id = msg.question_id;
+        let provider = self.provider.clone();
+        let execution = async move {
+            let suggestion = match complete(provider.as_ref(), &request, question_id, &name, false).await {
+                Ok(suggestion) => suggestion,
+                Err(e) => return report_failure(question_id, name, "SuggestRefinement", e),
+            };
+            Coordinator::from_registry().do_send(RefinementSuggestion { question_id, name, suggestion });
+        };
+
+        self.spawn_call(ctx, question_id, &msg.span, "suggest", execution);
+        true
+    }
+}
+
+impl Handler<SynthesizeAnswer> for LlmActor {
+    type Result = bool;
+
+    fn handle(&mut self, msg: SynthesizeAnswer, ctx: &mut Self::Context) -> Self::Result {
+        let suggestions = msg.suggestions.into_iter()
+            .map(|(actor, suggestion)| PromptSuggestion { actor, suggestion })
+            .collect();
+        let (images, unseen_images) = self.shown_images(self.provider.as_ref(), &msg.images);
+        let data = PromptData {
+            question: msg.question,
+            answer: msg.answer,
+            suggestions,
+            documents: msg.documents,
+            unseen_images,
+            constraints: msg.constraints.describe(),
+            ..self.prompt_data()
+        };
+        let prompt = self.prompts.render(Template::Synthesize, &data);
+        let request = CompletionRequest { system: None, prompt, images, params: self.params, template: Template::Synthesize, tools: self.tool_specs(self.provider.as_ref()), tool_rounds: Vec::new() };
+
+        let name = self.name.clone();
+        let question_id = msg.question_id;
+        let provider = self.provider.clone();
+        let execution = async move {
+            let answer = match write_answer(provider.as_ref(), request, &msg.constraints, question_id, &name, Stage::Refining).await {
+                Ok(answer) => answer,
+                Err(e) => return report_failure(question_id, name, "SynthesizeAnswer", e),
+            };
+            Coordinator::from_registry().do_send(AnswerRefinement { question_id, name, answer });
+        };
+
+        self.spawn_call(ctx, question_id, &msg.span, "synthesize", execution);
+        true
+    }
+}
+
+impl Handler<Summarize> for LlmActor {
+    type Result = bool;
+
+    fn handle(&mut self, msg: Summarize, ctx: &mut Self::Context) -> Self::Result {
+        let Some(latest) = msg.rounds.last() else { return false };
+        let versions = msg.rounds.iter()
+            .enumerate()
+            .map(|(index, round)| PromptVersion { number: index + 1, text: round.answer.clone(), evaluations: round.evaluations.iter().map(peer_evaluation).collect() })
+            .collect();
+        let data = PromptData {
+            question: msg.question,
+            answer: latest.answer.clone(),
+            peer_evaluations: latest.evaluations.iter().map(peer_evaluation).collect(),
+            versions,
+            ..self.prompt_data()
+        };
+        let template = if msg.ruling { Template::Decide } else { Template::Summarize };
+        let prompt = self.prompts.render(template, &data);
+        let request = CompletionRequest { system: Some(self.persona()), prompt, images: Vec::new(), params: self.params, template, tools: Vec::new(), tool_rounds: Vec::new() };
+
+        let name = self.name.clone();
+        let question_id = msg.question_id;
+        let count = msg.rounds.len();
+        let ruling = msg.ruling;
+        // A ruling is a judgment like a vote, while guidance is written like a refinement.
+        let (provider, evaluation_model) = if ruling { self.evaluator() } else { (self.provider.clone(), false) };
+        let execution = async move {
+            let result = match complete(provider.as_ref(), &request, question_id, &name, evaluation_model).await {
+                Ok(result) => result,
+                Err(e) => return report_failure(question_id, name, "Summarize", e),
+            };
+            let decision = if ruling {
+                let (verdict, reasoning) = split_verdict(&result);
+                let version = leading_number(&verdict)
+                    .and_then(|number| number.checked_sub(1))
+                    .filter(|index| *index < count);
+                if version.is_none() {
+                    error!("Unexpected ruling from Summarize: {}", result);
+                }
+                ChairDecision { question_id, name, version, text: reasoning }
+            } else {
+                ChairDecision { question_id, name, version: None, text: result.trim().to_string() }
+            };
+            Coordinator::from_registry().do_send(decision);
+        };
+
+        self.spawn_call(ctx, question_id, &msg.span, if ruling { "rule" } else { "summarize" }, execution);
+        true
+    }
+}
+
+impl Handler<Ping> for LlmActor {
+    type Result = ResponseFuture<Result<(), ProviderError>>;
+
+    fn handle(&mut self, _msg: Ping, _ctx: &mut Self::Context) -> Self::Result {
+        let provider = self.provider.clone();
+        let evaluators: Vec<_> = self.evaluator.iter().chain(&self.fast_evaluator).cloned().collect();
+        Box::pin(async move {
+            provider.check().await?;
+            for evaluator in evaluators {
+                evaluator.check().await?;
+            }
+            Ok(())
+        })
+    }
+}
+
+impl Handler<CancelCalls> for LlmActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: CancelCalls, ctx: &mut Self::Context) -> Self::Result {
+        let cancelled: Vec<SpawnHandle> = match msg.question_id {
+            Some(question_id) => self.calls.remove(&question_id).into_iter().flat_map(|calls| calls.into_values()).collect(),
+            None => self.calls.drain().flat_map(|(_, calls)| calls.into_values()).collect(),
+        };
+        if !cancelled.is_empty() {
+            debug!("LLM actor {} cancelled {} provider calls.", self.name, cancelled.len());
+        }
+        for handle in cancelled {
+            ctx.cancel_future(handle);
+        }
+    }
+}
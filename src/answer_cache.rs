@@ -0,0 +1,228 @@
+//! Answering a question with the consensus the panel reached on one like it in an earlier session, rather than
+//! deliberating on it again.
+//!
+//! Each question in the [History] is embedded once, at the first lookup after its run is recorded, and each new
+//! question is compared with them by meaning. When one is at least as similar as the
+//! configured threshold, and every actor that judged its answer in the final round approved it, that answer is
+//! given at once. A question asked with `fresh` is always deliberated on.
+
+use std::{sync::{Arc, Mutex, MutexGuard}, time::Instant};
+
+use serde::Deserialize;
+
+use crate::{
+    history::{History, HistoryEntry},
+    provider::{cosine_similarity, EmbeddingConfig, EmbeddingProvider},
+    result::{self, CachedAnswer, ConsensusResult, Evaluation, Feedback, QuestionId, Round},
+    usage::UsageSummary,
+};
+
+/// How many questions are embedded in one request.
+const EMBEDDING_BATCH: usize = 32;
+
+/// The `[history.answer_cache]` section of the config file.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AnswerCacheConfig {
+    /// How similar, from 0 to 1, a question must be to one answered before for its answer to be reused.
+    pub threshold: f64,
+    #[serde(flatten)]
+    pub embedding: EmbeddingConfig,
+}
+
+impl AnswerCacheConfig {
+    /// Checks the config, returning a description of the problem if it is unusable.
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.threshold > 0.0 && self.threshold <= 1.0) {
+            return Err("answer_cache threshold must be in (0, 1]".to_string());
+        }
+        self.embedding.validate().map_err(|reason| format!("answer_cache: {}", reason))
+    }
+
+    /// Names the embedding model, so that questions embedded with another one are embedded again.
+    fn embedding_model(&self) -> String {
+        format!("{:?}/{}", self.embedding.provider, self.embedding.model.as_deref().unwrap_or("default")).to_lowercase()
+    }
+}
+
+/// Finds the answers of past runs whose questions mean nearly the same as a new one.
+pub struct AnswerCache {
+    /// A connection of its own to the history database, which the runs are recorded to through another.
+    history: Mutex<History>,
+    embedder: Arc<dyn EmbeddingProvider>,
+    model: String,
+    threshold: f64,
+}
+
+impl AnswerCache {
+    pub fn new(config: &AnswerCacheConfig, history: History, embedder: Arc<dyn EmbeddingProvider>) -> Self {
+        AnswerCache { history: Mutex::new(history), embedder, model: config.embedding_model(), threshold: config.threshold }
+    }
+
+    /// The answer of the past run whose question is most similar to this one, as the result of asking it, if any
+    /// is similar enough and was approved by every actor that judged it in the final round. Embeds the questions
+    /// recorded since the last lookup along the way.
+    pub async fn lookup(&self, question: &str) -> Result<Option<ConsensusResult>, String> {
+        let started = Instant::now();
+        let pending = self.history().unembedded(&self.model).map_err(|e| e.to_string())?;
+        let texts: Vec<String> = pending.iter().map(|(_, question)| question.clone()).chain([question.to_string()]).collect();
+        let mut embeddings = Vec::with_capacity(texts.len());
+        for batch in texts.chunks(EMBEDDING_BATCH) {
+            embeddings.extend(self.embedder.embed(batch).await.map_err(|e| e.to_string())?);
+        }
+        if embeddings.len() != texts.len() {
+            return Err(format!("{} embeddings for {} questions", embeddings.len(), texts.len()));
+        }
+        let embedding = embeddings.pop().unwrap_or_default();
+
+        let history = self.history();
+        for ((run_id, _), stored) in pending.iter().zip(&embeddings) {
+            history.store_embedding(*run_id, &self.model, stored).map_err(|e| e.to_string())?;
+        }
+        let mut similar: Vec<(f64, i64)> = history.embeddings(&self.model).map_err(|e| e.to_string())?.into_iter()
+            .map(|(run_id, stored)| (cosine_similarity(&embedding, &stored), run_id))
+            .filter(|(similarity, _)| *similarity >= self.threshold)
+            .collect();
+        similar.sort_by(|a, b| b.0.total_cmp(&a.0));
+        for (similarity, run_id) in similar {
+            let Some(entry) = history.run(run_id).map_err(|e| e.to_string())? else { continue };
+            if let Some(result) = reuse(question, entry, similarity, started) {
+                return Ok(Some(result));
+            }
+        }
+        Ok(None)
+    }
+
+    fn history(&self) -> MutexGuard<'_, History> {
+        self.history.lock().expect("the history should not be poisoned")
+    }
+}
+
+/// The run's answer as the result of asking `question`, if every actor that judged it in the final round, without
+/// abstaining, approved it.
+fn reuse(question: &str, entry: HistoryEntry, similarity: f64, started: Instant) -> Option<ConsensusResult> {
+    let last = entry.votes.iter().map(|vote| vote.round).max()?;
+    let evaluations: Vec<Evaluation> = entry.votes.into_iter()
+        .filter(|vote| vote.round == last)
+        .map(|vote| Evaluation {
+            actor: vote.actor,
+            feedback: match vote.feedback.as_str() {
+                "Good" => Feedback::Good,
+                "Abstain" => Feedback::Abstain,
+                _ => Feedback::NeedsRefinement,
+            },
+            score: vote.score,
+            reasoning: vote.reasoning,
+        })
+        .collect();
+    let judged: Vec<&Evaluation> = evaluations.iter().filter(|evaluation| evaluation.feedback != Feedback::Abstain).collect();
+    if judged.is_empty() || judged.iter().any(|evaluation| evaluation.feedback != Feedback::Good) {
+        return None;
+    }
+    let rounds = vec![Round { author: entry.answered_by.clone(), answer: entry.answer.clone(), evaluations }];
+    Some(ConsensusResult {
+        question_id: QuestionId::default(),
+        question: question.to_string(),
+        answer: entry.answer,
+        consensus_reached: true,
+        converged: false,
+        flagged: None,
+        budget_exceeded: None,
+        deadlock: None,
+        ruling: None,
+        cached: Some(CachedAnswer { run_id: entry.id, question: entry.question, finished_at: entry.finished_at, similarity }),
+        answered_by: entry.answered_by,
+        candidates: Vec::new(),
+        confidence: result::confidence(&rounds, true),
+        rounds,
+        refinement_rounds: entry.refinement_rounds,
+        elapsed: started.elapsed(),
+        dissent: Vec::new(),
+        citations: Vec::new(),
+        fallbacks: Default::default(),
+        usage: UsageSummary::default(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        hash::{DefaultHasher, Hash, Hasher},
+        path::Path,
+        time::Duration,
+    };
+
+    use async_trait::async_trait;
+    use futures::executor::block_on;
+
+    use super::*;
+    use crate::provider::{ProviderError, ProviderKind};
+
+    /// Embeds a text as the counts of its words, so that questions sharing most of their words are similar.
+    struct BagOfWords;
+
+    #[async_trait]
+    impl EmbeddingProvider for BagOfWords {
+        async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, ProviderError> {
+            Ok(texts.iter().map(|text| {
+                let mut counts = vec![0.0; 64];
+                for word in text.split(|c: char| !c.is_alphanumeric()).filter(|word| !word.is_empty()) {
+                    let mut hasher = DefaultHasher::new();
+                    word.to_lowercase().hash(&mut hasher);
+                    counts[hasher.finish() as usize % 64] += 1.0;
+                }
+                counts
+            }).collect())
+        }
+    }
+
+    /// A run in which the only actor approved the answer as first drafted.
+    fn approved(question: &str, answer: &str) -> ConsensusResult {
+        let evaluation = Evaluation { actor: "Scholar".to_string(), feedback: Feedback::Good, score: None, reasoning: "Correct.".to_string() };
+        ConsensusResult {
+            question_id: QuestionId(1),
+            question: question.to_string(),
+            answer: answer.to_string(),
+            consensus_reached: true,
+            converged: false,
+            flagged: None,
+            budget_exceeded: None,
+            deadlock: None,
+            ruling: None,
+            cached: None,
+            answered_by: "Scholar".to_string(),
+            candidates: Vec::new(),
+            rounds: vec![Round { author: "Scholar".to_string(), answer: answer.to_string(), evaluations: vec![evaluation] }],
+            refinement_rounds: 0,
+            confidence: 1.0,
+            elapsed: Duration::from_secs(1),
+            dissent: Vec::new(),
+            citations: Vec::new(),
+            fallbacks: Default::default(),
+            usage: UsageSummary::default(),
+        }
+    }
+
+    fn cache() -> AnswerCache {
+        let mut history = History::open(Path::new(":memory:")).expect("an in-memory database should open");
+        history.record(&approved("What is the capital of France?", "Paris.")).expect("the run should be recorded");
+        let config = AnswerCacheConfig {
+            threshold: 0.9,
+            embedding: EmbeddingConfig { provider: ProviderKind::Ollama, model: None, base_url: None },
+        };
+        AnswerCache::new(&config, history, Arc::new(BagOfWords))
+    }
+
+    #[test]
+    fn near_duplicate_question_reuses_the_answer() {
+        let result = block_on(cache().lookup("What is the capital city of France?")).expect("the lookup should succeed");
+        let result = result.expect("the question should hit the cache");
+        assert_eq!(result.answer, "Paris.");
+        assert_eq!(result.cached.map(|cached| cached.question), Some("What is the capital of France?".to_string()));
+    }
+
+    #[test]
+    fn different_question_is_deliberated_on() {
+        let result = block_on(cache().lookup("How do the tides work?")).expect("the lookup should succeed");
+        assert!(result.is_none());
+    }
+}
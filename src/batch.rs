@@ -0,0 +1,175 @@
+//! Answering a file of questions, several at a time.
+//!
+//! Each non-empty line of a batch file is one question. Lines starting with `#` are comments, and a
+//! line starting with `{` is a JSON object `{"question": "...", "strategy": {...}}` that can override
+//! the strategy for that question.
+
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::{mpsc, Semaphore};
+use tracing::debug;
+
+use crate::{messages::{Feedback, QuestionOptions}, result::{CachedAnswer, ConsensusResult, Dissent}, strategy::ConsensusStrategy, ConsensusSystem};
+
+/// One question read from a batch file.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BatchEntry {
+    /// The line of the batch file the question is on, starting at 1.
+    #[serde(skip)]
+    pub line: usize,
+    pub question: String,
+    /// Overrides the configured strategy for this question.
+    #[serde(default)]
+    pub strategy: Option<ConsensusStrategy>,
+}
+
+/// Parses a batch file, returning a description of the first malformed line if there is one.
+pub fn parse(contents: &str) -> Result<Vec<BatchEntry>, String> {
+    let mut entries = Vec::new();
+    for (index, text) in contents.lines().enumerate() {
+        let line = index + 1;
+        let text = text.trim();
+        if text.is_empty() || text.starts_with('#') {
+            continue;
+        }
+        let entry = if text.starts_with('{') {
+            let entry: BatchEntry = serde_json::from_str(text).map_err(|e| format!("line {} is not a valid question object: {}", line, e))?;
+            if let Some(Err(reason)) = entry.strategy.as_ref().map(ConsensusStrategy::validate) {
+                return Err(format!("line {} has an invalid strategy: {}", line, reason));
+            }
+            BatchEntry { line, ..entry }
+        } else {
+            BatchEntry { line, question: text.to_string(), strategy: None }
+        };
+        if entry.question.trim().is_empty() {
+            return Err(format!("line {} has an empty question", line));
+        }
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// The outcome of one question in a batch, written as one line of the results file.
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchResult {
+    pub line: usize,
+    pub question: String,
+    /// `answered` or `failed`.
+    pub status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub answer: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub consensus_reached: Option<bool>,
+    /// Whether the panel stopped because the refinements had stopped changing the answer.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub converged: Option<bool>,
+    /// Why the moderator refused the question, if it did.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flagged: Option<String>,
+    /// Why the panel stopped refining the answer, if another round would have overrun a budget.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub budget_exceeded: Option<String>,
+    /// Why the panel stopped refining the answer, if it was going in circles.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deadlock: Option<String>,
+    /// The chair's reasoning, if it ruled on the answer.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ruling: Option<String>,
+    /// The earlier run the answer was reused from, if the question meant nearly the same as its question.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cached: Option<CachedAnswer>,
+    /// How many versions of the answer the panel produced, including the first draft.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rounds: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refinement_rounds: Option<u32>,
+    /// How firmly the panel stands behind the answer, from 0 to 1. See [ConsensusResult::confidence].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<f64>,
+    /// Every evaluation that said the answer needed refinement.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub dissent: Vec<Dissent>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl BatchResult {
+    fn answered(entry: BatchEntry, result: ConsensusResult) -> Self {
+        let dissent = result.rounds.iter()
+            .enumerate()
+            .flat_map(|(round, version)| version.evaluations.iter()
+                .filter(|evaluation| evaluation.feedback == Feedback::NeedsRefinement)
+                .map(move |evaluation| Dissent { round, actor: evaluation.actor.clone(), reasoning: evaluation.reasoning.clone() }))
+            .collect();
+        BatchResult {
+            line: entry.line,
+            question: entry.question,
+            status: "answered",
+            consensus_reached: Some(result.consensus_reached),
+            converged: Some(result.converged),
+            flagged: result.flagged,
+            budget_exceeded: result.budget_exceeded,
+            deadlock: result.deadlock,
+            ruling: result.ruling,
+            cached: result.cached,
+            rounds: Some(result.rounds.len()),
+            refinement_rounds: Some(result.refinement_rounds),
+            confidence: Some(result.confidence),
+            answer: Some(result.answer),
+            dissent,
+            error: None,
+        }
+    }
+
+    fn failed(entry: BatchEntry, error: String) -> Self {
+        BatchResult {
+            line: entry.line,
+            question: entry.question,
+            status: "failed",
+            answer: None,
+            consensus_reached: None,
+            converged: None,
+            flagged: None,
+            budget_exceeded: None,
+            deadlock: None,
+            ruling: None,
+            cached: None,
+            rounds: None,
+            refinement_rounds: None,
+            confidence: None,
+            dissent: Vec::new(),
+            error: Some(error),
+        }
+    }
+}
+
+/// Asks the panel every question, at most `concurrency` at a time, and hands each result to
+/// `on_result` as soon as it is ready, so results arrive in the order the questions finish.
+pub async fn run(system: &ConsensusSystem, entries: Vec<BatchEntry>, concurrency: usize, mut on_result: impl FnMut(BatchResult)) {
+    let permits = Arc::new(Semaphore::new(concurrency.max(1)));
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    for entry in entries {
+        let permit = permits.clone().acquire_owned().await.expect("the semaphore should never be closed");
+        let system = system.clone();
+        let sender = sender.clone();
+        actix::spawn(async move {
+            debug!("Asking the question on line {} of the batch.", entry.line);
+            let options = QuestionOptions { strategy: entry.strategy, ..QuestionOptions::default() };
+            let result = match system.ask_with(entry.question.clone(), options).await {
+                Ok(result) => BatchResult::answered(entry, result),
+                Err(e) => BatchResult::failed(entry, e.to_string()),
+            };
+            drop(permit);
+            let _ = sender.send(result);
+        });
+        // Pass on whatever has finished while waiting for a free slot.
+        while let Ok(result) = receiver.try_recv() {
+            on_result(result);
+        }
+    }
+    drop(sender);
+    while let Some(result) = receiver.recv().await {
+        on_result(result);
+    }
+}
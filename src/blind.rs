@@ -0,0 +1,165 @@
+//! Blind evaluation, which keeps the actors judging an answer from telling which of them wrote it.
+//!
+//! Models often introduce themselves in their answers or sign them, so under blind evaluation every answer an
+//! actor is asked to evaluate or vote on is stripped of such hints first. The [Coordinator](crate::Coordinator)
+//! still records who wrote each answer.
+
+/// What the author's name is replaced with.
+const AUTHOR: &str = "the author";
+
+/// How an opening line that introduces the author starts, and whether the introduction ends at the first comma
+/// rather than at the end of the first sentence.
+const INTRODUCTIONS: [(&str, bool); 8] = [
+    ("as a ", true),
+    ("as an ", true),
+    ("as the ", true),
+    ("as your ", true),
+    ("speaking as ", true),
+    ("i am ", false),
+    ("i'm ", false),
+    ("this is ", false),
+];
+
+/// The dashes a closing line signing the answer may open with, before the author's name.
+const DASHES: [char; 4] = ['-', '~', '\u{2014}', '\u{2013}'];
+
+/// The sign-offs a closing line signing the answer may open with, before the author's name, longest first.
+const SIGN_OFFS: [&str; 7] = ["best regards", "kind regards", "regards", "sincerely", "signed", "cheers", "yours"];
+
+/// Signatures are short, so a longer closing line is part of the answer.
+const MAX_SIGNATURE_CHARS: usize = 80;
+
+/// The answer without the hints of who wrote it that models tend to leave: an opening line introducing the author
+/// by name or domain, a closing line signing it, and any other mention of the author's name. A name too short to
+/// tell from an ordinary word, such as a single letter, is only stripped from the opening and closing lines.
+pub fn strip_authorship(answer: &str, name: &str, domain: &str) -> String {
+    let mentions = |line: &str| {
+        let line = line.to_lowercase();
+        contains_word(&line, &name.to_lowercase()) || (!domain.is_empty() && line.contains(&domain.to_lowercase()))
+    };
+    let mut lines: Vec<String> = answer.lines().map(str::to_string).collect();
+
+    if let Some(first) = lines.iter().position(|line| !line.trim().is_empty()) {
+        if mentions(&lines[first]) {
+            if let Some(rest) = without_introduction(&lines[first]) {
+                match rest.is_empty() {
+                    true => { lines.remove(first); },
+                    false => lines[first] = rest,
+                }
+            }
+        }
+    }
+    if let Some(last) = lines.iter().rposition(|line| !line.trim().is_empty()) {
+        if is_signature(&lines[last], name) {
+            lines.remove(last);
+        }
+    }
+
+    let mut stripped = lines.join("\n").trim().to_string();
+    if name.chars().count() >= 3 {
+        stripped = replace_word(&stripped, name, AUTHOR);
+    }
+    stripped
+}
+
+/// The line without the introduction it opens with, capitalized again, or None if it does not open with one, or
+/// the introduction's end cannot be told.
+fn without_introduction(line: &str) -> Option<String> {
+    let trimmed = line.trim();
+    let lower = trimmed.to_lowercase();
+    let &(_, to_comma) = INTRODUCTIONS.iter().find(|(start, _)| lower.starts_with(start))?;
+    let end = match to_comma {
+        true => trimmed.find(", "),
+        false => trimmed.find(". "),
+    };
+    // An introduction with nothing after it takes up the whole line.
+    let end = end.map(|end| end + 2).or_else(|| trimmed.ends_with(['.', ',', ':', '!']).then_some(trimmed.len()))?;
+    let rest = trimmed[end..].trim_start();
+    let mut chars = rest.chars();
+    Some(chars.next().map_or_else(String::new, |first| first.to_uppercase().chain(chars).collect()))
+}
+
+/// Whether the closing line signs the answer with the author's name: the name on its own, or after a dash or a
+/// sign-off, optionally followed by a comma and a few words about the author.
+fn is_signature(line: &str, name: &str) -> bool {
+    let name = name.to_lowercase();
+    let lower = line.trim().to_lowercase();
+    if name.is_empty() || lower.chars().count() > MAX_SIGNATURE_CHARS {
+        return false;
+    }
+    let mut rest = lower.trim_start_matches(DASHES).trim_start();
+    if let Some(sign_off) = SIGN_OFFS.iter().find(|sign_off| rest.starts_with(*sign_off)) {
+        rest = rest[sign_off.len()..].trim_start_matches([',', ':']).trim_start();
+    }
+    let Some(after) = rest.strip_prefix(&name) else { return false };
+    let after = after.trim_end_matches(['.', '!']);
+    after.is_empty() || after.starts_with(", ")
+}
+
+/// Whether `word` occurs in `text` with no letter or digit either side of it.
+fn contains_word(text: &str, word: &str) -> bool {
+    !word.is_empty() && text.match_indices(word).any(|(start, _)| at_word_boundary(text, start, start + word.len()))
+}
+
+/// `text` with every occurrence of `word` that stands as a word of its own replaced, capitalized where it starts a
+/// sentence.
+fn replace_word(text: &str, word: &str, with: &str) -> String {
+    let mut replaced = String::with_capacity(text.len());
+    let mut copied = 0;
+    for (start, _) in text.match_indices(word) {
+        if at_word_boundary(text, start, start + word.len()) {
+            replaced.push_str(&text[copied..start]);
+            let starts_sentence = text[..start].trim_end().chars().next_back().is_none_or(|last| matches!(last, '.' | '!' | '?'));
+            let mut chars = with.chars();
+            match (starts_sentence, chars.next()) {
+                (true, Some(first)) => replaced.extend(first.to_uppercase().chain(chars)),
+                _ => replaced.push_str(with),
+            }
+            copied = start + word.len();
+        }
+    }
+    replaced.push_str(&text[copied..]);
+    replaced
+}
+
+fn at_word_boundary(text: &str, start: usize, end: usize) -> bool {
+    !text[..start].chars().next_back().is_some_and(char::is_alphanumeric) && !text[end..].chars().next().is_some_and(char::is_alphanumeric)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn introduction_and_signature_are_stripped() {
+        let answer = "As a Rust expert, I would use an iterator.\nIt avoids the bounds checks.\n\n\u{2014} Ferris";
+        assert_eq!(strip_authorship(answer, "Ferris", "Rust"), "I would use an iterator.\nIt avoids the bounds checks.");
+        assert_eq!(strip_authorship("Use an iterator.\nBest regards, Ferris", "Ferris", "Rust"), "Use an iterator.");
+        assert_eq!(strip_authorship("Use an iterator.\nFerris, the Rust expert.", "Ferris", "Rust"), "Use an iterator.");
+    }
+
+    #[test]
+    fn name_is_replaced_throughout() {
+        let answer = "Use an iterator. Ferris has seen it done. Ask ferris otherwise, or Ferrisgate.";
+        assert_eq!(strip_authorship(answer, "Ferris", ""), "Use an iterator. The author has seen it done. Ask ferris otherwise, or Ferrisgate.");
+    }
+
+    #[test]
+    fn short_name_is_only_stripped_from_a_signature() {
+        assert_eq!(strip_authorship("Take plan A.\n- A", "A", ""), "Take plan A.");
+    }
+
+    #[test]
+    fn closing_lines_that_only_mention_the_domain_are_kept() {
+        for last in ["From Rust 1.70 on, this is stable.", "- Rust's borrow checker rejects this.", "Best of all, Rust checks it at compile time."] {
+            let answer = format!("Use a OnceLock.\n{}", last);
+            assert_eq!(strip_authorship(&answer, "Ferris", "Rust"), answer);
+        }
+    }
+
+    #[test]
+    fn closing_bullet_is_kept() {
+        let answer = "Either works:\n- a Vec\n- a VecDeque";
+        assert_eq!(strip_authorship(answer, "Ferris", "Rust"), answer);
+    }
+}
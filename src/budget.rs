@@ -0,0 +1,125 @@
+//! Limits on the tokens and estimated cost the panel spends, on each question and over the session, and on how long
+//! each question takes. A question stops being refined, and its latest version is accepted, once the next round is
+//! projected to overrun any of them.
+//!
+//! A question's deadline is also kept to as it nears: the rounds left are cut to those projected to fit, the last
+//! one is evaluated with the actors' fast models, and a question still deliberating when the deadline passes is
+//! given its latest version at once.
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::usage::UsageTotals;
+
+/// A limit on tokens, on estimated cost, on time, or on any of them. A limit left unset does not apply.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+pub struct Budget {
+    /// The most prompt and completion tokens, counted together.
+    #[serde(default)]
+    pub tokens: Option<u64>,
+    /// The most estimated cost, in the currency the actors are priced in.
+    #[serde(default)]
+    pub cost: Option<f64>,
+    /// The most wall-clock seconds from asking the question to its answer. Only a question's budget has one.
+    #[serde(default)]
+    pub deadline_secs: Option<f64>,
+}
+
+impl Budget {
+    /// Checks the budget, returning a description of the problem if it is unusable.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.tokens == Some(0) {
+            return Err("a token budget must be at least 1".to_string());
+        }
+        if self.cost.is_some_and(|cost| !cost.is_finite() || cost <= 0.0) {
+            return Err("a cost budget must be positive".to_string());
+        }
+        if self.deadline_secs.is_some_and(|secs| !secs.is_finite() || secs <= 0.0) {
+            return Err("a deadline must be a positive number of seconds".to_string());
+        }
+        Ok(())
+    }
+
+    /// Whether the budget limits tokens or cost, which only calls to providers that report their token counts
+    /// are held to.
+    pub fn limits_spending(&self) -> bool {
+        self.tokens.is_some() || self.cost.is_some()
+    }
+
+    /// Describes the deadline that taking `elapsed` would pass, if any.
+    pub fn late_by(&self, elapsed: Duration) -> Option<String> {
+        self.deadline_secs
+            .filter(|limit| elapsed.as_secs_f64() > *limit)
+            .map(|limit| format!("about {:.1}s against a deadline of {}s", elapsed.as_secs_f64(), limit))
+    }
+
+    /// How many more rounds fit before the deadline, if there is one, at the pace of the `rounds` taking `elapsed`
+    /// so far.
+    pub fn rounds_left(&self, elapsed: Duration, rounds: usize) -> Option<u32> {
+        let limit = self.deadline_secs?;
+        let pace = round_time(elapsed, rounds).as_secs_f64();
+        let left = (limit - elapsed.as_secs_f64()).max(0.0);
+        Some(if pace > 0.0 { (left / pace).floor().min(u32::MAX as f64) as u32 } else { u32::MAX })
+    }
+
+    /// Describes the limit that spending `usage` would overrun, if any.
+    pub fn overrun_by(&self, usage: &UsageTotals) -> Option<String> {
+        let tokens = usage.prompt_tokens + usage.completion_tokens;
+        if let Some(limit) = self.tokens.filter(|limit| tokens > *limit) {
+            return Some(format!("{} tokens against a budget of {}", tokens, limit));
+        }
+        if let Some(limit) = self.cost.filter(|limit| usage.estimated_cost > *limit) {
+            return Some(format!("a cost of about {:.4} against a budget of {}", usage.estimated_cost, limit));
+        }
+        None
+    }
+}
+
+/// The `[budget]` section of the config file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+pub struct BudgetSettings {
+    /// What each question may spend, unless the question sets a budget of its own.
+    #[serde(default)]
+    pub question: Budget,
+    /// What every question asked since the program started may spend together. Once it is spent, no more
+    /// questions are taken.
+    #[serde(default)]
+    pub session: Budget,
+}
+
+impl BudgetSettings {
+    /// Checks both budgets, returning a description of the first problem found.
+    pub fn validate(&self) -> Result<(), String> {
+        self.question.validate().map_err(|reason| format!("budget.question: {}", reason))?;
+        self.session.validate().map_err(|reason| format!("budget.session: {}", reason))?;
+        if self.session.deadline_secs.is_some() {
+            return Err("budget.session: a deadline applies to each question, so set it in budget.question".to_string());
+        }
+        Ok(())
+    }
+
+    /// Whether either budget limits tokens or cost.
+    pub fn limits_spending(&self) -> bool {
+        self.question.limits_spending() || self.session.limits_spending()
+    }
+}
+
+/// What one more round is projected to spend: the average of what the `rounds` so far spent, counting the first
+/// draft as a round like each refinement.
+pub fn next_round(spent: &UsageTotals, rounds: usize) -> UsageTotals {
+    let rounds = rounds.max(1) as u64;
+    UsageTotals {
+        calls: 0,
+        unmetered_calls: 0,
+        prompt_tokens: spent.prompt_tokens.div_ceil(rounds),
+        completion_tokens: spent.completion_tokens.div_ceil(rounds),
+        estimated_cost: spent.estimated_cost / rounds as f64,
+    }
+}
+
+/// How long one more round is projected to take: the average of the `rounds` so far, which took `elapsed`, counting
+/// the first draft as a round like each refinement.
+pub fn round_time(elapsed: Duration, rounds: usize) -> Duration {
+    elapsed.div_f64(rounds.max(1) as f64)
+}
@@ -0,0 +1,135 @@
+//! Answers that cite their sources, for questions whose answers must be checkable.
+//!
+//! With [citations](crate::constraints::AnswerConstraints::citations) on, the actors are asked to mark each
+//! claim with the number of its source, as in `[1]`, and to list the sources under a closing "Sources:" heading.
+//! An answer that does not is asked for again like one breaking any other constraint, the evaluators check that
+//! each cited claim is borne out by its source, and the sources of the answer the panel settles on are
+//! [parsed](parse) into the result.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+/// Headings the list of sources may go under, in lowercase.
+const HEADINGS: [&str; 3] = ["sources", "references", "citations"];
+
+/// A source the answer cites.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Citation {
+    /// The number the answer cites the source by.
+    pub number: u32,
+    /// The source as the answer lists it, such as a title and a URL.
+    pub source: String,
+    /// The sentences of the answer that cite the source.
+    pub claims: Vec<String>,
+}
+
+/// The instruction for the prompts to cite sources.
+pub(crate) const INSTRUCTION: &str = "Mark every factual claim with the number of the source it comes from in square \
+brackets, such as [1], and end the answer with a \"Sources:\" heading followed by each numbered source on a line of \
+its own, such as \"[1] Title, URL\". Cite only sources you are confident exist.";
+
+/// The sources the answer lists, in the order it lists them, with the sentences citing each. Empty if it lists none.
+pub fn parse(answer: &str) -> Vec<Citation> {
+    let Some((body, sources)) = split_sources(answer) else { return Vec::new() };
+    let mut claims: BTreeMap<u32, Vec<String>> = BTreeMap::new();
+    for sentence in sentences(body) {
+        for number in cited_numbers(sentence) {
+            let cited = claims.entry(number).or_default();
+            if !cited.iter().any(|claim| claim == sentence) {
+                cited.push(sentence.to_string());
+            }
+        }
+    }
+    sources.into_iter()
+        .map(|(number, source)| Citation { number, source, claims: claims.remove(&number).unwrap_or_default() })
+        .collect()
+}
+
+/// Checks that the answer lists its sources and cites none it does not list, returning how it falls short if it
+/// does.
+pub(crate) fn check(answer: &str) -> Result<(), String> {
+    let Some((body, sources)) = split_sources(answer) else {
+        return Err("it does not end with a \"Sources:\" heading listing the numbered sources it cites".to_string());
+    };
+    if sources.is_empty() {
+        return Err("its \"Sources:\" heading lists no numbered sources".to_string());
+    }
+    let mut unlisted: Vec<u32> = sentences(body).flat_map(cited_numbers).filter(|number| !sources.iter().any(|(listed, _)| listed == number)).collect();
+    unlisted.sort_unstable();
+    unlisted.dedup();
+    match unlisted.as_slice() {
+        [] => Ok(()),
+        numbers => {
+            let numbers: Vec<String> = numbers.iter().map(|number| format!("[{}]", number)).collect();
+            Err(format!("it cites {} without listing them under \"Sources:\"", numbers.join(", ")))
+        },
+    }
+}
+
+/// The answer without the sources listed at its end, if it lists any.
+pub(crate) fn body(answer: &str) -> &str {
+    split_sources(answer).map_or(answer, |(body, _)| body.trim_end())
+}
+
+/// The answer before its last sources heading, and the numbered sources listed after it, if it has the heading.
+fn split_sources(answer: &str) -> Option<(&str, Vec<(u32, String)>)> {
+    let mut offset = 0;
+    let mut heading = None;
+    for line in answer.split_inclusive('\n') {
+        if is_heading(line) {
+            heading = Some((offset, offset + line.len()));
+        }
+        offset += line.len();
+    }
+    let (start, end) = heading?;
+    let sources = answer[end..].lines().filter_map(numbered_source).collect();
+    Some((&answer[..start], sources))
+}
+
+/// Whether the line is a sources heading, such as `Sources:`, `## References` or `**Sources:**`.
+fn is_heading(line: &str) -> bool {
+    let heading = line.trim().trim_start_matches('#').trim().trim_matches('*').trim().trim_end_matches(':').trim_matches('*').trim();
+    HEADINGS.contains(&heading.to_lowercase().as_str())
+}
+
+/// The number and source of a line listing a source, such as `[1] Title, URL`, `1. Title` or `- [2]: Title`.
+fn numbered_source(line: &str) -> Option<(u32, String)> {
+    let line = line.trim().trim_start_matches(['-', '*', '•']).trim_start();
+    let (number, rest) = match line.strip_prefix('[') {
+        Some(bracketed) => bracketed.split_once(']')?,
+        None => line.split_at(line.find(|c: char| !c.is_ascii_digit())?),
+    };
+    let number = number.trim().parse().ok()?;
+    let source = rest.trim_start_matches(['.', ')', ':']).trim();
+    (!source.is_empty()).then(|| (number, source.to_string()))
+}
+
+/// The sentences of the text, ending at full stops, question and exclamation marks followed by a space, and
+/// at line breaks.
+fn sentences(text: &str) -> impl Iterator<Item = &str> {
+    text.lines().flat_map(|line| {
+        let mut sentences = Vec::new();
+        let mut start = 0;
+        for (index, c) in line.char_indices() {
+            let next = line[index + c.len_utf8()..].chars().next();
+            if matches!(c, '.' | '?' | '!') && next.is_none_or(char::is_whitespace) {
+                sentences.push(&line[start..index + c.len_utf8()]);
+                start = index + c.len_utf8();
+            }
+        }
+        sentences.push(&line[start..]);
+        sentences
+    })
+    .map(|sentence| sentence.trim().trim_start_matches(['-', '*', '•']).trim())
+    .filter(|sentence| !sentence.is_empty())
+}
+
+/// The numbers the sentence cites in square brackets, such as 1 and 2 in `... [1][2]` or `... [1, 2]`.
+fn cited_numbers(sentence: &str) -> Vec<u32> {
+    sentence.split('[')
+        .skip(1)
+        .filter_map(|part| part.split_once(']'))
+        .flat_map(|(inside, _)| inside.split(',').map(|number| number.trim().parse::<u32>()).collect::<Result<Vec<_>, _>>().unwrap_or_default())
+        .collect()
+}
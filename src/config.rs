@@ -0,0 +1,553 @@
+//! Loading the actor panel from a `consensus.toml` file.
+
+use std::{collections::{BTreeMap, HashSet}, fmt, fs, io, iter, path::{Path, PathBuf}, sync::Arc};
+
+#[cfg(feature = "actix")]
+use actix::MailboxError;
+use serde::{Deserialize, Serialize};
+
+use crate::{budget::BudgetSettings, constraints::AnswerConstraints, conversation::ConversationConfig, convergence::ConvergenceConfig, deadlock::TieBreak, documents::DocumentsConfig, moderation::ModerationConfig, personas::{self, Persona}, history::HistoryConfig, prompts::{PromptError, PromptsConfig}, retrieval::RetrievalConfig, sandbox::SandboxConfig, search::SearchConfig, provider::{CacheConfig, CredentialsConfig, LimitsConfig, ProviderConfig, ProviderError, RetryPolicy}, strategy::ConsensusSettings, tools::Tool, transcript::TranscriptConfig, usage::Pricing};
+
+/// Config file looked up in the working directory when no path is given.
+pub const DEFAULT_CONFIG_PATH: &str = "consensus.toml";
+
+/// The name the top-level panel goes by among the profiles.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// The panel shipped with the binary, used when no config file is found.
+const BUILT_IN_CONFIG: &str = include_str!("../consensus.toml");
+
+/// Errors raised while loading or validating the config file.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The config file could not be read.
+    Io { path: PathBuf, source: io::Error },
+    /// The config file is not valid TOML or does not match the expected shape.
+    Parse(toml::de::Error),
+    /// The config parsed but describes an unusable panel.
+    Invalid(String),
+    /// An actor's provider could not be created.
+    Provider { actor: String, source: ProviderError },
+    /// The history database could not be opened.
+    History { path: PathBuf, source: rusqlite::Error },
+    /// The retrieval vector store could not be opened.
+    VectorStore { path: PathBuf, source: rusqlite::Error },
+    /// The prompt templates could not be loaded.
+    Prompts(PromptError),
+    /// No profile of that name is defined.
+    UnknownProfile(String),
+    /// The actors could not be registered, because the [Coordinator](crate::Coordinator) could not be reached.
+    #[cfg(feature = "actix")]
+    Registration(MailboxError),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io { path, source } => write!(f, "unable to read {}: {}", path.display(), source),
+            ConfigError::Parse(e) => write!(f, "malformed config: {}", e),
+            ConfigError::Invalid(reason) => write!(f, "invalid config: {}", reason),
+            ConfigError::Provider { actor, source } => write!(f, "unable to create the provider for {}: {}", actor, source),
+            ConfigError::History { path, source } => write!(f, "unable to open the history database {}: {}", path.display(), source),
+            ConfigError::VectorStore { path, source } => write!(f, "unable to open the vector store {}: {}", path.display(), source),
+            ConfigError::Prompts(e) => write!(f, "unable to load the prompts: {}", e),
+            ConfigError::UnknownProfile(name) => write!(f, "no profile named \"{}\" is defined", name),
+            #[cfg(feature = "actix")]
+            ConfigError::Registration(e) => write!(f, "unable to register the actors: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Top-level layout of `consensus.toml`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    /// How the panel drafts answers and decides it has reached consensus.
+    #[serde(flatten)]
+    pub settings: ConsensusSettings,
+    /// Where to record transcripts of each deliberation, if anywhere.
+    #[serde(default)]
+    pub transcript: Option<TranscriptConfig>,
+    /// Named API keys, and which of them each provider uses.
+    #[serde(default)]
+    pub credentials: CredentialsConfig,
+    /// How failed provider calls are retried.
+    #[serde(default)]
+    pub retry: RetryPolicy,
+    /// Where provider responses are cached, if anywhere.
+    #[serde(default)]
+    pub cache: Option<CacheConfig>,
+    /// How fast and how many at once requests may be sent to the providers, if limited at all.
+    #[serde(default)]
+    pub limits: Option<LimitsConfig>,
+    /// The database past runs are stored in, if any.
+    #[serde(default)]
+    pub history: Option<HistoryConfig>,
+    /// Where to load prompt templates from in place of the built-in ones, if anywhere.
+    #[serde(default)]
+    pub prompts: Option<PromptsConfig>,
+    /// How the panel detects that refinements have stopped changing the answer, if it does.
+    #[serde(default)]
+    pub convergence: Option<ConvergenceConfig>,
+    /// How questions and answers are screened for harmful content before the panel evaluates them, if they are.
+    #[serde(default)]
+    pub moderation: Option<ModerationConfig>,
+    /// How much of the conversation follow-up questions are drafted in the context of.
+    #[serde(default)]
+    pub conversation: ConversationConfig,
+    /// How documents attached to questions are cut into excerpts for the prompts.
+    #[serde(default)]
+    pub documents: DocumentsConfig,
+    /// The corpus passages relevant to each question are retrieved from, if any.
+    #[serde(default)]
+    pub retrieval: Option<RetrievalConfig>,
+    /// The web search API fact checkers check answers' claims with, if any.
+    #[serde(default)]
+    pub search: Option<SearchConfig>,
+    /// How the code blocks of answers are run before the panel evaluates them, if they are.
+    #[serde(default)]
+    pub sandbox: Option<SandboxConfig>,
+    pub actors: Vec<ActorConfig>,
+    /// Named panels, each with its own actors and settings, that can be used in place of the one above.
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+/// A named panel from a `[profiles.<name>]` section of the config file. Settings it leaves out take their
+/// defaults, not those of the top-level panel.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Profile {
+    #[serde(flatten)]
+    pub settings: ConsensusSettings,
+    pub actors: Vec<ActorConfig>,
+}
+
+/// The part an actor plays on the panel.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ActorRole {
+    /// Evaluates answers on the merits of its domain.
+    #[default]
+    Member,
+    /// Looks for the weakest point of every answer and votes NeedsRefinement unless it is airtight. Its vote only
+    /// counts towards consensus for the first rounds, as the `[devils_advocate]` settings say.
+    DevilsAdvocate,
+    /// Checks the factual claims of every answer, whatever its domain, against the results of web searches it asks
+    /// for on the `[search]` section's backend, or against what it knows if there is none.
+    FactChecker,
+    /// Evaluates answers like a member, and chairs the panel: whenever a round ends without consensus, it reads every
+    /// evaluation and writes the guidance the next refinement follows, and in the final round it rules on which
+    /// version of the answer to accept. A panel has at most one chair.
+    Chair,
+}
+
+/// One persona on the panel.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ActorConfig {
+    /// Defaults to the name of the actor's persona, if it takes one.
+    #[serde(default)]
+    pub name: String,
+    /// The key of a persona from the [built-in library](personas), set as `persona`, which the actor builds its own
+    /// on, taking the persona's name in place of any it leaves out.
+    #[serde(default, rename = "persona")]
+    pub base: Option<String>,
+    /// The actor's domain, expertise, rubric, refusals and tone.
+    #[serde(flatten)]
+    pub persona: Persona,
+    /// The part the actor plays, defaulting to its persona's, or to an ordinary member of the panel.
+    #[serde(default)]
+    pub role: Option<ActorRole>,
+    /// How much the actor's vote counts under weighted strategies.
+    #[serde(default = "default_weight")]
+    pub weight: f64,
+    /// The tools the actor's models may call while answering and evaluating, defaulting to its persona's.
+    #[serde(default)]
+    pub tools: Vec<Tool>,
+    #[serde(flatten)]
+    pub provider: ProviderConfig,
+    /// A model the actor evaluates answers, votes and routes questions with, in place of the one it drafts,
+    /// refines and merges them with, such as a cheaper one for the many evaluations of each round.
+    #[serde(default)]
+    pub evaluation_model: Option<String>,
+    /// A model the actor evaluates answers with once a question's deadline is near, such as a smaller one than
+    /// `evaluation_model` or `model`, so that the last round fits.
+    #[serde(default)]
+    pub fast_model: Option<String>,
+    /// Providers to fail over to, in order, when the ones before fail for good, after any retries.
+    #[serde(default)]
+    pub fallback: Vec<ProviderConfig>,
+    /// What the actor's model costs, for the estimated cost of each answer.
+    #[serde(flatten)]
+    pub pricing: Pricing,
+}
+
+fn default_weight() -> f64 {
+    1.0
+}
+
+impl ActorConfig {
+    /// Parses and validates an actor from a TOML inline table, such as
+    /// `{ name = "Critic", domain = "Logic", expertise = ["Fallacies"], provider = "ollama" }`.
+    pub fn parse_inline(table: &str) -> Result<Self, ConfigError> {
+        #[derive(Deserialize)]
+        struct Entry {
+            actor: ActorConfig,
+        }
+        let mut entry: Entry = toml::from_str(&format!("actor = {}", table)).map_err(ConfigError::Parse)?;
+        if entry.actor.subpanels().next().is_some() {
+            return Err(ConfigError::Invalid("only actors in the config file can stand for a sub-panel, since it is one of the file's profiles".to_string()));
+        }
+        entry.actor.take_persona().map_err(ConfigError::Invalid)?;
+        entry.actor.validate().map_err(ConfigError::Invalid)?;
+        Ok(entry.actor)
+    }
+
+    /// Builds the actor's persona on the library persona it takes, if any, as [Persona::compose] says, and fills in
+    /// the name, role and tools it leaves out from the library persona's.
+    pub fn take_persona(&mut self) -> Result<(), String> {
+        let Some(key) = &self.base else { return Ok(()) };
+        let base = personas::find(key).ok_or_else(|| format!("no built-in persona is called \"{}\"", key))?;
+        if self.name.trim().is_empty() {
+            self.name = base.name.clone();
+        }
+        self.persona.compose(&base.persona);
+        self.role.get_or_insert(base.role);
+        if self.tools.is_empty() {
+            self.tools = base.tools.clone();
+        }
+        Ok(())
+    }
+
+    /// Replaces the actor's provider, and any it evaluates with, by the
+    /// [DryRunProvider](crate::provider::DryRunProvider), printing its requests under the actor's name, and drops
+    /// its fallbacks.
+    pub fn dry_run(&mut self) {
+        self.provider = self.provider.dry_run(self.name.clone());
+        self.evaluation_model = self.evaluation_model.as_ref().map(|_| format!("{} (evaluation model)", self.name));
+        self.fast_model = self.fast_model.as_ref().map(|_| format!("{} (fast model)", self.name));
+        self.fallback.clear();
+    }
+
+    /// Checks the entry, returning a description of the problem if the actor is unusable.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("every actor needs a non-empty name".to_string());
+        }
+        self.persona.validate().map_err(|reason| format!("actor \"{}\" {}", self.name, reason))?;
+        if !self.weight.is_finite() || self.weight <= 0.0 {
+            return Err(format!("actor \"{}\" needs a positive weight", self.name));
+        }
+        self.provider.validate().map_err(|reason| format!("actor \"{}\": {}", self.name, reason))?;
+        for (field, model) in [("evaluation_model", &self.evaluation_model), ("fast_model", &self.fast_model)] {
+            let Some(model) = model else { continue };
+            if model.trim().is_empty() {
+                return Err(format!("actor \"{}\" needs a non-empty {}", self.name, field));
+            }
+            if !self.provider.provider.takes_model() {
+                let provider = format!("{:?}", self.provider.provider).to_lowercase();
+                return Err(format!("actor \"{}\"'s {} provider cannot switch to another model, so {} does not apply", self.name, provider, field));
+            }
+        }
+        for (index, fallback) in self.fallback.iter().enumerate() {
+            fallback.validate().map_err(|reason| format!("actor \"{}\" fallback {}: {}", self.name, index + 1, reason))?;
+        }
+        let costs = [
+            Some(self.pricing.input_cost_per_million),
+            Some(self.pricing.output_cost_per_million),
+            self.pricing.evaluation_input_cost_per_million,
+            self.pricing.evaluation_output_cost_per_million,
+        ];
+        if costs.iter().flatten().any(|cost| !cost.is_finite() || *cost < 0.0) {
+            return Err(format!("actor \"{}\" needs non-negative token costs", self.name));
+        }
+        Ok(())
+    }
+
+    /// The profiles of the sub-panels the actor stands for, with its own provider or a fallback.
+    fn subpanels(&self) -> impl Iterator<Item = &str> {
+        iter::once(&self.provider).chain(&self.fallback).filter_map(|provider| provider.panel.as_deref())
+    }
+}
+
+impl Config {
+    /// Turns the config into a dry run: every actor's provider, in every profile, is replaced by the
+    /// [DryRunProvider](crate::provider::DryRunProvider), printing its requests under the actor's name, and the
+    /// response cache, convergence detection, moderation, retrieval, search and the answer cache, which would call
+    /// models and search APIs too, and the sandbox, which would run code, are turned off.
+    pub fn dry_run(&mut self) {
+        self.cache = None;
+        if let Some(history) = &mut self.history {
+            history.answer_cache = None;
+        }
+        self.convergence = None;
+        self.moderation = None;
+        self.retrieval = None;
+        self.search = None;
+        self.sandbox = None;
+        let actors = self.actors.iter_mut().chain(self.profiles.values_mut().flat_map(|profile| profile.actors.iter_mut()));
+        actors.for_each(ActorConfig::dry_run);
+    }
+
+    /// Prepares the config for replaying a transcript with [replay](crate::replay): the panel is made a
+    /// [dry run](Config::dry_run), whose actors are replaced by replaying ones for each question, and nothing the
+    /// replay does is recorded to the transcript or the history. The answers replayed are the ones recorded, so they
+    /// are not held to the constraints again, and no budget applies, since replayed calls spend nothing. Nor does a
+    /// minimum panel size, since each question is replayed with the actors that answered it.
+    pub fn replay(&mut self) {
+        self.dry_run();
+        self.transcript = None;
+        self.history = None;
+        let settings = iter::once(&mut self.settings).chain(self.profiles.values_mut().map(|profile| &mut profile.settings));
+        for settings in settings {
+            settings.constraints = AnswerConstraints::default();
+            settings.budget = BudgetSettings::default();
+            settings.min_actors = 1;
+        }
+    }
+
+    /// Loads the config from `path`, or from [DEFAULT_CONFIG_PATH] if it exists, falling back to the built-in panel.
+    pub fn load(path: Option<&Path>) -> Result<Self, ConfigError> {
+        let contents = match path {
+            Some(path) => read(path)?,
+            None if Path::new(DEFAULT_CONFIG_PATH).exists() => read(Path::new(DEFAULT_CONFIG_PATH))?,
+            None => BUILT_IN_CONFIG.to_string(),
+        };
+        Self::parse(&contents)
+    }
+
+    /// Parses and validates a config from TOML text.
+    pub fn parse(contents: &str) -> Result<Self, ConfigError> {
+        let mut config: Config = toml::from_str(contents).map_err(ConfigError::Parse)?;
+        let actors = config.actors.iter_mut().chain(config.profiles.values_mut().flat_map(|profile| profile.actors.iter_mut()));
+        for actor in actors {
+            actor.take_persona().map_err(ConfigError::Invalid)?;
+        }
+        config.validate()?;
+        let source = config.clone();
+        let actors = config.actors.iter_mut().chain(config.profiles.values_mut().flat_map(|profile| profile.actors.iter_mut()));
+        for actor in actors {
+            source.resolve_subpanels(actor)?;
+        }
+        Ok(config)
+    }
+
+    /// The names of the panel's actors whose provider, or any they fail over to, does not report the tokens it
+    /// uses, so that their calls escape token and cost budgets.
+    pub fn unmetered_actors(&self) -> Vec<&str> {
+        self.actors.iter()
+            .filter(|actor| std::iter::once(&actor.provider).chain(&actor.fallback).any(|provider| !provider.provider.metered()))
+            .map(|actor| actor.name.as_str())
+            .collect()
+    }
+
+    /// The config with the named profile's actors and settings in place of the top-level panel, or the config
+    /// as it is for [DEFAULT_PROFILE].
+    pub fn with_profile(&self, name: &str) -> Result<Config, ConfigError> {
+        if name == DEFAULT_PROFILE {
+            return Ok(self.clone());
+        }
+        let profile = self.profiles.get(name).ok_or_else(|| ConfigError::UnknownProfile(name.to_string()))?;
+        Ok(Config { settings: profile.settings.clone(), actors: profile.actors.clone(), ..self.clone() })
+    }
+
+    /// Gives each of the actor's providers standing for a sub-panel the config of its profile, whose own actors'
+    /// sub-panels are resolved in turn. Validation has ruled out panels that are sub-panels of themselves, so this
+    /// ends.
+    fn resolve_subpanels(&self, actor: &mut ActorConfig) -> Result<(), ConfigError> {
+        for provider in iter::once(&mut actor.provider).chain(&mut actor.fallback) {
+            let Some(profile) = &provider.panel else { continue };
+            let mut subpanel = self.with_profile(profile)?;
+            for actor in &mut subpanel.actors {
+                self.resolve_subpanels(actor)?;
+            }
+            provider.subpanel = Some(Arc::new(subpanel));
+        }
+        Ok(())
+    }
+
+    /// Checks that every sub-panel an actor stands for is a profile, and that no panel sits, however deeply, on
+    /// itself.
+    fn validate_subpanels(&self) -> Result<(), String> {
+        let panels: BTreeMap<&str, &[ActorConfig]> = iter::once((DEFAULT_PROFILE, self.actors.as_slice()))
+            .chain(self.profiles.iter().map(|(name, profile)| (name.as_str(), profile.actors.as_slice())))
+            .collect();
+        for actor in panels.values().flat_map(|actors| actors.iter()) {
+            if let Some(profile) = actor.subpanels().find(|profile| !panels.contains_key(profile)) {
+                return Err(format!("actor \"{}\" stands for the panel of profile \"{}\", which is not defined", actor.name, profile));
+            }
+        }
+        let mut checked = HashSet::new();
+        for panel in panels.keys() {
+            sits_on_itself(panel, &panels, &mut Vec::new(), &mut checked)?;
+        }
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        self.settings.validate().map_err(ConfigError::Invalid)?;
+        self.credentials.validate().map_err(ConfigError::Invalid)?;
+        self.retry.validate().map_err(ConfigError::Invalid)?;
+        if let Some(cache) = &self.cache {
+            cache.validate().map_err(ConfigError::Invalid)?;
+        }
+        if let Some(limits) = &self.limits {
+            limits.validate().map_err(ConfigError::Invalid)?;
+        }
+        if let Some(convergence) = &self.convergence {
+            convergence.validate().map_err(ConfigError::Invalid)?;
+        }
+        if let Some(moderation) = &self.moderation {
+            moderation.validate().map_err(ConfigError::Invalid)?;
+            if let ModerationConfig::Prompt { provider } = moderation {
+                self.credentials.check_names(&provider.keys).map_err(|reason| ConfigError::Invalid(format!("moderation: {}", reason)))?;
+            }
+        }
+        self.conversation.validate().map_err(ConfigError::Invalid)?;
+        self.documents.validate().map_err(ConfigError::Invalid)?;
+        if let Some(retrieval) = &self.retrieval {
+            retrieval.validate().map_err(ConfigError::Invalid)?;
+        }
+        if let Some(search) = &self.search {
+            search.validate().map_err(ConfigError::Invalid)?;
+            self.credentials.check_names(&search.keys).map_err(|reason| ConfigError::Invalid(format!("search: {}", reason)))?;
+        }
+        if let Some(sandbox) = &self.sandbox {
+            sandbox.validate().map_err(ConfigError::Invalid)?;
+        }
+        if let Some(history) = &self.history {
+            history.validate().map_err(ConfigError::Invalid)?;
+        }
+        validate_panel(&self.actors, &self.settings, &self.credentials).map_err(ConfigError::Invalid)?;
+        for (name, profile) in &self.profiles {
+            if name == DEFAULT_PROFILE {
+                return Err(ConfigError::Invalid(format!("the top-level panel is the \"{}\" profile, so no other profile may be named that", DEFAULT_PROFILE)));
+            }
+            let invalid = |reason: String| ConfigError::Invalid(format!("profile \"{}\": {}", name, reason));
+            profile.settings.validate().map_err(invalid)?;
+            validate_panel(&profile.actors, &profile.settings, &self.credentials).map_err(invalid)?;
+        }
+        self.validate_subpanels().map_err(ConfigError::Invalid)
+    }
+}
+
+/// Fails if the panel, reached from those on the `path`, is on the path already, and so sits on itself, or if any
+/// of its sub-panels does. Panels in `checked` are known not to.
+fn sits_on_itself<'a>(panel: &'a str, panels: &BTreeMap<&'a str, &'a [ActorConfig]>, path: &mut Vec<&'a str>, checked: &mut HashSet<&'a str>) -> Result<(), String> {
+    if let Some(start) = path.iter().position(|on_path| *on_path == panel) {
+        return Err(format!("the panel of profile \"{}\" sits on itself: {} -> {}", panel, path[start..].join(" -> "), panel));
+    }
+    if checked.contains(panel) {
+        return Ok(());
+    }
+    path.push(panel);
+    for subpanel in panels[panel].iter().flat_map(ActorConfig::subpanels) {
+        sits_on_itself(subpanel, panels, path, checked)?;
+    }
+    path.pop();
+    checked.insert(panel);
+    Ok(())
+}
+
+/// Checks that a panel has as many actors as its settings need, each usable, with a name of its own and only
+/// naming configured keys.
+fn validate_panel(actors: &[ActorConfig], settings: &ConsensusSettings, credentials: &CredentialsConfig) -> Result<(), String> {
+    if actors.is_empty() {
+        return Err("at least one actor must be defined".to_string());
+    }
+    if actors.len() < settings.min_actors {
+        return Err(format!("{} actors are defined, but min_actors is {}", actors.len(), settings.min_actors));
+    }
+    let mut names = HashSet::new();
+    for actor in actors {
+        actor.validate()?;
+        for provider in std::iter::once(&actor.provider).chain(&actor.fallback) {
+            credentials.check_names(&provider.keys).map_err(|reason| format!("actor \"{}\": {}", actor.name, reason))?;
+        }
+        if !names.insert(actor.name.as_str()) {
+            return Err(format!("actor \"{}\" is defined more than once", actor.name));
+        }
+    }
+    let chairs: Vec<&str> = actors.iter().filter(|actor| actor.role == Some(ActorRole::Chair)).map(|actor| actor.name.as_str()).collect();
+    if let [_, _, ..] = chairs.as_slice() {
+        return Err(format!("only one actor may chair the panel, but {} all do", chairs.join(", ")));
+    }
+    match settings.deadlock.as_ref().map(|deadlock| &deadlock.tie_break) {
+        Some(TieBreak::Moderator { actor }) if !names.contains(actor.as_str()) => {
+            Err(format!("the deadlock moderator \"{}\" is not an actor on the panel", actor))
+        },
+        Some(TieBreak::Chair) if chairs.is_empty() => Err("deadlocks are broken by the chair, but no actor chairs the panel".to_string()),
+        _ => Ok(()),
+    }
+}
+
+fn read(path: &Path) -> Result<String, ConfigError> {
+    fs::read_to_string(path).map_err(|source| ConfigError::Io { path: path.to_path_buf(), source })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A config of `rest` and the actors, each a TOML table's fields, on Ollama unless they name a provider.
+    fn config(actors: &[&str], rest: &str) -> Result<Config, String> {
+        let actors: String = actors.iter()
+            .map(|actor| {
+                let provider = if actor.contains("provider =") { "" } else { "provider = \"ollama\"\n" };
+                format!("[[actors]]\n{}\ndomain = \"Testing\"\nexpertise = [\"Tests\"]\n{}\n", actor, provider)
+            })
+            .collect();
+        Config::parse(&format!("{}\n{}", rest, actors)).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn built_in_config_is_valid() {
+        Config::parse(BUILT_IN_CONFIG).expect("the built-in config should be valid");
+    }
+
+    #[test]
+    fn panel_needs_distinct_names_and_enough_actors() {
+        assert!(config(&["name = \"A\"", "name = \"B\""], "").is_ok());
+        assert!(config(&["name = \"A\"", "name = \"A\""], "").unwrap_err().contains("actor \"A\" is defined more than once"));
+        assert!(config(&[], "actors = []").unwrap_err().contains("at least one actor"));
+        assert!(config(&["name = \"A\""], "min_actors = 2").unwrap_err().contains("min_actors is 2"));
+        assert!(config(&["name = \" \""], "").unwrap_err().contains("non-empty name"));
+    }
+
+    #[test]
+    fn panel_has_at_most_one_chair() {
+        let chairs = config(&["name = \"A\"\nrole = \"chair\"", "name = \"B\"\nrole = \"chair\""], "").unwrap_err();
+        assert!(chairs.contains("only one actor may chair the panel, but A, B all do"), "{}", chairs);
+    }
+
+    #[test]
+    fn deadlock_tie_break_needs_its_actor() {
+        let moderator = "[deadlock.tie_break]\nkind = \"moderator\"\nactor = \"Judge\"";
+        assert!(config(&["name = \"A\""], moderator).unwrap_err().contains("the deadlock moderator \"Judge\" is not an actor on the panel"));
+        assert!(config(&["name = \"A\"", "name = \"Judge\""], moderator).is_ok());
+        let chair = "[deadlock.tie_break]\nkind = \"chair\"";
+        assert!(config(&["name = \"A\""], chair).unwrap_err().contains("no actor chairs the panel"));
+    }
+
+    #[test]
+    fn panel_cannot_sit_on_itself() {
+        let profiles = "[[profiles.inner.actors]]\nname = \"Outer\"\ndomain = \"Testing\"\nexpertise = [\"Tests\"]\nprovider = \"panel\"\npanel = \"default\"";
+        let cycle = config(&["name = \"Inner\"\nprovider = \"panel\"\npanel = \"inner\""], profiles).unwrap_err();
+        assert!(cycle.contains("sits on itself: default -> inner -> default"), "{}", cycle);
+        let missing = config(&["name = \"Inner\"\nprovider = \"panel\"\npanel = \"nowhere\""], "").unwrap_err();
+        assert!(missing.contains("profile \"nowhere\", which is not defined"), "{}", missing);
+    }
+
+    #[test]
+    fn library_persona_fills_in_what_the_actor_leaves_out() {
+        let parsed = config(&["persona = \"quant\"", "name = \"Skeptic\"\npersona = \"devils-advocate\"\nrole = \"member\""], "").unwrap();
+        let (quant, skeptic) = (&parsed.actors[0], &parsed.actors[1]);
+        let library = personas::find("quant").unwrap();
+        assert_eq!(quant.name, library.name);
+        assert_eq!(quant.persona.domain, "Testing");
+        assert_eq!(quant.tools, library.tools);
+        assert_eq!(skeptic.name, "Skeptic");
+        assert_eq!(skeptic.role, Some(ActorRole::Member));
+        assert!(config(&["persona = \"nobody\""], "").unwrap_err().contains("no built-in persona is called \"nobody\""));
+    }
+}
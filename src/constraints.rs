@@ -0,0 +1,185 @@
+//! Limits on the length and format of answers, for embedding them in other tools.
+//!
+//! The constraints are written into the prompts for drafting, refining and synthesizing an answer, and every answer
+//! is checked against them, its [citations](crate::citations) included if it must cite its sources. An actor whose
+//! answer breaks them is told what is wrong and asked again, a few times at most, after which its last answer is used
+//! as it is.
+
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::citations;
+
+/// The `[constraints]` section of the config file. Without any, answers take whatever shape the actors give them.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AnswerConstraints {
+    /// The most words an answer may have.
+    #[serde(default)]
+    pub max_words: Option<usize>,
+    #[serde(default)]
+    pub format: Option<AnswerFormat>,
+    /// Whether answers must cite a numbered source for each claim and list the sources at their end, which the
+    /// evaluators check the claims against.
+    #[serde(default)]
+    pub citations: bool,
+}
+
+/// The shape an answer must take.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnswerFormat {
+    /// A bulleted list, every line of which is an item or continues one.
+    Bullets,
+    /// Markdown, with every code block closed.
+    Markdown,
+    /// A single fenced code block and nothing else.
+    CodeOnly,
+}
+
+impl fmt::Display for AnswerFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnswerFormat::Bullets => write!(f, "bullets"),
+            AnswerFormat::Markdown => write!(f, "markdown"),
+            AnswerFormat::CodeOnly => write!(f, "code_only"),
+        }
+    }
+}
+
+impl FromStr for AnswerFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace('-', "_").as_str() {
+            "bullets" => Ok(AnswerFormat::Bullets),
+            "markdown" => Ok(AnswerFormat::Markdown),
+            "code_only" => Ok(AnswerFormat::CodeOnly),
+            other => Err(format!("unknown answer format \"{}\"; use bullets, markdown or code_only", other)),
+        }
+    }
+}
+
+impl AnswerConstraints {
+    /// Checks the constraints, returning a description of the problem if they are unusable.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_words == Some(0) {
+            return Err("constraints max_words must be at least 1".to_string());
+        }
+        if self.citations && self.format == Some(AnswerFormat::CodeOnly) {
+            return Err("constraints citations cannot be listed in a code_only answer".to_string());
+        }
+        Ok(())
+    }
+
+    /// The constraints as instructions for the prompt, or an empty string if there are none.
+    pub fn describe(&self) -> String {
+        let mut instructions = Vec::new();
+        if let Some(max_words) = self.max_words {
+            instructions.push(format!("Keep the answer to at most {} words.", max_words));
+        }
+        instructions.extend(match self.format {
+            None => None,
+            Some(AnswerFormat::Bullets) => Some("Write the answer as a bulleted list, starting every item with a hyphen."),
+            Some(AnswerFormat::Markdown) => Some("Format the answer as Markdown."),
+            Some(AnswerFormat::CodeOnly) => Some("Respond with only code, in a single fenced code block, without any explanation before or after it."),
+        }.map(str::to_string));
+        if self.citations {
+            instructions.push(citations::INSTRUCTION.to_string());
+        }
+        instructions.join(" ")
+    }
+
+    /// Checks an answer against the constraints, returning how it breaks them if it does. The sources listed at
+    /// the end of an answer that must cite them are left out of its length and format.
+    pub fn check(&self, answer: &str) -> Result<(), String> {
+        let mut problems = Vec::new();
+        let whole = answer;
+        let answer = if self.citations { citations::body(answer) } else { answer };
+        if let Some(max_words) = self.max_words {
+            let words = answer.split_whitespace().count();
+            if words > max_words {
+                problems.push(format!("it is {} words long, more than the {} allowed", words, max_words));
+            }
+        }
+        let fences = answer.lines().filter(|line| line.trim_start().starts_with("```")).count();
+        match self.format {
+            Some(AnswerFormat::Bullets) if !is_bulleted(answer) => problems.push("it is not a bulleted list".to_string()),
+            Some(AnswerFormat::Markdown) if fences % 2 != 0 => problems.push("it leaves a code block open".to_string()),
+            Some(AnswerFormat::CodeOnly) if fences != 2 || !answer.trim().starts_with("```") || !answer.trim().ends_with("```") => {
+                problems.push("it is not a single fenced code block with nothing around it".to_string());
+            },
+            _ => (),
+        }
+        if self.citations {
+            if let Err(problem) = citations::check(whole) {
+                problems.push(problem);
+            }
+        }
+        if problems.is_empty() { Ok(()) } else { Err(problems.join(", and ")) }
+    }
+}
+
+/// Whether every line is a list item, or an indented continuation of one.
+fn is_bulleted(answer: &str) -> bool {
+    let mut lines = answer.trim().lines().filter(|line| !line.trim().is_empty()).peekable();
+    lines.peek().is_some() && lines.all(|line| {
+        let item = line.trim_start();
+        line.len() > item.len() || ["- ", "* ", "+ ", "• "].iter().any(|bullet| item.starts_with(bullet))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn constraints(max_words: Option<usize>, format: Option<AnswerFormat>) -> AnswerConstraints {
+        AnswerConstraints { max_words, format, ..AnswerConstraints::default() }
+    }
+
+    #[test]
+    fn long_answer_breaks_max_words() {
+        assert!(constraints(Some(3), None).check("One two three.").is_ok());
+        assert_eq!(constraints(Some(3), None).check("One two three four."), Err("it is 4 words long, more than the 3 allowed".to_string()));
+    }
+
+    #[test]
+    fn bullets_allow_indented_continuations() {
+        let bullets = constraints(None, Some(AnswerFormat::Bullets));
+        assert!(bullets.check("- one\n  continued\n\n* two\n+ three").is_ok());
+        assert!(bullets.check("Intro:\n- one").is_err());
+        assert!(bullets.check("").is_err());
+    }
+
+    #[test]
+    fn markdown_must_close_its_code_blocks() {
+        let markdown = constraints(None, Some(AnswerFormat::Markdown));
+        assert!(markdown.check("Run:\n```sh\nls\n```").is_ok());
+        assert_eq!(markdown.check("Run:\n```sh\nls"), Err("it leaves a code block open".to_string()));
+    }
+
+    #[test]
+    fn code_only_is_a_single_block_and_nothing_else() {
+        let code_only = constraints(None, Some(AnswerFormat::CodeOnly));
+        assert!(code_only.check("```rust\nfn main() {}\n```").is_ok());
+        assert!(code_only.check("Here:\n```rust\nfn main() {}\n```").is_err());
+        assert!(code_only.check("```rust\nfn main() {}").is_err());
+        assert!(code_only.check("```\na\n```\n```\nb\n```").is_err());
+    }
+
+    #[test]
+    fn every_broken_constraint_is_named() {
+        let problems = constraints(Some(1), Some(AnswerFormat::Markdown)).check("Run:\n```\nls").unwrap_err();
+        assert_eq!(problems, "it is 3 words long, more than the 1 allowed, and it leaves a code block open");
+    }
+
+    #[test]
+    fn formats_parse_as_they_display() {
+        for format in [AnswerFormat::Bullets, AnswerFormat::Markdown, AnswerFormat::CodeOnly] {
+            assert_eq!(format.to_string().parse(), Ok(format));
+        }
+        assert_eq!("Code-Only".parse(), Ok(AnswerFormat::CodeOnly));
+        assert!("prose".parse::<AnswerFormat>().is_err());
+        assert!(constraints(Some(0), None).validate().is_err());
+    }
+}
@@ -0,0 +1,62 @@
+//! Stopping the refinement loop once refinements no longer change what the answer says.
+//!
+//! Each refined answer is embedded along with the version before it. When the two are at least as similar as
+//! the configured threshold, the dissenters are only rewording the answer, and the panel stops with the latest
+//! version instead of spending more rounds on it.
+
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+use crate::provider::{cosine_similarity, EmbeddingConfig, EmbeddingProvider, ProviderError};
+
+/// The `[convergence]` section of the config file.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ConvergenceConfig {
+    /// How similar, from 0 to 1, a refinement must be to the version before it for the answer to have converged.
+    pub threshold: f64,
+    #[serde(flatten)]
+    pub embedding: EmbeddingConfig,
+}
+
+impl ConvergenceConfig {
+    /// Checks the config, returning a description of the problem if it is unusable.
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.threshold > 0.0 && self.threshold <= 1.0) {
+            return Err("convergence threshold must be in (0, 1]".to_string());
+        }
+        self.embedding.validate().map_err(|reason| format!("convergence: {}", reason))
+    }
+}
+
+/// Decides whether successive versions of an answer have converged.
+#[derive(Clone)]
+pub struct Convergence {
+    embedder: Arc<dyn EmbeddingProvider>,
+    threshold: f64,
+}
+
+impl Convergence {
+    pub fn new(embedder: Arc<dyn EmbeddingProvider>, threshold: f64) -> Self {
+        Convergence { embedder, threshold }
+    }
+
+    /// Builds the configured embedding provider.
+    pub fn from_config(config: &ConvergenceConfig) -> Result<Self, ProviderError> {
+        Ok(Convergence::new(config.embedding.build()?, config.threshold))
+    }
+
+    /// How similar two versions of an answer are in meaning, from 1 for the same down.
+    pub async fn similarity(&self, previous: &str, latest: &str) -> Result<f64, ProviderError> {
+        let embeddings = self.embedder.embed(&[previous.to_string(), latest.to_string()]).await?;
+        match embeddings.as_slice() {
+            [previous, latest] => Ok(cosine_similarity(previous, latest)),
+            _ => Err(ProviderError::InvalidResponse(format!("{} embeddings for 2 texts", embeddings.len()))),
+        }
+    }
+
+    /// Whether versions this similar mean the answer has converged.
+    pub fn converged(&self, similarity: f64) -> bool {
+        similarity >= self.threshold
+    }
+}
@@ -0,0 +1,91 @@
+//! Memory of earlier questions and answers, so that a follow-up question can be drafted in their context.
+
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+/// The `[conversation]` section of the config file.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ConversationConfig {
+    /// Whether the REPL starts with conversation memory on. `/context on|off` toggles it.
+    #[serde(default)]
+    pub enabled: bool,
+    /// How many of the latest exchanges are remembered.
+    #[serde(default = "default_max_exchanges")]
+    pub max_exchanges: usize,
+    /// The most characters of questions and answers remembered; the oldest exchanges are forgotten first.
+    #[serde(default = "default_max_chars")]
+    pub max_chars: usize,
+}
+
+fn default_max_exchanges() -> usize {
+    5
+}
+
+fn default_max_chars() -> usize {
+    8000
+}
+
+impl Default for ConversationConfig {
+    fn default() -> Self {
+        ConversationConfig { enabled: false, max_exchanges: default_max_exchanges(), max_chars: default_max_chars() }
+    }
+}
+
+impl ConversationConfig {
+    /// Checks the config, returning a description of the problem if it is unusable.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_exchanges == 0 {
+            return Err("conversation max_exchanges must be at least 1".to_string());
+        }
+        if self.max_chars == 0 {
+            return Err("conversation max_chars must be at least 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// An earlier question and the answer the panel settled on.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Exchange {
+    pub question: String,
+    pub answer: String,
+}
+
+impl Exchange {
+    fn len(&self) -> usize {
+        self.question.chars().count() + self.answer.chars().count()
+    }
+}
+
+/// The latest exchanges of a conversation, within the configured window.
+#[derive(Debug, Clone)]
+pub struct Conversation {
+    max_exchanges: usize,
+    max_chars: usize,
+    exchanges: VecDeque<Exchange>,
+}
+
+impl Conversation {
+    pub fn new(config: &ConversationConfig) -> Self {
+        Conversation { max_exchanges: config.max_exchanges, max_chars: config.max_chars, exchanges: VecDeque::new() }
+    }
+
+    /// Remembers an answered question, forgetting the oldest exchanges that no longer fit the window.
+    /// An exchange too long to fit on its own is not remembered.
+    pub fn record(&mut self, question: impl Into<String>, answer: impl Into<String>) {
+        self.exchanges.push_back(Exchange { question: question.into(), answer: answer.into() });
+        while self.exchanges.len() > self.max_exchanges || self.exchanges.iter().map(Exchange::len).sum::<usize>() > self.max_chars {
+            self.exchanges.pop_front();
+        }
+    }
+
+    /// The remembered exchanges, oldest first.
+    pub fn exchanges(&self) -> Vec<Exchange> {
+        self.exchanges.iter().cloned().collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.exchanges.clear();
+    }
+}
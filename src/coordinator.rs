@@ -0,0 +1,2193 @@
+//! The [Coordinator], which drives each question through answering, evaluation and refinement.
+
+use std::{collections::{BTreeMap, HashMap, HashSet, VecDeque}, mem, time::{Duration, Instant}};
+
+use actix::prelude::*;
+use chrono::Utc;
+use futures::future::join_all;
+use tokio::sync::oneshot;
+use tracing::{debug, field, info_span, warn, Instrument, Span};
+
+use crate::{
+    actors::LlmActor,
+    blind,
+    budget::{self, Budget},
+    citations,
+    constraints::AnswerConstraints,
+    conversation::Exchange,
+    convergence::Convergence,
+    deadlock::TieBreak,
+    deliberation::{Conclusion, DeliberationState, Members},
+    diff,
+    documents::Excerpt,
+    error::ConsensusError,
+    history::HistoryRecorder,
+    moderation::{Moderation, REFUSAL},
+    sandbox::{CodeRun, Sandbox},
+    observer::ConsensusObserver,
+    messages::{ActorFailed, ActorHealth, ActorInfo, ActorRole, AnswerEvaluation, AnswerReviewed, AnswersCompared, AnswerToken, AnswerQuestion, AnswerRefinement, AskQuestion, CancelCalls, CancelQuestion, CandidateVote, ChairDecision, Configure, CodeTested, ConsensusReached, ContentScreened, DeliberationUpdate, DetectConvergence, DraftAnswer, EvaluateAnswer, Feedback, FlushHistory, GetActorStats, GetAnswerHistory, GetUsage, HealthCheck, ListActors, ModerateContent, NumberQuestionsAfter, Observe, Ping, ProviderFailed, QuestionId, QuestionRouted, RecordHistory, RecordTranscript, RefineAnswer, RefinementSuggestion, Register, RegisterAll, ReloadPanel, Reset, Review, ReviewAnswer, RouteQuestion, Shutdown, Subscribe, SubscribeTokens, SuggestRefinement, Summarize, SynthesizeAnswer, TestCode, Unregister, UsageReport, UseTools, VoteOnCandidates, COORDINATOR, MODERATOR, REVIEWER, SANDBOX},
+    prompts,
+    provider::{Image, ToolOutput},
+    result::{self, Candidate, ConsensusResult, Evaluation, Round},
+    selection::Selector,
+    stats::StatsTracker,
+    strategy::{ConsensusSettings, DraftMode, RefinementCombine, RefinementMode, Sampling, Stage},
+    tools::Tool,
+    transcript::{Transcript, TranscriptEvent},
+    usage::{UsageSummary, UsageTracker},
+    AskError,
+};
+
+/// How often the watchdog looks for stalled questions.
+const WATCHDOG_INTERVAL: Duration = Duration::from_secs(1);
+
+// Define the Coordinator Actor
+#[derive(Default)]
+pub struct Coordinator {
+    llm_actors: HashMap<String, Addr<LlmActor>>,
+    /// The weight and role of each registered actor.
+    members: Roster,
+    /// Knowledge domain of each registered actor, which questions are routed by.
+    domains: HashMap<String, String>,
+    /// The tools of each actor that may call any, which the Coordinator runs for it.
+    tools: HashMap<String, Vec<Tool>>,
+    /// Session-wide settings.
+    settings: ConsensusSettings,
+    /// Picks the actors that draft, refine and synthesize, under the configured selection policy.
+    selector: Selector,
+    /// Stops questions whose refinements have stopped changing the answer, when enabled.
+    convergence: Option<Convergence>,
+    /// Screens each question and version of its answer before it is evaluated, when enabled.
+    moderation: Option<Moderation>,
+    /// Runs the code in each version of the answer before it is evaluated, when enabled.
+    sandbox: Option<Sandbox>,
+    /// The id given to the most recent question.
+    last_question_id: QuestionId,
+    /// Every question in flight.
+    deliberations: HashMap<QuestionId, Deliberation>,
+    /// Questions waiting, oldest first, while `max_concurrent_questions` others are in flight or a reloaded panel
+    /// waits for them to finish.
+    queue: VecDeque<QueuedQuestion>,
+    /// The panel [ReloadPanel] replaces this one with once the questions in flight finish.
+    reload: Option<ReloadPanel>,
+    listeners: Listeners,
+    /// Tokens used by every provider call, per question and for the session.
+    usage: UsageTracker,
+    /// How each actor has fared this session.
+    stats: StatsTracker,
+}
+
+/// A question asked while the panel was busy with as many others as it takes at once.
+struct QueuedQuestion {
+    msg: AskQuestion,
+    responder: oneshot::Sender<Result<ConsensusResult, AskError>>,
+}
+
+/// Everything the [Coordinator] tracks about one question in flight.
+struct Deliberation {
+    question: String,
+    /// Earlier questions and answers the answer is drafted in the context of.
+    context: Vec<Exchange>,
+    /// Excerpts of the documents attached to the question, quoted when the answer is drafted, evaluated and refined.
+    documents: Vec<Excerpt>,
+    /// Images attached to the question, shown when the answer is drafted, evaluated and refined.
+    images: Vec<Image>,
+    /// Every version of the answer, how the panel evaluated it and who refines it next, under the question's
+    /// strategy and maximum rounds.
+    state: DeliberationState,
+    /// The length and format the answer is held to.
+    constraints: AnswerConstraints,
+    /// Instructions the question adds to the preamble it is drafted after.
+    instructions: Option<String>,
+    /// What the question may spend.
+    budget: Budget,
+    /// How many answers the drafter writes to draft the most representative of, if it drafts self-consistently.
+    sampling: Option<Sampling>,
+    /// What the question is waiting for.
+    stage: Stage,
+    /// When the current stage started or was last redispatched.
+    stage_started: Instant,
+    /// How many times the current stage has been redispatched.
+    redispatches: u32,
+    /// The actors the question was asked of, if only some of the panel. The rest sit it out.
+    only: Option<HashSet<String>>,
+    /// How many drafts are expected under best-of-N drafting; 0 when drafting a single answer.
+    expected_candidates: usize,
+    /// The actor picking who drafts the answer, until it has picked.
+    router: Option<String>,
+    /// Actors that have been asked for a draft.
+    drafters: HashSet<String>,
+    /// Drafts received so far under best-of-N drafting.
+    candidates: Vec<Candidate>,
+    /// Each actor's pick among the candidates, or among the rewrites when they are voted on.
+    candidate_votes: HashMap<String, Option<usize>>,
+    /// The index in the ballot of each candidate, in the order the voters are shown them.
+    ballot_order: Vec<usize>,
+    /// How many refinement suggestions are expected before synthesis; 0 when none are pending.
+    expected_suggestions: usize,
+    /// `(actor, suggestion)` pairs received for the synthesis in flight.
+    suggestions: Vec<(String, String)>,
+    /// The actor rewriting the answer, either alone, in turn or by synthesizing suggestions; None while suggestions
+    /// or rewrites to vote on are collected.
+    refiner: Option<String>,
+    /// Dissenters still to rewrite the answer, when rewrites are chained.
+    chain: VecDeque<String>,
+    /// The actors that have rewritten the answer so far this round, when rewrites are chained.
+    chain_authors: Vec<String>,
+    /// The answer as the last of them left it, for the next dissenter in the chain to rewrite.
+    chained: Option<String>,
+    /// How many rewrites are expected before the panel votes on them; 0 when none are pending.
+    expected_refinements: usize,
+    /// Rewrites received so far, when they are voted on.
+    refinements: Vec<Candidate>,
+    /// Asked to approve the answer once the panel agrees on it.
+    reviewer: Option<Recipient<ReviewAnswer>>,
+    /// Why the reviewer rejected the current version of the answer, until it is refined.
+    veto: Option<String>,
+    /// The chair asked for guidance on refining the current version of the answer, or for a ruling, once asked.
+    chair: Option<String>,
+    /// Whether the chair was asked to rule on the version to accept, rather than for guidance.
+    ruling_asked: bool,
+    /// The chair's guidance on refining the current version of the answer, once given.
+    guidance: Option<String>,
+    /// The chair's reasoning for the version of the answer it ruled to accept, if it ruled.
+    ruling: Option<String>,
+    /// Whether the current version of the answer is evaluated with the actors' fast models, since no round is
+    /// projected to fit after it before the question's deadline.
+    hurry: bool,
+    /// Whether the panel stopped because the latest refinement barely changed the answer.
+    converged: bool,
+    /// Why the moderator refused the question, if it did.
+    flagged: Option<String>,
+    /// How running the code blocks of the version being evaluated went, if the sandbox ran any.
+    code_runs: Vec<CodeRun>,
+    /// Why the panel stopped refining the answer, if the next round would have overrun a budget.
+    budget_exceeded: Option<String>,
+    /// Why the panel stopped refining the answer, if it was going in circles.
+    deadlock: Option<String>,
+    /// The version of the answer a tie-break settled on, when it was not the latest.
+    settled_on: Option<usize>,
+    /// The fallback that last answered for each actor whose own provider failed.
+    fallbacks: BTreeMap<String, String>,
+    /// When the question was received.
+    started: Instant,
+    /// Traces the whole deliberation.
+    span: Span,
+    /// Traces the current round, as a child of [Deliberation::span]. Every provider call made for the round is
+    /// traced under it.
+    round_span: Span,
+    /// Resolves the pending [AskQuestion] once consensus is reached or the question fails.
+    responder: Option<oneshot::Sender<Result<ConsensusResult, AskError>>>
+}
+
+/// What the deliberations count each registered actor's vote by.
+#[derive(Default)]
+struct Roster {
+    /// Voting weight of each registered actor.
+    weights: HashMap<String, f64>,
+    /// Actors playing a role other than [ActorRole::Member].
+    roles: HashMap<String, ActorRole>,
+}
+
+/// Whoever is told about each step of every deliberation.
+#[derive(Default)]
+struct Listeners {
+    /// Records each step of every deliberation, when enabled.
+    transcript: Option<Transcript>,
+    /// Stores every finished run, when enabled.
+    history: Option<Addr<HistoryRecorder>>,
+    /// Receive every step of every deliberation as it happens.
+    subscribers: Vec<Recipient<DeliberationUpdate>>,
+    /// Receive each answer as it is written.
+    token_subscribers: Vec<Recipient<AnswerToken>>,
+    /// Told about every draft, evaluation, refinement and final answer.
+    observers: Vec<Box<dyn ConsensusObserver>>,
+}
+
+impl Listeners {
+    /// Sends the event to every subscriber and appends it to the transcript, if one is being recorded.
+    fn record(&mut self, question_id: QuestionId, event: TranscriptEvent) {
+        self.subscribers.retain(|subscriber| subscriber.connected());
+        if !self.subscribers.is_empty() {
+            let update = DeliberationUpdate { question_id, timestamp: Utc::now(), event: event.clone() };
+            self.subscribers.iter().for_each(|subscriber| subscriber.do_send(update.clone()));
+        }
+        if !self.observers.is_empty() {
+            self.observe(question_id, &event);
+        }
+        if let Some(transcript) = self.transcript.as_mut() {
+            transcript.record(question_id, event);
+        }
+    }
+
+    /// Calls each observer's hook for the event, if it has one.
+    fn observe(&mut self, question_id: QuestionId, event: &TranscriptEvent) {
+        match event {
+            TranscriptEvent::Draft { author, answer } => {
+                self.observers.iter_mut().for_each(|observer| observer.on_draft(question_id, author, answer));
+            },
+            TranscriptEvent::Evaluation { round, actor, feedback, score, reasoning } => {
+                let evaluation = Evaluation { actor: actor.clone(), feedback: *feedback, score: *score, reasoning: reasoning.clone() };
+                self.observers.iter_mut().for_each(|observer| observer.on_evaluation(question_id, *round, &evaluation));
+            },
+            TranscriptEvent::Refinement { author, answer } => {
+                self.observers.iter_mut().for_each(|observer| observer.on_refinement(question_id, author, answer));
+            },
+            _ => {},
+        }
+    }
+}
+
+impl Deliberation {
+    /// Moves the question to the stage and restarts its timeout.
+    fn enter(&mut self, stage: Stage) {
+        if self.stage != stage {
+            self.redispatches = 0;
+        }
+        self.stage = stage;
+        self.stage_started = Instant::now();
+    }
+
+    /// The event announcing that the current stage is waiting on the given actors.
+    fn stage_started(&self, mut actors: Vec<String>) -> TranscriptEvent {
+        let round = match self.stage {
+            Stage::Drafting => 0,
+            // Votes on the drafts come before the first round, and votes on rewrites before the round they start.
+            Stage::Voting => self.state.rounds.len(),
+            Stage::Moderating | Stage::Testing | Stage::Evaluating | Stage::Chairing | Stage::Reviewing => self.state.rounds.len().saturating_sub(1),
+            Stage::Refining => self.state.rounds.len(),
+        };
+        actors.sort();
+        TranscriptEvent::StageStarted { stage: self.stage, round, actors }
+    }
+
+    /// Whether the actor deliberates on the question: every actor on the panel does, unless the question was asked
+    /// of only some of them.
+    fn deliberates(&self, name: &str) -> bool {
+        self.only.as_ref().is_none_or(|only| only.contains(name))
+    }
+
+    /// The actors on the panel that deliberate on the question.
+    fn panel<'a>(&'a self, actors: &'a HashMap<String, Addr<LlmActor>>) -> impl Iterator<Item = (&'a String, &'a Addr<LlmActor>)> + 'a {
+        actors.iter().filter(|(name, _)| self.deliberates(name))
+    }
+
+    /// Whether the actor is asked to evaluate the current version of the answer.
+    fn evaluates(&self, name: &str) -> bool {
+        self.deliberates(name) && self.state.evaluates(name)
+    }
+
+    /// How many of the actors evaluate the current version of the answer, which consensus is decided among.
+    fn evaluator_count(&self, actors: &HashMap<String, Addr<LlmActor>>) -> usize {
+        actors.keys().filter(|name| self.evaluates(name)).count()
+    }
+
+    /// The latest version of the answer, or nothing before it is drafted.
+    fn latest_answer(&self) -> &str {
+        self.state.rounds.last().map_or("", |round| round.answer.as_str())
+    }
+
+    /// The current answer as the actors judging it are shown it.
+    fn shown_answer(&self, blind: bool, domains: &HashMap<String, String>) -> String {
+        shown(self.latest_answer().to_string(), self.state.latest_authors(), blind, domains)
+    }
+
+    /// Sends the answer, as [Deliberation::shown_answer] shows it, to the given actors in turn for evaluation under
+    /// the question's strategy.
+    ///
+    /// In debate mode each actor also sees the other actors' evaluations of the previous version.
+    fn request_evaluations<'a>(&self, question_id: QuestionId, actors: impl Iterator<Item = (&'a String, &'a Addr<LlmActor>)>, debate: bool, answer: String) {
+        let mode = self.state.strategy.evaluation_mode();
+        let round = self.state.rounds.len().saturating_sub(1);
+        let previous_evaluations: &[Evaluation] = match self.state.rounds.len() {
+            len if debate && len >= 2 => &self.state.rounds[len - 2].evaluations,
+            _ => &[],
+        };
+        actors.for_each(|(name, addr)| addr.do_send(EvaluateAnswer {
+            question_id,
+            span: self.round_span.clone(),
+            round,
+            question: self.question.clone(),
+            answer: answer.clone(),
+            documents: self.documents.clone(),
+            images: self.images.clone(),
+            mode,
+            citations: self.constraints.citations,
+            code_runs: self.code_runs.clone(),
+            hurry: self.hurry,
+            peer_evaluations: previous_evaluations.iter()
+                .filter(|evaluation| evaluation.actor != *name)
+                .cloned()
+                .collect()
+        }));
+    }
+
+    /// What the panel is voting on: the drafts before the first round, and the rewrites after it.
+    fn ballot(&self) -> &[Candidate] {
+        if self.state.rounds.is_empty() { &self.candidates } else { &self.refinements }
+    }
+
+    /// Sends the ballot's candidates to the given voters, in the ballot's order.
+    fn send_ballot<'a>(&self, question_id: QuestionId, voters: impl Iterator<Item = &'a Addr<LlmActor>>, blind: bool, domains: &HashMap<String, String>) {
+        let ballot = self.ballot();
+        let candidates: Vec<String> = self.ballot_order.iter()
+            .filter_map(|index| ballot.get(*index))
+            .map(|candidate| shown(candidate.answer.clone(), std::slice::from_ref(&candidate.author), blind, domains))
+            .collect();
+        voters.for_each(|addr| addr.do_send(VoteOnCandidates {
+            question_id,
+            span: self.round_span.clone(),
+            question: self.question.clone(),
+            candidates: candidates.clone(),
+        }));
+    }
+
+    /// Tallies the candidate votes and returns the winning draft or rewrite.
+    fn choose_candidate(&mut self, weights: &HashMap<String, f64>) -> Candidate {
+        let ballot = if self.state.rounds.is_empty() { &mut self.candidates } else { &mut self.refinements };
+        for (name, choice) in &self.candidate_votes {
+            let weight = weights.get(name).copied().unwrap_or(1.0);
+            if let Some(candidate) = choice.and_then(|index| ballot.get_mut(index)) {
+                candidate.votes += weight;
+            }
+        }
+        // Ties go to the candidate that arrived first.
+        let winner = ballot.iter()
+            .enumerate()
+            .fold(None, |best: Option<(usize, f64)>, (index, candidate)| match best {
+                Some((_, votes)) if votes >= candidate.votes => best,
+                _ => Some((index, candidate.votes)),
+            })
+            .map(|(index, _)| index)
+            .unwrap_or(0);
+        ballot[winner].clone()
+    }
+
+    /// Asks the actor to draft the answer.
+    fn send_draft(&self, question_id: QuestionId, addr: &Addr<LlmActor>) {
+        addr.do_send(DraftAnswer {
+            question_id,
+            span: self.round_span.clone(),
+            question: self.question.clone(),
+            context: self.context.clone(),
+            documents: self.documents.clone(),
+            images: self.images.clone(),
+            constraints: self.constraints.clone(),
+            instructions: self.instructions.clone(),
+            sampling: self.sampling,
+        });
+    }
+
+    /// Asks the actor to rewrite the current answer, addressing its own objection and any veto.
+    fn send_refinement(&self, question_id: QuestionId, name: &str, addr: &Addr<LlmActor>) -> Result<(), ConsensusError> {
+        addr.do_send(RefineAnswer {
+            question_id,
+            span: self.round_span.clone(),
+            question: self.question.clone(),
+            answer: self.current_answer("refined")?,
+            reasoning: self.state.reasoning_of(name),
+            veto: self.veto.clone(),
+            guidance: self.guidance.clone(),
+            documents: self.documents.clone(),
+            images: self.images.clone(),
+            constraints: self.constraints.clone(),
+        });
+        Ok(())
+    }
+
+    /// Asks the chair for guidance on refining the answer, or for a ruling on which version to accept, showing it
+    /// every version so far with its evaluations.
+    fn send_summary(&self, question_id: QuestionId, addr: &Addr<LlmActor>, blind: bool, domains: &HashMap<String, String>) {
+        let rounds = self.state.rounds.iter().zip(&self.state.authors)
+            .map(|(round, authors)| Round { answer: shown(round.answer.clone(), authors, blind, domains), ..round.clone() })
+            .collect();
+        addr.do_send(Summarize { question_id, span: self.round_span.clone(), question: self.question.clone(), rounds, ruling: self.ruling_asked });
+    }
+
+    /// The current version of the answer, which is to be `purpose`, failing if there is none yet. While rewrites
+    /// are chained, it is the latest rewrite.
+    fn current_answer(&self, purpose: &str) -> Result<String, ConsensusError> {
+        self.chained.clone()
+            .or_else(|| self.state.rounds.last().map(|round| round.answer.clone()))
+            .ok_or_else(|| ConsensusError::StateConflict(format!("there is no answer to get {}", purpose)))
+    }
+
+    /// Starts tracing the next round, once the answer is sent back to be refined.
+    fn start_round(&mut self) {
+        self.round_span = info_span!(parent: &self.span, "round", round = self.state.rounds.len(), consensus = field::Empty);
+    }
+
+    /// Records how the question ended on its span.
+    fn record_outcome(&self, outcome: &str) {
+        self.span.record("outcome", outcome);
+        self.span.record("refinement_rounds", self.state.rounds.len().saturating_sub(1));
+        self.span.record("elapsed_ms", self.started.elapsed().as_millis() as u64);
+    }
+
+    fn into_result(mut self, question_id: QuestionId, consensus_reached: bool, usage: UsageSummary) -> ConsensusResult {
+        // A flagged question is refused, whatever the panel made of earlier versions of the answer.
+        let refused = self.flagged.is_some();
+        // A tie-break may settle on an earlier version, which is judged on the rounds up to it.
+        let settled = &self.state.rounds[..self.settled_on.map_or(self.state.rounds.len(), |round| round + 1)];
+        ConsensusResult {
+            question_id,
+            consensus_reached,
+            converged: self.converged,
+            answered_by: self.state.rounds.first().map(|round| round.author.clone()).unwrap_or_default(),
+            refinement_rounds: self.state.rounds.len().saturating_sub(1) as u32,
+            confidence: if refused { 0.0 } else { result::confidence(settled, consensus_reached) },
+            dissent: if consensus_reached || refused { Vec::new() } else { result::final_dissent(settled) },
+            citations: match settled.last() {
+                Some(round) if self.constraints.citations && !refused => citations::parse(&round.answer),
+                _ => Vec::new(),
+            },
+            answer: match settled.last() {
+                _ if refused => REFUSAL.to_string(),
+                Some(round) => round.answer.clone(),
+                None => String::new(),
+            },
+            flagged: self.flagged,
+            budget_exceeded: self.budget_exceeded,
+            deadlock: self.deadlock,
+            ruling: self.ruling,
+            cached: None,
+            fallbacks: self.fallbacks,
+            question: self.question,
+            candidates: mem::take(&mut self.candidates),
+            rounds: self.state.rounds,
+            elapsed: self.started.elapsed(),
+            usage,
+        }
+    }
+}
+
+impl Coordinator {
+    /// Takes a draft forward as the first version of the answer and asks the panel to evaluate it.
+    fn accept_draft(&mut self, question_id: QuestionId, author: String, answer: String) {
+        let Some(deliberation) = self.deliberations.get_mut(&question_id) else { return };
+        deliberation.state.accept_draft(author, answer);
+        self.screen(question_id);
+    }
+
+    /// Has the moderator screen the question and the latest version of the answer before its code is run and the
+    /// panel evaluates it, or goes straight on without moderation. The moderator reports back with [ContentScreened].
+    fn screen(&mut self, question_id: QuestionId) {
+        if self.moderation.is_none() {
+            return self.test_code(question_id);
+        }
+        let Some(deliberation) = self.deliberations.get_mut(&question_id) else { return };
+        debug!("Asking the moderator to screen question {} and its answer.", question_id);
+        deliberation.enter(Stage::Moderating);
+        self.listeners.record(question_id, deliberation.stage_started(vec![MODERATOR.to_string()]));
+        self.moderate(question_id);
+    }
+
+    /// Sends the question and the latest version of its answer to the moderator.
+    fn moderate(&self, question_id: QuestionId) {
+        let (Some(moderation), Some(deliberation)) = (self.moderation.clone(), self.deliberations.get(&question_id)) else { return };
+        let question = deliberation.question.clone();
+        let answer = deliberation.latest_answer().to_string();
+        let round = deliberation.state.rounds.len().saturating_sub(1);
+        let span = info_span!(parent: &deliberation.round_span, "moderate", flagged = field::Empty);
+        actix::spawn(async move {
+            let verdict = moderation.screen(&question, &answer).await;
+            if let Ok(flag) = &verdict {
+                Span::current().record("flagged", flag.is_some());
+            }
+            Coordinator::from_registry().do_send(ContentScreened { question_id, round, verdict });
+        }.instrument(span));
+    }
+
+    /// Has the sandbox run the code blocks of the latest version of the answer before the panel evaluates it, or asks
+    /// the panel to evaluate it straight away if there is no sandbox or no code it can run. The sandbox reports back
+    /// with [CodeTested].
+    fn test_code(&mut self, question_id: QuestionId) {
+        let Some(deliberation) = self.deliberations.get_mut(&question_id) else { return };
+        deliberation.code_runs.clear();
+        let answer = deliberation.latest_answer();
+        if self.sandbox.as_ref().is_none_or(|sandbox| sandbox.runnable(answer).is_empty()) {
+            return self.request_evaluation(question_id);
+        }
+        debug!("Running the code in the answer to question {}.", question_id);
+        deliberation.enter(Stage::Testing);
+        self.listeners.record(question_id, deliberation.stage_started(vec![SANDBOX.to_string()]));
+        self.run_code(question_id);
+    }
+
+    /// Sends the code blocks of the latest version of the answer to the sandbox.
+    fn run_code(&self, question_id: QuestionId) {
+        let (Some(sandbox), Some(deliberation)) = (self.sandbox.clone(), self.deliberations.get(&question_id)) else { return };
+        let blocks = sandbox.runnable(deliberation.latest_answer());
+        let round = deliberation.state.rounds.len().saturating_sub(1);
+        let span = info_span!(parent: &deliberation.round_span, "run_code", blocks = blocks.len(), passed = field::Empty);
+        actix::spawn(async move {
+            let runs = sandbox.run(&blocks).await;
+            Span::current().record("passed", runs.iter().filter(|run| run.passed()).count());
+            Coordinator::from_registry().do_send(CodeTested { question_id, round, runs });
+        }.instrument(span));
+    }
+
+    /// Asks the panel to evaluate the latest version of the answer.
+    fn request_evaluation(&mut self, question_id: QuestionId) {
+        let Some(deliberation) = self.deliberations.get_mut(&question_id) else { return };
+        debug!("Asking actors to evaluate the answer to question {}.", question_id);
+        let panel: Vec<String> = deliberation.panel(&self.llm_actors).map(|(name, _)| name.clone()).collect();
+        let evaluators = deliberation.state.start_evaluation(&self.members, &panel);
+        // The rounds left are cut to those projected to fit before the deadline, the last of them evaluated in a hurry.
+        let left = deliberation.budget.rounds_left(deliberation.started.elapsed(), deliberation.state.rounds.len());
+        if let Some(last) = left.map(|left| deliberation.state.evaluation_count.saturating_add(left)).filter(|last| *last < deliberation.state.max_rounds) {
+            debug!("Only {} more round(s) of question {} fit before its deadline.", last - deliberation.state.evaluation_count, question_id);
+            deliberation.state.max_rounds = last;
+        }
+        deliberation.hurry = left == Some(0);
+        deliberation.enter(Stage::Evaluating);
+        let mut evaluators: Vec<_> = evaluators.iter().filter_map(|name| self.llm_actors.get_key_value(name)).collect();
+        // Under blind evaluation, nobody can tell the author from being asked first or last.
+        if self.settings.blind {
+            self.selector.shuffle(&mut evaluators);
+        }
+        self.listeners.record(question_id, deliberation.stage_started(evaluators.iter().map(|(name, _)| (*name).clone()).collect()));
+        let answer = deliberation.shown_answer(self.settings.blind, &self.domains);
+        deliberation.request_evaluations(question_id, evaluators.into_iter(), self.settings.debate, answer);
+        self.compare_versions(question_id);
+    }
+
+    /// Asks the actors that voted NeedsRefinement to improve the answer, according to the refinement mode.
+    fn request_refinement(&mut self, question_id: QuestionId) -> Result<bool, ConsensusError> {
+        let Some(deliberation) = self.deliberations.get_mut(&question_id) else { return Ok(false) };
+        // Refinement starts from the version the panel evaluated, discarding any unfinished chain of rewrites.
+        deliberation.chain.clear();
+        deliberation.chain_authors.clear();
+        deliberation.chained = None;
+        deliberation.expected_refinements = 0;
+        deliberation.refinements.clear();
+        let question = deliberation.question.clone();
+        let answer = deliberation.current_answer("refined")?;
+        let dissenters = deliberation.state.dissenters();
+        // A stalled refinement is asked for again within the same round, as is one the chair has guided.
+        if !matches!(deliberation.stage, Stage::Refining | Stage::Chairing) {
+            deliberation.start_round();
+        }
+        deliberation.enter(Stage::Refining);
+
+        match self.settings.refinement {
+            RefinementMode::Single => {
+                let eligible = deliberation.state.eligible_refiners(deliberation.panel(&self.llm_actors).map(|(name, _)| name.clone()).collect());
+                let selected_key = deliberation.state.pick_refiner(&mut self.selector, eligible).ok_or(ConsensusError::NoActorsRegistered)?;
+                deliberation.refiner = Some(selected_key.clone());
+                self.listeners.record(question_id, deliberation.stage_started(vec![selected_key.clone()]));
+                match self.llm_actors.get(&selected_key) {
+                    Some(addr) =>  {
+                        debug!("Asking {} to refine the answer to question {}.", selected_key, question_id);
+                        deliberation.send_refinement(question_id, &selected_key, addr)?;
+                        Ok(true)
+                    },
+                    None => Ok(false),
+                }
+            },
+            RefinementMode::Every { combine } => {
+                // If only the reviewer objected, one actor rewrites the answer, taking turns with the others.
+                let eligible = if dissenters.is_empty() {
+                    let panel = deliberation.panel(&self.llm_actors).map(|(name, _)| name.clone()).collect();
+                    let eligible = deliberation.state.eligible_refiners(panel);
+                    deliberation.state.pick_refiner(&mut self.selector, eligible).into_iter().collect()
+                } else {
+                    dissenters
+                };
+                let refiners = self.selector.select(&eligible, eligible.len());
+                match combine {
+                    RefinementCombine::Chain => {
+                        debug!("Asking {} in turn to refine the answer to question {}.", refiners.join(", "), question_id);
+                        deliberation.chain = refiners.into();
+                        self.refine_next(question_id)
+                    },
+                    RefinementCombine::Vote => {
+                        debug!("Asking {} each to refine the answer to question {}.", refiners.join(", "), question_id);
+                        deliberation.refiner = None;
+                        deliberation.expected_refinements = refiners.len();
+                        self.listeners.record(question_id, deliberation.stage_started(refiners.clone()));
+                        for name in &refiners {
+                            if let Some(addr) = self.llm_actors.get(name) {
+                                deliberation.send_refinement(question_id, name, addr)?;
+                            }
+                        }
+                        Ok(true)
+                    },
+                }
+            },
+            RefinementMode::Synthesize { .. } => {
+                debug!("Asking {} for refinement suggestions to question {}.", dissenters.join(", "), question_id);
+                // The reviewer's objection counts as a suggestion that has already arrived.
+                // So does the chair's guidance.
+                deliberation.suggestions = deliberation.veto.iter().map(|veto| (REVIEWER.to_string(), veto.clone()))
+                    .chain(deliberation.chair.clone().zip(deliberation.guidance.clone()))
+                    .collect();
+                deliberation.expected_suggestions = dissenters.len() + deliberation.suggestions.len();
+                deliberation.refiner = None;
+                if dissenters.is_empty() {
+                    return self.synthesize(question_id);
+                }
+                self.listeners.record(question_id, deliberation.stage_started(dissenters.clone()));
+                for name in &dissenters {
+                    if let Some(addr) = self.llm_actors.get(name) {
+                        let reasoning = deliberation.state.reasoning_of(name);
+                        addr.do_send(SuggestRefinement { question_id, span: deliberation.round_span.clone(), question: question.clone(), answer: answer.clone(), reasoning });
+                    }
+                }
+                Ok(true)
+            },
+        }
+    }
+
+    /// The span of the question's current round, which whatever happens to the question is traced under.
+    /// Disabled if the question is not in flight.
+    fn span(&self, question_id: QuestionId) -> Span {
+        self.deliberations.get(&question_id).map_or_else(Span::none, |deliberation| deliberation.round_span.clone())
+    }
+
+    /// Asks the next dissenter in the chain to rewrite the answer as the one before them left it.
+    fn refine_next(&mut self, question_id: QuestionId) -> Result<bool, ConsensusError> {
+        let Some(deliberation) = self.deliberations.get_mut(&question_id) else { return Ok(false) };
+        let Some(name) = deliberation.chain.pop_front() else { return Ok(false) };
+        deliberation.refiner = Some(name.clone());
+        self.listeners.record(question_id, deliberation.stage_started(vec![name.clone()]));
+        match self.llm_actors.get(&name) {
+            Some(addr) => {
+                deliberation.send_refinement(question_id, &name, addr)?;
+                Ok(true)
+            },
+            None => Ok(false),
+        }
+    }
+
+    /// Has the panel vote on the rewrites collected so far, or takes the only one forward.
+    fn vote_on_refinements(&mut self, question_id: QuestionId) {
+        let Some(deliberation) = self.deliberations.get_mut(&question_id) else { return };
+        deliberation.expected_refinements = 0;
+        if deliberation.refinements.len() == 1 {
+            let Candidate { author, answer, .. } = deliberation.refinements.remove(0);
+            self.accept_refinement(question_id, vec![author], answer);
+            return;
+        }
+        debug!("Received all {} refinements for question {}. Asking actors to vote on them.", deliberation.refinements.len(), question_id);
+        deliberation.candidate_votes.clear();
+        self.open_ballot(question_id);
+    }
+
+    /// Takes a refined answer, rewritten by the `authors` in turn, forward as the next version and asks the panel to
+    /// evaluate it, unless the panel has evaluated the maximum number of times.
+    fn accept_refinement(&mut self, question_id: QuestionId, authors: Vec<String>, answer: String) {
+        let Some(deliberation) = self.deliberations.get_mut(&question_id) else { return };
+        deliberation.chained = None;
+        deliberation.veto = None;
+        deliberation.chair = None;
+        deliberation.guidance = None;
+        if let Some(previous) = deliberation.state.rounds.last() {
+            let changes = diff::diff_words(&previous.answer, &answer);
+            self.listeners.record(question_id, TranscriptEvent::Revised { round: deliberation.state.rounds.len(), author: authors.join(", "), changes });
+        }
+        if deliberation.state.rounds_left() {
+            authors.iter().for_each(|author| self.stats.record_refinement(author));
+        }
+        if deliberation.state.accept_refinement(authors, answer) {
+            self.screen(question_id);
+        } else {
+            debug!("Evaluated the maximum number of times. Breaking the loop.");
+            self.finish(question_id, false);
+        }
+    }
+
+    /// Compares the latest version of the answer with the one before it while the panel evaluates it, so that
+    /// the question can stop if they mean the same. The comparison reports back with [AnswersCompared].
+    fn compare_versions(&self, question_id: QuestionId) {
+        let (Some(convergence), Some(deliberation)) = (self.convergence.clone(), self.deliberations.get(&question_id)) else { return };
+        let [.., previous, latest] = deliberation.state.rounds.as_slice() else { return };
+        let (previous, latest) = (previous.answer.clone(), latest.answer.clone());
+        let round = deliberation.state.rounds.len() - 1;
+        let span = info_span!(parent: &deliberation.round_span, "compare", similarity = field::Empty);
+        actix::spawn(async move {
+            match convergence.similarity(&previous, &latest).await {
+                Ok(similarity) => {
+                    Span::current().record("similarity", similarity);
+                    Coordinator::from_registry().do_send(AnswersCompared { question_id, round, similarity });
+                },
+                Err(e) => warn!("Unable to compare version {} of the answer to question {} with the one before it: {}", round, question_id, e),
+            }
+        }.instrument(span));
+    }
+
+    /// Asks the given actors to draft an answer to the question.
+    fn request_drafts(&mut self, question_id: QuestionId, drafters: Vec<String>) {
+        let Some(deliberation) = self.deliberations.get_mut(&question_id) else { return };
+        deliberation.drafters.extend(drafters.iter().cloned());
+        self.listeners.record(question_id, deliberation.stage_started(drafters.clone()));
+        drafters.iter()
+            .filter_map(|name| self.llm_actors.get(name))
+            .for_each(|addr| deliberation.send_draft(question_id, addr));
+    }
+
+    /// Has the actor the router chose draft the answer, or one picked by the selection policy if it chose
+    /// nobody on the panel.
+    fn route(&mut self, question_id: QuestionId, choice: Option<String>) {
+        let Some(deliberation) = self.deliberations.get(&question_id) else { return };
+        let drafter = match choice.filter(|name| self.llm_actors.contains_key(name) && deliberation.deliberates(name)) {
+            Some(name) => {
+                debug!("Routed question {} to {}.", question_id, name);
+                name
+            },
+            None => {
+                debug!("Question {} was not routed to anyone on the panel. Picking a drafter instead.", question_id);
+                let Some(name) = self.selector.select_one(deliberation.panel(&self.llm_actors).map(|(name, _)| name)) else { return };
+                name
+            },
+        };
+        self.request_drafts(question_id, vec![drafter]);
+    }
+
+    /// Whether the actor is on the panel. Messages from actors that have left are ignored.
+    fn is_member(&self, name: &str) -> bool {
+        let member = self.llm_actors.contains_key(name);
+        if !member {
+            debug!("Ignoring a message from {}, who is no longer on the panel.", name);
+        }
+        member
+    }
+
+    /// Asks every actor deliberating on the question to vote on the ballot. Under blind evaluation the candidates are
+    /// shown in a random order.
+    fn open_ballot(&mut self, question_id: QuestionId) {
+        let Some(deliberation) = self.deliberations.get_mut(&question_id) else { return };
+        deliberation.enter(Stage::Voting);
+        self.listeners.record(question_id, deliberation.stage_started(deliberation.panel(&self.llm_actors).map(|(name, _)| name.clone()).collect()));
+        deliberation.ballot_order = (0..deliberation.ballot().len()).collect();
+        if self.settings.blind {
+            self.selector.shuffle(&mut deliberation.ballot_order);
+        }
+        deliberation.send_ballot(question_id, deliberation.panel(&self.llm_actors).map(|(_, addr)| addr), self.settings.blind, &self.domains);
+    }
+
+    /// Tallies the candidate votes and takes the winning draft or rewrite forward.
+    fn conclude_vote(&mut self, question_id: QuestionId) {
+        let Some(deliberation) = self.deliberations.get_mut(&question_id) else { return };
+        let Candidate { author, answer, votes } = deliberation.choose_candidate(&self.members.weights);
+        debug!("The panel chose the version by {} with {} votes for question {}.", author, votes, question_id);
+        self.listeners.record(question_id, TranscriptEvent::CandidateChosen { author: author.clone(), votes });
+        if deliberation.state.rounds.is_empty() {
+            self.accept_draft(question_id, author, answer);
+        } else {
+            deliberation.refinements.clear();
+            self.accept_refinement(question_id, vec![author], answer);
+        }
+    }
+
+    /// Decides a complete round of evaluations, finishing the question or asking for a refinement.
+    fn conclude_evaluation(&mut self, question_id: QuestionId) -> bool {
+        let Some(deliberation) = self.deliberations.get(&question_id) else { return false };
+        let conclusion = deliberation.state.conclude(&self.members);
+        let reached = conclusion == Conclusion::Reached;
+        deliberation.round_span.record("consensus", reached);
+        // Refining the answer would not bring anyone on the panel closer to judging it.
+        if conclusion == Conclusion::Abstained && deliberation.veto.is_none() {
+            debug!("Every evaluator of question {} abstained, so the answer is accepted without consensus.", question_id);
+            self.finish(question_id, false);
+            return true;
+        }
+        if !reached || deliberation.veto.is_some() {
+            if let Some(reason) = self.settings.deadlock.as_ref().and_then(|deadlock| deadlock.detect(&deliberation.state.rounds)) {
+                return self.break_deadlock(question_id, reason);
+            }
+            if let Some(reason) = self.budget_overrun(question_id) {
+                return self.stop_for_budget(question_id, reason);
+            }
+            if let Some(chair) = self.chair(deliberation) {
+                // In the final round, the chair rules on an evaluated version rather than refine one nobody evaluates.
+                let ruling = !reached && !deliberation.state.rounds_left();
+                return self.consult_chair(question_id, chair, ruling);
+            }
+            let refined = self.request_refinement(question_id);
+            return self.proceed(question_id, refined);
+        }
+        debug!("The panel reached consensus on question {} under the {:?} strategy.", question_id, deliberation.state.strategy);
+        if deliberation.reviewer.is_some() {
+            let reviewed = self.request_review(question_id).map(|()| true);
+            return self.proceed(question_id, reviewed);
+        }
+        self.finish(question_id, true);
+        true
+    }
+
+    /// The evaluators of the current version of the answer that have yet to give their verdict.
+    fn pending_evaluators<'a>(&'a self, deliberation: &'a Deliberation) -> impl Iterator<Item = &'a String> {
+        self.llm_actors.keys().filter(|name| deliberation.evaluates(name) && !deliberation.state.feedback.contains_key(*name))
+    }
+
+    /// Whether the evaluations in already decide the round, however the pending ones go, so that under
+    /// `early_decision` the panel need not wait for them.
+    fn decided_early(&self, question_id: QuestionId) -> bool {
+        let Some(deliberation) = self.deliberations.get(&question_id) else { return false };
+        deliberation.state.decided_early(&self.members, self.pending_evaluators(deliberation))
+    }
+
+    /// Stops the evaluations of the question still in flight, once the round is decided without them.
+    fn cancel_pending_evaluations(&self, question_id: QuestionId) {
+        let Some(deliberation) = self.deliberations.get(&question_id) else { return };
+        let pending: Vec<&String> = self.pending_evaluators(deliberation).collect();
+        debug!("Question {} is decided without the evaluations of {}, which are no longer waited on.", question_id, pending.iter().map(|name| name.as_str()).collect::<Vec<_>>().join(", "));
+        for name in pending {
+            if let Some(addr) = self.llm_actors.get(name) {
+                addr.do_send(CancelCalls { question_id: Some(question_id) });
+            }
+        }
+    }
+
+    /// Why refining the answer to the question once more would overrun its budget or the session's, or pass its
+    /// deadline, if it would.
+    fn budget_overrun(&self, question_id: QuestionId) -> Option<String> {
+        let deliberation = self.deliberations.get(&question_id)?;
+        let spent = self.usage.question(question_id);
+        let next = budget::next_round(&spent, deliberation.state.rounds.len());
+        if let Some(reason) = deliberation.budget.overrun_by(&(spent + next)) {
+            return Some(format!("another round would bring the question to {}", reason));
+        }
+        let elapsed = deliberation.started.elapsed();
+        if let Some(reason) = deliberation.budget.late_by(elapsed + budget::round_time(elapsed, deliberation.state.rounds.len())) {
+            return Some(format!("another round would take the question to {}", reason));
+        }
+        self.settings.budget.session.overrun_by(&(self.usage.session().total + next))
+            .map(|reason| format!("another round would bring the session to {}", reason))
+    }
+
+    /// Accepts the latest version of the answer without consensus, rather than refine it beyond a budget.
+    fn stop_for_budget(&mut self, question_id: QuestionId, reason: String) -> bool {
+        let Some(deliberation) = self.deliberations.get_mut(&question_id) else { return false };
+        debug!("Stopping question {} without consensus because {}.", question_id, reason);
+        let round = deliberation.state.rounds.len().saturating_sub(1);
+        deliberation.budget_exceeded = Some(reason.clone());
+        self.listeners.record(question_id, TranscriptEvent::BudgetExceeded { round, reason });
+        self.finish(question_id, false);
+        true
+    }
+
+    /// Accepts the version of the answer the tie-break picks without consensus, rather than keep refining an
+    /// answer the panel is going in circles on.
+    fn break_deadlock(&mut self, question_id: QuestionId, reason: String) -> bool {
+        let chair = self.deliberations.get(&question_id).and_then(|deliberation| self.chair(deliberation));
+        let (Some(settings), Some(deliberation)) = (&self.settings.deadlock, self.deliberations.get_mut(&question_id)) else { return false };
+        if let (TieBreak::Chair, Some(chair)) = (&settings.tie_break, chair) {
+            debug!("Asking the chair to rule on question {} because {}.", question_id, reason);
+            deliberation.deadlock = Some(reason);
+            return self.consult_chair(question_id, chair, true);
+        }
+        let round = settings.break_tie(&deliberation.state.rounds, &self.members.weights);
+        debug!("Stopping question {} without consensus at version {} because {}.", question_id, round, reason);
+        deliberation.settled_on = Some(round);
+        deliberation.deadlock = Some(reason.clone());
+        self.listeners.record(question_id, TranscriptEvent::Deadlocked { round, reason });
+        self.finish(question_id, false);
+        true
+    }
+
+    /// The actor chairing the panel on the question, if one deliberates on it.
+    fn chair(&self, deliberation: &Deliberation) -> Option<String> {
+        self.members.roles.iter()
+            .filter(|(name, role)| **role == ActorRole::Chair && self.llm_actors.contains_key(*name) && deliberation.deliberates(name))
+            .map(|(name, _)| name)
+            .min()
+            .cloned()
+    }
+
+    /// Asks the chair for guidance on refining the answer, which the refinement then follows, or to rule on the
+    /// version of the answer to accept. The chair replies with a [ChairDecision].
+    fn consult_chair(&mut self, question_id: QuestionId, chair: String, ruling: bool) -> bool {
+        let Some(deliberation) = self.deliberations.get_mut(&question_id) else { return false };
+        let Some(addr) = self.llm_actors.get(&chair) else { return false };
+        debug!("Asking {} to {} question {}.", chair, if ruling { "rule on" } else { "guide the refinement of" }, question_id);
+        // Guidance starts the next round, which the refinement carries on; a ruling ends the question instead.
+        if !ruling && deliberation.stage != Stage::Chairing {
+            deliberation.start_round();
+        }
+        deliberation.enter(Stage::Chairing);
+        deliberation.chair = Some(chair.clone());
+        deliberation.ruling_asked = ruling;
+        self.listeners.record(question_id, deliberation.stage_started(vec![chair]));
+        deliberation.send_summary(question_id, addr, self.settings.blind, &self.domains);
+        true
+    }
+
+    /// Asks the question's reviewer to approve the answer the panel agreed on. The decision comes back
+    /// to the [Coordinator] as an [AnswerReviewed].
+    fn request_review(&mut self, question_id: QuestionId) -> Result<(), ConsensusError> {
+        let Some(deliberation) = self.deliberations.get_mut(&question_id) else { return Ok(()) };
+        let Some(reviewer) = deliberation.reviewer.clone() else { return Ok(()) };
+        deliberation.enter(Stage::Reviewing);
+        self.listeners.record(question_id, deliberation.stage_started(vec![REVIEWER.to_string()]));
+        let request = ReviewAnswer {
+            question_id,
+            question: deliberation.question.clone(),
+            answer: deliberation.current_answer("reviewed")?,
+        };
+        debug!("Asking the reviewer to approve the answer to question {}.", question_id);
+        Arbiter::current().spawn(async move {
+            let review = reviewer.send(request).await.unwrap_or_else(|e| {
+                warn!("Unable to reach the reviewer of question {}, so the answer is accepted: {}", question_id, e);
+                Review::Approve
+            });
+            Coordinator::from_registry().do_send(AnswerReviewed { question_id, review });
+        });
+        Ok(())
+    }
+
+    /// Asks one actor to merge the refinement suggestions collected so far into a revised answer.
+    fn synthesize(&mut self, question_id: QuestionId) -> Result<bool, ConsensusError> {
+        let Some(deliberation) = self.deliberations.get_mut(&question_id) else { return Ok(false) };
+        // Prefer the configured synthesizer, falling back to one of the dissenters.
+        let synthesizer = match &self.settings.refinement {
+            RefinementMode::Synthesize { synthesizer: Some(name) } if self.llm_actors.contains_key(name) && deliberation.deliberates(name) => name.clone(),
+            _ => {
+                let authors = deliberation.suggestions.iter()
+                    .map(|(author, _)| author)
+                    .filter(|author| self.llm_actors.contains_key(*author))
+                    .cloned()
+                    .collect();
+                match deliberation.state.pick_refiner(&mut self.selector, authors) {
+                    Some(author) => author,
+                    // Every suggester has left the panel.
+                    None => {
+                        let panel = deliberation.panel(&self.llm_actors).map(|(name, _)| name.clone()).collect();
+                        deliberation.state.pick_refiner(&mut self.selector, panel).ok_or(ConsensusError::NoActorsRegistered)?
+                    },
+                }
+            },
+        };
+        let request = SynthesizeAnswer {
+            question_id,
+            span: deliberation.round_span.clone(),
+            question: deliberation.question.clone(),
+            answer: deliberation.current_answer("synthesized into")?,
+            suggestions: mem::take(&mut deliberation.suggestions),
+            documents: deliberation.documents.clone(),
+            images: deliberation.images.clone(),
+            constraints: deliberation.constraints.clone(),
+        };
+        deliberation.expected_suggestions = 0;
+        deliberation.refiner = Some(synthesizer.clone());
+        self.listeners.record(question_id, deliberation.stage_started(vec![synthesizer.clone()]));
+        match self.llm_actors.get(&synthesizer) {
+            Some(addr) => {
+                debug!("Asking {} to synthesize the suggestions into a refined answer to question {}.", synthesizer, question_id);
+                addr.do_send(request);
+                Ok(true)
+            },
+            None => Ok(false),
+        }
+    }
+
+    /// Whether the question went on to its next step, failing it if the step could not be taken.
+    fn proceed(&mut self, question_id: QuestionId, step: Result<bool, ConsensusError>) -> bool {
+        step.unwrap_or_else(|error| {
+            self.fail(question_id, error);
+            false
+        })
+    }
+
+    /// Carries the question on without an actor that has left the panel, dropping its votes and
+    /// finding someone else to do any work that was waiting on it.
+    fn release(&mut self, question_id: QuestionId, name: &str) {
+        let Some(deliberation) = self.deliberations.get_mut(&question_id) else { return };
+        let was_dissenter = deliberation.state.feedback.get(name) == Some(&Feedback::NeedsRefinement);
+        deliberation.state.forget_verdict(name);
+        deliberation.candidate_votes.remove(name);
+        match deliberation.stage {
+            Stage::Drafting if deliberation.router.as_deref() == Some(name) => {
+                deliberation.router = None;
+                self.route(question_id, None);
+            },
+            Stage::Drafting if deliberation.drafters.remove(name) && deliberation.candidates.iter().all(|candidate| candidate.author != name) => {
+                self.redispatch(question_id);
+            },
+            Stage::Voting if deliberation.candidate_votes.len() >= deliberation.panel(&self.llm_actors).count() => self.conclude_vote(question_id),
+            Stage::Evaluating if deliberation.state.feedback.len() >= deliberation.evaluator_count(&self.llm_actors) => {
+                self.conclude_evaluation(question_id);
+            },
+            Stage::Chairing if deliberation.chair.as_deref() == Some(name) => {
+                // Decide the round again without the departed chair, going on without one if nobody else chairs.
+                deliberation.chair = None;
+                self.conclude_evaluation(question_id);
+            },
+            Stage::Refining if deliberation.chain.iter().any(|next| next == name) => deliberation.chain.retain(|next| next != name),
+            Stage::Refining if deliberation.refiner.as_deref() == Some(name) => {
+                // Decide the round again without the departed actor, which refines again if anyone still dissents.
+                self.conclude_evaluation(question_id);
+            },
+            Stage::Refining if was_dissenter && deliberation.expected_suggestions > 0 => {
+                if deliberation.suggestions.iter().any(|(author, _)| author == name) {
+                    return;
+                }
+                deliberation.expected_suggestions -= 1;
+                if deliberation.expected_suggestions == 0 {
+                    self.conclude_evaluation(question_id);
+                } else if deliberation.suggestions.len() >= deliberation.expected_suggestions {
+                    let synthesized = self.synthesize(question_id);
+                    self.proceed(question_id, synthesized);
+                }
+            },
+            Stage::Refining if was_dissenter && deliberation.expected_refinements > 0 => {
+                if deliberation.refinements.iter().any(|candidate| candidate.author == name) {
+                    return;
+                }
+                deliberation.expected_refinements -= 1;
+                if deliberation.expected_refinements == 0 {
+                    self.conclude_evaluation(question_id);
+                } else if deliberation.refinements.len() >= deliberation.expected_refinements {
+                    self.vote_on_refinements(question_id);
+                }
+            },
+            _ => (),
+        }
+    }
+
+    /// Redispatches or fails every question whose current stage has run past its timeout.
+    fn check_stalled(&mut self) {
+        let timeouts = self.settings.timeouts;
+        let stalled: Vec<QuestionId> = self.deliberations.iter()
+            .filter(|(_, deliberation)| deliberation.stage_started.elapsed() >= timeouts.limit(deliberation.stage))
+            .map(|(question_id, _)| *question_id)
+            .collect();
+        for question_id in stalled {
+            let Some(deliberation) = self.deliberations.get_mut(&question_id) else { continue };
+            if deliberation.redispatches < timeouts.redispatches {
+                deliberation.redispatches += 1;
+                self.redispatch(question_id);
+            } else {
+                self.time_out(question_id);
+            }
+        }
+    }
+
+    /// Gives every question whose deadline has passed the latest version of its answer without consensus, stopping
+    /// the calls still in flight for it. A question without a draft yet is left to its stage timeouts, and one the
+    /// panel has agreed on to its reviewer.
+    fn check_deadlines(&mut self) {
+        let late: Vec<(QuestionId, String)> = self.deliberations.iter()
+            .filter(|(_, deliberation)| !deliberation.state.rounds.is_empty() && deliberation.stage != Stage::Reviewing)
+            .filter_map(|(question_id, deliberation)| deliberation.budget.late_by(deliberation.started.elapsed()).map(|reason| (*question_id, reason)))
+            .collect();
+        for (question_id, reason) in late {
+            self.llm_actors.values().for_each(|addr| addr.do_send(CancelCalls { question_id: Some(question_id) }));
+            self.stop_for_budget(question_id, format!("the question took {}", reason));
+        }
+    }
+
+    /// Sends the question's current stage again to the actors that have not responded.
+    fn redispatch(&mut self, question_id: QuestionId) {
+        let Some(deliberation) = self.deliberations.get_mut(&question_id) else { return };
+        let stage = deliberation.stage;
+        debug!("Question {} stalled during {}. Redispatching.", question_id, stage);
+        deliberation.stage_started = Instant::now();
+        let pending: Vec<String> = match stage {
+            Stage::Drafting => {
+                // Ask actors that have not drafted yet, since the original drafters may be stuck. A stuck router is
+                // given up on, and its pick ignored if it comes.
+                deliberation.router = None;
+                let missing = deliberation.expected_candidates.saturating_sub(deliberation.candidates.len()).max(1);
+                let idle = deliberation.panel(&self.llm_actors)
+                    .map(|(name, _)| name)
+                    .filter(|name| deliberation.candidates.iter().all(|candidate| candidate.author != **name));
+                let drafters = self.selector.select(idle, missing);
+                deliberation.drafters.extend(drafters.iter().cloned());
+                drafters.iter()
+                    .filter_map(|name| self.llm_actors.get(name))
+                    .for_each(|addr| deliberation.send_draft(question_id, addr));
+                drafters
+            },
+            Stage::Voting => {
+                let voters: Vec<String> = deliberation.panel(&self.llm_actors)
+                    .map(|(name, _)| name)
+                    .filter(|name| !deliberation.candidate_votes.contains_key(*name))
+                    .cloned()
+                    .collect();
+                deliberation.send_ballot(question_id, voters.iter().filter_map(|name| self.llm_actors.get(name)), self.settings.blind, &self.domains);
+                voters
+            },
+            Stage::Moderating => {
+                self.moderate(question_id);
+                vec![MODERATOR.to_string()]
+            },
+            Stage::Testing => {
+                self.run_code(question_id);
+                vec![SANDBOX.to_string()]
+            },
+            Stage::Evaluating => {
+                let evaluators = self.llm_actors.iter().filter(|(name, _)| deliberation.evaluates(name) && !deliberation.state.feedback.contains_key(*name));
+                let names = evaluators.clone().map(|(name, _)| name.clone()).collect();
+                let answer = deliberation.shown_answer(self.settings.blind, &self.domains);
+                deliberation.request_evaluations(question_id, evaluators, self.settings.debate, answer);
+                names
+            },
+            Stage::Chairing => {
+                let chair = deliberation.chair.clone().unwrap_or_default();
+                if let Some(addr) = self.llm_actors.get(&chair) {
+                    deliberation.send_summary(question_id, addr, self.settings.blind, &self.domains);
+                }
+                vec![chair]
+            },
+            Stage::Refining => {
+                let refined = self.request_refinement(question_id);
+                self.proceed(question_id, refined);
+                Vec::new()
+            },
+            Stage::Reviewing => Vec::new(),
+        };
+        if !matches!(stage, Stage::Refining | Stage::Reviewing) {
+            self.listeners.record(question_id, TranscriptEvent::Redispatched { stage, actors: pending });
+        }
+    }
+
+    /// Fails the question with the error, recording it under the actor, or the moderator, whose call failed, or
+    /// under [COORDINATOR] if none did.
+    fn fail(&mut self, question_id: QuestionId, error: ConsensusError) {
+        let Some(deliberation) = self.deliberations.remove(&question_id) else { return };
+        self.usage.finish(question_id);
+        debug!("Question {} failed: {}", question_id, error);
+        deliberation.record_outcome("failed");
+        let actor = error.actor().unwrap_or(COORDINATOR).to_string();
+        self.listeners.record(question_id, TranscriptEvent::Failed { actor, error: error.reason() });
+        if let Some(responder) = deliberation.responder {
+            let _ = responder.send(Err(AskError::Failed(error)));
+        }
+        self.take_up_queued();
+    }
+
+    /// Fails the question because its current stage stalled.
+    fn time_out(&mut self, question_id: QuestionId) {
+        let Some(deliberation) = self.deliberations.remove(&question_id) else { return };
+        self.usage.finish(question_id);
+        debug!("Question {} timed out during {}.", question_id, deliberation.stage);
+        deliberation.record_outcome("timed_out");
+        self.listeners.record(question_id, TranscriptEvent::TimedOut { stage: deliberation.stage });
+        if let Some(responder) = deliberation.responder {
+            let _ = responder.send(Err(AskError::Failed(ConsensusError::Timeout { stage: deliberation.stage })));
+        }
+        self.take_up_queued();
+    }
+
+    /// Stops deliberating on the question, failing it with [AskError::Cancelled], and aborts the actors' calls for it.
+    fn cancel(&mut self, question_id: QuestionId) -> bool {
+        let Some(deliberation) = self.deliberations.remove(&question_id) else { return false };
+        self.usage.finish(question_id);
+        debug!("Question {} was cancelled during {}.", question_id, deliberation.stage);
+        deliberation.record_outcome("cancelled");
+        self.listeners.record(question_id, TranscriptEvent::Cancelled);
+        self.llm_actors.values().for_each(|addr| addr.do_send(CancelCalls { question_id: Some(question_id) }));
+        if let Some(responder) = deliberation.responder {
+            let _ = responder.send(Err(AskError::Cancelled));
+        }
+        self.take_up_queued();
+        true
+    }
+
+    /// Delivers the result to whoever asked the question and forgets the question.
+    /// `consensus_reached` is false when the panel ran out of rounds.
+    fn finish(&mut self, question_id: QuestionId, consensus_reached: bool) {
+        let Some(mut deliberation) = self.deliberations.remove(&question_id) else { return };
+        let responder = deliberation.responder.take();
+        if let (true, [_, .., last]) = (consensus_reached, deliberation.state.authors.as_slice()) {
+            last.iter().for_each(|author| self.stats.record_agreed(author));
+        }
+        deliberation.record_outcome(match (consensus_reached, deliberation.converged) {
+            _ if deliberation.flagged.is_some() => "flagged",
+            (false, _) if deliberation.budget_exceeded.is_some() => "budget_exceeded",
+            (false, _) if deliberation.deadlock.is_some() => "deadlocked",
+            (false, _) if deliberation.ruling.is_some() => "ruled",
+            (true, _) => "consensus",
+            (false, true) => "converged",
+            (false, false) => "no_consensus",
+        });
+        let result = deliberation.into_result(question_id, consensus_reached, self.usage.finish(question_id));
+        self.listeners.record(question_id, TranscriptEvent::Consensus {
+            answer: result.answer.clone(),
+            reached: consensus_reached,
+            refinement_rounds: result.refinement_rounds,
+            elapsed_secs: result.elapsed.as_secs_f64(),
+        });
+        self.listeners.observers.iter_mut().for_each(|observer| observer.on_final(&result));
+        if let Some(history) = &self.listeners.history {
+            history.do_send(ConsensusReached(result.clone()));
+        }
+        if let Some(responder) = responder {
+            if responder.send(Ok(result)).is_err() {
+                debug!("The asker stopped waiting before the answer to question {} was ready.", question_id);
+            }
+        }
+        self.take_up_queued();
+    }
+}
+
+impl Members for Roster {
+    fn weight(&self, name: &str) -> f64 {
+        self.weights.get(name).copied().unwrap_or(1.0)
+    }
+
+    fn role(&self, name: &str) -> ActorRole {
+        self.roles.get(name).copied().unwrap_or_default()
+    }
+}
+
+impl Actor for Coordinator {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(WATCHDOG_INTERVAL, |coordinator, _ctx| {
+            coordinator.check_stalled();
+            coordinator.check_deadlines();
+        });
+    }
+}
+
+impl Handler<Register> for Coordinator {
+    type Result = bool;
+
+    fn handle(&mut self, msg: Register, _ctx: &mut Self::Context) -> Self::Result {
+        self.register(msg);
+        true
+    }
+}
+
+impl Handler<RegisterAll> for Coordinator {
+    type Result = usize;
+
+    fn handle(&mut self, msg: RegisterAll, _ctx: &mut Self::Context) -> Self::Result {
+        for registration in msg.0 {
+            self.register(registration);
+        }
+        self.llm_actors.len()
+    }
+}
+
+impl Coordinator {
+    /// Adds the actor to the panel, replacing any actor with the same name.
+    fn register(&mut self, msg: Register) {
+        self.llm_actors.insert(msg.name.clone(), msg.actor);
+        self.members.weights.insert(msg.name.clone(), msg.weight);
+        self.domains.insert(msg.name.clone(), msg.domain);
+        match msg.role {
+            ActorRole::Member => self.members.roles.remove(&msg.name),
+            role => self.members.roles.insert(msg.name.clone(), role),
+        };
+        if msg.tools.is_empty() {
+            self.tools.remove(&msg.name);
+        } else {
+            self.tools.insert(msg.name.clone(), msg.tools);
+        }
+        self.usage.set_pricing(&msg.name, msg.pricing);
+        debug!("{} registered with Coordinator with weight {}.", msg.name, msg.weight);
+    }
+
+    /// Takes the actor off the panel, returning false if there is no such actor.
+    fn remove_actor(&mut self, name: &str) -> bool {
+        if self.llm_actors.remove(name).is_none() {
+            return false;
+        }
+        self.members.weights.remove(name);
+        self.domains.remove(name);
+        self.members.roles.remove(name);
+        self.tools.remove(name);
+        debug!("{} left the panel.", name);
+        true
+    }
+
+    /// Replaces the session settings.
+    fn configure(&mut self, settings: ConsensusSettings) {
+        debug!("Coordinator settings changed to {:?}.", settings);
+        if settings.selection != self.settings.selection {
+            self.selector = Selector::new(&settings.selection);
+        }
+        self.settings = settings;
+    }
+
+    /// Replaces the panel and settings with the reloaded ones, if any are waiting and no question is in flight.
+    fn reload_if_idle(&mut self) {
+        if !self.deliberations.is_empty() {
+            return;
+        }
+        let Some(reload) = self.reload.take() else { return };
+        let names: HashSet<&str> = reload.actors.iter().map(|registration| registration.name.as_str()).collect();
+        let leaving: Vec<String> = self.llm_actors.keys().filter(|name| !names.contains(name.as_str())).cloned().collect();
+        for name in leaving {
+            self.remove_actor(&name);
+        }
+        debug!("The reloaded panel of {} actors takes over.", reload.actors.len());
+        for registration in reload.actors {
+            self.register(registration);
+        }
+        self.configure(reload.settings);
+    }
+}
+
+impl Handler<ReloadPanel> for Coordinator {
+    type Result = ();
+
+    fn handle(&mut self, msg: ReloadPanel, _ctx: &mut Self::Context) -> Self::Result {
+        if !self.deliberations.is_empty() {
+            debug!("The reloaded panel waits for the {} questions in flight to finish.", self.deliberations.len());
+        }
+        self.reload = Some(msg);
+        self.take_up_queued();
+    }
+}
+
+impl Handler<Unregister> for Coordinator {
+    type Result = bool;
+
+    fn handle(&mut self, msg: Unregister, _ctx: &mut Self::Context) -> Self::Result {
+        if !self.remove_actor(&msg.name) {
+            return false;
+        }
+        if self.llm_actors.is_empty() {
+            // Nobody is left to deliberate. Dropping each responder resolves its AskQuestion with AskError::Abandoned.
+            for question_id in self.deliberations.drain().map(|(question_id, _)| question_id).collect::<Vec<_>>() {
+                self.usage.finish(question_id);
+                self.listeners.record(question_id, TranscriptEvent::Abandoned);
+            }
+            // Nor is anybody left to take up the queued questions, which fail as asking an empty panel does, unless
+            // a reloaded panel was waiting for the questions just abandoned.
+            self.take_up_queued();
+            return true;
+        }
+        for question_id in self.deliberations.keys().copied().collect::<Vec<_>>() {
+            self.release(question_id, &msg.name);
+        }
+        true
+    }
+}
+
+impl Handler<HealthCheck> for Coordinator {
+    type Result = ResponseFuture<Vec<ActorHealth>>;
+
+    fn handle(&mut self, _msg: HealthCheck, _ctx: &mut Self::Context) -> Self::Result {
+        let pings: Vec<_> = self.llm_actors.iter()
+            .map(|(name, addr)| {
+                let name = name.clone();
+                let ping = addr.send(Ping);
+                async move {
+                    let error = match ping.await {
+                        Ok(Ok(())) => None,
+                        Ok(Err(e)) => Some(e.to_string()),
+                        Err(e) => Some(format!("unable to reach the actor: {}", e)),
+                    };
+                    ActorHealth { name, error }
+                }
+            })
+            .collect();
+        Box::pin(async move {
+            let mut health = join_all(pings).await;
+            health.sort_by(|a, b| a.name.cmp(&b.name));
+            health
+        })
+    }
+}
+
+impl Handler<ListActors> for Coordinator {
+    type Result = MessageResult<ListActors>;
+
+    fn handle(&mut self, _msg: ListActors, _ctx: &mut Self::Context) -> Self::Result {
+        let mut actors: Vec<ActorInfo> = self.llm_actors.keys()
+            .map(|name| ActorInfo { name: name.clone(), weight: self.members.weights.get(name).copied().unwrap_or(1.0) })
+            .collect();
+        actors.sort_by(|a, b| a.name.cmp(&b.name));
+        MessageResult(actors)
+    }
+}
+
+impl Handler<AskQuestion> for Coordinator {
+    type Result = ResponseFuture<Result<ConsensusResult, AskError>>;
+
+    fn handle(&mut self, msg: AskQuestion, _ctx: &mut Self::Context) -> Self::Result {
+        debug!("Received AskQuestion: {}", msg.question);
+        let (responder, result) = oneshot::channel();
+        self.reload_if_idle();
+        if self.is_busy() {
+            if let Some(queued) = &msg.options.queued {
+                let _ = queued.send(self.queue.len() + 1);
+            }
+            self.queue.push_back(QueuedQuestion { msg, responder });
+            debug!("The panel is at its limit of {} questions at once, so the question is queued at position {}.", self.deliberations.len(), self.queue.len());
+        } else {
+            self.take_up(msg, responder);
+        }
+        Box::pin(async move { result.await.unwrap_or(Err(AskError::Abandoned)) })
+    }
+}
+
+impl Coordinator {
+    /// Whether the panel is deliberating on as many questions as it takes at once, or is waiting for those in flight
+    /// to finish before it is reloaded.
+    fn is_busy(&self) -> bool {
+        self.reload.is_some() || self.settings.max_concurrent_questions.is_some_and(|max| self.deliberations.len() >= max)
+    }
+
+    /// Takes up the questions at the front of the queue while the panel has room for them, skipping any whose
+    /// askers stopped waiting, once any reloaded panel has taken over.
+    fn take_up_queued(&mut self) {
+        self.reload_if_idle();
+        while !self.is_busy() {
+            let Some(queued) = self.queue.pop_front() else { return };
+            if queued.responder.is_closed() {
+                debug!("Dropping a queued question whose asker stopped waiting for it.");
+                continue;
+            }
+            self.take_up(queued.msg, queued.responder);
+        }
+    }
+
+    /// Fails every queued question with the error, returning how many there were.
+    fn clear_queue(&mut self, error: fn() -> AskError) -> usize {
+        let queued = self.queue.len();
+        for queued in self.queue.drain(..) {
+            let _ = queued.responder.send(Err(error()));
+        }
+        queued
+    }
+
+    /// Starts deliberating on the question, resolving `responder` with its result once the panel is done with it.
+    fn take_up(&mut self, msg: AskQuestion, responder: oneshot::Sender<Result<ConsensusResult, AskError>>) {
+        if self.llm_actors.is_empty() {
+            let _ = responder.send(Err(AskError::Failed(ConsensusError::NoActorsRegistered)));
+            return;
+        }
+        let only: Option<HashSet<String>> = (!msg.options.actors.is_empty()).then(|| msg.options.actors.iter().cloned().collect());
+        let mut unknown: Vec<String> = only.iter().flatten().filter(|name| !self.llm_actors.contains_key(*name)).cloned().collect();
+        if !unknown.is_empty() {
+            unknown.sort();
+            let _ = responder.send(Err(AskError::Failed(ConsensusError::UnknownActors(unknown))));
+            return;
+        }
+        // The actors the question is asked of, which the panel's size is judged by.
+        let panel: Vec<String> = self.llm_actors.keys().filter(|name| only.as_ref().is_none_or(|only| only.contains(*name))).cloned().collect();
+        if panel.len() < self.settings.min_actors {
+            let error = ConsensusError::PanelTooSmall { actors: panel.len(), minimum: self.settings.min_actors };
+            let _ = responder.send(Err(AskError::Failed(error)));
+            return;
+        }
+        if let Some(reason) = self.settings.budget.session.overrun_by(&self.usage.session().total) {
+            let _ = responder.send(Err(AskError::BudgetExceeded(reason)));
+            return;
+        }
+
+        // Select the LLM actors to draft, or the one to pick who drafts
+        let drafts = match self.settings.draft {
+            DraftMode::Single | DraftMode::Routed { .. } | DraftMode::SelfConsistent(_) => 1,
+            DraftMode::BestOfN { candidates } => candidates.clamp(1, panel.len()),
+        };
+        let router = match &self.settings.draft {
+            // With a single actor on the panel, there is nobody else to route the question to.
+            DraftMode::Routed { router } if panel.len() > 1 => match router {
+                Some(name) if panel.contains(name) => Some(name.clone()),
+                _ => self.selector.select_one(&panel),
+            },
+            _ => None,
+        };
+        let drafters = match router {
+            Some(_) => Vec::new(),
+            None => self.selector.select(&panel, drafts),
+        };
+
+        self.last_question_id = QuestionId(self.last_question_id.0 + 1);
+        let question_id = self.last_question_id;
+        if let Some(accepted) = &msg.options.accepted {
+            let _ = accepted.send(question_id);
+        }
+        let strategy = msg.options.strategy.unwrap_or(self.settings.strategy);
+        let span = info_span!(
+            "question",
+            question_id = question_id.0,
+            ?strategy,
+            outcome = field::Empty,
+            refinement_rounds = field::Empty,
+            elapsed_ms = field::Empty,
+        );
+        let round_span = info_span!(parent: &span, "round", round = 0, consensus = field::Empty);
+        let _span = round_span.clone().entered();
+        let question = prompts::fit_question(msg.question);
+        let deliberation = Deliberation {
+            question: question.clone(),
+            context: msg.options.context.clone(),
+            documents: msg.options.documents.clone(),
+            images: msg.options.images.clone(),
+            state: DeliberationState::new(&self.settings, strategy, msg.options.max_rounds.unwrap_or(self.settings.max_rounds)),
+            constraints: msg.options.constraints.clone().unwrap_or_else(|| self.settings.constraints.clone()),
+            instructions: msg.options.instructions.clone(),
+            budget: msg.options.budget.unwrap_or(self.settings.budget.question),
+            sampling: match self.settings.draft {
+                DraftMode::SelfConsistent(sampling) => Some(sampling),
+                _ => None,
+            },
+            stage: Stage::Drafting,
+            stage_started: Instant::now(),
+            redispatches: 0,
+            only,
+            expected_candidates: if drafts > 1 { drafts } else { 0 },
+            router: router.clone(),
+            drafters: HashSet::new(),
+            candidates: Vec::new(),
+            candidate_votes: HashMap::new(),
+            ballot_order: Vec::new(),
+            expected_suggestions: 0,
+            suggestions: Vec::new(),
+            refiner: None,
+            chain: VecDeque::new(),
+            chain_authors: Vec::new(),
+            chained: None,
+            expected_refinements: 0,
+            refinements: Vec::new(),
+            reviewer: msg.options.reviewer.clone(),
+            veto: None,
+            hurry: false,
+            converged: false,
+            flagged: None,
+            code_runs: Vec::new(),
+            budget_exceeded: None,
+            deadlock: None,
+            settled_on: None,
+            chair: None,
+            ruling_asked: false,
+            guidance: None,
+            ruling: None,
+            fallbacks: BTreeMap::new(),
+            started: Instant::now(),
+            span,
+            round_span: round_span.clone(),
+            responder: Some(responder),
+        };
+        self.listeners.record(question_id, TranscriptEvent::Question { question: question.clone(), strategy });
+        self.deliberations.insert(question_id, deliberation);
+
+        // Ask the LLM actors for an answer, or the router whom to ask
+        match router.as_ref().and_then(|name| self.llm_actors.get(name)) {
+            Some(addr) => {
+                debug!("Asking {} to route question {}.", router.as_deref().unwrap_or_default(), question_id);
+                let panel = panel.into_iter()
+                    .map(|name| {
+                        let domain = self.domains.get(&name).cloned().unwrap_or_default();
+                        (name, domain)
+                    })
+                    .collect();
+                addr.do_send(RouteQuestion { question_id, span: round_span.clone(), question, panel });
+            },
+            None => self.request_drafts(question_id, drafters),
+        }
+    }
+}
+
+impl Handler<QuestionRouted> for Coordinator {
+    type Result = bool;
+
+    fn handle(&mut self, msg: QuestionRouted, _ctx: &mut Self::Context) -> Self::Result {
+        let _span = self.span(msg.question_id).entered();
+        debug!("{} routed question {} to {:?}.", msg.name, msg.question_id, msg.choice);
+        if !self.is_member(&msg.name) {
+            return false;
+        }
+        let Some(deliberation) = self.deliberations.get_mut(&msg.question_id).filter(|deliberation| deliberation.router.as_ref() == Some(&msg.name)) else {
+            debug!("Ignoring routing of question {} from {}, which is not routing it.", msg.question_id, msg.name);
+            return false;
+        };
+        deliberation.router = None;
+        self.listeners.record(msg.question_id, TranscriptEvent::Routed { router: msg.name, choice: msg.choice.clone() });
+        self.route(msg.question_id, msg.choice);
+        true
+    }
+}
+
+impl Handler<AnswerQuestion> for Coordinator {
+    type Result = bool;
+
+    fn handle(&mut self, msg: AnswerQuestion, _ctx: &mut Self::Context) -> Self::Result {
+        let _span = self.span(msg.question_id).entered();
+        debug!("Received answer to question {} from {}: {}", msg.question_id, msg.name, msg.answer);
+        if !self.is_member(&msg.name) {
+            return false;
+        }
+        let Some(deliberation) = self.deliberations.get_mut(&msg.question_id).filter(|deliberation| deliberation.stage == Stage::Drafting) else {
+            debug!("Ignoring answer from {} to question {}, which is not being drafted.", msg.name, msg.question_id);
+            return false;
+        };
+        self.listeners.record(msg.question_id, TranscriptEvent::Draft { author: msg.name.clone(), answer: msg.answer.clone() });
+        if deliberation.expected_candidates == 0 {
+            self.accept_draft(msg.question_id, msg.name, msg.answer);
+            return true;
+        }
+
+        deliberation.candidates.push(Candidate { author: msg.name, answer: msg.answer, votes: 0.0 });
+        if deliberation.candidates.len() == deliberation.expected_candidates {
+            debug!("Received all {} drafts for question {}. Asking actors to vote on them.", deliberation.candidates.len(), msg.question_id);
+            self.open_ballot(msg.question_id);
+        }
+        true
+    }
+}
+
+impl Handler<CandidateVote> for Coordinator {
+    type Result = bool;
+
+    fn handle(&mut self, msg: CandidateVote, _ctx: &mut Self::Context) -> Self::Result {
+        let _span = self.span(msg.question_id).entered();
+        debug!("{} voted for candidate {:?} for question {}. {}", msg.name, msg.choice, msg.question_id, msg.reasoning);
+        if !self.is_member(&msg.name) {
+            return false;
+        }
+        let Some(deliberation) = self.deliberations.get_mut(&msg.question_id).filter(|deliberation| deliberation.stage == Stage::Voting) else {
+            debug!("Ignoring candidate vote from {} for question {}, which has no candidates in flight.", msg.name, msg.question_id);
+            return false;
+        };
+        // The voter numbered the candidates in the order it was shown them, so its pick is mapped back to the ballot's.
+        let choice = msg.choice.and_then(|shown| deliberation.ballot_order.get(shown).copied());
+        self.listeners.record(msg.question_id, TranscriptEvent::CandidateVote { actor: msg.name.clone(), choice, reasoning: msg.reasoning });
+        deliberation.candidate_votes.insert(msg.name, choice);
+        if deliberation.candidate_votes.len() >= deliberation.panel(&self.llm_actors).count() {
+            self.conclude_vote(msg.question_id);
+        }
+        true
+    }
+}
+
+impl Handler<AnswerEvaluation> for Coordinator {
+    type Result = bool;
+
+    fn handle(&mut self, msg: AnswerEvaluation, _ctx: &mut Self::Context) -> Self::Result {
+        let _span = self.span(msg.question_id).entered();
+        debug!("{} evaluated the answer to question {} as {:?}. {}", msg.name, msg.question_id, msg.evaluation, msg.reasoning);
+        if !self.is_member(&msg.name) {
+            return false;
+        }
+        let Some(deliberation) = self.deliberations.get_mut(&msg.question_id)
+            .filter(|deliberation| deliberation.stage == Stage::Evaluating && deliberation.state.rounds.len() == msg.round + 1)
+            .filter(|deliberation| deliberation.evaluates(&msg.name)) else {
+            debug!("Ignoring evaluation from {} for round {} of question {}, which it is not evaluating.", msg.name, msg.round, msg.question_id);
+            return false;
+        };
+        self.stats.record_verdict(&msg.name, msg.evaluation);
+        self.listeners.record(msg.question_id, TranscriptEvent::Evaluation {
+            round: msg.round,
+            actor: msg.name.clone(),
+            feedback: msg.evaluation,
+            score: msg.score,
+            reasoning: msg.reasoning.clone(),
+        });
+        deliberation.state.record_evaluation(Evaluation { actor: msg.name, feedback: msg.evaluation, score: msg.score, reasoning: msg.reasoning });
+        if deliberation.state.feedback.len() < deliberation.evaluator_count(&self.llm_actors) {
+            if !(self.settings.early_decision && self.decided_early(msg.question_id)) {
+                return true;
+            }
+            self.cancel_pending_evaluations(msg.question_id);
+        }
+        self.conclude_evaluation(msg.question_id)
+    }
+}
+
+impl Handler<RefinementSuggestion> for Coordinator {
+    type Result = bool;
+
+    fn handle(&mut self, msg: RefinementSuggestion, _ctx: &mut Self::Context) -> Self::Result {
+        let _span = self.span(msg.question_id).entered();
+        debug!("{} suggested a refinement to question {}: {}", msg.name, msg.question_id, msg.suggestion);
+        if !self.is_member(&msg.name) {
+            return false;
+        }
+        let Some(deliberation) = self.deliberations.get_mut(&msg.question_id)
+            .filter(|deliberation| deliberation.stage == Stage::Refining && deliberation.expected_suggestions > 0)
+            .filter(|deliberation| deliberation.suggestions.iter().all(|(author, _)| *author != msg.name)) else {
+            debug!("Ignoring refinement suggestion from {} for question {}, which has no synthesis in flight.", msg.name, msg.question_id);
+            return false;
+        };
+        self.listeners.record(msg.question_id, TranscriptEvent::Suggestion { actor: msg.name.clone(), suggestion: msg.suggestion.clone() });
+        deliberation.suggestions.push((msg.name, msg.suggestion));
+        if deliberation.suggestions.len() < deliberation.expected_suggestions {
+            return true;
+        }
+        let synthesized = self.synthesize(msg.question_id);
+        self.proceed(msg.question_id, synthesized)
+    }
+}
+
+impl Handler<ChairDecision> for Coordinator {
+    type Result = bool;
+
+    fn handle(&mut self, msg: ChairDecision, _ctx: &mut Self::Context) -> Self::Result {
+        let _span = self.span(msg.question_id).entered();
+        if !self.is_member(&msg.name) {
+            return false;
+        }
+        let Some(deliberation) = self.deliberations.get_mut(&msg.question_id)
+            .filter(|deliberation| deliberation.stage == Stage::Chairing && deliberation.chair.as_deref() == Some(msg.name.as_str())) else {
+            debug!("Ignoring the decision of {} on question {}, which is not waiting on it.", msg.name, msg.question_id);
+            return false;
+        };
+        let latest = deliberation.state.rounds.len().saturating_sub(1);
+        if !deliberation.ruling_asked {
+            debug!("{} guided the refinement of question {}: {}", msg.name, msg.question_id, msg.text);
+            self.listeners.record(msg.question_id, TranscriptEvent::ChairGuidance { round: latest, chair: msg.name, guidance: msg.text.clone() });
+            deliberation.guidance = Some(msg.text);
+            let refined = self.request_refinement(msg.question_id);
+            return self.proceed(msg.question_id, refined);
+        }
+        // A ruling that names no version accepts the latest.
+        let round = msg.version.filter(|version| *version <= latest).unwrap_or(latest);
+        debug!("{} ruled that version {} of the answer to question {} be accepted: {}", msg.name, round, msg.question_id, msg.text);
+        deliberation.settled_on = Some(round);
+        deliberation.ruling = Some(msg.text.clone());
+        self.listeners.record(msg.question_id, TranscriptEvent::ChairRuling { round, chair: msg.name, reasoning: msg.text });
+        self.finish(msg.question_id, false);
+        true
+    }
+}
+
+impl Handler<AnswerRefinement> for Coordinator {
+    type Result = bool;
+
+    fn handle(&mut self, msg: AnswerRefinement, _ctx: &mut Self::Context) -> Self::Result {
+        let _span = self.span(msg.question_id).entered();
+        if !self.is_member(&msg.name) {
+            return false;
+        }
+        let Some(deliberation) = self.deliberations.get_mut(&msg.question_id).filter(|deliberation| deliberation.stage == Stage::Refining) else {
+            debug!("Ignoring refinement to question {}, which is not being refined.", msg.question_id);
+            return false;
+        };
+        debug!("Received new answer to question {} from {}: {}", msg.question_id, msg.name, msg.answer);
+        if deliberation.expected_refinements > 0 {
+            if deliberation.refinements.iter().any(|candidate| candidate.author == msg.name) {
+                debug!("Ignoring a second refinement to question {} from {}.", msg.question_id, msg.name);
+                return false;
+            }
+            self.listeners.record(msg.question_id, TranscriptEvent::Refinement { author: msg.name.clone(), answer: msg.answer.clone() });
+            deliberation.refinements.push(Candidate { author: msg.name, answer: msg.answer, votes: 0.0 });
+            if deliberation.refinements.len() >= deliberation.expected_refinements {
+                self.vote_on_refinements(msg.question_id);
+            }
+            return true;
+        }
+        if !deliberation.chain_authors.is_empty() || !deliberation.chain.is_empty() {
+            if deliberation.refiner.as_deref() != Some(msg.name.as_str()) {
+                debug!("Ignoring refinement to question {} from {}, whose turn it is not.", msg.question_id, msg.name);
+                return false;
+            }
+            self.listeners.record(msg.question_id, TranscriptEvent::Refinement { author: msg.name.clone(), answer: msg.answer.clone() });
+            deliberation.chained = Some(msg.answer.clone());
+            deliberation.chain_authors.push(msg.name);
+            if !deliberation.chain.is_empty() {
+                let refined = self.refine_next(msg.question_id);
+                return self.proceed(msg.question_id, refined);
+            }
+            let authors = mem::take(&mut deliberation.chain_authors);
+            self.accept_refinement(msg.question_id, authors, msg.answer);
+            return true;
+        }
+        self.listeners.record(msg.question_id, TranscriptEvent::Refinement { author: msg.name.clone(), answer: msg.answer.clone() });
+        self.accept_refinement(msg.question_id, vec![msg.name], msg.answer);
+        true
+    }
+}
+
+impl Handler<AnswerReviewed> for Coordinator {
+    type Result = bool;
+
+    fn handle(&mut self, msg: AnswerReviewed, _ctx: &mut Self::Context) -> Self::Result {
+        let _span = self.span(msg.question_id).entered();
+        let Some(deliberation) = self.deliberations.get_mut(&msg.question_id).filter(|deliberation| deliberation.stage == Stage::Reviewing) else {
+            debug!("Ignoring review of question {}, which is not being reviewed.", msg.question_id);
+            return false;
+        };
+        let reason = match msg.review {
+            Review::Approve => {
+                debug!("The reviewer approved the answer to question {}.", msg.question_id);
+                self.finish(msg.question_id, true);
+                return true;
+            },
+            Review::Reject { reason } => reason,
+        };
+        debug!("The reviewer rejected the answer to question {}: {}", msg.question_id, reason);
+        self.listeners.record(msg.question_id, TranscriptEvent::Evaluation {
+            round: deliberation.state.rounds.len().saturating_sub(1),
+            actor: REVIEWER.to_string(),
+            feedback: Feedback::NeedsRefinement,
+            score: None,
+            reasoning: reason.clone(),
+        });
+        if let Some(round) = deliberation.state.rounds.last_mut() {
+            round.evaluations.push(Evaluation { actor: REVIEWER.to_string(), feedback: Feedback::NeedsRefinement, score: None, reasoning: reason.clone() });
+        }
+        deliberation.veto = Some(reason);
+        let refined = self.request_refinement(msg.question_id);
+        self.proceed(msg.question_id, refined)
+    }
+}
+
+impl Handler<ProviderFailed> for Coordinator {
+    type Result = bool;
+
+    fn handle(&mut self, msg: ProviderFailed, _ctx: &mut Self::Context) -> Self::Result {
+        let _span = self.span(msg.question_id).entered();
+        if !self.is_member(&msg.name) {
+            return false;
+        }
+        if !self.deliberations.contains_key(&msg.question_id) {
+            debug!("Ignoring provider failure from {} for question {}, which is not in flight.", msg.name, msg.question_id);
+            return false;
+        }
+        self.fail(msg.question_id, msg.error);
+        true
+    }
+}
+
+impl Handler<ActorFailed> for Coordinator {
+    type Result = bool;
+
+    fn handle(&mut self, msg: ActorFailed, _ctx: &mut Self::Context) -> Self::Result {
+        let _span = self.span(msg.question_id).entered();
+        if !self.is_member(&msg.name) {
+            return false;
+        }
+        let Some(deliberation) = self.deliberations.get_mut(&msg.question_id) else {
+            debug!("Ignoring the restart of {} for question {}, which is not in flight.", msg.name, msg.question_id);
+            return false;
+        };
+        // The restart aborted the actor's work on the stage, so it is sent again like a stalled stage's.
+        if deliberation.redispatches < self.settings.timeouts.redispatches {
+            warn!("{} was restarted while working on question {}. Redispatching.", msg.name, msg.question_id);
+            deliberation.redispatches += 1;
+            self.redispatch(msg.question_id);
+        } else {
+            self.fail(msg.question_id, ConsensusError::ActorFailed { actor: msg.name });
+        }
+        true
+    }
+}
+
+impl Handler<UsageReport> for Coordinator {
+    type Result = bool;
+
+    fn handle(&mut self, msg: UsageReport, _ctx: &mut Self::Context) -> Self::Result {
+        let _span = self.span(msg.question_id).entered();
+        debug!("{} used {:?} tokens for question {}.", msg.name, msg.usage, msg.question_id);
+        if let Some(backend) = msg.backend {
+            debug!("{}'s provider failed, so {} answered for it.", msg.name, backend);
+            if let Some(deliberation) = self.deliberations.get_mut(&msg.question_id) {
+                deliberation.fallbacks.insert(msg.name.clone(), backend);
+            }
+        }
+        // Calls that finish after their question is over still count toward the session.
+        let question_id = self.deliberations.contains_key(&msg.question_id).then_some(msg.question_id);
+        self.usage.record(question_id, &msg.name, msg.usage.as_ref(), msg.evaluation);
+        self.stats.record_call(&msg.name, msg.template, msg.latency);
+        true
+    }
+}
+
+impl Handler<UseTools> for Coordinator {
+    type Result = MessageResult<UseTools>;
+
+    /// Runs each call to a tool the actor may use, recording it in the question's transcript. A call to any other
+    /// tool, or one that fails, returns why, so that the model can call it again or do without it.
+    fn handle(&mut self, msg: UseTools, _ctx: &mut Self::Context) -> Self::Result {
+        let _span = self.span(msg.question_id).entered();
+        let allowed = self.tools.get(&msg.name).map(Vec::as_slice).unwrap_or_default();
+        let mut outputs = Vec::with_capacity(msg.calls.len());
+        for call in msg.calls {
+            let content = match Tool::from_name(&call.name).filter(|tool| allowed.contains(tool)) {
+                Some(tool) => tool.run(&call.arguments).unwrap_or_else(|e| format!("Error: {}", e)),
+                None => format!("Error: {} is not one of your tools.", call.name),
+            };
+            debug!("{} called {} with {} for question {}: {}", msg.name, call.name, call.arguments, msg.question_id, content);
+            if self.deliberations.contains_key(&msg.question_id) {
+                let event = TranscriptEvent::ToolUsed { actor: msg.name.clone(), tool: call.name.clone(), arguments: call.arguments, output: content.clone() };
+                self.listeners.record(msg.question_id, event);
+            }
+            outputs.push(ToolOutput { id: call.id, name: call.name, content });
+        }
+        MessageResult(outputs)
+    }
+}
+
+impl Handler<GetAnswerHistory> for Coordinator {
+    type Result = MessageResult<GetAnswerHistory>;
+
+    fn handle(&mut self, msg: GetAnswerHistory, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult(self.deliberations.get(&msg.question_id).map(|deliberation| deliberation.state.rounds.clone()))
+    }
+}
+
+impl Handler<GetUsage> for Coordinator {
+    type Result = MessageResult<GetUsage>;
+
+    fn handle(&mut self, _msg: GetUsage, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult(self.usage.session().clone())
+    }
+}
+
+impl Handler<GetActorStats> for Coordinator {
+    type Result = MessageResult<GetActorStats>;
+
+    fn handle(&mut self, _msg: GetActorStats, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult(self.stats.actors().clone())
+    }
+}
+
+impl Handler<Configure> for Coordinator {
+    type Result = bool;
+
+    fn handle(&mut self, msg: Configure, _ctx: &mut Self::Context) -> Self::Result {
+        self.configure(msg.0);
+        // A higher limit makes room for queued questions.
+        self.take_up_queued();
+        true
+    }
+}
+
+impl Handler<NumberQuestionsAfter> for Coordinator {
+    type Result = ();
+
+    fn handle(&mut self, msg: NumberQuestionsAfter, _ctx: &mut Self::Context) -> Self::Result {
+        self.last_question_id = self.last_question_id.max(msg.0);
+    }
+}
+
+impl Handler<DetectConvergence> for Coordinator {
+    type Result = bool;
+
+    fn handle(&mut self, msg: DetectConvergence, _ctx: &mut Self::Context) -> Self::Result {
+        debug!("Convergence detection {}.", if msg.0.is_some() { "enabled" } else { "disabled" });
+        self.convergence = msg.0;
+        true
+    }
+}
+
+impl Handler<ModerateContent> for Coordinator {
+    type Result = bool;
+
+    fn handle(&mut self, msg: ModerateContent, _ctx: &mut Self::Context) -> Self::Result {
+        debug!("Moderation {}.", if msg.0.is_some() { "enabled" } else { "disabled" });
+        self.moderation = msg.0;
+        true
+    }
+}
+
+impl Handler<ContentScreened> for Coordinator {
+    type Result = bool;
+
+    fn handle(&mut self, msg: ContentScreened, _ctx: &mut Self::Context) -> Self::Result {
+        let _span = self.span(msg.question_id).entered();
+        // A redispatched screening can report back twice.
+        let Some(deliberation) = self.deliberations.get_mut(&msg.question_id)
+            .filter(|deliberation| deliberation.stage == Stage::Moderating && deliberation.state.rounds.len() == msg.round + 1) else {
+            debug!("Ignoring the screening of question {}, which is not being moderated.", msg.question_id);
+            return false;
+        };
+        match msg.verdict {
+            Ok(None) => self.test_code(msg.question_id),
+            Ok(Some(reason)) => {
+                debug!("The moderator flagged question {}: {}", msg.question_id, reason);
+                // The flagged version is left out of the result, along with any drafts it was chosen from.
+                deliberation.state.rounds.pop();
+                deliberation.state.authors.pop();
+                deliberation.candidates.clear();
+                deliberation.flagged = Some(reason.clone());
+                self.listeners.record(msg.question_id, TranscriptEvent::Flagged { round: msg.round, reason });
+                self.finish(msg.question_id, false);
+            },
+            Err(error) => self.fail(msg.question_id, ConsensusError::Provider { actor: MODERATOR.to_string(), call: "Moderate", error }),
+        }
+        true
+    }
+}
+
+impl Handler<TestCode> for Coordinator {
+    type Result = bool;
+
+    fn handle(&mut self, msg: TestCode, _ctx: &mut Self::Context) -> Self::Result {
+        debug!("Running answers' code {}.", if msg.0.is_some() { "enabled" } else { "disabled" });
+        self.sandbox = msg.0;
+        true
+    }
+}
+
+impl Handler<CodeTested> for Coordinator {
+    type Result = bool;
+
+    fn handle(&mut self, msg: CodeTested, _ctx: &mut Self::Context) -> Self::Result {
+        let _span = self.span(msg.question_id).entered();
+        // A redispatched run can report back twice.
+        let Some(deliberation) = self.deliberations.get_mut(&msg.question_id)
+            .filter(|deliberation| deliberation.stage == Stage::Testing && deliberation.state.rounds.len() == msg.round + 1) else {
+            debug!("Ignoring the code run for question {}, which is not being tested.", msg.question_id);
+            return false;
+        };
+        deliberation.code_runs = msg.runs.clone();
+        self.listeners.record(msg.question_id, TranscriptEvent::CodeRan { round: msg.round, runs: msg.runs });
+        self.request_evaluation(msg.question_id);
+        true
+    }
+}
+
+impl Handler<AnswersCompared> for Coordinator {
+    type Result = bool;
+
+    fn handle(&mut self, msg: AnswersCompared, _ctx: &mut Self::Context) -> Self::Result {
+        let _span = self.span(msg.question_id).entered();
+        debug!("Version {} of the answer to question {} is {:.3} similar to the one before it.", msg.round, msg.question_id, msg.similarity);
+        let Some(convergence) = &self.convergence else { return false };
+        if !convergence.converged(msg.similarity) {
+            return false;
+        }
+        // The panel may have moved on while the versions were compared.
+        let Some(deliberation) = self.deliberations.get_mut(&msg.question_id)
+            .filter(|deliberation| deliberation.stage == Stage::Evaluating && deliberation.state.rounds.len() == msg.round + 1) else {
+            return false;
+        };
+        debug!("The refinements of question {} have converged. Stopping without consensus.", msg.question_id);
+        // The evaluations of the converged version are dropped unfinished, so that its result reflects the
+        // last complete round.
+        if let Some(round) = deliberation.state.rounds.last_mut() {
+            round.evaluations.clear();
+        }
+        deliberation.converged = true;
+        self.listeners.record(msg.question_id, TranscriptEvent::Converged { round: msg.round, similarity: msg.similarity });
+        self.llm_actors.values().for_each(|addr| addr.do_send(CancelCalls { question_id: Some(msg.question_id) }));
+        self.finish(msg.question_id, false);
+        true
+    }
+}
+
+impl Handler<RecordTranscript> for Coordinator {
+    type Result = bool;
+
+    fn handle(&mut self, msg: RecordTranscript, _ctx: &mut Self::Context) -> Self::Result {
+        debug!("Transcript recording {}.", if msg.0.is_some() { "started" } else { "stopped" });
+        self.listeners.transcript = msg.0;
+        true
+    }
+}
+
+impl Handler<RecordHistory> for Coordinator {
+    type Result = bool;
+
+    fn handle(&mut self, msg: RecordHistory, _ctx: &mut Self::Context) -> Self::Result {
+        debug!("History recording {}.", if msg.0.is_some() { "started" } else { "stopped" });
+        self.listeners.history = msg.0;
+        true
+    }
+}
+
+impl Handler<Subscribe> for Coordinator {
+    type Result = bool;
+
+    fn handle(&mut self, msg: Subscribe, _ctx: &mut Self::Context) -> Self::Result {
+        self.listeners.subscribers.push(msg.0);
+        debug!("{} subscribers to deliberation updates.", self.listeners.subscribers.len());
+        true
+    }
+}
+
+impl Handler<Observe> for Coordinator {
+    type Result = bool;
+
+    fn handle(&mut self, msg: Observe, _ctx: &mut Self::Context) -> Self::Result {
+        self.listeners.observers.push(msg.0);
+        debug!("{} observers of deliberations.", self.listeners.observers.len());
+        true
+    }
+}
+
+impl Handler<SubscribeTokens> for Coordinator {
+    type Result = bool;
+
+    fn handle(&mut self, msg: SubscribeTokens, _ctx: &mut Self::Context) -> Self::Result {
+        self.listeners.token_subscribers.push(msg.0);
+        true
+    }
+}
+
+impl Handler<AnswerToken> for Coordinator {
+    type Result = ();
+
+    fn handle(&mut self, msg: AnswerToken, _ctx: &mut Self::Context) -> Self::Result {
+        let Some(deliberation) = self.deliberations.get(&msg.question_id) else { return };
+        // Only the answer the panel is waiting on is passed on: not best-of-N drafts, which are written
+        // side by side, nor a response to a stage that has since moved on.
+        let current = deliberation.stage == msg.stage && match msg.stage {
+            Stage::Drafting => deliberation.expected_candidates == 0,
+            Stage::Refining => deliberation.refiner.as_deref() == Some(msg.name.as_str()),
+            Stage::Voting | Stage::Moderating | Stage::Testing | Stage::Evaluating | Stage::Chairing | Stage::Reviewing => false,
+        };
+        if !current {
+            return;
+        }
+        let subscribers = &mut self.listeners.token_subscribers;
+        subscribers.retain(|subscriber| subscriber.connected());
+        subscribers.iter().for_each(|subscriber| subscriber.do_send(msg.clone()));
+    }
+}
+
+impl Handler<Reset> for Coordinator {
+    type Result = bool;
+
+    fn handle(&mut self, _msg: Reset, _ctx: &mut Self::Context) -> Self::Result {
+        // Dropping each responder resolves its AskQuestion with AskError::Abandoned.
+        self.queue.clear();
+        for question_id in self.deliberations.drain().map(|(question_id, _)| question_id).collect::<Vec<_>>() {
+            self.usage.finish(question_id);
+            self.listeners.record(question_id, TranscriptEvent::Abandoned);
+        }
+        true
+    }
+}
+
+impl Handler<CancelQuestion> for Coordinator {
+    type Result = usize;
+
+    fn handle(&mut self, msg: CancelQuestion, _ctx: &mut Self::Context) -> Self::Result {
+        let question_ids = match msg.question_id {
+            Some(question_id) => vec![question_id],
+            None => self.deliberations.keys().copied().collect(),
+        };
+        // The queue is cleared first, so that cancelling the questions in flight does not take up the next.
+        let queued = if msg.question_id.is_none() { self.clear_queue(|| AskError::Cancelled) } else { 0 };
+        queued + question_ids.into_iter().filter(|question_id| self.cancel(*question_id)).count()
+    }
+}
+
+impl Handler<Shutdown> for Coordinator {
+    type Result = ResponseFuture<()>;
+
+    fn handle(&mut self, _msg: Shutdown, _ctx: &mut Self::Context) -> Self::Result {
+        self.clear_queue(|| AskError::Cancelled);
+        for question_id in self.deliberations.keys().copied().collect::<Vec<_>>() {
+            self.cancel(question_id);
+        }
+        // Calls can outlive their questions, such as a retry of an evaluation that has already been decided.
+        self.llm_actors.values().for_each(|addr| addr.do_send(CancelCalls { question_id: None }));
+        if let Some(transcript) = self.listeners.transcript.as_mut() {
+            transcript.flush();
+        }
+        let history = self.listeners.history.clone();
+        Box::pin(async move {
+            if let Some(history) = history {
+                let _ = history.send(FlushHistory).await;
+            }
+        })
+    }
+}
+
+impl actix::Supervised for Coordinator {}
+impl SystemService for Coordinator {}
+
+/// The answer as the actors judging it are shown it: under blind evaluation, without hints that the `authors`
+/// wrote it.
+fn shown(answer: String, authors: &[String], blind: bool, domains: &HashMap<String, String>) -> String {
+    match blind {
+        true => authors.iter().fold(answer, |answer, author| {
+            blind::strip_authorship(&answer, author, domains.get(author).map_or("", String::as_str))
+        }),
+        false => answer,
+    }
+}
@@ -0,0 +1,227 @@
+//! Noticing when the panel is going in circles, and breaking the tie instead of spending the rest of the rounds.
+//!
+//! A panel deadlocks in one of two ways: an evaluator keeps changing its mind, calling one version of the answer
+//! Good and the next NeedsRefinement and back again, or the refinements keep undoing each other, so that the
+//! answer comes back to a version the panel has already been through. The second is noticed by comparing the
+//! [simhash] of each version's wording, which differs in only a few bits between answers that say nearly the same
+//! thing. Once either happens, the configured [TieBreak] picks the version to accept without consensus.
+
+use std::{collections::{BTreeMap, HashMap}, hash::{DefaultHasher, Hash, Hasher}};
+
+use serde::{Deserialize, Serialize};
+
+use crate::result::{Feedback, Round};
+
+/// How many words each shingle hashed into a [simhash] spans.
+const SHINGLE: usize = 3;
+
+/// The `[deadlock]` section of the config file. Without it, the panel refines the answer until it agrees or runs
+/// out of rounds, however it goes.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DeadlockSettings {
+    /// How many times one evaluator must change its verdict across the rounds for the panel to be deadlocked.
+    pub flips: u32,
+    /// How many of the 64 bits of two versions' [simhash]es may differ for the answer to have come back to the
+    /// earlier one.
+    pub max_distance: u32,
+    pub tie_break: TieBreak,
+}
+
+impl Default for DeadlockSettings {
+    fn default() -> Self {
+        DeadlockSettings { flips: 2, max_distance: 3, tie_break: TieBreak::default() }
+    }
+}
+
+/// How a deadlocked panel picks the version of the answer to accept.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TieBreak {
+    /// The actor moderates: the latest version it judged Good is accepted, or the latest version if it judged
+    /// none of them Good.
+    Moderator { actor: String },
+    /// The version with the largest weighted share of Good verdicts is accepted, the later one on a tie.
+    Weighted,
+    /// The panel's chair rules on which version to accept, as it does in the final round. Should the chair have
+    /// left the panel, the latest version is accepted.
+    Chair,
+    /// The latest version is accepted, with the dissent of the actors that still objected to it.
+    #[default]
+    AcceptWithDissent,
+}
+
+impl DeadlockSettings {
+    /// Checks the settings, returning a description of the problem if they are unusable.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.flips == 0 {
+            return Err("deadlock.flips must be at least 1".to_string());
+        }
+        if self.max_distance >= 32 {
+            return Err("deadlock.max_distance must be less than 32, or unrelated answers would match".to_string());
+        }
+        match &self.tie_break {
+            TieBreak::Moderator { actor } if actor.trim().is_empty() => Err("the deadlock moderator must name an actor".to_string()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Why the panel is deadlocked, now that it has evaluated the latest of `rounds`, if it is.
+    pub fn detect(&self, rounds: &[Round]) -> Option<String> {
+        flip_flop(rounds, self.flips).or_else(|| cycle(rounds, self.max_distance))
+    }
+
+    /// The version of the answer, starting at 0 for the first draft, that the tie-break accepts. Verdicts are
+    /// weighted by `weights` under [TieBreak::Weighted], counting 1 for actors without one.
+    pub fn break_tie(&self, rounds: &[Round], weights: &HashMap<String, f64>) -> usize {
+        let latest = rounds.len().saturating_sub(1);
+        match &self.tie_break {
+            TieBreak::Moderator { actor } => rounds.iter()
+                .rposition(|round| round.evaluations.iter().any(|evaluation| evaluation.actor == *actor && evaluation.feedback == Feedback::Good))
+                .unwrap_or(latest),
+            TieBreak::Weighted => {
+                let mut best: Option<(usize, f64)> = None;
+                for (index, round) in rounds.iter().enumerate() {
+                    let (mut good, mut total) = (0.0, 0.0);
+                    for evaluation in round.evaluations.iter().filter(|evaluation| evaluation.feedback != Feedback::Abstain) {
+                        let weight = weights.get(&evaluation.actor).copied().unwrap_or(1.0);
+                        total += weight;
+                        if evaluation.feedback == Feedback::Good {
+                            good += weight;
+                        }
+                    }
+                    if total > 0.0 && best.is_none_or(|(_, share)| good / total >= share) {
+                        best = Some((index, good / total));
+                    }
+                }
+                best.map_or(latest, |(index, _)| index)
+            },
+            TieBreak::Chair | TieBreak::AcceptWithDissent => latest,
+        }
+    }
+}
+
+/// Describes the first evaluator to have changed its verdict `limit` times, leaving out abstentions, if any has.
+fn flip_flop(rounds: &[Round], limit: u32) -> Option<String> {
+    let mut verdicts: BTreeMap<&str, (Feedback, u32)> = BTreeMap::new();
+    for evaluation in rounds.iter().flat_map(|round| &round.evaluations).filter(|evaluation| evaluation.feedback != Feedback::Abstain) {
+        let (last, flips) = verdicts.entry(&evaluation.actor).or_insert((evaluation.feedback, 0));
+        if *last != evaluation.feedback {
+            *last = evaluation.feedback;
+            *flips += 1;
+        }
+    }
+    verdicts.into_iter()
+        .find(|(_, (_, flips))| *flips >= limit)
+        .map(|(actor, (_, flips))| format!("{} changed its verdict {} times", actor, flips))
+}
+
+/// Describes how the latest version of the answer came back to one from at least two rounds before, if it did.
+/// The version just before it is left to convergence detection.
+fn cycle(rounds: &[Round], max_distance: u32) -> Option<String> {
+    let (latest, earlier) = rounds.split_last()?;
+    let hash = simhash(&latest.answer);
+    earlier[..earlier.len().saturating_sub(1)].iter()
+        .rposition(|round| (simhash(&round.answer) ^ hash).count_ones() <= max_distance)
+        .map(|round| format!("version {} of the answer came back to version {}", rounds.len() - 1, round))
+}
+
+/// A 64-bit fingerprint of the text's wording, from the overlapping runs of [SHINGLE] words in it, ignoring case
+/// and punctuation. Texts that share most of their wording differ in few bits.
+pub fn simhash(text: &str) -> u64 {
+    let words: Vec<String> = text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    let mut bits = [0i64; 64];
+    for shingle in words.windows(SHINGLE.min(words.len()).max(1)) {
+        let mut hasher = DefaultHasher::new();
+        shingle.hash(&mut hasher);
+        let hash = hasher.finish();
+        for (bit, count) in bits.iter_mut().enumerate() {
+            *count += if hash >> bit & 1 == 1 { 1 } else { -1 };
+        }
+    }
+    bits.iter().enumerate().filter(|(_, count)| **count > 0).fold(0, |hash, (bit, _)| hash | 1 << bit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::result::Evaluation;
+
+    fn evaluation(actor: &str, feedback: Feedback) -> Evaluation {
+        Evaluation { actor: actor.to_string(), feedback, score: None, reasoning: String::new() }
+    }
+
+    fn round(answer: &str, evaluations: Vec<Evaluation>) -> Round {
+        Round { author: "author".to_string(), answer: answer.to_string(), evaluations }
+    }
+
+    /// Versions of the answer, each with a single evaluation from `critic` that needs it refined.
+    fn versions(answers: &[&str]) -> Vec<Round> {
+        answers.iter().map(|answer| round(answer, vec![evaluation("critic", Feedback::NeedsRefinement)])).collect()
+    }
+
+    const FIRST: &str = "The borrow checker rejects two mutable references to the same value at once.";
+    const SECOND: &str = "Rust allows only one mutable reference to a value in a scope, which prevents data races.";
+    const THIRD: &str = "Lifetimes let the compiler prove that every reference outlives none of the data it points to.";
+
+    #[test]
+    fn verdict_flip_flop_deadlocks_once_it_reaches_flips() {
+        let flips = [Feedback::Good, Feedback::NeedsRefinement, Feedback::Abstain, Feedback::Good];
+        let rounds: Vec<Round> = [FIRST, SECOND, THIRD, FIRST].iter().zip(flips)
+            .map(|(answer, feedback)| round(answer, vec![evaluation("critic", feedback)]))
+            .collect();
+        assert_eq!(flip_flop(&rounds[..3], 2), None);
+        assert_eq!(flip_flop(&rounds, 2), Some("critic changed its verdict 2 times".to_string()));
+        assert_eq!(flip_flop(&rounds, 3), None);
+    }
+
+    #[test]
+    fn answer_coming_back_two_versions_later_is_a_cycle() {
+        let rounds = versions(&[FIRST, SECOND, &FIRST.to_uppercase()]);
+        assert_eq!(cycle(&rounds, 3), Some("version 2 of the answer came back to version 0".to_string()));
+        assert_eq!(cycle(&versions(&[FIRST, SECOND, THIRD]), 3), None);
+    }
+
+    #[test]
+    fn answer_coming_back_to_the_version_before_is_left_to_convergence() {
+        assert_eq!(cycle(&versions(&[SECOND, FIRST, FIRST]), 3), None);
+        let settings = DeadlockSettings::default();
+        assert_eq!(settings.detect(&versions(&[SECOND, FIRST, FIRST])), None);
+    }
+
+    /// Three versions: the moderator approves the first, the second wins the weighted share, and the third ties it.
+    fn contested() -> Vec<Round> {
+        vec![
+            round(FIRST, vec![evaluation("moderator", Feedback::Good), evaluation("heavy", Feedback::NeedsRefinement)]),
+            round(SECOND, vec![evaluation("moderator", Feedback::NeedsRefinement), evaluation("heavy", Feedback::Good)]),
+            round(THIRD, vec![evaluation("moderator", Feedback::NeedsRefinement), evaluation("heavy", Feedback::Good), evaluation("other", Feedback::Abstain)]),
+        ]
+    }
+
+    fn break_tie(tie_break: TieBreak, rounds: &[Round]) -> usize {
+        let weights = HashMap::from([("heavy".to_string(), 3.0)]);
+        DeadlockSettings { tie_break, ..DeadlockSettings::default() }.break_tie(rounds, &weights)
+    }
+
+    #[test]
+    fn moderator_picks_the_last_version_it_approved() {
+        let moderator = TieBreak::Moderator { actor: "moderator".to_string() };
+        assert_eq!(break_tie(moderator.clone(), &contested()), 0);
+        assert_eq!(break_tie(moderator, &versions(&[FIRST, SECOND])), 1);
+    }
+
+    #[test]
+    fn weighted_picks_the_largest_good_share_and_the_later_on_a_tie() {
+        assert_eq!(break_tie(TieBreak::Weighted, &contested()), 2);
+        assert_eq!(break_tie(TieBreak::Weighted, &contested()[..2]), 1);
+    }
+
+    #[test]
+    fn accept_with_dissent_picks_the_latest() {
+        assert_eq!(break_tie(TieBreak::AcceptWithDissent, &contested()), 2);
+        assert_eq!(break_tie(TieBreak::Chair, &contested()), 2);
+    }
+}
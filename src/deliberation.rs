@@ -0,0 +1,279 @@
+//! The draft, evaluate and refine state machine every question moves through, shared by the
+//! [Coordinator](crate::Coordinator), which drives it with actor messages, and the [Engine](crate::engine::Engine),
+//! which drives it with plain futures.
+//!
+//! A [DeliberationState] keeps the versions of the answer and the panel's verdicts on them, and decides who evaluates
+//! each version, whether the panel agrees on it and who refines it next. It calls no provider and waits on nothing,
+//! so whoever drives it is free to run the calls it asks for however its runtime does.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    config::ActorRole,
+    result::{Evaluation, Feedback, Round},
+    selection::Selector,
+    strategy::{self, ConsensusSettings, ConsensusStrategy, ExcludeAuthor, Vote},
+};
+
+/// What the state machine needs to know about the actors on the panel.
+pub(crate) trait Members {
+    /// How much the actor's vote counts under weighted strategies.
+    fn weight(&self, name: &str) -> f64;
+
+    /// The part the actor plays on the panel.
+    fn role(&self, name: &str) -> ActorRole;
+}
+
+/// How a complete round of evaluations ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Conclusion {
+    /// The panel agrees on the answer under the question's strategy.
+    Reached,
+    /// Every evaluator abstained, so refining the answer would not bring anyone closer to judging it.
+    Abstained,
+    /// The panel does not agree on the answer yet.
+    Dissent,
+}
+
+/// Where one question stands: every version of its answer so far, and how the panel is evaluating the latest.
+pub(crate) struct DeliberationState {
+    /// The strategy that applies to the question.
+    pub strategy: ConsensusStrategy,
+    /// How many times the panel evaluates the answer before accepting it without consensus.
+    pub max_rounds: u32,
+    /// How many times the panel has been asked to evaluate the answer.
+    pub evaluation_count: u32,
+    /// Every version of the answer, with its evaluations.
+    pub rounds: Vec<Round>,
+    /// The actors who wrote each version of the answer, in the order of the rounds: its drafter or refiner, or each
+    /// refiner in turn when rewrites are chained.
+    pub authors: Vec<Vec<String>>,
+    /// The authors of the version being evaluated, when authors are left out of evaluating their own answers.
+    pub excluded: Vec<String>,
+    /// The verdict of each actor that has evaluated the current version of the answer.
+    pub feedback: HashMap<String, Feedback>,
+    /// Scores from the current round, when the strategy scores answers.
+    pub scores: HashMap<String, u8>,
+    /// The actors picked so far to rewrite the answer alone or synthesize it, so that the dissenters take turns.
+    pub refined_by: HashSet<String>,
+    exclude_author: ExcludeAuthor,
+    /// How many versions of the answer a devil's advocate can hold back.
+    blocking_rounds: u32,
+}
+
+impl DeliberationState {
+    /// The state of a question that has yet to be drafted, deliberated on under the settings it was asked with.
+    pub fn new(settings: &ConsensusSettings, strategy: ConsensusStrategy, max_rounds: u32) -> Self {
+        DeliberationState {
+            strategy,
+            max_rounds,
+            evaluation_count: 0,
+            rounds: Vec::new(),
+            authors: Vec::new(),
+            excluded: Vec::new(),
+            feedback: HashMap::new(),
+            scores: HashMap::new(),
+            refined_by: HashSet::new(),
+            exclude_author: settings.exclude_author.clone(),
+            blocking_rounds: settings.devils_advocate.blocking_rounds,
+        }
+    }
+
+    /// Takes the draft forward as the first version of the answer.
+    pub fn accept_draft(&mut self, author: String, answer: String) {
+        self.authors.push(vec![author.clone()]);
+        self.rounds.push(Round { author, answer, evaluations: Vec::new() });
+    }
+
+    /// Takes a refined answer, rewritten by the `authors` in turn, forward as the next version. Returns whether the
+    /// panel evaluates it, which it does unless it has evaluated the maximum number of times.
+    pub fn accept_refinement(&mut self, authors: Vec<String>, answer: String) -> bool {
+        let author = authors.join(", ");
+        self.authors.push(authors);
+        self.rounds.push(Round { author, answer, evaluations: Vec::new() });
+        self.feedback.clear();
+        self.scores.clear();
+        self.rounds_left()
+    }
+
+    /// Whether the panel has evaluated the answer fewer than the maximum number of times.
+    pub fn rounds_left(&self) -> bool {
+        self.evaluation_count < self.max_rounds
+    }
+
+    /// The actors who wrote the latest version of the answer.
+    pub fn latest_authors(&self) -> &[String] {
+        self.authors.last().map_or(&[], Vec::as_slice)
+    }
+
+    /// Starts a round of evaluations of the latest version of the answer by the `panel`, the actors deliberating on
+    /// the question, and returns those that evaluate it. Its authors sit out if `exclude_author` applies to the
+    /// question's strategy, as long as someone else whose vote counts is left to evaluate it.
+    pub fn start_evaluation(&mut self, members: &impl Members, panel: &[String]) -> Vec<String> {
+        self.evaluation_count += 1;
+        let authors: Vec<String> = self.latest_authors().iter().filter(|author| panel.contains(*author)).cloned().collect();
+        let others_count = panel.iter().any(|name| !authors.contains(name) && self.counts(members, name));
+        self.excluded = match self.exclude_author.applies_to(&self.strategy) && others_count {
+            true => authors,
+            false => Vec::new(),
+        };
+        panel.iter().filter(|name| self.evaluates(name)).cloned().collect()
+    }
+
+    /// Whether the actor, if it deliberates on the question, evaluates the current version of the answer.
+    pub fn evaluates(&self, name: &str) -> bool {
+        !self.excluded.iter().any(|author| author == name)
+    }
+
+    /// Records an evaluation of the latest version of the answer.
+    pub fn record_evaluation(&mut self, evaluation: Evaluation) {
+        self.feedback.insert(evaluation.actor.clone(), evaluation.feedback);
+        if let Some(score) = evaluation.score {
+            self.scores.insert(evaluation.actor.clone(), score);
+        }
+        if let Some(round) = self.rounds.last_mut() {
+            round.evaluations.push(evaluation);
+        }
+    }
+
+    /// Forgets the actor's verdict on the current version of the answer, once it has left the panel.
+    #[cfg(feature = "actix")]
+    pub fn forget_verdict(&mut self, name: &str) {
+        self.feedback.remove(name);
+        self.scores.remove(name);
+    }
+
+    /// The evaluations of the current version of the answer so far, as the strategy counts them.
+    pub fn votes(&self, members: &impl Members) -> Vec<Vote> {
+        self.feedback.iter()
+            .filter(|(name, _)| self.counts(members, name))
+            .map(|(name, feedback)| Vote { feedback: *feedback, weight: members.weight(name), score: self.scores.get(name).copied() })
+            .collect()
+    }
+
+    /// Whether the actor's vote counts towards consensus on the current version of the answer. A devil's advocate
+    /// only blocks consensus on the first versions; after that its objections are still refined on when the rest
+    /// of the panel dissents, but no longer hold the answer back.
+    pub fn counts(&self, members: &impl Members, name: &str) -> bool {
+        let advisory = self.rounds.len() > self.blocking_rounds as usize;
+        !(advisory && members.role(name) == ActorRole::DevilsAdvocate)
+    }
+
+    /// Whether the evaluations in already decide the round, however those of the `pending` evaluators go, so that
+    /// under `early_decision` the panel need not wait for them.
+    pub fn decided_early<'a>(&self, members: &impl Members, pending: impl IntoIterator<Item = &'a String>) -> bool {
+        let pending: Vec<f64> = pending.into_iter()
+            .filter(|name| self.counts(members, name))
+            .map(|name| members.weight(name))
+            .collect();
+        self.strategy.decided(&self.votes(members), &pending).is_some()
+    }
+
+    /// Decides a complete round of evaluations.
+    pub fn conclude(&self, members: &impl Members) -> Conclusion {
+        let votes = self.votes(members);
+        if self.strategy.is_reached(&votes) {
+            Conclusion::Reached
+        } else if strategy::all_abstained(&votes) {
+            Conclusion::Abstained
+        } else {
+            Conclusion::Dissent
+        }
+    }
+
+    /// The actors that voted NeedsRefinement in the current round.
+    pub fn dissenters(&self) -> Vec<String> {
+        self.feedback.iter()
+            .filter(|(_, feedback)| **feedback == Feedback::NeedsRefinement)
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    /// The actors that may rewrite the answer alone: the dissenters, or the whole `panel` if nobody on it dissented,
+    /// such as when only a reviewer objected.
+    pub fn eligible_refiners(&self, panel: Vec<String>) -> Vec<String> {
+        match self.dissenters() {
+            dissenters if dissenters.is_empty() => panel,
+            dissenters => dissenters,
+        }
+    }
+
+    /// The eligible actors that have not yet been picked to refine the answer, or all of them once every one has
+    /// had a turn.
+    pub fn due_to_refine(&self, eligible: Vec<String>) -> Vec<String> {
+        let due: Vec<String> = eligible.iter().filter(|name| !self.refined_by.contains(*name)).cloned().collect();
+        if due.is_empty() { eligible } else { due }
+    }
+
+    /// Picks the actor among the eligible ones that refines the answer, giving those who have not yet had a turn
+    /// theirs first.
+    pub fn pick_refiner(&mut self, selector: &mut Selector, eligible: Vec<String>) -> Option<String> {
+        let refiner = selector.select_one(&self.due_to_refine(eligible))?;
+        self.refined_by.insert(refiner.clone());
+        Some(refiner)
+    }
+
+    /// The reasoning the actor gave in its evaluation of the latest version of the answer.
+    pub fn reasoning_of(&self, name: &str) -> String {
+        self.rounds.last()
+            .and_then(|round| round.evaluations.iter().find(|evaluation| evaluation.actor == name))
+            .map(|evaluation| evaluation.reasoning.clone())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A panel of members and a devil's advocate, all of equal weight.
+    struct Panel;
+
+    impl Members for Panel {
+        fn weight(&self, _: &str) -> f64 {
+            1.0
+        }
+
+        fn role(&self, name: &str) -> ActorRole {
+            if name == "advocate" { ActorRole::DevilsAdvocate } else { ActorRole::Member }
+        }
+    }
+
+    /// A unanimous deliberation on an answer the member refined, in which the devil's advocate can no longer hold
+    /// the answer back.
+    fn refined() -> DeliberationState {
+        let settings = ConsensusSettings { exclude_author: ExcludeAuthor::Always(true), ..Default::default() };
+        let mut state = DeliberationState::new(&settings, ConsensusStrategy::Unanimous, 3);
+        state.accept_draft("member".to_string(), "draft".to_string());
+        state.accept_refinement(vec!["member".to_string()], "refined".to_string());
+        state
+    }
+
+    fn verdict(actor: &str, feedback: Feedback) -> Evaluation {
+        Evaluation { actor: actor.to_string(), feedback, score: None, reasoning: String::new() }
+    }
+
+    #[test]
+    fn author_evaluates_when_only_advisory_verdicts_are_left() {
+        let panel = ["member".to_string(), "advocate".to_string()];
+
+        let mut state = refined();
+        assert_eq!(state.start_evaluation(&Panel, &panel), panel);
+        assert!(state.excluded.is_empty());
+        state.record_evaluation(verdict("advocate", Feedback::NeedsRefinement));
+        assert!(!state.decided_early(&Panel, &panel[..1]));
+        state.record_evaluation(verdict("member", Feedback::Good));
+        assert_eq!(state.conclude(&Panel), Conclusion::Reached);
+    }
+
+    #[test]
+    fn author_sits_out_when_a_counted_vote_is_left() {
+        let panel = ["member".to_string(), "advocate".to_string(), "other".to_string()];
+
+        let mut state = refined();
+        let mut evaluators = state.start_evaluation(&Panel, &panel);
+        evaluators.sort();
+        assert_eq!(evaluators, ["advocate", "other"]);
+        assert_eq!(state.excluded, ["member"]);
+    }
+}
@@ -0,0 +1,180 @@
+//! Word-level differences between versions of an answer, so that each refinement can be shown as what it
+//! changed rather than as a whole new answer.
+
+use serde::{Deserialize, Serialize};
+
+/// The most cells the table of common subsequences may have, about 4 MB of it. A rewrite whose differing middle
+/// would need more is shown as that middle removed and added whole.
+const MAX_TABLE_CELLS: usize = 1_000_000;
+
+/// A run of text that a later version of an answer kept from the one before it, removed or added.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Change {
+    Kept(String),
+    Removed(String),
+    Added(String),
+}
+
+impl Change {
+    pub fn text(&self) -> &str {
+        match self {
+            Change::Kept(text) | Change::Removed(text) | Change::Added(text) => text,
+        }
+    }
+}
+
+/// The changes that turn `before` into `after`, word by word. Each word keeps the whitespace after it, and words
+/// differing only in that whitespace count as kept, with the spacing of `after`. Concatenating the kept and added
+/// text gives `after` back, and the kept and removed text gives `before` up to its spacing. A rewrite too long to
+/// compare word by word is shown as removed and added whole, past the words it starts and ends with unchanged.
+pub fn diff_words(before: &str, after: &str) -> Vec<Change> {
+    let (old, new) = (words(before), words(after));
+    // Refinements usually leave most of the answer alone, so the common ends are set aside before the
+    // quadratic part.
+    let prefix = old.iter().zip(&new).take_while(|(old, new)| same(old, new)).count();
+    let suffix = old[prefix..].iter().rev().zip(new[prefix..].iter().rev()).take_while(|(old, new)| same(old, new)).count();
+    let (old_middle, new_middle) = (&old[prefix..old.len() - suffix], &new[prefix..new.len() - suffix]);
+
+    let mut changes = Vec::new();
+    new[..prefix].iter().for_each(|word| push(&mut changes, Change::Kept(word.to_string())));
+    if old_middle.len().saturating_mul(new_middle.len()) > MAX_TABLE_CELLS {
+        old_middle.iter().for_each(|word| push(&mut changes, Change::Removed(word.to_string())));
+        new_middle.iter().for_each(|word| push(&mut changes, Change::Added(word.to_string())));
+    } else {
+        diff_middle(old_middle, new_middle, &mut changes);
+    }
+    new[new.len() - suffix..].iter().for_each(|word| push(&mut changes, Change::Kept(word.to_string())));
+    changes
+}
+
+/// Appends the changes that turn `old` into `new`, keeping the longest common subsequence of their words.
+fn diff_middle(old: &[&str], new: &[&str], changes: &mut Vec<Change>) {
+    // common[i][j] is the length of the longest common subsequence of old[i..] and new[j..].
+    let mut common = vec![vec![0u32; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if same(old[i], new[j]) {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && same(old[i], new[j]) {
+            push(changes, Change::Kept(new[j].to_string()));
+            (i, j) = (i + 1, j + 1);
+        } else if i < old.len() && (j == new.len() || common[i + 1][j] >= common[i][j + 1]) {
+            push(changes, Change::Removed(old[i].to_string()));
+            i += 1;
+        } else {
+            push(changes, Change::Added(new[j].to_string()));
+            j += 1;
+        }
+    }
+}
+
+/// The text the changes turn the earlier version into.
+pub fn after(changes: &[Change]) -> String {
+    changes.iter().filter(|change| !matches!(change, Change::Removed(_))).map(Change::text).collect()
+}
+
+/// How many words were added and removed.
+pub fn count_changed_words(changes: &[Change]) -> (usize, usize) {
+    changes.iter().fold((0, 0), |(added, removed), change| match change {
+        Change::Added(text) => (added + text.split_whitespace().count(), removed),
+        Change::Removed(text) => (added, removed + text.split_whitespace().count()),
+        Change::Kept(_) => (added, removed),
+    })
+}
+
+/// Splits the text into words, each with the whitespace after it. Whitespace before the first word is a word of
+/// its own.
+fn words(text: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    let mut start = 0;
+    let mut in_space = text.starts_with(char::is_whitespace);
+    for (index, c) in text.char_indices() {
+        if in_space && !c.is_whitespace() {
+            if index > start {
+                words.push(&text[start..index]);
+            }
+            start = index;
+        }
+        in_space = c.is_whitespace();
+    }
+    if start < text.len() {
+        words.push(&text[start..]);
+    }
+    words
+}
+
+fn same(old: &str, new: &str) -> bool {
+    old.trim_end() == new.trim_end()
+}
+
+/// Appends the change to the last one if it is of the same kind, or on its own.
+fn push(changes: &mut Vec<Change>, change: Change) {
+    if let Some(last) = changes.last_mut() {
+        match (last, &change) {
+            (Change::Kept(text), Change::Kept(word)) | (Change::Removed(text), Change::Removed(word)) | (Change::Added(text), Change::Added(word)) => {
+                return text.push_str(word);
+            },
+            _ => (),
+        }
+    }
+    changes.push(change);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The text the changes turn into the later version from, up to its spacing.
+    fn before(changes: &[Change]) -> String {
+        changes.iter().filter(|change| !matches!(change, Change::Added(_))).map(Change::text).collect()
+    }
+
+    #[test]
+    fn changes_give_both_versions_back() {
+        let pairs = [
+            ("", "A new answer."),
+            ("An old answer.", ""),
+            ("The quick brown fox jumps over the lazy dog.", "The quick red fox leaps over the dog."),
+            ("  Indented\nlines  stay put.\n", "Indented\n\nlines stay put, mostly.\n"),
+            ("Caf\u{e9} na\u{ef}ve r\u{e9}sum\u{e9}", "Caf\u{e9} r\u{e9}sum\u{e9} na\u{ef}ve"),
+        ];
+        for (old, new) in pairs {
+            let changes = diff_words(old, new);
+            assert_eq!(after(&changes), new);
+            assert_eq!(before(&changes).split_whitespace().collect::<Vec<_>>(), old.split_whitespace().collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn only_changed_words_are_marked() {
+        let changes = diff_words("The quick brown fox.", "The quick red fox.");
+        assert_eq!(changes, vec![
+            Change::Kept("The quick ".to_string()),
+            Change::Removed("brown ".to_string()),
+            Change::Added("red ".to_string()),
+            Change::Kept("fox.".to_string()),
+        ]);
+        assert_eq!(count_changed_words(&changes), (1, 1));
+    }
+
+    #[test]
+    fn long_rewrite_is_replaced_whole() {
+        let old: String = (0..2000).map(|word| format!("old{} ", word)).collect();
+        let new: String = (0..2000).map(|word| format!("new{} ", word % 7)).collect();
+        let (old, new) = (format!("Intro {}outro", old), format!("Intro {}outro", new));
+        let changes = diff_words(&old, &new);
+        assert_eq!(changes.len(), 4);
+        assert_eq!(after(&changes), new);
+        assert_eq!(before(&changes), old);
+        assert_eq!(count_changed_words(&changes), (2000, 2000));
+    }
+}
@@ -0,0 +1,162 @@
+//! Live feedback on stderr while the panel deliberates: a status line saying what the panel is waiting
+//! on, and each answer as the actor's model writes it.
+
+use std::{io::{self, Write}, time::Duration};
+
+use actix::prelude::*;
+use llm_consensus::{messages::{AnswerToken, DeliberationUpdate, QuestionId}, strategy::Stage, transcript::TranscriptEvent};
+
+/// How often the spinner on the status line turns.
+pub const SPINNER_INTERVAL: Duration = Duration::from_millis(100);
+pub const SPINNER_FRAMES: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+
+/// Moves to the start of the line and erases it.
+const CLEAR_LINE: &str = "\r\x1b[2K";
+
+/// What a question is waiting on.
+pub struct Status {
+    pub question_id: QuestionId,
+    pub stage: Stage,
+    pub round: usize,
+    pub actors: Vec<String>,
+    /// How many of the actors have responded.
+    pub received: usize,
+}
+
+impl Status {
+    /// The status of the stage the event starts, if it starts one.
+    pub fn started(question_id: QuestionId, event: &TranscriptEvent) -> Option<Status> {
+        match event {
+            TranscriptEvent::StageStarted { stage, round, actors } => {
+                Some(Status { question_id, stage: *stage, round: *round, actors: actors.clone(), received: 0 })
+            },
+            _ => None,
+        }
+    }
+
+    /// Counts the event if it is one of the responses the stage is waiting on.
+    pub fn record(&mut self, question_id: QuestionId, event: &TranscriptEvent) {
+        let response = matches!(event, TranscriptEvent::Draft { .. } | TranscriptEvent::CandidateVote { .. } | TranscriptEvent::Evaluation { .. } | TranscriptEvent::Suggestion { .. } | TranscriptEvent::Refinement { .. });
+        if response && question_id == self.question_id {
+            self.received += 1;
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        let waiting = self.actors.len();
+        match self.stage {
+            Stage::Drafting if waiting == 1 => format!("{} drafting the answer…", self.actors[0]),
+            Stage::Drafting => format!("{}/{} drafts in", self.received, waiting),
+            Stage::Voting if self.round == 0 => format!("{}/{} votes on the drafts in", self.received, waiting),
+            Stage::Voting => format!("Round {}: {}/{} votes on the refinements in", self.round, self.received, waiting),
+            Stage::Moderating => "Screening the question and answer…".to_string(),
+            Stage::Testing => "Running the answer's code…".to_string(),
+            Stage::Evaluating => format!("Round {}: {}/{} evaluations in", self.round + 1, self.received, waiting),
+            Stage::Chairing => format!("Round {}: {} chairing…", self.round + 1, self.actors.first().map_or("the chair", String::as_str)),
+            Stage::Refining if waiting == 1 => format!("Round {} refinement by {}…", self.round, self.actors[0]),
+            Stage::Refining => format!("Round {}: {}/{} dissenters have responded", self.round, self.received, waiting),
+            Stage::Reviewing => "Waiting for the answer to be reviewed…".to_string(),
+        }
+    }
+}
+
+/// Shows the progress of each question on stderr. Expects to be subscribed to both [DeliberationUpdate]s
+/// and, if answers are streamed, [AnswerToken]s.
+pub struct TerminalDisplay {
+    /// Whether the status line is drawn.
+    progress: bool,
+    status: Option<Status>,
+    /// The question, actor and stage of the answer being written, which replaces the status line until it is done.
+    writing: Option<(QuestionId, String, Stage)>,
+    frame: usize,
+}
+
+impl TerminalDisplay {
+    pub fn new(progress: bool) -> Self {
+        TerminalDisplay { progress, status: None, writing: None, frame: 0 }
+    }
+
+    fn draw_status(&mut self) {
+        let Some(status) = self.status.as_ref().filter(|_| self.progress && self.writing.is_none()) else { return };
+        self.frame = (self.frame + 1) % SPINNER_FRAMES.len();
+        let mut stderr = io::stderr().lock();
+        let _ = write!(stderr, "{}{} {}", CLEAR_LINE, SPINNER_FRAMES[self.frame], status.describe());
+        let _ = stderr.flush();
+    }
+
+    fn clear_status(&self) {
+        if self.progress && self.status.is_some() && self.writing.is_none() {
+            eprint!("{}", CLEAR_LINE);
+        }
+    }
+}
+
+impl Actor for TerminalDisplay {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(SPINNER_INTERVAL, |display, _ctx| display.draw_status());
+    }
+}
+
+impl Handler<DeliberationUpdate> for TerminalDisplay {
+    type Result = ();
+
+    fn handle(&mut self, msg: DeliberationUpdate, _ctx: &mut Self::Context) -> Self::Result {
+        let question_id = msg.question_id;
+        // End the line of an answer once it has all arrived, so that later output starts on a line of its own.
+        let answered = matches!(msg.event, TranscriptEvent::Draft { .. } | TranscriptEvent::Refinement { .. }) || msg.event.is_final();
+        if answered && self.writing.as_ref().is_some_and(|(writing, ..)| *writing == question_id) {
+            self.writing = None;
+            eprintln!();
+        }
+
+        // The reviewer asks on the terminal, so the status line makes way for it.
+        let reviewing = matches!(msg.event, TranscriptEvent::StageStarted { stage: Stage::Reviewing, .. });
+        if msg.event.is_final() || reviewing {
+            if self.status.as_ref().is_some_and(|status| status.question_id == question_id) {
+                self.clear_status();
+                self.status = None;
+            }
+            return;
+        }
+        match Status::started(question_id, &msg.event) {
+            Some(status) => self.status = Some(status),
+            None => if let Some(status) = self.status.as_mut() {
+                status.record(question_id, &msg.event);
+            },
+        }
+        self.draw_status();
+    }
+}
+
+impl Handler<AnswerToken> for TerminalDisplay {
+    type Result = ();
+
+    fn handle(&mut self, msg: AnswerToken, _ctx: &mut Self::Context) -> Self::Result {
+        let answer = (msg.question_id, msg.name, msg.stage);
+        if self.writing.as_ref() != Some(&answer) {
+            self.clear_status();
+            let action = if answer.2 == Stage::Drafting { "drafting" } else { "refining" };
+            eprintln!("{} is {} the answer:", answer.1, action);
+            self.writing = Some(answer);
+        }
+        let mut stderr = io::stderr().lock();
+        let _ = write!(stderr, "{}", msg.token);
+        let _ = stderr.flush();
+    }
+}
+
+/// Erases the status line, so that the caller can print without it in the way.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct ClearStatus;
+
+impl Handler<ClearStatus> for TerminalDisplay {
+    type Result = ();
+
+    fn handle(&mut self, _msg: ClearStatus, _ctx: &mut Self::Context) -> Self::Result {
+        self.clear_status();
+        self.status = None;
+    }
+}
@@ -0,0 +1,127 @@
+//! Documents attached to a question, such as source files or notes, cut into excerpts that are quoted in the
+//! prompts the panel answers and evaluates the question with.
+
+use std::mem;
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+/// The `[documents]` section of the config file.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DocumentsConfig {
+    /// The most characters in one excerpt. Documents are cut between lines where they can be.
+    #[serde(default = "default_chunk_chars")]
+    pub chunk_chars: usize,
+    /// The most characters of excerpts quoted in each prompt, across every document.
+    #[serde(default = "default_max_chars")]
+    pub max_chars: usize,
+}
+
+fn default_chunk_chars() -> usize {
+    2000
+}
+
+fn default_max_chars() -> usize {
+    24_000
+}
+
+impl Default for DocumentsConfig {
+    fn default() -> Self {
+        DocumentsConfig { chunk_chars: default_chunk_chars(), max_chars: default_max_chars() }
+    }
+}
+
+impl DocumentsConfig {
+    /// Checks the config, returning a description of the problem if it is unusable.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.chunk_chars == 0 {
+            return Err("documents chunk_chars must be at least 1".to_string());
+        }
+        if self.max_chars < self.chunk_chars {
+            return Err("documents max_chars must be at least chunk_chars".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// A document attached to a question.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Document {
+    /// What the document is called in the prompts, such as its file name.
+    pub name: String,
+    pub text: String,
+}
+
+/// A piece of a document quoted in the prompts.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Excerpt {
+    /// The name of the document it is from.
+    pub source: String,
+    /// Which piece of the document it is, counting from 1.
+    pub part: usize,
+    pub text: String,
+}
+
+/// Cuts the documents into excerpts and picks as many as fit within the configured number of characters, taking
+/// the first excerpt of every document, then the second of every document, and so on, so that one long document
+/// does not crowd the others out. Logs a warning naming the documents that did not fit whole.
+pub fn excerpts(documents: &[Document], config: &DocumentsConfig) -> Vec<Excerpt> {
+    let chunked: Vec<Vec<Excerpt>> = documents.iter()
+        .map(|document| chunk(&document.text, config.chunk_chars).into_iter()
+            .enumerate()
+            .map(|(index, text)| Excerpt { source: document.name.clone(), part: index + 1, text })
+            .collect())
+        .collect();
+    let mut chosen = vec![0; chunked.len()];
+    let mut budget = config.max_chars;
+    let longest = chunked.iter().map(Vec::len).max().unwrap_or(0);
+    for part in 0..longest {
+        for (document, excerpts) in chunked.iter().enumerate() {
+            let Some(excerpt) = excerpts.get(part) else { continue };
+            let len = excerpt.text.chars().count();
+            // A document whose next excerpt did not fit is not continued further on.
+            if chosen[document] == part && len <= budget {
+                budget -= len;
+                chosen[document] += 1;
+            }
+        }
+    }
+    for (document, excerpts) in chunked.iter().enumerate() {
+        if chosen[document] < excerpts.len() {
+            warn!("Only {} of the {} parts of {} fit within the {} characters of documents a prompt may quote.",
+                chosen[document], excerpts.len(), documents[document].name, config.max_chars);
+        }
+    }
+    chunked.into_iter()
+        .zip(chosen)
+        .flat_map(|(excerpts, chosen)| excerpts.into_iter().take(chosen))
+        .collect()
+}
+
+/// Cuts the text into pieces of at most `max_chars` characters, between lines where it can, and leaves out
+/// pieces with nothing but whitespace.
+pub(crate) fn chunk(text: &str, max_chars: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    let mut current_len = 0;
+    for line in text.split_inclusive('\n') {
+        let mut line = line;
+        let mut len = line.chars().count();
+        if current_len + len > max_chars && current_len > 0 {
+            chunks.push(mem::take(&mut current));
+            current_len = 0;
+        }
+        // A line too long for an excerpt of its own is cut wherever the excerpt is full.
+        while len > max_chars {
+            let end = line.char_indices().nth(max_chars).map(|(index, _)| index).unwrap_or(line.len());
+            chunks.push(line[..end].to_string());
+            line = &line[end..];
+            len -= max_chars;
+        }
+        current.push_str(line);
+        current_len += len;
+    }
+    chunks.push(current);
+    chunks.retain(|chunk| !chunk.trim().is_empty());
+    chunks
+}
@@ -0,0 +1,122 @@
+//! Line editing for the REPL: arrow-key history kept across sessions, Ctrl-R search, and questions written over
+//! several lines.
+
+use std::{env, io, path::PathBuf, thread};
+
+use rustyline::{
+    completion::Completer,
+    error::ReadlineError,
+    highlight::Highlighter,
+    hint::Hinter,
+    history::FileHistory,
+    validate::{ValidationContext, ValidationResult, Validator},
+    Config, Editor, Helper,
+};
+use tokio::sync::oneshot;
+use tracing::warn;
+
+/// Opens a question written over several lines, which runs until the same delimiter or a blank line.
+const DELIMITER: &str = "\"\"\"";
+
+/// The file in the home directory that the REPL's history is kept in between sessions.
+const HISTORY_FILE: &str = ".llm_consensus_history";
+
+/// How many lines of history are kept.
+const MAX_HISTORY: usize = 1000;
+
+/// Reads the REPL's input a line, or a delimited question, at a time.
+pub struct LineEditor {
+    /// Taken while a line is being read.
+    editor: Option<Editor<QuestionHelper, FileHistory>>,
+    /// Where the history is loaded from and saved to, if there is a home directory.
+    history: Option<PathBuf>,
+}
+
+/// Keeps a delimited question open until it is closed, so that Enter starts a new line of it.
+struct QuestionHelper;
+
+/// What [LineEditor::read] read.
+pub enum Input {
+    Line(String),
+    /// Ctrl-C was pressed at the prompt.
+    Interrupted,
+    /// Ctrl-D was pressed at the prompt, or the input ended.
+    End,
+}
+
+impl LineEditor {
+    /// An editor with the history of earlier sessions.
+    pub fn new() -> io::Result<Self> {
+        let config = Config::builder()
+            .auto_add_history(true)
+            .max_history_size(MAX_HISTORY)
+            .map_err(io::Error::other)?
+            .build();
+        let mut editor = Editor::with_config(config).map_err(io::Error::other)?;
+        editor.set_helper(Some(QuestionHelper));
+        let history = env::var_os("HOME").map(|home| PathBuf::from(home).join(HISTORY_FILE));
+        if let Some(path) = history.as_ref().filter(|path| path.exists()) {
+            if let Err(e) = editor.load_history(path) {
+                warn!("Unable to read the REPL history in {}: {}", path.display(), e);
+            }
+        }
+        Ok(LineEditor { editor: Some(editor), history })
+    }
+
+    /// Reads the next line, or every line of a delimited question, without holding up the actor system.
+    pub async fn read(&mut self, prompt: &str) -> io::Result<Input> {
+        let Some(mut editor) = self.editor.take() else { return Ok(Input::End) };
+        // Reading the terminal blocks, so it happens on a thread of its own.
+        let (sender, line) = oneshot::channel();
+        let prompt = prompt.to_string();
+        thread::spawn(move || {
+            let line = editor.readline(&prompt);
+            let _ = sender.send((editor, line));
+        });
+        let Ok((editor, line)) = line.await else { return Ok(Input::End) };
+        self.editor = Some(editor);
+        match line {
+            Ok(line) => Ok(Input::Line(strip_delimiters(&line).to_string())),
+            Err(ReadlineError::Interrupted) => Ok(Input::Interrupted),
+            Err(ReadlineError::Eof) => Ok(Input::End),
+            Err(ReadlineError::Io(e)) => Err(e),
+            Err(e) => Err(io::Error::other(e)),
+        }
+    }
+
+    /// Saves the history for the next session.
+    pub fn save_history(&mut self) {
+        let (Some(editor), Some(path)) = (self.editor.as_mut(), &self.history) else { return };
+        if let Err(e) = editor.save_history(path) {
+            warn!("Unable to save the REPL history to {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// The question inside the delimiters, if the input is delimited.
+fn strip_delimiters(input: &str) -> &str {
+    match input.trim().strip_prefix(DELIMITER) {
+        Some(question) => question.strip_suffix(DELIMITER).unwrap_or(question),
+        None => input,
+    }
+}
+
+impl Validator for QuestionHelper {
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        let open = ctx.input().trim_start().strip_prefix(DELIMITER)
+            .is_some_and(|question| !question.trim_end().ends_with(DELIMITER) && !question.ends_with('\n'));
+        Ok(if open { ValidationResult::Incomplete } else { ValidationResult::Valid(None) })
+    }
+}
+
+impl Completer for QuestionHelper {
+    type Candidate = String;
+}
+
+impl Hinter for QuestionHelper {
+    type Hint = String;
+}
+
+impl Highlighter for QuestionHelper {}
+
+impl Helper for QuestionHelper {}
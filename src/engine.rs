@@ -0,0 +1,436 @@
+//! A consensus engine written against plain `async`/`await`, for applications that do not run actix.
+//!
+//! [Engine::ask] drives a question through the same draft, evaluate and refine state machine as the
+//! [Coordinator](crate::Coordinator), calling the panel's providers directly instead of through actors, and sends
+//! its steps down [Engine::subscribe]'s channels. It covers drafting by one actor, evaluation by the whole panel
+//! and refinement by one dissenter at a time, with answer constraints, weights, devil's advocates, `exclude_author`
+//! and `early_decision`; the rest is only offered by [ConsensusSystem](crate::ConsensusSystem). The bundled
+//! providers still use reqwest and tokio's timers, so running the engine in WASM takes an [LlmProvider] of your
+//! own.
+
+use std::{sync::{atomic::{AtomicU64, Ordering}, Arc, Mutex}, time::Instant};
+
+use async_trait::async_trait;
+use futures::{channel::mpsc::{self, UnboundedReceiver, UnboundedSender}, stream::FuturesUnordered, StreamExt};
+use tracing::debug;
+
+use crate::{
+    citations,
+    config::{ActorConfig, ActorRole, Config, ConfigError},
+    deliberation::{Conclusion, DeliberationState, Members},
+    error::ConsensusError,
+    parsing::{ask_for_evaluation, keep_to_constraints, Caller},
+    personas::Persona,
+    prompts::{PromptData, Prompts, Template},
+    provider::{CompletionRequest, CredentialsConfig, GenerationParams, Limiter, LlmProvider, ProviderError, ResponseCache, RetryPolicy, Usage},
+    result::{self, ConsensusResult, Evaluation, QuestionId},
+    selection::Selector,
+    strategy::{ConsensusSettings, EvaluationMode},
+    transcript::TranscriptEvent,
+    usage::{Pricing, UsageTracker},
+};
+
+/// An actor on the [Engine]'s panel.
+pub struct Panelist {
+    name: String,
+    persona: Persona,
+    role: ActorRole,
+    /// How much the actor's vote counts under weighted strategies.
+    weight: f64,
+    provider: Arc<dyn LlmProvider>,
+    /// The provider the actor evaluates with, if not its own.
+    evaluator: Option<Arc<dyn LlmProvider>>,
+    params: GenerationParams,
+    pricing: Pricing,
+}
+
+impl Panelist {
+    pub fn new(name: String, persona: Persona, provider: Arc<dyn LlmProvider>) -> Self {
+        Panelist { name, persona, role: ActorRole::Member, weight: 1.0, provider, evaluator: None, params: GenerationParams::default(), pricing: Pricing::default() }
+    }
+
+    /// Sets the part the actor plays on the panel.
+    pub fn with_role(mut self, role: ActorRole) -> Self {
+        self.role = role;
+        self
+    }
+
+    /// Sets how much the actor's vote counts under weighted strategies.
+    pub fn with_weight(mut self, weight: f64) -> Self {
+        self.weight = weight;
+        self
+    }
+
+    /// Sets the provider the actor evaluates answers with, in place of the one it writes them with.
+    pub fn with_evaluator(mut self, evaluator: Arc<dyn LlmProvider>) -> Self {
+        self.evaluator = Some(evaluator);
+        self
+    }
+
+    /// Sets the sampling settings the actor requests.
+    pub fn with_params(mut self, params: GenerationParams) -> Self {
+        self.params = params;
+        self
+    }
+
+    /// Sets the prices the actor's token usage is costed at.
+    pub fn with_pricing(mut self, pricing: Pricing) -> Self {
+        self.pricing = pricing;
+        self
+    }
+
+    /// Builds the actor from its config entry, like [LlmActor::from_config](crate::LlmActor::from_config).
+    pub fn from_config(config: &ActorConfig, credentials: &CredentialsConfig, retry: &RetryPolicy, cache: Option<&Arc<ResponseCache>>, limiter: Option<&Arc<Limiter>>) -> Result<Self, ProviderError> {
+        let provider = config.provider.build_with_fallback(&config.fallback, credentials, retry, cache, limiter)?;
+        let panelist = Panelist::new(config.name.clone(), config.persona.clone(), provider)
+            .with_role(config.role.unwrap_or_default())
+            .with_weight(config.weight)
+            .with_params(config.provider.params)
+            .with_pricing(config.pricing);
+        Ok(match &config.evaluation_model {
+            Some(model) => panelist.with_evaluator(config.provider.with_model(model).build_with_fallback(&config.fallback, credentials, retry, cache, limiter)?),
+            None => panelist,
+        })
+    }
+
+    fn prompt_data(&self) -> PromptData {
+        PromptData::actor(&self.name, &self.persona, self.role)
+    }
+
+    /// The provider the actor evaluates with, and whether it is one of its own rather than the one it answers with.
+    fn evaluator(&self) -> (&dyn LlmProvider, bool) {
+        match &self.evaluator {
+            Some(evaluator) => (evaluator.as_ref(), true),
+            None => (self.provider.as_ref(), false),
+        }
+    }
+}
+
+/// Deliberates on questions with a panel of [Panelist]s, one future per question. Questions can be asked
+/// concurrently through a shared reference.
+pub struct Engine {
+    panel: Vec<Panelist>,
+    settings: ConsensusSettings,
+    prompts: Arc<Prompts>,
+    selector: Mutex<Selector>,
+    /// Numbers the questions asked.
+    next_question: AtomicU64,
+    subscribers: Mutex<Vec<UnboundedSender<(QuestionId, TranscriptEvent)>>>,
+}
+
+impl Engine {
+    pub fn new(settings: ConsensusSettings) -> Self {
+        Engine {
+            panel: Vec::new(),
+            selector: Mutex::new(Selector::new(&settings.selection)),
+            settings,
+            prompts: Prompts::built_in(),
+            next_question: AtomicU64::new(0),
+            subscribers: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Adds the actor to the panel.
+    pub fn with_panelist(mut self, panelist: Panelist) -> Self {
+        self.panel.push(panelist);
+        self
+    }
+
+    /// Sets the templates the panel renders its prompts from, in place of the built-in ones.
+    pub fn with_prompts(mut self, prompts: Arc<Prompts>) -> Self {
+        self.prompts = prompts;
+        self
+    }
+
+    /// Builds the engine from the config's settings, prompts and actors. The sections the engine does not offer,
+    /// such as moderation and history, are ignored.
+    pub fn from_config(config: &Config) -> Result<Self, ConfigError> {
+        let cache = match &config.cache {
+            Some(cache_config) => Some(Arc::new(ResponseCache::open(cache_config).map_err(|source| ConfigError::Io {
+                path: cache_config.directory.clone().unwrap_or_default(),
+                source,
+            })?)),
+            None => None,
+        };
+        let limiter = config.limits.as_ref().map(|limits| Arc::new(Limiter::new(limits)));
+        let mut engine = Engine::new(config.settings.clone());
+        if let Some(prompts_config) = &config.prompts {
+            engine.prompts = Arc::new(Prompts::load(prompts_config).map_err(ConfigError::Prompts)?);
+        }
+        for actor_config in &config.actors {
+            let panelist = Panelist::from_config(actor_config, &config.credentials, &config.retry, cache.as_ref(), limiter.as_ref())
+                .map_err(|source| ConfigError::Provider { actor: actor_config.name.clone(), source })?;
+            engine.panel.push(panelist);
+        }
+        Ok(engine)
+    }
+
+    /// A channel receiving every step of every question asked from now on, with the question it belongs to.
+    pub fn subscribe(&self) -> UnboundedReceiver<(QuestionId, TranscriptEvent)> {
+        let (sender, receiver) = mpsc::unbounded();
+        self.subscribers.lock().expect("the subscribers should not be poisoned").push(sender);
+        receiver
+    }
+
+    /// Checks that every panelist's providers can be reached, as [LlmProvider::check] does.
+    pub async fn check(&self) -> Result<(), ProviderError> {
+        for panelist in &self.panel {
+            panelist.provider.check().await?;
+            if let Some(evaluator) = &panelist.evaluator {
+                evaluator.check().await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Deliberates on the question until the panel agrees on an answer or has evaluated it `max_rounds` times,
+    /// in which case the latest version is accepted without consensus.
+    pub async fn ask(&self, question: &str) -> Result<ConsensusResult, ConsensusError> {
+        if self.panel.is_empty() {
+            return Err(ConsensusError::NoActorsRegistered);
+        }
+        if self.panel.len() < self.settings.min_actors {
+            return Err(ConsensusError::PanelTooSmall { actors: self.panel.len(), minimum: self.settings.min_actors });
+        }
+        let question_id = QuestionId(self.next_question.fetch_add(1, Ordering::Relaxed) + 1);
+        let mut deliberation = Deliberation {
+            engine: self,
+            question_id,
+            question: question.to_string(),
+            started: Instant::now(),
+            usage: UsageTracker::default(),
+            state: DeliberationState::new(&self.settings, self.settings.strategy, self.settings.max_rounds),
+        };
+        for panelist in &self.panel {
+            deliberation.usage.set_pricing(&panelist.name, panelist.pricing);
+        }
+        self.publish(question_id, TranscriptEvent::Question { question: question.to_string(), strategy: self.settings.strategy });
+        deliberation.run().await
+    }
+
+    fn publish(&self, question_id: QuestionId, event: TranscriptEvent) {
+        let mut subscribers = self.subscribers.lock().expect("the subscribers should not be poisoned");
+        subscribers.retain(|subscriber| subscriber.unbounded_send((question_id, event.clone())).is_ok());
+    }
+
+    fn select_one<'a>(&self, eligible: impl IntoIterator<Item = &'a String>) -> Option<String> {
+        self.selector.lock().expect("the selector should not be poisoned").select_one(eligible)
+    }
+
+    fn panelist(&self, name: &str) -> Option<&Panelist> {
+        self.panel.iter().find(|panelist| panelist.name == name)
+    }
+}
+
+impl Members for Engine {
+    fn weight(&self, name: &str) -> f64 {
+        self.panelist(name).map_or(1.0, |panelist| panelist.weight)
+    }
+
+    fn role(&self, name: &str) -> ActorRole {
+        self.panelist(name).map(|panelist| panelist.role).unwrap_or_default()
+    }
+}
+
+/// One question in progress on the [Engine].
+struct Deliberation<'a> {
+    engine: &'a Engine,
+    question_id: QuestionId,
+    question: String,
+    started: Instant,
+    usage: UsageTracker,
+    /// Every version of the answer, how the panel evaluated it and who refines it next.
+    state: DeliberationState,
+}
+
+impl<'a> Deliberation<'a> {
+    async fn run(mut self) -> Result<ConsensusResult, ConsensusError> {
+        let names: Vec<String> = self.engine.panel.iter().map(|panelist| panelist.name.clone()).collect();
+        let drafter = self.engine.select_one(&names).ok_or(ConsensusError::NoActorsRegistered)?;
+        let answer = self.draft(&drafter).await?;
+        self.engine.publish(self.question_id, TranscriptEvent::Draft { author: drafter.clone(), answer: answer.clone() });
+        self.state.accept_draft(drafter, answer);
+
+        loop {
+            self.evaluate(&names).await?;
+            match self.state.conclude(self.engine) {
+                Conclusion::Reached => {
+                    debug!("The panel reached consensus on question {} under the {:?} strategy.", self.question_id, self.state.strategy);
+                    return Ok(self.finish(true));
+                },
+                Conclusion::Abstained => {
+                    debug!("Every evaluator of question {} abstained, so the answer is accepted without consensus.", self.question_id);
+                    return Ok(self.finish(false));
+                },
+                Conclusion::Dissent => (),
+            }
+
+            let eligible = self.state.eligible_refiners(names.clone());
+            let refiner = {
+                let mut selector = self.engine.selector.lock().expect("the selector should not be poisoned");
+                self.state.pick_refiner(&mut selector, eligible).ok_or(ConsensusError::NoActorsRegistered)?
+            };
+            let answer = self.refine(&refiner).await?;
+            self.engine.publish(self.question_id, TranscriptEvent::Refinement { author: refiner.clone(), answer: answer.clone() });
+            if !self.state.accept_refinement(vec![refiner], answer) {
+                debug!("Evaluated the maximum number of times. Breaking the loop.");
+                return Ok(self.finish(false));
+            }
+        }
+    }
+
+    async fn draft(&mut self, name: &str) -> Result<String, ConsensusError> {
+        let panelist = self.panelist(name)?;
+        let constraints = &self.engine.settings.constraints;
+        let data = PromptData {
+            question: self.question.clone(),
+            constraints: constraints.describe(),
+            preamble: self.engine.prompts.preamble(&panelist.persona),
+            ..panelist.prompt_data()
+        };
+        let prompt = self.engine.prompts.render(Template::Draft, &data);
+        let request = CompletionRequest { system: None, prompt, images: Vec::new(), params: panelist.params, template: Template::Draft, tools: Vec::new(), tool_rounds: Vec::new() };
+        self.write_answer(panelist, request, "DraftAnswer").await
+    }
+
+    async fn refine(&mut self, name: &str) -> Result<String, ConsensusError> {
+        let panelist = self.panelist(name)?;
+        let round = self.state.rounds.last().ok_or_else(|| ConsensusError::StateConflict("there is no answer to get refined".to_string()))?;
+        let data = PromptData {
+            question: self.question.clone(),
+            answer: round.answer.clone(),
+            reasoning: self.state.reasoning_of(name),
+            constraints: self.engine.settings.constraints.describe(),
+            ..panelist.prompt_data()
+        };
+        let prompt = self.engine.prompts.render(Template::Refine, &data);
+        let system = self.engine.prompts.render(Template::Persona, &panelist.prompt_data());
+        let request = CompletionRequest { system: Some(system), prompt, images: Vec::new(), params: panelist.params, template: Template::Refine, tools: Vec::new(), tool_rounds: Vec::new() };
+        self.write_answer(panelist, request, "RefineAnswer").await
+    }
+
+    /// Asks for an answer, then holds it to the constraints.
+    async fn write_answer(&mut self, panelist: &Panelist, request: CompletionRequest, call: &'static str) -> Result<String, ConsensusError> {
+        let provider = panelist.provider.as_ref();
+        let mut calls = Calls { evaluation: false, usage: Vec::new() };
+        let answer = match calls.complete(provider, &request).await {
+            Ok(answer) => keep_to_constraints(provider, request, answer, &self.engine.settings.constraints, &panelist.name, &mut calls).await,
+            Err(error) => Err(error),
+        };
+        for (usage, evaluation_model) in calls.usage {
+            self.usage.record(Some(self.question_id), &panelist.name, usage.as_ref(), evaluation_model);
+        }
+        answer.map_err(|error| ConsensusError::Provider { actor: panelist.name.clone(), call, error })
+    }
+
+    /// Asks the `panel`'s evaluators at once for their verdicts on the latest version of the answer, recording them
+    /// as they come in. Under `early_decision`, the evaluations still in flight are dropped once those in decide the
+    /// round.
+    async fn evaluate(&mut self, panel: &[String]) -> Result<(), ConsensusError> {
+        let engine = self.engine;
+        let evaluators = self.state.start_evaluation(engine, panel);
+        let round = self.state.rounds.last().ok_or_else(|| ConsensusError::StateConflict("there is no answer to get evaluated".to_string()))?;
+        let mode = self.state.strategy.evaluation_mode();
+        let template = match mode {
+            EvaluationMode::Binary => Template::BinaryEvaluation,
+            EvaluationMode::Scored { .. } => Template::ScoredEvaluation,
+        };
+        let question_id = self.question_id;
+        let mut calls: FuturesUnordered<_> = evaluators.iter()
+            .filter_map(|name| engine.panelist(name))
+            .map(|panelist| {
+                let data = PromptData { question: self.question.clone(), answer: round.answer.clone(), citations: engine.settings.constraints.citations, ..panelist.prompt_data() };
+                let prompt = engine.prompts.render(template, &data);
+                let system = engine.prompts.render(Template::Persona, &panelist.prompt_data());
+                let request = CompletionRequest { system: Some(system), prompt, images: Vec::new(), params: panelist.params, template, tools: Vec::new(), tool_rounds: Vec::new() };
+                evaluate(panelist, request, mode)
+            })
+            .collect();
+        while let Some((panelist, outcome)) = calls.next().await {
+            let (evaluation, usage) = outcome?;
+            for (usage, evaluation_model) in usage {
+                self.usage.record(Some(question_id), &panelist.name, usage.as_ref(), evaluation_model);
+            }
+            engine.publish(question_id, TranscriptEvent::Evaluation {
+                round: self.state.rounds.len() - 1,
+                actor: evaluation.actor.clone(),
+                feedback: evaluation.feedback,
+                score: evaluation.score,
+                reasoning: evaluation.reasoning.clone(),
+            });
+            self.state.record_evaluation(evaluation);
+            if engine.settings.early_decision && !calls.is_empty() {
+                let pending = evaluators.iter().filter(|name| !self.state.feedback.contains_key(*name));
+                if self.state.decided_early(engine, pending) {
+                    debug!("Question {} is decided without the {} evaluations still in flight, which are dropped.", question_id, calls.len());
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn panelist(&self, name: &str) -> Result<&'a Panelist, ConsensusError> {
+        self.engine.panelist(name).ok_or_else(|| ConsensusError::StateConflict(format!("{} is not on the panel", name)))
+    }
+
+    fn finish(mut self, consensus_reached: bool) -> ConsensusResult {
+        let rounds = self.state.rounds;
+        let answer = rounds.last().map(|round| round.answer.clone()).unwrap_or_default();
+        let result = ConsensusResult {
+            question_id: self.question_id,
+            question: self.question,
+            answer: answer.clone(),
+            consensus_reached,
+            converged: false,
+            flagged: None,
+            budget_exceeded: None,
+            deadlock: None,
+            ruling: None,
+            cached: None,
+            answered_by: rounds.first().map(|round| round.author.clone()).unwrap_or_default(),
+            candidates: Vec::new(),
+            refinement_rounds: rounds.len().saturating_sub(1) as u32,
+            confidence: result::confidence(&rounds, consensus_reached),
+            dissent: if consensus_reached { Vec::new() } else { result::final_dissent(&rounds) },
+            citations: if self.engine.settings.constraints.citations { citations::parse(&answer) } else { Vec::new() },
+            rounds,
+            elapsed: self.started.elapsed(),
+            fallbacks: Default::default(),
+            usage: self.usage.finish(self.question_id),
+        };
+        self.engine.publish(self.question_id, TranscriptEvent::Consensus {
+            answer,
+            reached: consensus_reached,
+            refinement_rounds: result.refinement_rounds,
+            elapsed_secs: result.elapsed.as_secs_f64(),
+        });
+        result
+    }
+}
+
+/// Calls a panelist's provider, keeping the usage each call reports, and whether it was made with the evaluation
+/// model, for the [Deliberation] to record.
+struct Calls {
+    evaluation: bool,
+    usage: Vec<(Option<Usage>, bool)>,
+}
+
+#[async_trait]
+impl Caller for Calls {
+    async fn complete(&mut self, provider: &dyn LlmProvider, request: &CompletionRequest) -> Result<String, ProviderError> {
+        let completion = provider.complete(request).await?;
+        self.usage.push((completion.usage, self.evaluation));
+        Ok(completion.text)
+    }
+}
+
+/// Asks the panelist for its verdict. Returns the usage of each call made, and whether it was made with the evaluation
+/// model, alongside it.
+async fn evaluate(panelist: &Panelist, request: CompletionRequest, mode: EvaluationMode) -> (&Panelist, Result<(Evaluation, Vec<(Option<Usage>, bool)>), ConsensusError>) {
+    let (provider, evaluation) = panelist.evaluator();
+    let mut calls = Calls { evaluation, usage: Vec::new() };
+    let asked = ask_for_evaluation(provider, request, mode, &panelist.name, &mut calls).await;
+    let evaluation = asked.map(|(feedback, score, reasoning)| (Evaluation { actor: panelist.name.clone(), feedback, score, reasoning }, calls.usage));
+    (panelist, evaluation)
+}
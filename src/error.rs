@@ -0,0 +1,83 @@
+//! Why the panel could not answer a question.
+
+use std::{error, fmt};
+
+use crate::{provider::ProviderError, strategy::Stage};
+
+/// Errors a question fails with once the panel has taken it up. The [Coordinator](crate::Coordinator) fails the
+/// question instead of stopping, so that one bad response cannot wedge the other questions in flight.
+#[derive(Debug)]
+pub enum ConsensusError {
+    /// An actor's provider, or the moderator's, kept failing after every retry while making the named call.
+    Provider { actor: String, call: &'static str, error: ProviderError },
+    /// An actor's response to the named call could not be used, even after it was asked again.
+    Parse { actor: String, call: &'static str, reason: String },
+    /// The panel stalled in the given stage, even after any redispatches.
+    Timeout { stage: Stage },
+    /// An actor kept failing while working on the question, even after it was restarted and the stage redispatched.
+    ActorFailed { actor: String },
+    /// The panel has no actors to deliberate with.
+    NoActorsRegistered,
+    /// The panel has fewer actors than the settings' `min_actors`.
+    PanelTooSmall { actors: usize, minimum: usize },
+    /// The question was asked of only some of the panel, naming actors who are not on it.
+    UnknownActors(Vec<String>),
+    /// The question was in no state to take its next step, such as being refined without an answer.
+    StateConflict(String),
+}
+
+impl ConsensusError {
+    /// The actor, or the moderator, whose call failed, if the question failed on a call.
+    pub fn actor(&self) -> Option<&str> {
+        match self {
+            ConsensusError::Provider { actor, .. } | ConsensusError::Parse { actor, .. } | ConsensusError::ActorFailed { actor } => Some(actor),
+            _ => None,
+        }
+    }
+
+    /// What went wrong, without who it went wrong for. A failed call is described as `call: problem`.
+    pub fn reason(&self) -> String {
+        match self {
+            ConsensusError::Provider { call, error, .. } => format!("{}: {}", call, error),
+            ConsensusError::Parse { call, reason, .. } => format!("{}: {}", call, reason),
+            ConsensusError::Timeout { stage } => format!("timed out during {}", stage),
+            ConsensusError::ActorFailed { .. } => "failed while working on the question".to_string(),
+            ConsensusError::NoActorsRegistered => "no actors are registered".to_string(),
+            ConsensusError::PanelTooSmall { actors, minimum } => format!("the panel has {} actors, fewer than the {} it needs", actors, minimum),
+            ConsensusError::UnknownActors(names) => format!("{} not on the panel", describe_unknown(names)),
+            ConsensusError::StateConflict(reason) => reason.clone(),
+        }
+    }
+}
+
+impl fmt::Display for ConsensusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConsensusError::Provider { actor, call, error } => write!(f, "{} could not reach its model: {}: {}", actor, call, error),
+            ConsensusError::Parse { actor, call, reason } => write!(f, "{} gave an unusable response to {}: {}", actor, call, reason),
+            ConsensusError::Timeout { stage } => write!(f, "the panel timed out during {}", stage),
+            ConsensusError::ActorFailed { actor } => write!(f, "{} kept failing while working on the question", actor),
+            ConsensusError::NoActorsRegistered => write!(f, "no actors are registered"),
+            ConsensusError::PanelTooSmall { actors, minimum } => write!(f, "the panel has {} actors, fewer than the {} it needs", actors, minimum),
+            ConsensusError::UnknownActors(names) => write!(f, "the question was asked of {} not on the panel", describe_unknown(names)),
+            ConsensusError::StateConflict(reason) => write!(f, "the question could not go on: {}", reason),
+        }
+    }
+}
+
+/// Names the actors that are not on the panel, as in "A, who is" or "A, B, who are".
+fn describe_unknown(names: &[String]) -> String {
+    match names {
+        [name] => format!("{}, who is", name),
+        names => format!("{}, who are", names.join(", ")),
+    }
+}
+
+impl error::Error for ConsensusError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ConsensusError::Provider { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
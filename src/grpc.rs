@@ -0,0 +1,345 @@
+//! gRPC interface that lets other services put questions to the panel, with the typed messages of
+//! `proto/consensus.proto`. Built with the `grpc` feature.
+//!
+//! * `Ask` submits a question and returns the panel's id for it as soon as the panel takes it up.
+//! * `GetStatus` reports whether the question is being deliberated, answered or failed, with the full result
+//!   once it is answered.
+//! * `WatchDeliberation` streams every step the panel has taken on the question so far, then each step it
+//!   takes until the last.
+//!
+//! The panel deliberates on every submitted question at once. Only questions asked over gRPC can be
+//! looked up, although the panel may be answering others at the same time, such as over HTTP.
+
+use std::{collections::HashMap, net::SocketAddr, sync::{Arc, Mutex}};
+
+use actix::prelude::*;
+use tokio::sync::{broadcast, mpsc};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{transport::Server, Request, Response, Status};
+use tracing::{debug, info, warn};
+
+use crate::{
+    conversation::Exchange,
+    diff::Change,
+    messages::{DeliberationUpdate, Feedback, QuestionId, QuestionOptions},
+    result::{self, ConsensusResult},
+    strategy::ConsensusStrategy,
+    transcript::TranscriptEvent,
+    ConsensusSystem,
+};
+
+mod proto {
+    tonic::include_proto!("llm_consensus.v1");
+}
+
+use proto::{consensus_server::{Consensus, ConsensusServer}, deliberation_event::Event, question_status::State};
+
+/// How many updates a watcher can fall behind by before it misses some.
+const UPDATE_BUFFER: usize = 256;
+
+/// What the service knows of one question.
+#[derive(Default)]
+struct Followed {
+    /// Whether the question was asked over gRPC, rather than by another of the panel's askers.
+    asked: bool,
+    question: String,
+    /// The result or why the question failed, once the deliberation is over.
+    outcome: Option<Result<ConsensusResult, String>>,
+    /// Every step the panel has taken on the question, oldest first.
+    updates: Vec<DeliberationUpdate>,
+}
+
+/// The questions the service follows, shared by every request.
+struct Questions {
+    followed: Mutex<HashMap<QuestionId, Followed>>,
+    /// Passes each update on to the watchers.
+    updates: broadcast::Sender<DeliberationUpdate>,
+}
+
+impl Questions {
+    /// Keeps the update and passes it on to the watchers. The updates of questions asked by someone else are
+    /// dropped once the question is over, since nobody can look them up.
+    fn record(&self, update: DeliberationUpdate) {
+        let mut followed = self.followed.lock().expect("questions lock should not be poisoned");
+        let question_id = update.question_id;
+        let entry = followed.entry(question_id).or_default();
+        if update.event.is_final() && !entry.asked {
+            followed.remove(&question_id);
+        } else {
+            entry.updates.push(update.clone());
+        }
+        // Sent under the lock, so that a watcher sees each update either in its snapshot or on its receiver.
+        let _ = self.updates.send(update);
+    }
+
+    fn follow(&self, question_id: QuestionId, question: String) {
+        let mut followed = self.followed.lock().expect("questions lock should not be poisoned");
+        let entry = followed.entry(question_id).or_default();
+        entry.asked = true;
+        entry.question = question;
+    }
+
+    fn finish(&self, question_id: QuestionId, outcome: Result<ConsensusResult, String>) {
+        if let Some(entry) = self.followed.lock().expect("questions lock should not be poisoned").get_mut(&question_id) {
+            entry.outcome = Some(outcome);
+        }
+    }
+
+    /// The question's status, or None if it was not asked over gRPC.
+    fn status(&self, question_id: QuestionId) -> Option<proto::QuestionStatus> {
+        let followed = self.followed.lock().expect("questions lock should not be poisoned");
+        let entry = followed.get(&question_id).filter(|entry| entry.asked)?;
+        let (state, result, error) = match &entry.outcome {
+            None => (State::Deliberating, None, None),
+            Some(Ok(result)) => (State::Answered, Some(result.into()), None),
+            Some(Err(error)) => (State::Failed, None, Some(error.clone())),
+        };
+        Some(proto::QuestionStatus { question_id: question_id.0, question: entry.question.clone(), state: state as i32, result, error })
+    }
+
+    /// The updates so far on the question, and a receiver of those still to come, or None if it was not asked
+    /// over gRPC.
+    fn watch(&self, question_id: QuestionId) -> Option<(Vec<DeliberationUpdate>, broadcast::Receiver<DeliberationUpdate>)> {
+        let followed = self.followed.lock().expect("questions lock should not be poisoned");
+        let entry = followed.get(&question_id).filter(|entry| entry.asked)?;
+        Some((entry.updates.clone(), self.updates.subscribe()))
+    }
+}
+
+/// Passes every [DeliberationUpdate] of the panel to [Questions].
+struct UpdateRelay(Arc<Questions>);
+
+impl Actor for UpdateRelay {
+    type Context = Context<Self>;
+}
+
+impl Handler<DeliberationUpdate> for UpdateRelay {
+    type Result = ();
+
+    fn handle(&mut self, msg: DeliberationUpdate, _ctx: &mut Self::Context) -> Self::Result {
+        self.0.record(msg);
+    }
+}
+
+struct ConsensusService {
+    system: ConsensusSystem,
+    questions: Arc<Questions>,
+}
+
+#[tonic::async_trait]
+impl Consensus for ConsensusService {
+    async fn ask(&self, request: Request<proto::AskRequest>) -> Result<Response<proto::AskResponse>, Status> {
+        let proto::AskRequest { question, strategy, max_rounds, context } = request.into_inner();
+        if question.trim().is_empty() {
+            return Err(Status::invalid_argument("the question must not be empty"));
+        }
+        let strategy = strategy.map(|strategy| strategy.parse::<ConsensusStrategy>()).transpose().map_err(Status::invalid_argument)?;
+        if max_rounds == Some(0) {
+            return Err(Status::invalid_argument("max_rounds must be at least 1"));
+        }
+        let context = context.into_iter().map(|exchange| Exchange { question: exchange.question, answer: exchange.answer }).collect();
+
+        let (accepted, mut question_id) = mpsc::unbounded_channel();
+        // Clients follow the question by the id the panel gives it, which a cached answer has none of.
+        let options = QuestionOptions { strategy, max_rounds, context, accepted: Some(accepted), fresh: true, ..QuestionOptions::default() };
+        let system = self.system.clone();
+        let asking = tokio::spawn({
+            let question = question.clone();
+            async move { system.ask_with(question, options).await }
+        });
+        let Some(question_id) = question_id.recv().await else {
+            // The panel did not take the question up, so the ask has already failed with the reason.
+            let error = match asking.await {
+                Ok(Err(e)) => e.to_string(),
+                _ => "the panel did not take the question".to_string(),
+            };
+            return Err(Status::unavailable(error));
+        };
+        debug!("Deliberating on question {} for a gRPC client.", question_id);
+        self.questions.follow(question_id, question);
+        let questions = self.questions.clone();
+        tokio::spawn(async move {
+            let outcome = match asking.await {
+                Ok(result) => result.map_err(|e| e.to_string()),
+                Err(e) => Err(e.to_string()),
+            };
+            questions.finish(question_id, outcome);
+        });
+        Ok(Response::new(proto::AskResponse { question_id: question_id.0 }))
+    }
+
+    async fn get_status(&self, request: Request<proto::GetStatusRequest>) -> Result<Response<proto::QuestionStatus>, Status> {
+        let question_id = QuestionId(request.into_inner().question_id);
+        self.questions.status(question_id)
+            .map(Response::new)
+            .ok_or_else(|| Status::not_found(format!("no question with id {}", question_id)))
+    }
+
+    type WatchDeliberationStream = ReceiverStream<Result<proto::DeliberationEvent, Status>>;
+
+    async fn watch_deliberation(&self, request: Request<proto::WatchDeliberationRequest>) -> Result<Response<Self::WatchDeliberationStream>, Status> {
+        let question_id = QuestionId(request.into_inner().question_id);
+        let (past, mut updates) = self.questions.watch(question_id)
+            .ok_or_else(|| Status::not_found(format!("no question with id {}", question_id)))?;
+        let (sender, receiver) = mpsc::channel(UPDATE_BUFFER);
+        tokio::spawn(async move {
+            for update in &past {
+                if sender.send(Ok(update.into())).await.is_err() {
+                    return;
+                }
+            }
+            if past.iter().any(|update| update.event.is_final()) {
+                return;
+            }
+            loop {
+                match updates.recv().await {
+                    Ok(update) if update.question_id != question_id => continue,
+                    Ok(update) => {
+                        let last = update.event.is_final();
+                        if sender.send(Ok((&update).into())).await.is_err() || last {
+                            return;
+                        }
+                    },
+                    Err(broadcast::error::RecvError::Lagged(missed)) => warn!("A gRPC watcher of question {} fell behind and missed {} updates.", question_id, missed),
+                    Err(broadcast::error::RecvError::Closed) => return,
+                }
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(receiver)))
+    }
+}
+
+/// Serves the gRPC API on `address` until the server fails.
+pub async fn serve(system: ConsensusSystem, address: SocketAddr) -> Result<(), tonic::transport::Error> {
+    let (updates, _) = broadcast::channel(UPDATE_BUFFER);
+    let questions = Arc::new(Questions { followed: Mutex::new(HashMap::new()), updates });
+    system.subscribe(UpdateRelay(questions.clone()).start().recipient());
+    info!("Serving the consensus gRPC API on {}.", address);
+    Server::builder()
+        .add_service(ConsensusServer::new(ConsensusService { system, questions }))
+        .serve(address)
+        .await
+}
+
+impl From<&ConsensusResult> for proto::ConsensusResult {
+    fn from(result: &ConsensusResult) -> Self {
+        proto::ConsensusResult {
+            answer: result.answer.clone(),
+            consensus_reached: result.consensus_reached,
+            converged: result.converged,
+            flagged: result.flagged.clone(),
+            budget_exceeded: result.budget_exceeded.clone(),
+            deadlock: result.deadlock.clone(),
+            ruling: result.ruling.clone(),
+            answered_by: result.answered_by.clone(),
+            refinement_rounds: result.refinement_rounds,
+            confidence: result.confidence,
+            elapsed_secs: result.elapsed.as_secs_f64(),
+            dissent: result.dissent.iter()
+                .map(|dissent| proto::Dissent { round: dissent.round as u32, actor: dissent.actor.clone(), reasoning: dissent.reasoning.clone() })
+                .collect(),
+            rounds: result.rounds.iter()
+                .map(|round| proto::Round {
+                    author: round.author.clone(),
+                    answer: round.answer.clone(),
+                    evaluations: round.evaluations.iter().map(proto::Evaluation::from).collect(),
+                })
+                .collect(),
+            fallbacks: result.fallbacks.clone().into_iter().collect(),
+            citations: result.citations.iter()
+                .map(|citation| proto::Citation { number: citation.number, source: citation.source.clone(), claims: citation.claims.clone() })
+                .collect(),
+        }
+    }
+}
+
+impl From<&result::Evaluation> for proto::Evaluation {
+    fn from(evaluation: &result::Evaluation) -> Self {
+        proto::Evaluation {
+            actor: evaluation.actor.clone(),
+            feedback: proto::Feedback::from(evaluation.feedback) as i32,
+            score: evaluation.score.map(u32::from),
+            reasoning: evaluation.reasoning.clone(),
+        }
+    }
+}
+
+impl From<Feedback> for proto::Feedback {
+    fn from(feedback: Feedback) -> Self {
+        match feedback {
+            Feedback::Good => proto::Feedback::Good,
+            Feedback::NeedsRefinement => proto::Feedback::NeedsRefinement,
+            Feedback::Abstain => proto::Feedback::Abstain,
+        }
+    }
+}
+
+impl From<Change> for proto::Change {
+    fn from(change: Change) -> Self {
+        let change = match change {
+            Change::Kept(text) => proto::change::Change::Kept(text),
+            Change::Removed(text) => proto::change::Change::Removed(text),
+            Change::Added(text) => proto::change::Change::Added(text),
+        };
+        proto::Change { change: Some(change) }
+    }
+}
+
+impl From<&DeliberationUpdate> for proto::DeliberationEvent {
+    fn from(update: &DeliberationUpdate) -> Self {
+        let event = match update.event.clone() {
+            TranscriptEvent::Question { question, strategy } => Event::Question(proto::QuestionAsked { question, strategy: strategy.to_string() }),
+            TranscriptEvent::Draft { author, answer } => Event::Draft(proto::Draft { author, answer }),
+            TranscriptEvent::CandidateVote { actor, choice, reasoning } => {
+                Event::CandidateVote(proto::CandidateVote { actor, choice: choice.map(|choice| choice as u32), reasoning })
+            },
+            TranscriptEvent::Routed { router, choice } => Event::Routed(proto::Routed { router, choice }),
+            TranscriptEvent::CandidateChosen { author, votes } => Event::CandidateChosen(proto::CandidateChosen { author, votes }),
+            TranscriptEvent::Evaluation { round, actor, feedback, score, reasoning } => Event::Evaluation(proto::EvaluationGiven {
+                round: round as u32,
+                evaluation: Some(proto::Evaluation {
+                    actor,
+                    feedback: proto::Feedback::from(feedback) as i32,
+                    score: score.map(u32::from),
+                    reasoning,
+                }),
+            }),
+            TranscriptEvent::StageStarted { stage, round, actors } => {
+                Event::StageStarted(proto::StageStarted { stage: stage.to_string(), round: Some(round as u32), actors })
+            },
+            TranscriptEvent::Suggestion { actor, suggestion } => Event::Suggestion(proto::Suggestion { actor, suggestion }),
+            TranscriptEvent::ChairGuidance { round, chair, guidance } => Event::ChairGuidance(proto::ChairGuidance { round: round as u32, chair, guidance }),
+            TranscriptEvent::ChairRuling { round, chair, reasoning } => Event::ChairRuling(proto::ChairRuling { round: round as u32, chair, reasoning }),
+            TranscriptEvent::Refinement { author, answer } => Event::Refinement(proto::Draft { author, answer }),
+            TranscriptEvent::Revised { round, author, changes } => Event::Revised(proto::Revision {
+                round: round as u32,
+                author,
+                changes: changes.into_iter().map(proto::Change::from).collect(),
+            }),
+            TranscriptEvent::Converged { round, similarity } => Event::Converged(proto::Converged { round: round as u32, similarity }),
+            TranscriptEvent::Flagged { round, reason } => Event::Flagged(proto::Flagged { round: round as u32, reason }),
+            TranscriptEvent::CodeRan { round, runs } => Event::CodeRan(proto::CodeRan {
+                round: round as u32,
+                runs: runs.into_iter()
+                    .map(|run| proto::CodeRun { block: run.block as u32, language: run.language, exit_code: run.exit_code, timed_out: run.timed_out, output: run.output })
+                    .collect(),
+            }),
+            TranscriptEvent::ToolUsed { actor, tool, arguments, output } => {
+                Event::ToolUsed(proto::ToolUsed { actor, tool, arguments: arguments.to_string(), output })
+            },
+            TranscriptEvent::BudgetExceeded { round, reason } => Event::BudgetExceeded(proto::BudgetExceeded { round: round as u32, reason }),
+            TranscriptEvent::Deadlocked { round, reason } => Event::Deadlocked(proto::Deadlocked { round: round as u32, reason }),
+            TranscriptEvent::Consensus { answer, reached, refinement_rounds, elapsed_secs } => {
+                Event::Consensus(proto::Settled { answer, reached, refinement_rounds, elapsed_secs })
+            },
+            TranscriptEvent::Abandoned => Event::Abandoned(proto::Ended {}),
+            TranscriptEvent::Cancelled => Event::Cancelled(proto::Ended {}),
+            TranscriptEvent::Failed { actor, error } => Event::Failed(proto::Failed { actor, error }),
+            TranscriptEvent::Redispatched { stage, actors } => {
+                Event::Redispatched(proto::StageStarted { stage: stage.to_string(), round: None, actors })
+            },
+            TranscriptEvent::TimedOut { stage } => Event::TimedOut(proto::TimedOut { stage: stage.to_string() }),
+        };
+        proto::DeliberationEvent { question_id: update.question_id.0, timestamp: update.timestamp.to_rfc3339(), event: Some(event) }
+    }
+}
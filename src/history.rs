@@ -0,0 +1,265 @@
+//! SQLite-backed history of past consensus runs, fed by the [HistoryRecorder] actor.
+
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "actix")]
+use actix::prelude::*;
+use chrono::Utc;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "actix")]
+use tracing::{debug, error};
+
+#[cfg(feature = "actix")]
+use crate::messages::{ConsensusReached, FlushHistory};
+use crate::{answer_cache::AnswerCacheConfig, result::ConsensusResult};
+
+/// The `[history]` section of the config file.
+#[derive(Debug, Clone, Deserialize)]
+pub struct HistoryConfig {
+    /// The SQLite database file. It is created if missing.
+    pub path: PathBuf,
+    /// How questions are matched with those answered before, if their answers are reused.
+    #[serde(default)]
+    pub answer_cache: Option<AnswerCacheConfig>,
+}
+
+impl HistoryConfig {
+    /// Checks the config, returning a description of the problem if it is unusable.
+    pub fn validate(&self) -> Result<(), String> {
+        match &self.answer_cache {
+            Some(answer_cache) => answer_cache.validate(),
+            None => Ok(()),
+        }
+    }
+}
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    finished_at TEXT NOT NULL,
+    question TEXT NOT NULL,
+    answer TEXT NOT NULL,
+    answered_by TEXT NOT NULL,
+    refinement_rounds INTEGER NOT NULL,
+    elapsed_secs REAL NOT NULL
+);
+CREATE TABLE IF NOT EXISTS votes (
+    run_id INTEGER NOT NULL REFERENCES runs(id),
+    round INTEGER NOT NULL,
+    actor TEXT NOT NULL,
+    feedback TEXT NOT NULL,
+    score INTEGER,
+    reasoning TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS votes_run_id ON votes(run_id);
+CREATE TABLE IF NOT EXISTS question_embeddings (
+    run_id INTEGER NOT NULL REFERENCES runs(id),
+    model TEXT NOT NULL,
+    embedding BLOB NOT NULL,
+    PRIMARY KEY (run_id, model)
+);
+";
+
+/// A past consensus run as stored in the history database.
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryEntry {
+    pub id: i64,
+    /// When the panel settled on the answer, as an RFC 3339 timestamp.
+    pub finished_at: String,
+    pub question: String,
+    pub answer: String,
+    pub answered_by: String,
+    pub refinement_rounds: u32,
+    pub elapsed_secs: f64,
+    /// Every evaluation given during the run, in order.
+    pub votes: Vec<HistoryVote>,
+}
+
+/// One actor's evaluation of one version of the answer.
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryVote {
+    /// The version of the answer being evaluated, starting at 0 for the first draft.
+    pub round: u32,
+    pub actor: String,
+    pub feedback: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub score: Option<u8>,
+    pub reasoning: String,
+}
+
+/// A connection to the history database.
+pub struct History {
+    connection: Connection,
+}
+
+impl History {
+    /// Opens the database at `path`, creating it and its tables if needed.
+    pub fn open(path: &Path) -> rusqlite::Result<Self> {
+        let connection = Connection::open(path)?;
+        connection.execute_batch(SCHEMA)?;
+        Ok(History { connection })
+    }
+
+    /// Stores a finished run with every evaluation it received.
+    pub fn record(&mut self, result: &ConsensusResult) -> rusqlite::Result<i64> {
+        let transaction = self.connection.transaction()?;
+        transaction.execute(
+            "INSERT INTO runs (finished_at, question, answer, answered_by, refinement_rounds, elapsed_secs) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![Utc::now().to_rfc3339(), result.question, result.answer, result.answered_by, result.refinement_rounds, result.elapsed.as_secs_f64()],
+        )?;
+        let run_id = transaction.last_insert_rowid();
+        for (round, version) in result.rounds.iter().enumerate() {
+            for evaluation in &version.evaluations {
+                transaction.execute(
+                    "INSERT INTO votes (run_id, round, actor, feedback, score, reasoning) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                    params![run_id, round, evaluation.actor, format!("{:?}", evaluation.feedback), evaluation.score, evaluation.reasoning],
+                )?;
+            }
+        }
+        transaction.commit()?;
+        Ok(run_id)
+    }
+
+    /// Lists the most recent runs first, keeping only those whose question or answer contains `search`.
+    pub fn search(&self, search: Option<&str>, limit: usize) -> rusqlite::Result<Vec<HistoryEntry>> {
+        let pattern = format!("%{}%", search.unwrap_or_default());
+        let mut statement = self.connection.prepare(
+            "SELECT id, finished_at, question, answer, answered_by, refinement_rounds, elapsed_secs FROM runs
+             WHERE question LIKE ?1 OR answer LIKE ?1 ORDER BY id DESC LIMIT ?2",
+        )?;
+        let mut entries = statement
+            .query_map(params![pattern, limit as i64], entry)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        for entry in &mut entries {
+            entry.votes = self.votes(entry.id)?;
+        }
+        Ok(entries)
+    }
+
+    /// The run stored under `id`, with every evaluation it received, if there is one.
+    pub fn run(&self, id: i64) -> rusqlite::Result<Option<HistoryEntry>> {
+        let entry = self.connection
+            .query_row(
+                "SELECT id, finished_at, question, answer, answered_by, refinement_rounds, elapsed_secs FROM runs WHERE id = ?1",
+                params![id],
+                entry,
+            )
+            .optional()?;
+        match entry {
+            Some(mut entry) => {
+                entry.votes = self.votes(id)?;
+                Ok(Some(entry))
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// The id and question of every run whose question has not been embedded with `model` yet, oldest first.
+    pub fn unembedded(&self, model: &str) -> rusqlite::Result<Vec<(i64, String)>> {
+        let mut statement = self.connection.prepare(
+            "SELECT id, question FROM runs WHERE id NOT IN (SELECT run_id FROM question_embeddings WHERE model = ?1) ORDER BY id",
+        )?;
+        let questions = statement.query_map(params![model], |row| Ok((row.get(0)?, row.get(1)?)))?.collect();
+        questions
+    }
+
+    /// Stores the embedding of a run's question made with `model`.
+    pub fn store_embedding(&self, run_id: i64, model: &str, embedding: &[f32]) -> rusqlite::Result<()> {
+        let embedding: Vec<u8> = embedding.iter().flat_map(|value| value.to_le_bytes()).collect();
+        self.connection.execute(
+            "INSERT OR REPLACE INTO question_embeddings (run_id, model, embedding) VALUES (?1, ?2, ?3)",
+            params![run_id, model, embedding],
+        )?;
+        Ok(())
+    }
+
+    /// Every run whose question has been embedded with `model`, by id, with the embedding.
+    pub fn embeddings(&self, model: &str) -> rusqlite::Result<Vec<(i64, Vec<f32>)>> {
+        let mut statement = self.connection.prepare("SELECT run_id, embedding FROM question_embeddings WHERE model = ?1")?;
+        let embeddings = statement
+            .query_map(params![model], |row| {
+                let embedding: Vec<u8> = row.get(1)?;
+                let embedding = embedding.chunks_exact(4).map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])).collect();
+                Ok((row.get(0)?, embedding))
+            })?
+            .collect();
+        embeddings
+    }
+
+    fn votes(&self, run_id: i64) -> rusqlite::Result<Vec<HistoryVote>> {
+        let mut statement = self.connection.prepare(
+            "SELECT round, actor, feedback, score, reasoning FROM votes WHERE run_id = ?1 ORDER BY rowid",
+        )?;
+        let votes = statement
+            .query_map(params![run_id], |row| Ok(HistoryVote {
+                round: row.get(0)?,
+                actor: row.get(1)?,
+                feedback: row.get(2)?,
+                score: row.get(3)?,
+                reasoning: row.get(4)?,
+            }))?
+            .collect();
+        votes
+    }
+}
+
+/// Reads a run, without its votes, from a row of `id, finished_at, question, answer, answered_by,
+/// refinement_rounds, elapsed_secs`.
+fn entry(row: &Row) -> rusqlite::Result<HistoryEntry> {
+    Ok(HistoryEntry {
+        id: row.get(0)?,
+        finished_at: row.get(1)?,
+        question: row.get(2)?,
+        answer: row.get(3)?,
+        answered_by: row.get(4)?,
+        refinement_rounds: row.get(5)?,
+        elapsed_secs: row.get(6)?,
+        votes: Vec::new(),
+    })
+}
+
+#[cfg(feature = "actix")]
+/// Actor that stores every run the [Coordinator](crate::Coordinator) finishes in the [History].
+pub struct HistoryRecorder {
+    history: History,
+}
+
+#[cfg(feature = "actix")]
+impl HistoryRecorder {
+    pub fn new(history: History) -> Self {
+        HistoryRecorder { history }
+    }
+}
+
+#[cfg(feature = "actix")]
+impl Actor for HistoryRecorder {
+    type Context = Context<Self>;
+}
+
+#[cfg(feature = "actix")]
+impl Handler<ConsensusReached> for HistoryRecorder {
+    type Result = bool;
+
+    fn handle(&mut self, msg: ConsensusReached, _ctx: &mut Self::Context) -> Self::Result {
+        match self.history.record(&msg.0) {
+            Ok(id) => {
+                debug!("Recorded run {} in the history.", id);
+                true
+            },
+            Err(e) => {
+                error!("Unable to record the run in the history: {}", e);
+                false
+            }
+        }
+    }
+}
+
+#[cfg(feature = "actix")]
+impl Handler<FlushHistory> for HistoryRecorder {
+    type Result = ();
+
+    fn handle(&mut self, _msg: FlushHistory, _ctx: &mut Self::Context) -> Self::Result {
+        // Runs are stored as they arrive, so by the time this is handled every earlier one has been.
+    }
+}
@@ -0,0 +1,71 @@
+//! Answers questions by consensus among a panel of LLM-backed actors.
+//!
+//! One actor drafts an answer, every actor evaluates it against its own knowledge domain, and
+//! dissenting actors refine it until the panel agrees. [ConsensusSystem] is the entry point for
+//! embedding the engine in another application. Applications that do not run actix can build without the
+//! default `actix` feature and deliberate with the plain async [Engine](engine::Engine) instead, or with the
+//! `tokio-runtime` feature, run it as a tokio task.
+
+#[cfg(feature = "actix")]
+pub mod actors;
+pub mod answer_cache;
+#[cfg(feature = "actix")]
+pub mod batch;
+pub mod blind;
+pub mod budget;
+pub mod citations;
+pub mod config;
+pub mod constraints;
+pub mod convergence;
+pub mod conversation;
+#[cfg(feature = "actix")]
+pub mod coordinator;
+pub mod deadlock;
+pub mod diff;
+pub mod documents;
+pub mod engine;
+pub mod error;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod history;
+#[cfg(feature = "actix")]
+pub mod messages;
+pub mod moderation;
+pub mod observer;
+pub mod personas;
+pub mod prompts;
+pub mod provider;
+pub mod replay;
+pub mod report;
+pub mod result;
+pub mod retrieval;
+pub mod sandbox;
+pub mod search;
+pub mod selection;
+#[cfg(feature = "actix")]
+pub mod server;
+pub mod session;
+pub mod stats;
+pub mod strategy;
+#[cfg(feature = "tokio-runtime")]
+pub mod task;
+pub mod tools;
+pub mod transcript;
+pub mod usage;
+#[cfg(feature = "actix")]
+pub mod watch;
+mod deliberation;
+mod parsing;
+#[cfg(feature = "actix")]
+mod system;
+
+#[cfg(feature = "actix")]
+pub use actors::LlmActor;
+#[cfg(feature = "actix")]
+pub use coordinator::Coordinator;
+pub use error::ConsensusError;
+pub use observer::ConsensusObserver;
+pub use result::ConsensusResult;
+pub use strategy::{ConsensusSettings, ConsensusStrategy};
+#[cfg(feature = "actix")]
+pub use system::{AskError, ConsensusSystem};
@@ -1,472 +1,1542 @@
+use std::{convert::Infallible, fs::{self, File}, future::{self, Future}, io::{self, BufWriter, IsTerminal, Read, Write}, path::{Path, PathBuf}, process::ExitCode, sync::Arc};
+
+mod display;
+mod editor;
+mod review;
+mod telemetry;
+mod tui;
+
 use actix::prelude::*;
-use jemini::{GeminiError, JeminiClient};
-use log::{debug, error, info};
-use rand::seq::SliceRandom;
-use std::{collections::HashMap, env, io::{self, Write}, time::Instant};
+use clap::{Parser, Subcommand, ValueEnum};
+use display::{ClearStatus, TerminalDisplay};
+use editor::{Input, LineEditor};
+use futures::{stream::FuturesUnordered, StreamExt};
+use review::TerminalReviewer;
+use llm_consensus::{batch, config::{ActorConfig, Config, Profile, DEFAULT_CONFIG_PATH, DEFAULT_PROFILE}, constraints::AnswerFormat, conversation::Conversation, documents::{self, Document}, history::History, messages::{Feedback, QuestionId, QuestionOptions, ReviewAnswer}, personas::{self, Persona}, provider::Image, replay::{self, Recording, ReplayProvider, RoundGate}, report, server, session::Session, stats::ActorStats, usage::UsageTotals, watch::ConfigWatcher, AskError, ConsensusResult, ConsensusSettings, ConsensusStrategy, ConsensusSystem};
+use tokio::{signal, sync::mpsc};
+use tracing::{error, info, warn, Level};
 
-/// Define feedback (Good or Needs Refinement)
-#[derive(Debug, Clone, Copy, PartialEq, MessageResponse)]
-enum Feedback {
-    Good,
-    NeedsRefinement,
-}
+/// Address the HTTP API listens on when `serve` is given no address.
+const DEFAULT_SERVE_ADDRESS: &str = "127.0.0.1:8080";
 
-/// Registers the LLM actor's name and [Addr] with the [Coordinator].
-#[derive(Message)]
-#[rtype(result = "bool")]
-struct Register {
-    name: String,
-    actor: Addr<LlmActor>
-}
+/// Exit status of `ask` when the panel answered but ran out of rounds before agreeing.
+/// Any other failure exits with 1.
+const NO_CONSENSUS_EXIT_CODE: u8 = 2;
 
-/// Sent to the [Coordinator] or an LLM actor to request an answer.
-#[derive(Message)]
-#[rtype(result = "bool")]
-struct AskQuestion(String);
+/// Exit status after Ctrl-C, as shells report for an interrupted process.
+const INTERRUPTED_EXIT_CODE: u8 = 130;
 
-/// Send as the answer to a question posed in [AskQuestion].
-#[derive(Message)]
-#[rtype(result = "bool")]
-struct AnswerQuestion(String);
+/// What /help prints in the REPL.
+const REPL_HELP: &str = "\
+/help                   lists these commands
+/history                lists the questions answered this session
+/reasoning              shows every version of the last answer and how each actor evaluated it
+/rounds [n]             shows or sets the most times the panel evaluates each answer
+/actors                 lists the panel
+/only [names|all]       shows or sets the actors questions are asked of, separated by commas
+/stats                  shows how each actor has fared this session
+/add <name or persona>  adds an actor from the config file or the built-in personas
+/add { name = ... }     adds a new actor
+/remove <name>          removes an actor
+/panel [name]           lists the profiles or switches to one
+/context on|off         turns conversation memory on or off
+/export [file]          writes a Markdown report of the last deliberation
+/exit                   ends the session
 
-// Define the message types
-#[derive(Message)]
-#[rtype(result = "bool")]
-struct AnswerReadinessRequest;
+A question can start with !rounds=<n>, !strategy=<strategy>, !words=<n>, !format=<format>, !budget=<cost>
+or !deadline=<secs> to override the settings for it alone, and with !fresh to deliberate on it even if the
+answer to a similar one is cached. A question starting with \"\"\" runs over several lines, until a closing
+\"\"\" or a blank line, and @<file> asks the contents of the file.
 
-#[derive(Message)]
-#[rtype(result = "String")]
-struct GetAnswer;
+Questions typed while the panel is busy wait in a queue and are answered in turn. Ctrl-C cancels every
+question asked, and ends the session when there are none.";
 
-#[derive(Debug, Message)]
-#[rtype(result = "bool")]
-struct EvaluateAnswer {
-    question: String,
-    answer: String
+/// Answers questions by consensus among a panel of LLM-backed actors.
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    /// Panel config file. Defaults to consensus.toml in the working directory, or the built-in panel.
+    #[arg(long, short, global = true, env = "CONSENSUS_CONFIG")]
+    config: Option<PathBuf>,
+    /// The panel to use: one of the config file's `[profiles.<name>]`, or "default" for its top-level actors
+    /// and settings.
+    #[arg(long, short, global = true, env = "CONSENSUS_PROFILE", default_value = DEFAULT_PROFILE)]
+    profile: String,
+    /// How many times the panel evaluates an answer before accepting it without consensus.
+    #[arg(long, global = true)]
+    max_rounds: Option<u32>,
+    /// Consensus strategy: unanimous, majority, super_majority:<fraction>, weighted:<threshold>
+    /// or scored:<threshold>[:min|mean].
+    #[arg(long, short, global = true)]
+    strategy: Option<ConsensusStrategy>,
+    /// Seeds the random picks of which actors draft, refine and synthesize, so that runs are repeatable.
+    #[arg(long, global = true, env = "CONSENSUS_SEED")]
+    seed: Option<u64>,
+    /// The most words an answer may have. Actors whose answers are longer are asked to shorten them.
+    #[arg(long, global = true)]
+    max_words: Option<usize>,
+    /// The shape answers must take: bullets, markdown or code_only. Actors whose answers do not are asked again.
+    #[arg(long, global = true)]
+    answer_format: Option<AnswerFormat>,
+    /// Has answers cite a numbered source for each claim and list the sources at their end, and the evaluators
+    /// check the claims against them. The sources are listed in the result.
+    #[arg(long, global = true)]
+    citations: bool,
+    /// The most each question may cost, estimated from the actors' prices. The panel stops refining an answer,
+    /// and accepts it without agreement, once another round is projected to cost more.
+    #[arg(long, global = true)]
+    budget: Option<f64>,
+    /// The most prompt and completion tokens each question may use, held to like --budget.
+    #[arg(long, global = true)]
+    token_budget: Option<u64>,
+    /// The most seconds each question may take. The panel cuts the rounds to those projected to fit, evaluates
+    /// the last with the actors' fast models, and accepts the latest version without agreement once the
+    /// deadline nears or passes.
+    #[arg(long, global = true)]
+    deadline: Option<f64>,
+    /// Print every prompt to stderr instead of sending it to a model, which answers with a stub that votes
+    /// for the first candidate and asks for every answer to be refined, so that the panel goes through every
+    /// stage until it runs out of rounds. The response cache, convergence detection, moderation and retrieval
+    /// are turned off, since they would call models too.
+    #[arg(long, global = true)]
+    dry_run: bool,
+    /// How results are printed.
+    #[arg(long, short, global = true, value_enum, default_value_t = OutputFormat::Text)]
+    output: OutputFormat,
+    /// Don't show the answer on stderr while it is being written. It is only shown when stderr is a
+    /// terminal and the output is text.
+    #[arg(long, global = true)]
+    no_stream: bool,
+    /// Don't show what the panel is waiting on in a status line on stderr. It is only shown when stderr
+    /// is a terminal, the output is text and the log is below info level, since the log narrates the same.
+    #[arg(long, global = true)]
+    no_progress: bool,
+    /// Before accepting an answer the panel agreed on, show it and ask whether to accept it. A rejection's
+    /// reason goes back to the panel as an evaluation by "User", and the answer is refined to address it.
+    /// Only for ask and repl, and stdin must be a terminal.
+    #[arg(long, global = true)]
+    review: bool,
+    /// Don't check that every actor can reach its models before starting, which otherwise costs each actor a
+    /// tiny call and exits with 1 if any cannot.
+    #[arg(long, global = true)]
+    skip_health_check: bool,
+    /// Reload the panel whenever the config file, or a template in its `[prompts]` directory, changes: the actors,
+    /// their personas, the prompts and the settings of the panel in use are replaced once the questions in flight
+    /// finish, and questions asked in the meantime wait for the new panel. Actors added or removed with /add and
+    /// /remove are replaced too. Only for repl and serve.
+    #[arg(long, global = true)]
+    watch: bool,
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-#[derive(Message)]
-#[rtype(result = "bool")]
-struct AnswerEvaluation {
-    name: String,
-    evaluation: Feedback,
-    reasoning: String
+#[derive(Subcommand)]
+enum Command {
+    /// Asks the panel one question, prints its answer and exits with 0 if the panel agreed,
+    /// 2 if it ran out of rounds first or the moderator refused the question, or 1 if it could not answer.
+    Ask {
+        /// The question, or `@<file>` to ask the contents of the file. Read from stdin if neither this nor
+        /// --question is given.
+        words: Vec<String>,
+        /// The question, as a single argument.
+        #[arg(long, short, conflicts_with = "words")]
+        question: Option<String>,
+        /// Also writes a Markdown report of the deliberation to this file: every version of the answer,
+        /// each actor's verdict and reasoning on it, and the result.
+        #[arg(long)]
+        report: Option<PathBuf>,
+        /// Files the panel answers the question with, such as notes or source code. Each is cut into excerpts
+        /// that are quoted when the answer is drafted, evaluated and refined, within the `[documents]` limits of the
+        /// config file. The question may follow the files, as in `--context notes.md main.rs "question"`.
+        #[arg(long, num_args = 1..)]
+        context: Vec<PathBuf>,
+        /// An image the question is about, such as a screenshot or a diagram: PNG, JPEG, GIF or WebP. Repeat it to
+        /// attach several. Actors whose models cannot see images are told that they are not shown them.
+        #[arg(long)]
+        image: Vec<PathBuf>,
+        /// Asks only these actors, separated by commas, as in `--actors "The Technician,Programming Nerd"`. The
+        /// rest of the panel sits the question out, and consensus is decided among the actors asked.
+        #[arg(long, value_delimiter = ',')]
+        actors: Vec<String>,
+        /// Deliberates on the question even if the history's `[history.answer_cache]` has the answer to one meaning
+        /// nearly the same.
+        #[arg(long)]
+        fresh: bool,
+        /// Instructions added to the preamble the answer is drafted after, such as who it is for, as in
+        /// `--instructions "Answer for a ten-year-old."`.
+        #[arg(long)]
+        instructions: Option<String>,
+    },
+    /// Reads questions from stdin until "exit" or `/exit`. This is the default when stdin is a terminal;
+    /// otherwise the whole of stdin is asked as one question. Ctrl-C cancels the question the panel
+    /// is deliberating on, or ends the session at the prompt.
+    ///
+    /// The prompt can be edited, Up and Down step through earlier input, kept in ~/.llm_consensus_history
+    /// between sessions, and Ctrl-R searches it. A question starting with """ runs over several lines,
+    /// until a closing """ or a blank line, and `@<file>` asks the contents of the file. Pasted text keeps its
+    /// line breaks. A question longer than 100,000 characters is cut off there.
+    ///
+    /// `/help` lists the commands. `/history` lists the questions answered this session, `/reasoning` shows
+    /// every version of the last answer with each actor's evaluation of it, and `/rounds <n>` sets the most
+    /// times the panel evaluates each answer for the rest of the session.
+    /// `/actors` lists the panel, `/only <name>, <name>...` asks the questions after it of only those actors and
+    /// `/only all` of the whole panel again, `/add <name>` adds an actor defined in the config file,
+    /// `/add { name = "...", domain = "...", expertise = [...] }` adds a new one and `/remove <name>`
+    /// removes one; `/add <persona>` also adds a persona from the built-in library, with the provider of the
+    /// panel's first actor. `/panel` lists the config file's profiles and `/panel <name>` replaces the panel
+    /// with one of them, or with the top-level panel for "default". `/context on` drafts each answer in the
+    /// context of the questions and answers before it, within the `[conversation]` window of the config file, and
+    /// `/context off` forgets them. `/export [file]` writes a Markdown report of the last question's deliberation,
+    /// to deliberation-<id>.md by default.
+    ///
+    /// A question can start with directives overriding the settings for it alone: `!rounds=<n>` for the
+    /// maximum rounds, `!strategy=<strategy>` in the same form as --strategy, `!words=<n>` for the most
+    /// words in the answer, `!format=<format>` in the same form as --answer-format, `!budget=<cost>` for the
+    /// most the question may cost and `!deadline=<secs>` for the most seconds it may take, e.g.
+    /// `!rounds=3 !strategy=majority How do I ...?`. `!fresh` has the panel deliberate on the question even if
+    /// the answer to a similar one is cached.
+    ///
+    /// Questions can be typed while the panel deliberates. They wait in a queue and are answered in turn, one
+    /// at a time unless the config file sets `max_concurrent_questions`, and each is drafted in the context of
+    /// the conversation as it stood when it was typed. Ctrl-C cancels them all, and quits when there are none.
+    Repl {
+        /// Saves the session under this name as it goes, and resumes it if it was saved before: the panel as it
+        /// was changed, the conversation memory and the answers /history lists, in place of the --profile.
+        #[arg(long)]
+        session: Option<String>,
+    },
+    /// Asks questions in a full-screen view of the panel: the answer as it evolves in the middle, a pane
+    /// per actor with its latest verdict and reasoning, and the round the panel is on. Esc cancels the
+    /// question the panel is deliberating on, or quits when there is none.
+    Tui,
+    /// Answers every question in a file, one per line, and writes a JSON line per question with its
+    /// answer, round counts and dissent. Exits with 1 if any question failed, otherwise 2 if any
+    /// ran out of rounds or was refused.
+    Batch {
+        file: PathBuf,
+        /// Where the results go. Defaults to stdout.
+        #[arg(long)]
+        out: Option<PathBuf>,
+        /// How many questions the panel deliberates on at once.
+        #[arg(long, short = 'j', default_value_t = 4)]
+        concurrency: usize,
+    },
+    /// Replays the deliberations recorded in a transcript file: each question in it is asked again, and every
+    /// actor answers with what the transcript records it answering instead of calling its model, so that the panel
+    /// takes the same steps to the same answer. Prints each answer like ask, and exits like batch.
+    ///
+    /// The panel is the config file's, holding the actors the transcript names. Where it picks a different actor to
+    /// draft or refine than the recorded panel did, the picked actor gives the recorded response in its place; the
+    /// --seed the transcript was recorded with, if any, picks the same actors. The transcript records no spending,
+    /// so a question stopped by its budget is replayed until the transcript has nothing more for the panel.
+    Replay {
+        transcript: PathBuf,
+        /// Replays only the question recorded under this id.
+        #[arg(long, short)]
+        question: Option<u64>,
+        /// Pauses before each round of refinement and its evaluation until Enter is pressed.
+        #[arg(long)]
+        step: bool,
+    },
+    /// Exposes the panel over HTTP, and over gRPC too with --grpc when built with the grpc feature.
+    Serve {
+        #[arg(default_value = DEFAULT_SERVE_ADDRESS)]
+        address: String,
+        /// Also serves the gRPC API of proto/consensus.proto on this address, e.g. 127.0.0.1:50051.
+        #[cfg(feature = "grpc")]
+        #[arg(long)]
+        grpc: Option<std::net::SocketAddr>,
+    },
+    /// Lists past runs from the history database, optionally only those mentioning the search text.
+    History {
+        search: Vec<String>,
+        /// How many runs to list, most recent first.
+        #[arg(long, short, default_value_t = 20)]
+        limit: usize,
+    },
+    /// The built-in personas, which an actor in the config file takes with `persona = "<key>"`.
+    Personas {
+        #[command(subcommand)]
+        command: PersonasCommand,
+    },
 }
 
-#[derive(Message)]
-#[rtype(result = "bool")]
-struct RefineAnswer {
-    question: String,
-    answer: String
+#[derive(Subcommand)]
+enum PersonasCommand {
+    /// Lists every built-in persona with its key, name and what it brings to a panel.
+    List,
 }
 
-#[derive(Message)]
-#[rtype(result = "bool")]
-struct AnswerRefinement(String);
+/// The config file, with the panels in it, and the settings given on the command line, which every panel
+/// is held to.
+#[derive(Clone)]
+struct Panels {
+    config: Config,
+    /// The config file given on the command line, if any.
+    path: Option<PathBuf>,
+    /// The panel in use, which --watch reloads.
+    profile: String,
+    watch: bool,
+    max_rounds: Option<u32>,
+    strategy: Option<ConsensusStrategy>,
+    seed: Option<u64>,
+    max_words: Option<usize>,
+    answer_format: Option<AnswerFormat>,
+    citations: bool,
+    budget: Option<f64>,
+    token_budget: Option<u64>,
+    deadline: Option<f64>,
+    dry_run: bool,
+}
+
+impl Panels {
+    /// The config with the named profile's panel and the command line's settings.
+    fn get(&self, profile: &str) -> Result<Config, String> {
+        let config = self.config.with_profile(profile).map_err(|e| e.to_string())?;
+        self.overridden(config)
+    }
 
-#[derive(Message)]
-#[rtype(result = "bool")]
-struct Reset;
+    /// The config with a resumed session's panel and the command line's settings.
+    fn resume(&self, panel: Profile) -> Result<Config, String> {
+        self.overridden(Config { settings: panel.settings, actors: panel.actors, ..self.config.clone() })
+    }
 
-// LLM actor that interacts with LLM API
-struct LlmActor {
-    name: String,
-    domain: String,
-    tuning: String,
+    /// Re-reads the config file, returning it and the panel in use from it with the command line's settings.
+    fn reread(&self) -> Result<(Config, Config), String> {
+        let file = Config::load(self.path.as_deref()).map_err(|e| e.to_string())?;
+        let panel = self.overridden(file.with_profile(&self.profile).map_err(|e| e.to_string())?)?;
+        Ok((file, panel))
+    }
+
+    /// Watches the config file and the panel's templates, if --watch was given.
+    fn watcher(&self, config: &Config) -> Result<Option<ConfigWatcher>, String> {
+        if !self.watch {
+            return Ok(None);
+        }
+        let path = self.path.clone()
+            .or_else(|| Path::new(DEFAULT_CONFIG_PATH).exists().then(|| PathBuf::from(DEFAULT_CONFIG_PATH)))
+            .ok_or_else(|| format!("--watch needs a config file, and none was given nor is there a {} in the working directory", DEFAULT_CONFIG_PATH))?;
+        let templates = config.prompts.as_ref().and_then(|prompts| prompts.directory.as_deref());
+        ConfigWatcher::new(&path, templates).map(Some).map_err(|e| format!("unable to watch {}: {}", path.display(), e))
+    }
+
+    /// The config with the command line's settings in place of its own.
+    fn overridden(&self, mut config: Config) -> Result<Config, String> {
+        if let Some(max_rounds) = self.max_rounds {
+            config.settings.max_rounds = max_rounds;
+        }
+        if let Some(strategy) = self.strategy {
+            config.settings.strategy = strategy;
+        }
+        if let Some(seed) = self.seed {
+            config.settings.selection.seed = Some(seed);
+        }
+        if let Some(max_words) = self.max_words {
+            config.settings.constraints.max_words = Some(max_words);
+        }
+        if let Some(format) = self.answer_format {
+            config.settings.constraints.format = Some(format);
+        }
+        if self.citations {
+            config.settings.constraints.citations = true;
+        }
+        if let Some(cost) = self.budget {
+            config.settings.budget.question.cost = Some(cost);
+        }
+        if let Some(tokens) = self.token_budget {
+            config.settings.budget.question.tokens = Some(tokens);
+        }
+        if let Some(secs) = self.deadline {
+            config.settings.budget.question.deadline_secs = Some(secs);
+        }
+        if self.dry_run {
+            config.dry_run();
+        }
+        config.settings.validate().map_err(|e| format!("invalid settings: {}", e))?;
+        if !self.dry_run && config.settings.budget.limits_spending() {
+            warn_unmetered(&config);
+        }
+        Ok(config)
+    }
 }
 
-impl Actor for LlmActor {
-    type Context = Context<Self>;
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// The answer as plain text, with progress logged to stderr.
+    Text,
+    /// Each result as a JSON document.
+    Json,
 }
 
-async fn call_gemini(prompt: String) -> Result<String, GeminiError> {
-    let client = JeminiClient::new()?;
-    let response = client.text_only(prompt.as_str()).await?;
-    Ok(response.most_recent().expect(format!("{} should return an answer", prompt).as_str()).to_owned())
+#[actix::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let _telemetry = telemetry::init();
+    if let Some(Command::Personas { command: PersonasCommand::List }) = &cli.command {
+        return print_personas(cli.output);
+    }
+
+    let panels = match Config::load(cli.config.as_deref()) {
+        Ok(config) => Panels {
+            config,
+            path: cli.config.clone(),
+            profile: cli.profile.clone(),
+            watch: cli.watch,
+            max_rounds: cli.max_rounds,
+            strategy: cli.strategy,
+            seed: cli.seed,
+            max_words: cli.max_words,
+            answer_format: cli.answer_format,
+            citations: cli.citations,
+            budget: cli.budget,
+            token_budget: cli.token_budget,
+            deadline: cli.deadline,
+            dry_run: cli.dry_run,
+        },
+        Err(e) => {
+            error!("Unable to load the actor panel: {}", e);
+            return ExitCode::FAILURE
+        }
+    };
+    let mut config = match panels.get(&cli.profile) {
+        Ok(config) => config,
+        Err(e) => {
+            error!("Unable to use the {} panel: {}", cli.profile, e);
+            return ExitCode::FAILURE
+        }
+    };
+
+    // Without a subcommand, piped input is a single question rather than a REPL session.
+    let command = cli.command.unwrap_or_else(|| match io::stdin().is_terminal() {
+        true => Command::Repl { session: None },
+        false => Command::Ask { words: Vec::new(), question: None, report: None, context: Vec::new(), image: Vec::new(), actors: Vec::new(), fresh: false, instructions: None },
+    });
+    if let Command::History { search, limit } = &command {
+        return print_history(&config, &search.join(" "), *limit, cli.output);
+    }
+    if let Command::Replay { .. } = &command {
+        config.replay();
+    }
+    match &command {
+        Command::Repl { session: Some(_) } if cli.dry_run => {
+            error!("A dry run cannot be saved as a session, so --session cannot be used with --dry-run.");
+            return ExitCode::FAILURE
+        },
+        // The panel is resumed before it starts, and the rest of the session once the REPL does.
+        Command::Repl { session: Some(name) } => match open_session(name).and_then(|(_, saved)| saved.map(|saved| panels.resume(saved.panel)).transpose()) {
+            Ok(Some(resumed)) => config = resumed,
+            Ok(None) => {},
+            Err(e) => {
+                error!("Unable to use the {} session: {}", name, e);
+                return ExitCode::FAILURE
+            }
+        },
+        _ => {},
+    }
+
+    let system = match ConsensusSystem::from_config(&config).await {
+        Ok(system) => system,
+        Err(e) => {
+            error!("Unable to start the actor panel: {}", e);
+            return ExitCode::FAILURE
+        }
+    };
+
+    if !confirm_panel(&system, &config).await {
+        return ExitCode::FAILURE
+    }
+    if !cli.skip_health_check && !check_health(&system, &config).await {
+        return ExitCode::FAILURE
+    }
+
+    let interactive = matches!(command, Command::Ask { .. } | Command::Repl { .. } | Command::Replay { .. }) && cli.output == OutputFormat::Text && io::stderr().is_terminal();
+    let progress = !cli.no_progress && !telemetry::log_enabled(Level::INFO);
+    let display = (interactive && (progress || !cli.no_stream)).then(|| {
+        let display = TerminalDisplay::new(progress).start();
+        system.subscribe(display.clone().recipient());
+        if !cli.no_stream {
+            system.subscribe_tokens(display.clone().recipient());
+        }
+        display
+    });
+
+    let reviewer = match cli.review && matches!(command, Command::Ask { .. } | Command::Repl { .. }) {
+        true if !io::stdin().is_terminal() => {
+            error!("--review asks whether to accept each answer on stdin, so stdin must be a terminal.");
+            return ExitCode::FAILURE
+        },
+        true => Some(TerminalReviewer::new(display.clone()).start().recipient()),
+        false => None,
+    };
+
+    // Ctrl-C cancels the questions in flight and stops the panel cleanly, except in the REPL, which
+    // only cancels its question.
+    let interruptible = !matches!(command, Command::Repl { .. });
+    let code = tokio::select! {
+        code = run(command, &system, &config, &panels, cli.output, display.as_ref(), reviewer) => code,
+        Ok(()) = signal::ctrl_c(), if interruptible => {
+            info!("Interrupted. Cancelling the questions in flight.");
+            ExitCode::from(INTERRUPTED_EXIT_CODE)
+        },
+    };
+    if let Err(e) = system.shutdown().await {
+        error!("Unable to shut the panel down cleanly: {}", e);
+    }
+    code
 }
 
-// LLM Actor Message Handlers
-impl Handler<AskQuestion> for LlmActor {
-    type Result = bool;
+/// The file the session called `name` is saved in, with what was saved in it if anything was.
+fn open_session(name: &str) -> Result<(PathBuf, Option<Session>), String> {
+    let path = Session::path(name)?;
+    let saved = Session::load(&path)?;
+    Ok((path, saved))
+}
 
-    fn handle(&mut self, msg: AskQuestion, _: &mut Self::Context) -> Self::Result {
-        debug!("LLM actor {} received AskQuestion: {}", self.name, msg.0);
+/// Checks that the Coordinator registered every configured actor, and that the panel is as large as the settings
+/// need, before any question is asked.
+async fn confirm_panel(system: &ConsensusSystem, config: &Config) -> bool {
+    match system.registered().await {
+        Ok(registered) if registered < config.actors.len() => {
+            error!("Only {} of the {} configured actors were registered.", registered, config.actors.len());
+            false
+        },
+        Ok(registered) if registered < config.settings.min_actors => {
+            error!("The panel has {} actors, fewer than the {} min_actors needs.", registered, config.settings.min_actors);
+            false
+        },
+        Ok(registered) => {
+            info!("All {} actors are registered.", registered);
+            true
+        },
+        Err(e) => {
+            error!("Unable to confirm the panel's actors: {}", e);
+            false
+        }
+    }
+}
 
-        let prompt = format!("Please answer the following question without referring to yourself as a language model:\n\n{}", msg.0);
-        let execution = async move {
-            let response = call_gemini(prompt).await.expect("expect successful response");
-            Coordinator::from_registry().do_send(AnswerQuestion(response));
-        };
+/// Checks that every actor on the panel can reach its models with its credentials, logging each one that cannot
+/// along with the provider it is configured with.
+async fn check_health(system: &ConsensusSystem, config: &Config) -> bool {
+    match system.check_health().await {
+        Ok(actors) => {
+            let failed: Vec<_> = actors.iter().filter_map(|actor| Some((&actor.name, actor.error.as_ref()?))).collect();
+            for (name, error) in &failed {
+                match config.actors.iter().find(|actor| actor.name == **name) {
+                    Some(actor) => error!("{} cannot reach its model through {}: {}", name, actor.provider.describe(), error),
+                    None => error!("{} cannot reach its model: {}", name, error),
+                }
+            }
+            if !failed.is_empty() {
+                error!("Fix the panel's configuration, or pass --skip-health-check to start anyway.");
+            }
+            failed.is_empty()
+        },
+        Err(e) => {
+            error!("Unable to check the panel's health: {}", e);
+            false
+        }
+    }
+}
 
-        Arbiter::current().spawn(execution);
-        true
-    }
-}
-
-impl Handler<EvaluateAnswer> for LlmActor {
-    type Result = bool;
-
-    fn handle(&mut self, msg: EvaluateAnswer, _: &mut Self::Context) -> Self::Result {
-        let name = self.name.clone();
-        let prompt = format!(r"
----
-Question: {}
----
-Answer: {}
----
-Your Instructions:
-You are part of a team of LLMs that were given the above question to answer by consensus. The first model chosen answered with the answer above. You need to evaluate this answer based on your knowledge domain of {}. The only answers you may provide are Good and NeedsRefinement.
-
-Consider how the answer might indirectly or tangentially relate to the domain. A direct connection is not required. Focus on how the answer could enable, inspire, or be used in activities related to the domain. Specifically, you should consider aspects like:{}
-
-The most important part of choosing your answer is whether the question is related to your domain at all. If it is not, then you should answer exactly Good since you are not qualified to evaluate the answer. Otherwise, if you think this was a good answer, respond with exactly Good. If you think this was a bad answer, respond with exactly NeedsRefinement. Additionally, you must also provide reasoning for why you think this answer is Good or NeedsRefinement answer by putting that reasoning on a new line.
----
-Examples:
-
-Question: What's a good beginner programming language?
-Answer: Python
-Your domain: art and imagination
-Evaluation: Good
-Reasoning: This isn't related to your domain.
-
-Question: How can I make my software easier to update?
-Answer: Decoupling
-Your domain: technical rigor
-Evaluation: NeedsRefinement
-Reasoning: Decoupling and high cohesion are only one aspect of maintainable software, and the answer doesn't go into enough detail.", msg.question, msg.answer, self.domain, self.tuning).replace("\"", "");
-        let execution = async {
-            let result = call_gemini(prompt).await.expect("EvaluateAnswer should produce good response");
-            let mut result_parts: Vec<&str> = result.split("\n")
-                .filter(|s| !(*s).is_empty())
-                .collect();
-            let cleaned_result = result_parts[0].replace(" ", "");
-            let reasoning = result_parts.split_off(1).join("\n\n");
-            Coordinator::from_registry().do_send(AnswerEvaluation{ name: name, evaluation: match cleaned_result.as_str() {
-                "Good" => Feedback::Good,
-                "NeedsRefinement" => {
-                    Feedback::NeedsRefinement
+/// Runs a command that needs the panel.
+async fn run(command: Command, system: &ConsensusSystem, config: &Config, panels: &Panels, output: OutputFormat, display: Option<&Addr<TerminalDisplay>>, reviewer: Option<Recipient<ReviewAnswer>>) -> ExitCode {
+    match command {
+        Command::Ask { mut words, question, report, mut context, image, actors, fresh, instructions } => {
+            // --context takes every argument after it, so a question following the files is the last of them.
+            if words.is_empty() && question.is_none() && context.last().is_some_and(|last| !last.exists()) {
+                words = context.pop().into_iter().map(|last| last.to_string_lossy().into_owned()).collect();
+            }
+            let question = match question {
+                Some(question) => question,
+                None if !words.is_empty() => words.join(" "),
+                None => match read_stdin() {
+                    Ok(question) => question,
+                    Err(e) => {
+                        error!("Unable to read the question from stdin: {}", e);
+                        return ExitCode::FAILURE
+                    }
                 },
-                _ => {
-                    error!("Unexpected response from EvaluateAnswer: {}", result);
-                    Feedback::NeedsRefinement
+            };
+            let attachments = read_documents(&context)
+                .and_then(|documents| Ok((documents::excerpts(&documents, &config.documents), read_images(&image)?)));
+            let (documents, images) = match attachments {
+                Ok(attachments) => attachments,
+                Err(e) => {
+                    error!("{}", e);
+                    return ExitCode::FAILURE
                 }
-            }, reasoning: reasoning});
-        };
+            };
+            let actors = actors.iter().map(|name| name.trim().to_string()).filter(|name| !name.is_empty()).collect();
+            ask(system, question, QuestionOptions { documents, images, actors, reviewer, fresh, instructions, ..QuestionOptions::default() }, report, output, display).await
+        },
+        Command::Repl { session } => {
+            repl(system, config, panels, output, display, reviewer, session.as_deref()).await;
+            ExitCode::SUCCESS
+        },
+        Command::Tui => match tui::run(system).await {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                error!("Unable to run the full-screen interface: {}", e);
+                ExitCode::FAILURE
+            }
+        },
+        Command::Batch { file, out, concurrency } => run_batch(system, &file, out, concurrency).await,
+        Command::Replay { transcript, question, step } => replay(system, config, &transcript, question, step, output, display).await,
+        Command::Serve { address, #[cfg(feature = "grpc")] grpc } => {
+            match panels.watcher(config) {
+                Ok(Some(watcher)) => {
+                    actix::spawn(serve_reloads(system.clone(), panels.clone(), watcher));
+                },
+                Ok(None) => {},
+                Err(e) => {
+                    error!("{}", e);
+                    return ExitCode::FAILURE
+                },
+            }
+            #[cfg(feature = "grpc")]
+            if let Some(grpc) = grpc {
+                let system = system.clone();
+                actix::spawn(async move {
+                    if let Err(e) = llm_consensus::grpc::serve(system, grpc).await {
+                        error!("Unable to serve the consensus gRPC API on {}: {}", grpc, e);
+                    }
+                });
+            }
+            match server::serve(system.clone(), &address).await {
+                Ok(()) => ExitCode::SUCCESS,
+                Err(e) => {
+                    error!("Unable to serve the consensus API on {}: {}", address, e);
+                    ExitCode::FAILURE
+                }
+            }
+        },
+        Command::History { .. } | Command::Personas { .. } => unreachable!("history and personas are handled before the panel starts"),
+    }
+}
+
+fn read_stdin() -> io::Result<String> {
+    let mut input = String::new();
+    io::stdin().read_to_string(&mut input)?;
+    Ok(input)
+}
 
-        Arbiter::current().spawn(execution);
-        true
+/// Asks a single question, prints the answer to stdout, writes the report if one was asked for and reports
+/// whether the panel agreed on it.
+async fn ask(system: &ConsensusSystem, question: String, options: QuestionOptions, report: Option<PathBuf>, output: OutputFormat, display: Option<&Addr<TerminalDisplay>>) -> ExitCode {
+    let question = question.trim();
+    if question.is_empty() {
+        error!("No question was given.");
+        return ExitCode::FAILURE
+    }
+    let question = match read_question(question) {
+        Ok(question) => question,
+        Err(e) => {
+            error!("{}", e);
+            return ExitCode::FAILURE
+        }
+    };
+    let result = system.ask_with(question, options).await;
+    clear_status(display).await;
+    let result = match result {
+        Ok(result) => result,
+        Err(e) => {
+            error!("Unable to answer the question: {}", e);
+            return ExitCode::FAILURE
+        }
+    };
+    if output == OutputFormat::Json {
+        print_json(&result);
+    } else {
+        println!("{}", result.answer);
+        report_cached(&result, "ask it with --fresh");
+        report_dissent(&result);
+        log_summary(system, &result).await;
+    }
+    if let Some(path) = report {
+        if let Err(e) = fs::write(&path, report::markdown(&result)) {
+            error!("Unable to write the report to {}: {}", path.display(), e);
+            return ExitCode::FAILURE
+        }
+    }
+    match result.consensus_reached {
+        true => ExitCode::SUCCESS,
+        false => ExitCode::from(NO_CONSENSUS_EXIT_CODE),
     }
 }
 
-impl Handler<RefineAnswer> for LlmActor {
-    type Result = bool;
+/// Answers every question in the batch file, writing each result as a JSON line to `out` or stdout.
+async fn run_batch(system: &ConsensusSystem, file: &PathBuf, out: Option<PathBuf>, concurrency: usize) -> ExitCode {
+    let entries = match fs::read_to_string(file).map_err(|e| e.to_string()).and_then(|contents| batch::parse(&contents)) {
+        Ok(entries) => entries,
+        Err(e) => {
+            error!("Unable to read the questions in {}: {}", file.display(), e);
+            return ExitCode::FAILURE
+        }
+    };
+    let mut writer: Box<dyn Write> = match &out {
+        Some(path) => match File::create(path) {
+            Ok(file) => Box::new(BufWriter::new(file)),
+            Err(e) => {
+                error!("Unable to create {}: {}", path.display(), e);
+                return ExitCode::FAILURE
+            }
+        },
+        None => Box::new(io::stdout()),
+    };
 
-    fn handle(&mut self, msg: RefineAnswer, _: &mut Self::Context) -> Self::Result {
-        // Simulate refining the answer by calling OpenAI again with a refinement prompt
-        let prompt = format!(r"
----
-Question: {}
----
-Answer: {}
----
-Your Instructions:
-A user asked this question, and they received the specified answer. When asked to evaluate this answer, you said it needed refinement. Please refine the answer as necessary for your knowledge domain, {}.
+    let total = entries.len();
+    let (mut failed, mut without_consensus, mut write_error) = (0, 0, None);
+    batch::run(system, entries, concurrency, |result| {
+        match result.consensus_reached {
+            None => failed += 1,
+            Some(false) => without_consensus += 1,
+            Some(true) => (),
+        }
+        info!("Finished line {} ({}).", result.line, result.status);
+        let written = serde_json::to_string(&result)
+            .map_err(io::Error::from)
+            .and_then(|json| writeln!(writer, "{}", json))
+            .and_then(|()| writer.flush());
+        if let Err(e) = written {
+            write_error.get_or_insert(e);
+        }
+    }).await;
 
-Specifically, keep the following things in mind while refining the answer. They do not need to be included, but they should influence your refinement:{}", msg.question, msg.answer, self.domain, self.tuning).replace("\"", "");
+    info!("Answered {} of {} questions, {} without consensus.", total - failed, total, without_consensus);
+    if let Some(e) = write_error {
+        error!("Unable to write the results: {}", e);
+        return ExitCode::FAILURE
+    }
+    match (failed, without_consensus) {
+        (0, 0) => ExitCode::SUCCESS,
+        (0, _) => ExitCode::from(NO_CONSENSUS_EXIT_CODE),
+        _ => ExitCode::FAILURE,
+    }
+}
 
-        let execution = async move{
-            let response = call_gemini(prompt).await.expect("expect successful response");
-            Coordinator::from_registry().do_send(AnswerRefinement(response));
-        };
+/// Replays the questions recorded in the transcript, or only the one recorded under `question_id`, printing each
+/// answer like [ask].
+async fn replay(system: &ConsensusSystem, config: &Config, path: &PathBuf, question_id: Option<u64>, step: bool, output: OutputFormat, display: Option<&Addr<TerminalDisplay>>) -> ExitCode {
+    let recordings = match fs::read_to_string(path).map_err(|e| e.to_string()).and_then(|transcript| replay::read(&transcript)) {
+        Ok(recordings) => recordings,
+        Err(e) => {
+            error!("Unable to read the transcript {}: {}", path.display(), e);
+            return ExitCode::FAILURE
+        }
+    };
+    let recordings: Vec<Recording> = recordings.into_iter()
+        .filter(|recording| question_id.is_none_or(|id| recording.question_id == QuestionId(id)))
+        .collect();
+    if recordings.is_empty() {
+        error!("The transcript {} records no {}.", path.display(), question_id.map_or_else(|| "questions".to_string(), |id| format!("question {}", id)));
+        return ExitCode::FAILURE
+    }
 
-        Arbiter::current().spawn(execution);
-        true
+    let (mut failed, mut without_consensus) = (0, 0);
+    for recording in &recordings {
+        match replay_question(system, config, recording, step, output, display).await {
+            Ok(true) => (),
+            Ok(false) => without_consensus += 1,
+            Err(e) => {
+                error!("Unable to replay question {}: {}", recording.question_id, e);
+                failed += 1;
+            },
+        }
+    }
+    match (failed, without_consensus) {
+        (0, 0) => ExitCode::SUCCESS,
+        (0, _) => ExitCode::from(NO_CONSENSUS_EXIT_CODE),
+        _ => ExitCode::FAILURE,
+    }
+}
+
+/// Replays one question on the actors of the panel the transcript names, prints the answer and reports whether
+/// the panel agreed on it.
+async fn replay_question(system: &ConsensusSystem, config: &Config, recording: &Recording, step: bool, output: OutputFormat, display: Option<&Addr<TerminalDisplay>>) -> Result<bool, String> {
+    let recorded = recording.actors();
+    if let Some(missing) = recorded.iter().find(|name| config.actors.iter().all(|actor| actor.name != **name)) {
+        return Err(format!("{} took part in it, but is not on the panel", missing));
+    }
+    for actor in system.actors().await.map_err(|e| e.to_string())? {
+        if !recorded.contains(actor.name.as_str()) {
+            system.unregister(actor.name).await.map_err(|e| e.to_string())?;
+        }
     }
+    let gate = step.then(|| Arc::new(RoundGate::new()));
+    let script = recording.script(gate.clone());
+    for actor in config.actors.iter().filter(|actor| recorded.contains(actor.name.as_str())) {
+        system.add_actor_with(actor, Arc::new(ReplayProvider::new(actor.name.clone(), script.clone())));
+    }
+    system.detect_convergence(recording.convergence());
+    system.moderate(recording.moderation());
+
+    info!("Replaying question {}: {}", recording.question_id, recording.question);
+    let asked = system.ask_with(recording.question.clone(), QuestionOptions { strategy: Some(recording.strategy), ..QuestionOptions::default() });
+    let result = match &gate {
+        Some(gate) => tokio::select! {
+            result = asked => result,
+            never = step_through(gate, display) => match never {},
+        },
+        None => asked.await,
+    };
+    clear_status(display).await;
+    let result = result.map_err(|e| e.to_string())?;
+    if output == OutputFormat::Json {
+        print_json(&result);
+    } else {
+        println!("{}", result.answer);
+        report_dissent(&result);
+    }
+    if recording.answer().is_some_and(|answer| answer != result.answer) {
+        warn!("The replay of question {} settled on a different answer than the transcript records.", recording.question_id);
+    }
+    Ok(result.consensus_reached)
 }
 
-// Define the Coordinator Actor
-#[derive(Default)]
-struct Coordinator {
-    llm_actors: HashMap<String, Addr<LlmActor>>,
-    current_question: Option<String>,
-    feedback: HashMap<String, Feedback>,
-    answer: Option<String>,
-    evaluation_count: u32
+/// Lets a replay through the gate a round at a time, each once Enter is pressed. Every round is let through once
+/// stdin ends.
+async fn step_through(gate: &RoundGate, display: Option<&Addr<TerminalDisplay>>) -> Infallible {
+    loop {
+        let round = gate.held().await;
+        clear_status(display).await;
+        eprint!("Round {} is next. Press Enter to replay it.", round);
+        let pressed = tokio::task::spawn_blocking(|| io::stdin().read_line(&mut String::new())).await;
+        match pressed {
+            Ok(Ok(read)) if read > 0 => gate.open(round),
+            _ => {
+                gate.open(usize::MAX);
+                return future::pending().await
+            },
+        }
+    }
 }
 
-impl Coordinator {
-    fn reset(&mut self) {
-        self.current_question = None;
-        self.answer = None;
-        self.feedback.clear();
-        self.evaluation_count = 0;
+/// Reads questions from stdin until "exit" or the end of input, with line editing and history. Questions typed
+/// while the panel is busy wait in the Coordinator's queue, so the prompt comes back as soon as each is asked.
+async fn repl(system: &ConsensusSystem, config: &Config, panels: &Panels, output: OutputFormat, display: Option<&Addr<TerminalDisplay>>, reviewer: Option<Recipient<ReviewAnswer>>, session: Option<&str>) {
+    // The config of the panel in use, which /panel and --watch replace, and the config file, which --watch rereads.
+    let mut config = config.clone();
+    let mut panels = panels.clone();
+    let mut watcher = match panels.watcher(&config) {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            error!("{}", e);
+            return
+        }
+    };
+    one_at_a_time(&mut config.settings);
+    system.configure(config.settings.clone());
+    // The conversation so far, while conversation memory is on.
+    let mut conversation = config.conversation.enabled.then(|| Conversation::new(&config.conversation));
+    // Every question answered this session, which /history lists and the last of which /reasoning and /export
+    // show.
+    let mut answered: Vec<ConsensusResult> = Vec::new();
+    // The actors /only asks the questions of, or the whole panel if empty.
+    let mut only: Vec<String> = Vec::new();
+    let (session_path, saved) = match session.map(open_session).transpose() {
+        Ok(Some((path, saved))) => (Some(path), saved),
+        Ok(None) => (None, None),
+        Err(e) => {
+            error!("Unable to resume the session: {}", e);
+            return
+        }
+    };
+    if let Some(resumed) = saved {
+        conversation = resumed.conversation.map(|exchanges| {
+            let mut conversation = Conversation::new(&config.conversation);
+            exchanges.into_iter().for_each(|exchange| conversation.record(exchange.question, exchange.answer));
+            conversation
+        });
+        if let Some(last) = resumed.answered.iter().map(|result| result.question_id).max() {
+            system.number_questions_after(last);
+        }
+        answered = resumed.answered;
+        info!("Resumed the session, with {} questions answered so far.", answered.len());
+    }
+    let mut editor = match LineEditor::new() {
+        Ok(editor) => editor,
+        Err(e) => {
+            error!("Unable to read from the terminal: {}", e);
+            return
+        }
+    };
+    // The questions asked and not yet answered, in flight or queued.
+    let mut asked = FuturesUnordered::new();
+    loop {
+        let read = editor.read("Enter a question: ");
+        tokio::pin!(read);
+        // Answers are reported as they land, while the next question is being typed.
+        let input = loop {
+            tokio::select! {
+                input = &mut read => break input,
+                Some((question, result)) = asked.next() => {
+                    report_answer(system, output, display, &mut conversation, &mut answered, question, result).await;
+                    save_session(session_path.as_deref(), &config, conversation.as_ref(), &answered);
+                },
+                () = config_changed(&mut watcher) => {
+                    if let Some(reloaded) = reload_panel(system, &mut panels, one_at_a_time).await {
+                        config = reloaded;
+                        save_session(session_path.as_deref(), &config, conversation.as_ref(), &answered);
+                    }
+                },
+                Ok(()) = signal::ctrl_c() => {
+                    if asked.is_empty() {
+                        println!();
+                        break Ok(Input::End);
+                    }
+                    cancel_asked(system).await;
+                },
+            }
+        };
+        let question = match input {
+            Ok(Input::Line(input)) => input.trim().to_string(),
+            Ok(Input::Interrupted) if !asked.is_empty() => {
+                cancel_asked(system).await;
+                continue;
+            },
+            Ok(Input::Interrupted | Input::End) => break,
+            Err(e) => {
+                error!("Unable to read from the terminal: {}", e);
+                break;
+            },
+        };
+
+        if question == "exit" || question == "/exit" {
+            break;
+        }
+        if let Some(command) = question.strip_prefix('/') {
+            run_command(system, &mut config, &mut panels, &mut conversation, &mut only, &answered, command).await;
+            save_session(session_path.as_deref(), &config, conversation.as_ref(), &answered);
+            continue;
+        }
+
+        let (options, question) = match take_directives(&question, &config.settings) {
+            Ok((options, question)) => (options, question.to_string()),
+            Err(e) => {
+                error!("{}", e);
+                continue;
+            },
+        };
+        if question.is_empty() {
+            error!("The directives need a question after them.");
+            continue;
+        }
+        if options.budget.is_some_and(|budget| budget.limits_spending()) && !config.settings.budget.limits_spending() {
+            warn_unmetered(&config);
+        }
+        let question = match read_question(&question) {
+            Ok(question) => question,
+            Err(e) => {
+                error!("{}", e);
+                continue;
+            },
+        };
+        // The context is the conversation as it stands now, without the answers to questions still in flight.
+        let context = conversation.as_ref().map(Conversation::exchanges).unwrap_or_default();
+        let (queued, mut places) = mpsc::unbounded_channel();
+        let asking = system.ask_with(question.clone(), QuestionOptions { context, actors: only.clone(), reviewer: reviewer.clone(), queued: Some(queued), ..options });
+        asked.push(async move {
+            tokio::pin!(asking);
+            loop {
+                tokio::select! {
+                    result = &mut asking => return (question, result),
+                    Some(place) = places.recv() => info!("The panel is busy, so the question waits in the queue at position {}.", place),
+                }
+            }
+        });
+        // The review is typed at the same prompt, so each answer is waited for before the next question.
+        if reviewer.is_some() {
+            wait_for_answers(system, output, display, &mut conversation, &mut answered, &mut asked).await;
+            save_session(session_path.as_deref(), &config, conversation.as_ref(), &answered);
+        }
     }
+    wait_for_answers(system, output, display, &mut conversation, &mut answered, &mut asked).await;
+    save_session(session_path.as_deref(), &config, conversation.as_ref(), &answered);
+    editor.save_history();
 }
 
-impl Actor for Coordinator {
-    type Context = Context<Self>;
+/// Waits for --watch to see the config file change, or forever if it is not watching.
+async fn config_changed(watcher: &mut Option<ConfigWatcher>) {
+    if let Some(watcher) = watcher {
+        if watcher.changed().await {
+            return;
+        }
+    }
+    future::pending().await
 }
 
-impl Handler<Register> for Coordinator {
-    type Result = bool;
+/// Reloads the panel in use from the config file once --watch has seen it change, returning the panel's new config
+/// with `adjust` applied to its settings, or None if the panel could not be reloaded and carries on as it was.
+async fn reload_panel(system: &ConsensusSystem, panels: &mut Panels, adjust: fn(&mut ConsensusSettings)) -> Option<Config> {
+    let reloaded = match panels.reread() {
+        Ok((file, mut panel)) => {
+            adjust(&mut panel.settings);
+            system.reload(&panel).await.map(|()| (file, panel)).map_err(|e| e.to_string())
+        },
+        Err(e) => Err(e),
+    };
+    match reloaded {
+        Ok((file, panel)) => {
+            info!("Reloaded the {} panel from the config file. Questions in flight finish with the panel they started with.", panels.profile);
+            panels.config = file;
+            Some(panel)
+        },
+        Err(e) => {
+            error!("The config file changed, but the panel cannot be reloaded from it, so it carries on as it was: {}", e);
+            None
+        },
+    }
+}
 
-    fn handle(&mut self, msg: Register, _ctx: &mut Self::Context) -> Self::Result {
-        self.llm_actors.insert(msg.name.clone(), msg.actor);
-        debug!("{} registered with Coordinator.", msg.name);
-        true
+/// Reloads the panel being served each time --watch sees the config file change.
+async fn serve_reloads(system: ConsensusSystem, mut panels: Panels, mut watcher: ConfigWatcher) {
+    while watcher.changed().await {
+        reload_panel(&system, &mut panels, |_| {}).await;
     }
 }
 
-impl Handler<AskQuestion> for Coordinator {
-    type Result = bool;
+/// Saves the panel, the conversation and the answers so far, if the REPL keeps a session.
+fn save_session(path: Option<&Path>, config: &Config, conversation: Option<&Conversation>, answered: &[ConsensusResult]) {
+    let Some(path) = path else { return };
+    let session = Session {
+        panel: Profile { settings: config.settings.clone(), actors: config.actors.clone() },
+        conversation: conversation.map(Conversation::exchanges),
+        answered: answered.to_vec(),
+    };
+    if let Err(e) = session.save(path) {
+        warn!("Unable to save the session: {}", e);
+    }
+}
 
-    fn handle(&mut self, msg: AskQuestion, _ctx: &mut Self::Context) -> Self::Result {
-        debug!("Received AskQuestion: {}", msg.0);
-        self.current_question = Some(msg.0.clone());
+/// Makes the panel take the REPL's questions one at a time, unless the settings allow more at once.
+fn one_at_a_time(settings: &mut ConsensusSettings) {
+    settings.max_concurrent_questions.get_or_insert(1);
+}
 
-        // Select a random LLM actor
-        let keys = self.llm_actors.keys().collect::<Vec<&String>>();
-        let llm_actor = self.llm_actors.get(keys.choose(&mut rand::thread_rng()).expect("choose() should select a random key").to_owned());
+/// Cancels every question the REPL asked, which ends the wait for each.
+async fn cancel_asked(system: &ConsensusSystem) {
+    if let Err(e) = system.cancel_all().await {
+        error!("Unable to cancel the questions: {}", e);
+    }
+}
 
-        // Ask the LLM actor for an answer
-        match llm_actor {
-            Some(addr) =>  {
-                addr.do_send(msg);
-                true
+/// Reports the answers to the questions asked as they land, cancelling them if Ctrl-C is pressed.
+async fn wait_for_answers<F>(system: &ConsensusSystem, output: OutputFormat, display: Option<&Addr<TerminalDisplay>>, conversation: &mut Option<Conversation>, answered: &mut Vec<ConsensusResult>, asked: &mut FuturesUnordered<F>)
+where
+    F: Future<Output = (String, Result<ConsensusResult, AskError>)>,
+{
+    loop {
+        tokio::select! {
+            answer = asked.next() => match answer {
+                Some((question, result)) => report_answer(system, output, display, conversation, answered, question, result).await,
+                None => return,
             },
-            None => false,
-        }
-    }
-}
-
-impl Handler<AnswerQuestion> for Coordinator {
-    type Result = bool;
-
-    fn handle(&mut self, msg: AnswerQuestion, _ctx: &mut Self::Context) -> Self::Result {
-        debug!("Received answer to current question: {}", msg.0);
-        self.answer = Some(msg.0.clone());
-
-        debug!("Asking actors to evaluate answer.");
-        self.llm_actors.values().for_each(|addr| addr.do_send(EvaluateAnswer{
-            question: self.current_question.as_ref().expect("current_question should exist").clone(),
-            answer: msg.0.clone()
-        }));
-        self.evaluation_count += 1;
-        true
-    }
-}
-
-impl Handler<AnswerEvaluation> for Coordinator {
-    type Result = bool;
-
-    fn handle(&mut self, msg: AnswerEvaluation, _ctx: &mut Self::Context) -> Self::Result {
-        debug!("{} evaluated the answer as {:?}. {}", msg.name, msg.evaluation, msg.reasoning);
-        self.feedback.insert(msg.name, msg.evaluation);
-        if self.feedback.len() == self.llm_actors.len() {
-            if !self.feedback.values().all(|&f| f == Feedback::Good) {
-                // Select a random actor that voted NeedsRefinement
-                let keys: Vec<String> = self.feedback.clone().into_iter()
-                    .filter(|(_, value)| *value == Feedback::NeedsRefinement)
-                    .map(|(key, _)| key)
-                    .collect();
-                let selected_key = keys.choose(&mut rand::thread_rng()).expect("choose() should select a random key").to_owned();
-                let llm_actor = self.llm_actors.get(&selected_key);
-
-                let refinement_request = RefineAnswer {
-                    question: self.current_question.clone().expect("current_question should exist to get the answer refined"),
-                    answer: self.answer.clone().expect("answer should exist to get it refined")
-                };
-                return match llm_actor {
-                    Some(addr) =>  {
-                        debug!("Asking {} to refine the answer.", selected_key);
-                        addr.do_send(refinement_request);
-                        true
-                    },
-                    None => false,
-                }
-            }
+            Ok(()) = signal::ctrl_c() => cancel_asked(system).await,
         }
-        true
     }
 }
 
-impl Handler<AnswerRefinement> for Coordinator {
-    type Result = bool;
+/// Shows the answer to a question asked in the REPL and adds it to the conversation.
+async fn report_answer(system: &ConsensusSystem, output: OutputFormat, display: Option<&Addr<TerminalDisplay>>, conversation: &mut Option<Conversation>, answered: &mut Vec<ConsensusResult>, question: String, result: Result<ConsensusResult, AskError>) {
+    clear_status(display).await;
+    if let (Some(conversation), Ok(result)) = (conversation.as_mut(), &result) {
+        conversation.record(question, result.answer.clone());
+    }
+    match result {
+        Ok(result) => {
+            if output == OutputFormat::Json {
+                print_json(&result);
+            } else {
+                info!("Final answer: {}", result.answer);
+                report_cached(&result, "start it with !fresh");
+                report_dissent(&result);
+                log_summary(system, &result).await;
+            }
+            answered.push(result);
+        },
+        Err(AskError::Cancelled) => info!("The question was cancelled."),
+        Err(e) => error!("Unable to answer the question: {}", e),
+    }
+}
+
+/// The question, or the contents of the file it names if it is `@<file>`.
+fn read_question(question: &str) -> Result<String, String> {
+    let Some(path) = question.strip_prefix('@').map(str::trim) else { return Ok(question.to_string()) };
+    let contents = fs::read_to_string(path).map_err(|e| format!("Unable to read the question from {}: {}", path, e))?;
+    match contents.trim() {
+        "" => Err(format!("{} holds no question.", path)),
+        question => Ok(question.to_string()),
+    }
+}
+
+/// Reads the files attached to a question with --context, naming each by its path.
+fn read_documents(paths: &[PathBuf]) -> Result<Vec<Document>, String> {
+    paths.iter()
+        .map(|path| match fs::read_to_string(path) {
+            Ok(text) => Ok(Document { name: path.display().to_string(), text }),
+            Err(e) => Err(format!("Unable to read the context document {}: {}", path.display(), e)),
+        })
+        .collect()
+}
 
-    fn handle(&mut self, msg: AnswerRefinement, _ctx: &mut Self::Context) -> Self::Result {
-        self.answer = Some(msg.0.clone());
-        debug!("Received new answer to current question: {}", msg.0);
-        // TODO: Make max count configurable.
-        if self.evaluation_count < 5 {
-            self.evaluation_count += 1;
-            self.feedback.clear();
-            debug!("Asking actors to evaluate new answer.");
-            self.llm_actors.values().for_each(|addr| addr.do_send(EvaluateAnswer{
-                question: self.current_question.as_ref().expect("current_question should exist").clone(),
-                answer: msg.0.clone()
-            }));
-        } else {
-            debug!("Evaluated the maximum number of times. Breaking the loop.");
-            self.feedback.iter_mut().for_each(|(_, value)| *value = Feedback::Good);
+/// Reads the images attached to a question with --image.
+fn read_images(paths: &[PathBuf]) -> Result<Vec<Image>, String> {
+    paths.iter()
+        .map(|path| {
+            let data = fs::read(path).map_err(|e| format!("Unable to read the image {}: {}", path.display(), e))?;
+            Image::new(data).ok_or_else(|| format!("{} is not a PNG, JPEG, GIF or WebP image.", path.display()))
+        })
+        .collect()
+}
+
+/// Splits the `!name=value` and `!fresh` directives at the start of a question from the question itself, returning the
+/// overrides they ask for. Constraints and budgets asked for are added to the panel's own.
+fn take_directives<'a>(input: &'a str, settings: &ConsensusSettings) -> Result<(QuestionOptions, &'a str), String> {
+    let constraints = &settings.constraints;
+    let mut options = QuestionOptions::default();
+    let mut rest = input.trim_start();
+    while let Some(directive) = rest.strip_prefix('!') {
+        let (directive, remainder) = directive.split_once(char::is_whitespace).unwrap_or((directive, ""));
+        rest = remainder.trim_start();
+        if directive == "fresh" {
+            options.fresh = true;
+            continue;
+        }
+        let Some((name, value)) = directive.split_once('=') else {
+            return Err(format!("The directive !{} needs a value, as in !{}=<value>.", directive, directive));
+        };
+        match name {
+            "rounds" => match value.parse() {
+                Ok(0) | Err(_) => return Err(format!("!rounds needs a number of rounds of at least 1, not \"{}\".", value)),
+                Ok(rounds) => options.max_rounds = Some(rounds),
+            },
+            "strategy" => options.strategy = Some(value.parse::<ConsensusStrategy>().map_err(|e| format!("Invalid !strategy: {}.", e))?),
+            "words" => match value.parse() {
+                Ok(0) | Err(_) => return Err(format!("!words needs a number of words of at least 1, not \"{}\".", value)),
+                Ok(words) => options.constraints.get_or_insert_with(|| constraints.clone()).max_words = Some(words),
+            },
+            "format" => {
+                let format = value.parse::<AnswerFormat>().map_err(|e| format!("Invalid !format: {}.", e))?;
+                options.constraints.get_or_insert_with(|| constraints.clone()).format = Some(format);
+            },
+            "budget" => match value.parse::<f64>() {
+                Ok(cost) if cost.is_finite() && cost > 0.0 => options.budget.get_or_insert(settings.budget.question).cost = Some(cost),
+                _ => return Err(format!("!budget needs a positive cost, not \"{}\".", value)),
+            },
+            "deadline" => match value.parse::<f64>() {
+                Ok(secs) if secs.is_finite() && secs > 0.0 => options.budget.get_or_insert(settings.budget.question).deadline_secs = Some(secs),
+                _ => return Err(format!("!deadline needs a positive number of seconds, not \"{}\".", value)),
+            },
+            other => return Err(format!("Unknown directive !{}. The directives are !rounds, !strategy, !words, !format, !budget, !deadline and !fresh.", other)),
         }
-        true
     }
+    Ok((options, rest))
 }
 
-impl Handler<AnswerReadinessRequest> for Coordinator {
-    type Result = bool;
+/// Warns that token and cost budgets do not count the calls of the panel's actors whose providers do not report the
+/// tokens they use, if there are any.
+fn warn_unmetered(config: &Config) {
+    let unmetered = config.unmetered_actors();
+    if !unmetered.is_empty() {
+        warn!("The budget cannot count the calls of {}, whose providers do not report the tokens they use.", unmetered.join(", "));
+    }
+}
 
-    fn handle(&mut self, _msg: AnswerReadinessRequest, _ctx: &mut Self::Context) -> Self::Result {
-        self.answer.is_some() && 
-        !self.feedback.is_empty() && 
-        self.feedback.len() == self.llm_actors.len() &&
-        self.feedback.values().all(|v| v == &Feedback::Good)
+/// The actors `/add` takes by name: those on the panel, then every one the config file defines, in any profile.
+fn defined_actors<'a>(config: &'a Config, panels: &'a Panels) -> impl Iterator<Item = &'a ActorConfig> {
+    let profiles = panels.config.profiles.values().flat_map(|profile| &profile.actors);
+    config.actors.iter().chain(&panels.config.actors).chain(profiles)
+}
+
+/// Runs one of the REPL's `/` commands, other than `/exit`, for changing the panel, the settings, the conversation
+/// memory or the actors `only` the questions are asked of, or for looking back over the questions `answered` this
+/// session.
+async fn run_command(system: &ConsensusSystem, config: &mut Config, panels: &mut Panels, conversation: &mut Option<Conversation>, only: &mut Vec<String>, answered: &[ConsensusResult], command: &str) {
+    let (name, argument) = command.split_once(' ').map_or((command, ""), |(name, argument)| (name, argument.trim()));
+    match (name, argument) {
+        ("help", _) => println!("{}", REPL_HELP),
+        ("history", _) if answered.is_empty() => println!("No questions have been answered yet."),
+        ("history", _) => for result in answered {
+            let outcome = if result.consensus_reached { "consensus" } else { "no consensus" };
+            println!("#{} {} ({}, {} refinement rounds)", result.question_id, result.question, outcome, result.refinement_rounds);
+        },
+        ("reasoning", _) => match answered.last() {
+            Some(result) => print_reasoning(result),
+            None => error!("There is no deliberation to show yet."),
+        },
+        ("rounds", "") => println!("The panel evaluates each answer at most {} times.", config.settings.max_rounds),
+        ("rounds", argument) => match argument.parse() {
+            Ok(0) | Err(_) => error!("/rounds needs a number of rounds of at least 1, not \"{}\".", argument),
+            Ok(rounds) => {
+                config.settings.max_rounds = rounds;
+                system.configure(config.settings.clone());
+                info!("The panel will evaluate each answer at most {} times.", rounds);
+            },
+        },
+        ("actors", _) => match system.actors().await {
+            Ok(actors) => actors.iter().for_each(|actor| println!("{} (weight {})", actor.name, actor.weight)),
+            Err(e) => error!("Unable to list the actors: {}", e),
+        },
+        ("only", "") if only.is_empty() => println!("Questions are asked of the whole panel."),
+        ("only", "") => println!("Questions are asked of {} only.", only.join(", ")),
+        ("only", "all") => {
+            only.clear();
+            info!("Questions will be asked of the whole panel.");
+        },
+        ("only", argument) => {
+            let names: Vec<String> = argument.split(',').map(str::trim).filter(|name| !name.is_empty()).map(str::to_string).collect();
+            let on_panel = match system.actors().await {
+                Ok(actors) => actors,
+                Err(e) => {
+                    error!("Unable to list the actors: {}", e);
+                    return
+                }
+            };
+            match names.iter().find(|name| on_panel.iter().all(|actor| actor.name != **name)) {
+                Some(missing) => error!("No actor named {} is on the panel.", missing),
+                None => {
+                    info!("Questions will be asked of {} only.", names.join(", "));
+                    *only = names;
+                },
+            }
+        },
+        ("stats", _) => match system.actor_stats().await {
+            Ok(stats) if stats.is_empty() => println!("No actor has been asked anything yet."),
+            Ok(stats) => stats.iter().for_each(|(name, stats)| println!("{}: {}", name, describe_stats(stats))),
+            Err(e) => error!("Unable to get the actors' stats: {}", e),
+        },
+        ("add", "") | ("remove", "") => error!("/{} needs an actor.", name),
+        ("add", argument) => {
+            let mut actor_config = if argument.starts_with('{') {
+                match ActorConfig::parse_inline(argument) {
+                    Ok(actor_config) => actor_config,
+                    Err(e) => {
+                        error!("Unable to read the actor: {}", e);
+                        return
+                    }
+                }
+            } else if let Some(actor_config) = defined_actors(config, panels).find(|actor| actor.name == argument) {
+                actor_config.clone()
+            } else if let (Some(persona), Some(first)) = (personas::find(argument), config.actors.first()) {
+                let mut actor_config = ActorConfig { name: String::new(), base: Some(persona.key.clone()), persona: Persona::default(), role: None, weight: 1.0, tools: Vec::new(), ..first.clone() };
+                if let Err(e) = actor_config.take_persona() {
+                    error!("Unable to take the {} persona: {}", argument, e);
+                    return
+                }
+                actor_config
+            } else {
+                error!("No actor named {} is defined in the config file, and no built-in persona has that key.", argument);
+                return
+            };
+            if panels.dry_run {
+                actor_config.dry_run();
+            }
+            match system.add_actor(&actor_config) {
+                Ok(()) => {
+                    info!("{} joined the panel.", actor_config.name);
+                    config.actors.retain(|actor| actor.name != actor_config.name);
+                    config.actors.push(actor_config);
+                },
+                Err(e) => error!("Unable to create the provider for {}: {}", actor_config.name, e),
+            }
+        },
+        ("remove", argument) => match system.unregister(argument).await {
+            Ok(true) => {
+                info!("{} left the panel.", argument);
+                config.actors.retain(|actor| actor.name != argument);
+                if only.iter().any(|name| name == argument) {
+                    only.retain(|name| name != argument);
+                    if only.is_empty() {
+                        info!("Questions will be asked of the whole panel.");
+                    } else {
+                        info!("Questions will be asked of {} only.", only.join(", "));
+                    }
+                }
+            },
+            Ok(false) => error!("No actor named {} is on the panel.", argument),
+            Err(e) => error!("Unable to remove {}: {}", argument, e),
+        },
+        ("panel", "") => {
+            let describe = |actors: &[ActorConfig]| actors.iter().map(|actor| actor.name.as_str()).collect::<Vec<_>>().join(", ");
+            println!("{}: {}", DEFAULT_PROFILE, describe(&panels.config.actors));
+            for (name, profile) in &panels.config.profiles {
+                println!("{}: {}", name, describe(&profile.actors));
+            }
+        },
+        ("panel", argument) => {
+            let mut panel = match panels.get(argument) {
+                Ok(panel) => panel,
+                Err(e) => {
+                    error!("Unable to use the {} panel: {}", argument, e);
+                    return
+                }
+            };
+            one_at_a_time(&mut panel.settings);
+            match system.replace_panel(&panel.actors, panel.settings.clone()).await {
+                Ok(()) => {
+                    info!("Switched to the {} panel.", argument);
+                    panels.profile = argument.to_string();
+                    only.clear();
+                    *config = panel;
+                },
+                Err(e) => error!("Unable to use the {} panel: {}", argument, e),
+            }
+        },
+        ("context", "on") => {
+            if conversation.is_none() {
+                *conversation = Some(Conversation::new(&config.conversation));
+            }
+            info!("Answers will be drafted in the context of the conversation.");
+        },
+        ("context", "off") => {
+            *conversation = None;
+            info!("The conversation is forgotten, and each question will be answered on its own.");
+        },
+        ("context", _) => error!("/context needs on or off."),
+        ("export", argument) => {
+            let Some(result) = answered.last() else {
+                error!("There is no deliberation to export yet.");
+                return
+            };
+            let path = match argument {
+                "" => PathBuf::from(format!("deliberation-{}.md", result.question_id)),
+                path => PathBuf::from(path),
+            };
+            match fs::write(&path, report::markdown(result)) {
+                Ok(()) => info!("Wrote the report to {}.", path.display()),
+                Err(e) => error!("Unable to write the report to {}: {}", path.display(), e),
+            }
+        },
+        _ => error!("Unknown command /{}. /help lists the commands.", name),
     }
 }
 
-impl Handler<GetAnswer> for Coordinator {
-    type Result = String;
+/// Describes how an actor has fared, leaving out what it has not done yet.
+fn describe_stats(stats: &ActorStats) -> String {
+    let mut parts = Vec::new();
+    if let Some(latency) = stats.mean_answer_latency() {
+        parts.push(format!("writes an answer in {:.1}s on average over {} calls", latency.as_secs_f64(), stats.answer_calls));
+    }
+    if let Some(latency) = stats.mean_evaluation_latency() {
+        parts.push(format!("judges in {:.1}s on average over {} calls", latency.as_secs_f64(), stats.evaluation_calls));
+    }
+    if let Some(share) = stats.good_share() {
+        parts.push(format!("found {:.0}% of {} answers Good", share * 100.0, stats.good + stats.needs_refinement));
+    }
+    if stats.abstained > 0 {
+        parts.push(format!("abstained on {} answers", stats.abstained));
+    }
+    if let Some(share) = stats.refinement_success() {
+        parts.push(format!("had {:.0}% of {} rewrites agreed on", share * 100.0, stats.refinements));
+    }
+    match parts.is_empty() {
+        true => "nothing yet".to_string(),
+        false => parts.join(", "),
+    }
+}
 
-    fn handle(&mut self, _msg: GetAnswer, _ctx: &mut Self::Context) -> Self::Result {
-        if let Some(answer) = &self.answer {
-            return answer.to_owned();
+/// Prints every version of the answer the panel deliberated on last, with how each actor evaluated it and why.
+fn print_reasoning(result: &ConsensusResult) {
+    println!("Question: {}", result.question);
+    for (round, version) in result.rounds.iter().enumerate() {
+        let action = if round == 0 { "drafted" } else { "refined" };
+        println!("\nVersion {}, {} by {}:\n{}", round, action, version.author, version.answer);
+        for evaluation in &version.evaluations {
+            let verdict = match evaluation.feedback {
+                Feedback::Good => "good",
+                Feedback::NeedsRefinement => "needs refinement",
+                Feedback::Abstain => "abstained",
+            };
+            match evaluation.score {
+                Some(score) => println!("  {}: {} ({}/10). {}", evaluation.actor, verdict, score, evaluation.reasoning),
+                None => println!("  {}: {}. {}", evaluation.actor, verdict, evaluation.reasoning),
+            }
         }
-        "System error: Requested answer when answer was not ready.".to_string()
     }
 }
 
-impl Handler<Reset> for Coordinator {
-    type Result = bool;
+/// Erases the display's status line, if there is one, so that the result is printed on a line of its own.
+async fn clear_status(display: Option<&Addr<TerminalDisplay>>) {
+    if let Some(display) = display {
+        let _ = display.send(ClearStatus).await;
+    }
+}
 
-    fn handle(&mut self, _msg: Reset, _ctx: &mut Self::Context) -> Self::Result {
-        self.reset();
-        true
+fn print_json(result: &ConsensusResult) {
+    match serde_json::to_string_pretty(result) {
+        Ok(json) => println!("{}", json),
+        Err(e) => error!("Unable to serialize the result: {}", e),
     }
 }
 
-impl actix::Supervised for Coordinator {}
-impl SystemService for Coordinator {}
+/// Says which earlier question the answer was reused from, if it was, and how to have the panel deliberate on the
+/// question instead.
+fn report_cached(result: &ConsensusResult, refresh: &str) {
+    if let Some(cached) = &result.cached {
+        eprintln!("The panel agreed on this answer on {} to a question {:.0}% similar: \"{}\". To have it deliberate on this one, {}.",
+            cached.finished_at, cached.similarity * 100.0, cached.question, refresh);
+    }
+}
 
-#[actix::main]
-async fn main() {
-    env_logger::init();
-
-    if env::var("GEMINI_API_KEY").is_err() {
-        error!("No Gemini API key has been set in the GEMINI_API_KEY environment variable. Generate an API key and set it with \"export GEMINI_API_KEY=<your API key>\".");
-        return
-    }
-
-    Coordinator::from_registry().do_send(Register { 
-        name: "High Society".to_string(), 
-        actor: LlmActor {
-            name: "High Society".to_string(),
-            domain: "Society and Culture".to_string(),
-            tuning: r"
-* Social norms, values, and beliefs
-* Historical context and events
-* Cultural diversity and traditions
-* Social structures and institutions (e.g., family, education, government)
-* Impact on human behavior and interactions
-* Ethical and moral considerations
-* Current events and social issues
-* Demographics and population trends
-* Communication styles and languages
-* Arts, literature, and folklore as reflections of society".to_string()
-        }.start()});
-    Coordinator::from_registry().do_send(Register { 
-        name: "The Technician".to_string(), 
-        actor: LlmActor {
-            name: "The Technician".to_string(),
-            domain: "Technical Detail".to_string(),
-            tuning: r"
-* Accuracy and precision of information
-* Specific measurements, quantities, and units
-* Technical specifications and standards
-* Detailed procedures and processes
-* Scientific principles and theories
-* Mathematical formulas and equations
-* Logical reasoning and problem-solving
-* Causality and cause-and-effect relationships
-* Step-by-step explanations and instructions
-* Attention to detail and completeness".to_string()
-        }.start()});
-    Coordinator::from_registry().do_send(Register { 
-        name: "Art Boy".to_string(), 
-        actor: LlmActor {
-            name: "Art Boy".to_string(),
-            domain: "Art and Imagination".to_string(),
-            tuning: r"
-* Creative expression and generation across various mediums (visual, auditory, written, etc.)
-* Tools and techniques for artistic creation (digital and traditional)
-* Exploration of emotions, ideas, and concepts through art
-* Imagination, innovation, and originality
-* Aesthetic qualities and principles (e.g., composition, color, form)
-* Art history, movements, and styles
-* Cultural and social influences on art
-* Potential for visualizing data or creating simulations for artistic purposes
-* Interactive art and installations
-* The role of art in communication and storytelling".to_string()
-        }.start()});
-    Coordinator::from_registry().do_send(Register { 
-        name: "Programming Nerd".to_string(), 
-        actor: LlmActor {
-            name: "Programming Nerd".to_string(),
-            domain: "Computer Science".to_string(),
-            tuning: r"
-* Algorithms and data structures
-* Programming languages and paradigms
-* Software engineering principles
-* Computer architecture and hardware
-* Networking and distributed systems
-* Artificial intelligence and machine learning
-* Cybersecurity and data privacy
-* Computational theory and complexity
-* Databases and data management
-* Operating systems and system programming".to_string()
-        }.start()});
+/// Shows on stderr which actors still objected to an answer that was only accepted because the panel ran out
+/// of rounds or budget, converged or deadlocked, and why, or why the moderator refused the question.
+fn report_dissent(result: &ConsensusResult) {
+    if result.consensus_reached {
+        return;
+    }
+    if let Some(reason) = &result.flagged {
+        eprintln!("The moderator refused the question: {}", reason);
+        return;
+    }
+    if result.converged {
+        eprintln!("The panel's refinements stopped changing this answer before it agreed on it.");
+    } else if let Some(reason) = &result.budget_exceeded {
+        eprintln!("The panel stopped before agreeing on this answer, since {}.", reason);
+    } else if let Some(reason) = &result.deadlock {
+        eprintln!("The panel was going in circles, since {}, so it settled on this answer without agreeing on it.", reason);
+    } else if result.ruling.is_some() {
+        eprintln!("The panel ran out of rounds before agreeing, so its chair ruled on this answer.");
+    } else {
+        eprintln!("The panel ran out of rounds before agreeing on this answer.");
+    }
+    if let Some(reasoning) = &result.ruling {
+        eprintln!("The chair's ruling: {}", reasoning);
+    }
+    for dissent in &result.dissent {
+        eprintln!("{} still objected: {}", dissent.actor, dissent.reasoning);
+    }
+}
 
-    loop {
-        // Get user input
-        print!("Enter a question: ");
-        io::stdout().flush().expect("stdout should flush"); // Ensure prompt is printed immediately
+/// Logs how confident the panel is in the answer, which actors' fallbacks answered for them, and the tokens and
+/// estimated cost of the answer and of the session so far.
+async fn log_summary(system: &ConsensusSystem, result: &ConsensusResult) {
+    for (actor, backend) in &result.fallbacks {
+        warn!("{}'s provider failed, so its fallback {} answered for it.", actor, backend);
+    }
+    info!("The panel is {:.0}% confident in this answer, which {}", result.confidence * 100.0, describe_usage(&result.usage.total));
+    match system.usage().await {
+        Ok(session) => info!("This session {}", describe_usage(&session.total)),
+        Err(e) => error!("Unable to read the session's usage: {}", e),
+    }
+}
 
-        let mut input = String::new();
-        io::stdin().read_line(&mut input).expect("stdin should be able to read a line");
-        let question = input.trim().to_string();
+/// Summarizes token usage and estimated cost, e.g.
+/// "used 1200 prompt and 300 completion tokens in 5 calls, costing about 0.0042."
+fn describe_usage(usage: &UsageTotals) -> String {
+    let unmetered = match usage.unmetered_calls {
+        0 => String::new(),
+        calls => format!(" ({} calls reported no usage)", calls),
+    };
+    format!("used {} prompt and {} completion tokens in {} calls{}, costing about {:.4}.",
+        usage.prompt_tokens, usage.completion_tokens, usage.calls, unmetered, usage.estimated_cost)
+}
 
-        if question == "exit" {
-            break;
+/// Prints the most recent runs in the configured history database whose question or answer contains `search`.
+fn print_history(config: &Config, search: &str, limit: usize, output: OutputFormat) -> ExitCode {
+    let Some(history_config) = &config.history else {
+        error!("No history database is configured. Add a [history] section with a path to the config file.");
+        return ExitCode::FAILURE
+    };
+    let entries = match History::open(&history_config.path).and_then(|history| history.search(Some(search), limit)) {
+        Ok(entries) => entries,
+        Err(e) => {
+            error!("Unable to read the history in {}: {}", history_config.path.display(), e);
+            return ExitCode::FAILURE
         }
+    };
 
-        // Ask the Coordinator actor
-        let question_received = Coordinator::from_registry()
-            .send(AskQuestion(question))
-            .await
-            .expect("should be able to ask question to Coordinator");
+    if output == OutputFormat::Json {
+        return match serde_json::to_string_pretty(&entries) {
+            Ok(json) => {
+                println!("{}", json);
+                ExitCode::SUCCESS
+            },
+            Err(e) => {
+                error!("Unable to serialize the history: {}", e);
+                ExitCode::FAILURE
+            }
+        };
+    }
+    for entry in entries {
+        println!("#{} {} ({} refinement rounds, {:.1}s)", entry.id, entry.finished_at, entry.refinement_rounds, entry.elapsed_secs);
+        println!("Question: {}", entry.question);
+        println!("Answer: {}", entry.answer);
+        let final_round = entry.votes.iter().map(|vote| vote.round).max().unwrap_or_default();
+        let votes: Vec<String> = entry.votes.iter()
+            .filter(|vote| vote.round == final_round)
+            .map(|vote| match vote.score {
+                Some(score) => format!("{} {}", vote.actor, score),
+                None => format!("{} {}", vote.actor, vote.feedback),
+            })
+            .collect();
+        println!("Final votes: {}\n", votes.join(", "));
+    }
+    ExitCode::SUCCESS
+}
 
-        if question_received {
-            let mut answer_ready = false;
-            let mut timestamp = Instant::now();
-            while !answer_ready {
-                if timestamp.elapsed().as_millis() < 500 {
-                    continue;
-                }
-                timestamp = Instant::now();
-                answer_ready = Coordinator::from_registry()
-                    .send(AnswerReadinessRequest)
-                    .await
-                    .expect("should be able to check answer readiness with the Coordinator");
+/// Prints the built-in persona library.
+fn print_personas(output: OutputFormat) -> ExitCode {
+    let library = personas::library();
+    if output == OutputFormat::Json {
+        return match serde_json::to_string_pretty(library) {
+            Ok(json) => {
+                println!("{}", json);
+                ExitCode::SUCCESS
+            },
+            Err(e) => {
+                error!("Unable to serialize the personas: {}", e);
+                ExitCode::FAILURE
             }
-            let response = Coordinator::from_registry()
-                .send(GetAnswer)
-                .await
-                .expect("should be able to get the answer from the Coordinator");
-            info!("Final answer: {}", response);
-        }
+        };
+    }
+    for entry in library {
+        println!("{} ({}, {})", entry.key, entry.name, entry.persona.domain);
+        println!("  {}", entry.description);
+    }
+    ExitCode::SUCCESS
+}
+
+#[cfg(test)]
+mod tests {
+    use llm_consensus::{budget::{Budget, BudgetSettings}, constraints::AnswerConstraints};
+
+    use super::*;
+
+    #[test]
+    fn directives_are_taken_off_the_question() {
+        let (options, question) = take_directives("!rounds=2 !strategy=majority !fresh  Why?", &ConsensusSettings::default()).unwrap();
+        assert_eq!(question, "Why?");
+        assert_eq!(options.max_rounds, Some(2));
+        assert_eq!(options.strategy, Some(ConsensusStrategy::Majority));
+        assert!(options.fresh);
+    }
+
+    #[test]
+    fn directives_without_a_question_leave_it_empty() {
+        let (options, question) = take_directives("!rounds=2", &ConsensusSettings::default()).unwrap();
+        assert_eq!(question, "");
+        assert_eq!(options.max_rounds, Some(2));
+    }
 
-        Coordinator::from_registry()
-            .send(Reset)
-            .await
-            .expect("Coordinator should reset");
+    #[test]
+    fn bad_directives_are_rejected() {
+        let settings = ConsensusSettings::default();
+        assert!(take_directives("!rounds=0 Why?", &settings).unwrap_err().contains("at least 1"));
+        assert!(take_directives("!colour=red Why?", &settings).unwrap_err().starts_with("Unknown directive !colour."));
+        assert!(take_directives("!rounds Why?", &settings).unwrap_err().contains("needs a value"));
+        assert!(take_directives("!fresh=yes Why?", &settings).unwrap_err().starts_with("Unknown directive !fresh."));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn constraint_and_budget_directives_add_to_the_panels_own() {
+        let settings = ConsensusSettings {
+            constraints: AnswerConstraints { citations: true, ..AnswerConstraints::default() },
+            budget: BudgetSettings { question: Budget { tokens: Some(1000), ..Budget::default() }, ..BudgetSettings::default() },
+            ..ConsensusSettings::default()
+        };
+        let (options, _) = take_directives("!words=50 !format=bullets !budget=0.5 !deadline=30 Why?", &settings).unwrap();
+        assert_eq!(options.constraints, Some(AnswerConstraints { max_words: Some(50), format: Some(AnswerFormat::Bullets), citations: true }));
+        assert_eq!(options.budget, Some(Budget { tokens: Some(1000), cost: Some(0.5), deadline_secs: Some(30.0) }));
+    }
+}
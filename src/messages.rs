@@ -0,0 +1,611 @@
+//! Messages exchanged between the [Coordinator](crate::Coordinator) and the [LlmActor]s.
+
+use std::{collections::BTreeMap, time::Duration};
+
+use actix::prelude::*;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::sync::mpsc;
+use tracing::Span;
+
+use crate::{
+    actors::LlmActor,
+    budget::Budget,
+    constraints::AnswerConstraints,
+    conversation::Exchange,
+    documents::Excerpt,
+    convergence::Convergence,
+    error::ConsensusError,
+    history::HistoryRecorder,
+    moderation::Moderation,
+    observer::ConsensusObserver,
+    prompts::Template,
+    provider::{Image, ProviderError, ToolCall, ToolOutput, Usage},
+    result::{Evaluation, Round},
+    sandbox::{CodeRun, Sandbox},
+    stats::ActorStats,
+    tools::Tool,
+    strategy::{ConsensusSettings, ConsensusStrategy, EvaluationMode, Sampling, Stage},
+    transcript::{Transcript, TranscriptEvent},
+    usage::{Pricing, UsageSummary},
+    AskError,
+    ConsensusResult,
+};
+
+// The plain types the messages are made of, where they were first defined.
+pub use crate::{config::ActorRole, result::{Feedback, QuestionId}, transcript::{COORDINATOR, MODERATOR, REVIEWER, SANDBOX}};
+
+/// Registers the LLM actor's name and [Addr] with the [Coordinator](crate::Coordinator).
+#[derive(Message)]
+#[rtype(result = "bool")]
+pub struct Register {
+    pub name: String,
+    pub actor: Addr<LlmActor>,
+    /// The actor's knowledge domain, which questions are routed by.
+    pub domain: String,
+    /// How the Coordinator treats the actor's evaluations.
+    pub role: ActorRole,
+    /// How much the actor's vote counts under weighted strategies.
+    pub weight: f64,
+    /// What the actor's model costs, for estimating the cost of its calls.
+    pub pricing: Pricing,
+    /// The tools the Coordinator runs for the actor when its model calls them.
+    pub tools: Vec<Tool>,
+}
+
+/// Registers several actors with the [Coordinator](crate::Coordinator) at once. Resolves with how many actors the
+/// panel has once they are all registered, so that questions can wait for the whole panel.
+#[derive(Message)]
+#[rtype(result = "usize")]
+pub struct RegisterAll(pub Vec<Register>);
+
+/// Removes the named actor from the panel. Questions in flight carry on without it.
+/// Resolves to false if no actor has that name.
+#[derive(Message)]
+#[rtype(result = "bool")]
+pub struct Unregister {
+    pub name: String
+}
+
+/// Asks the [Coordinator](crate::Coordinator) who is on the panel.
+#[derive(Message)]
+#[rtype(result = "Vec<ActorInfo>")]
+pub struct ListActors;
+
+/// A registered actor, as listed by [ListActors].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ActorInfo {
+    pub name: String,
+    pub weight: f64,
+}
+
+/// Asks the [Coordinator](crate::Coordinator) to [Ping] every actor on the panel. Resolves with how each one
+/// fared, by name.
+#[derive(Message)]
+#[rtype(result = "Vec<ActorHealth>")]
+pub struct HealthCheck;
+
+/// Asks an LLM actor to check that it can reach the models it answers and evaluates with.
+#[derive(Message)]
+#[rtype(result = "Result<(), ProviderError>")]
+pub struct Ping;
+
+/// How one actor fared in a [HealthCheck].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ActorHealth {
+    pub name: String,
+    /// Why the actor could not reach its model, or None if it could.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Sent to the [Coordinator](crate::Coordinator) to ask the panel a question.
+/// Resolves with the result once the panel reaches consensus.
+#[derive(Message)]
+#[rtype(result = "Result<ConsensusResult, AskError>")]
+pub struct AskQuestion {
+    pub question: String,
+    pub options: QuestionOptions
+}
+
+/// Per-question overrides of the [Coordinator](crate::Coordinator)'s session settings.
+#[derive(Debug, Clone, Default)]
+pub struct QuestionOptions {
+    /// Consensus strategy for this question only.
+    pub strategy: Option<ConsensusStrategy>,
+    /// How many times the panel evaluates the answer to this question only before accepting it without consensus.
+    pub max_rounds: Option<u32>,
+    /// The length and format the answer to this question only is held to.
+    pub constraints: Option<AnswerConstraints>,
+    /// Instructions added to the preamble the answer to this question only is drafted after, such as who it is
+    /// for.
+    pub instructions: Option<String>,
+    /// What this question only may spend, in place of the question budget of the settings.
+    pub budget: Option<Budget>,
+    /// The names of the actors this question only is asked of, leaving the rest of the panel out of drafting,
+    /// evaluating and refining its answer, so that consensus is decided among them alone. The whole panel if empty.
+    pub actors: Vec<String>,
+    /// Earlier questions and answers of the conversation, oldest first, which the answer is drafted in the context of.
+    pub context: Vec<Exchange>,
+    /// Excerpts of the documents attached to this question, made with [excerpts](crate::documents::excerpts),
+    /// which are quoted when the answer is drafted, evaluated and refined. Any retrieved for the question are
+    /// added to them.
+    pub documents: Vec<Excerpt>,
+    /// Images the question is about, such as screenshots or diagrams, which are shown to the actors whose models
+    /// can see them when the answer is drafted, evaluated and refined.
+    pub images: Vec<Image>,
+    /// Asked to approve the answer once the panel agrees on it. A rejection sends the answer back to the panel
+    /// to refine, with the reviewer's reason as an extra evaluation by [REVIEWER].
+    pub reviewer: Option<Recipient<ReviewAnswer>>,
+    /// Sent the question's id as soon as the panel takes the question up, before it deliberates, for askers that
+    /// follow its [DeliberationUpdate]s. Dropped unsent if the panel does not take the question.
+    pub accepted: Option<mpsc::UnboundedSender<QuestionId>>,
+    /// Sent the question's place in the queue, counting from 1, if the panel is already deliberating on
+    /// [max_concurrent_questions](crate::ConsensusSettings::max_concurrent_questions) others when it is asked.
+    pub queued: Option<mpsc::UnboundedSender<usize>>,
+    /// Deliberates on the question even if the [AnswerCache](crate::answer_cache::AnswerCache) has the answer to
+    /// one meaning nearly the same.
+    pub fresh: bool,
+}
+
+/// Sent to a question's reviewer with the answer the panel agreed on, before it is accepted.
+#[derive(Message)]
+#[rtype(result = "Review")]
+pub struct ReviewAnswer {
+    pub question_id: QuestionId,
+    pub question: String,
+    pub answer: String,
+}
+
+/// A reviewer's decision on an answer.
+#[derive(Debug, Clone, PartialEq, MessageResponse)]
+pub enum Review {
+    Approve,
+    /// Sends the answer back to the panel to refine.
+    Reject { reason: String },
+}
+
+/// Delivers a reviewer's decision to the [Coordinator](crate::Coordinator).
+#[derive(Message)]
+#[rtype(result = "bool")]
+pub struct AnswerReviewed {
+    pub question_id: QuestionId,
+    pub review: Review,
+}
+
+/// Sent to an LLM actor to draft the first answer to a question.
+#[derive(Message)]
+#[rtype(result = "bool")]
+pub struct DraftAnswer {
+    pub question_id: QuestionId,
+    /// The span of the round the request belongs to, which the actor's call is traced under.
+    pub span: Span,
+    pub question: String,
+    /// Earlier questions and answers of the conversation, oldest first.
+    pub context: Vec<Exchange>,
+    /// Excerpts of the documents attached to the question.
+    pub documents: Vec<Excerpt>,
+    /// Images attached to the question.
+    pub images: Vec<Image>,
+    /// The length and format the answer is held to.
+    pub constraints: AnswerConstraints,
+    /// Instructions the question adds to the actor's preamble, if any.
+    pub instructions: Option<String>,
+    /// How many answers to write and pick the most representative of, under self-consistent drafting.
+    pub sampling: Option<Sampling>,
+}
+
+/// Sent to an LLM actor to pick the panel member whose domain best fits the question, who then drafts its answer.
+#[derive(Message)]
+#[rtype(result = "bool")]
+pub struct RouteQuestion {
+    pub question_id: QuestionId,
+    /// The span of the round the request belongs to, which the actor's call is traced under.
+    pub span: Span,
+    pub question: String,
+    /// The name and domain of every actor on the panel.
+    pub panel: Vec<(String, String)>,
+}
+
+/// Sent as the pick asked for in [RouteQuestion]. `choice` is None if the actor named nobody on the panel
+/// or could not reach its model.
+#[derive(Message)]
+#[rtype(result = "bool")]
+pub struct QuestionRouted {
+    pub question_id: QuestionId,
+    pub name: String,
+    pub choice: Option<String>,
+}
+
+/// Send as the answer to a question posed in [DraftAnswer].
+#[derive(Message)]
+#[rtype(result = "bool")]
+pub struct AnswerQuestion {
+    pub question_id: QuestionId,
+    pub name: String,
+    pub answer: String
+}
+
+#[derive(Debug, Message)]
+#[rtype(result = "bool")]
+pub struct EvaluateAnswer {
+    pub question_id: QuestionId,
+    /// The span of the round the request belongs to, which the actor's call is traced under.
+    pub span: Span,
+    /// The version of the answer being evaluated, starting at 0 for the first draft.
+    pub round: usize,
+    pub question: String,
+    pub answer: String,
+    /// Excerpts of the documents attached to the question.
+    pub documents: Vec<Excerpt>,
+    /// Images attached to the question.
+    pub images: Vec<Image>,
+    pub mode: EvaluationMode,
+    /// Whether the answer cites its sources, which the actor is asked to check its claims against.
+    pub citations: bool,
+    /// How running the answer's code blocks went, if the sandbox ran any.
+    pub code_runs: Vec<CodeRun>,
+    /// Whether the question's deadline is near, so that the actor evaluates with its fast model, if it has one.
+    pub hurry: bool,
+    /// Other actors' evaluations of the previous version of the answer, in debate mode.
+    pub peer_evaluations: Vec<Evaluation>
+}
+
+#[derive(Message)]
+#[rtype(result = "bool")]
+pub struct AnswerEvaluation {
+    pub question_id: QuestionId,
+    /// The round from the [EvaluateAnswer] being answered.
+    pub round: usize,
+    pub name: String,
+    pub evaluation: Feedback,
+    /// The 1 to 10 score, when evaluating in [EvaluationMode::Scored].
+    pub score: Option<u8>,
+    pub reasoning: String
+}
+
+#[derive(Message)]
+#[rtype(result = "bool")]
+pub struct RefineAnswer {
+    pub question_id: QuestionId,
+    /// The span of the round the request belongs to, which the actor's call is traced under.
+    pub span: Span,
+    pub question: String,
+    pub answer: String,
+    /// The reasoning the actor gave when it said the answer needed refinement.
+    pub reasoning: String,
+    /// Why the question's reviewer rejected the answer, if it did.
+    pub veto: Option<String>,
+    /// The chair's guidance on refining the answer, drawn from every evaluation, if the panel has a chair.
+    pub guidance: Option<String>,
+    /// Excerpts of the documents attached to the question.
+    pub documents: Vec<Excerpt>,
+    /// Images attached to the question.
+    pub images: Vec<Image>,
+    /// The length and format the answer is held to.
+    pub constraints: AnswerConstraints,
+}
+
+#[derive(Message)]
+#[rtype(result = "bool")]
+pub struct AnswerRefinement {
+    pub question_id: QuestionId,
+    pub name: String,
+    pub answer: String
+}
+
+/// Sent to a dissenting LLM actor to describe the changes the answer needs, without rewriting it.
+#[derive(Message)]
+#[rtype(result = "bool")]
+pub struct SuggestRefinement {
+    pub question_id: QuestionId,
+    /// The span of the round the request belongs to, which the actor's call is traced under.
+    pub span: Span,
+    pub question: String,
+    pub answer: String,
+    /// The reasoning the actor gave when it said the answer needed refinement.
+    pub reasoning: String
+}
+
+/// An actor's reply to [SuggestRefinement].
+#[derive(Message)]
+#[rtype(result = "bool")]
+pub struct RefinementSuggestion {
+    pub question_id: QuestionId,
+    pub name: String,
+    pub suggestion: String
+}
+
+/// Sent to the synthesizing LLM actor to merge every suggestion into one revised answer.
+/// The actor replies with an [AnswerRefinement].
+#[derive(Message)]
+#[rtype(result = "bool")]
+pub struct SynthesizeAnswer {
+    pub question_id: QuestionId,
+    /// The span of the round the request belongs to, which the actor's call is traced under.
+    pub span: Span,
+    pub question: String,
+    pub answer: String,
+    /// `(actor, suggestion)` pairs from the dissenting actors.
+    pub suggestions: Vec<(String, String)>,
+    /// Excerpts of the documents attached to the question.
+    pub documents: Vec<Excerpt>,
+    /// Images attached to the question.
+    pub images: Vec<Image>,
+    /// The length and format the answer is held to.
+    pub constraints: AnswerConstraints,
+}
+
+/// Sent to the panel's chair once a round of evaluations ends without consensus, for guidance on refining the answer
+/// or, in the final round, a ruling on which version of it to accept. The chair replies with a [ChairDecision].
+#[derive(Message)]
+#[rtype(result = "bool")]
+pub struct Summarize {
+    pub question_id: QuestionId,
+    /// The span of the round the request belongs to, which the actor's call is traced under.
+    pub span: Span,
+    pub question: String,
+    /// Every version of the answer so far and its evaluations, the one just evaluated last.
+    pub rounds: Vec<Round>,
+    /// Whether the chair is to rule on the version to accept, rather than guide the next refinement.
+    pub ruling: bool,
+}
+
+/// The chair's reply to [Summarize].
+#[derive(Message)]
+#[rtype(result = "bool")]
+pub struct ChairDecision {
+    pub question_id: QuestionId,
+    pub name: String,
+    /// The version of the answer the chair ruled to accept, starting at 0 for the first draft. None for guidance,
+    /// or for a ruling that named no version there is.
+    pub version: Option<usize>,
+    /// The chair's guidance, or the reasoning for its ruling.
+    pub text: String,
+}
+
+/// Sent by an LLM actor when its provider failed for good, after any retries, or its response could not be used.
+/// The [Coordinator](crate::Coordinator) fails the question with [AskError::Failed].
+#[derive(Message)]
+#[rtype(result = "bool")]
+pub struct ProviderFailed {
+    pub question_id: QuestionId,
+    pub name: String,
+    pub error: ConsensusError,
+}
+
+/// Sent by an LLM actor as its supervisor restarts it after a call panicked, once for every question it had calls
+/// in flight for, since restarting aborted them. The [Coordinator](crate::Coordinator) sends the question's stage
+/// again, as if it had stalled, or fails the question with [ConsensusError::ActorFailed] once it has been sent
+/// again as often as the stage timeouts allow.
+#[derive(Message)]
+#[rtype(result = "bool")]
+pub struct ActorFailed {
+    pub name: String,
+    pub question_id: QuestionId,
+}
+
+/// Sent by an LLM actor after each successful provider call, with the tokens the call used.
+/// `usage` is None when the provider does not report token counts, and `backend` names the fallback that answered
+/// if the actor's own provider failed.
+#[derive(Message)]
+#[rtype(result = "bool")]
+pub struct UsageReport {
+    pub question_id: QuestionId,
+    pub name: String,
+    pub usage: Option<Usage>,
+    pub backend: Option<String>,
+    /// Whether the call was made with the actor's evaluation model, which may be priced differently.
+    pub evaluation: bool,
+    /// The prompt the call was made with, which says what the call was for.
+    pub template: Template,
+    /// How long the call took.
+    pub latency: Duration,
+}
+
+/// Asks the [Coordinator](crate::Coordinator) to run the tools an actor's model called while working on a
+/// question. Resolves with what each call returned, in order, or why it failed, for the model to go on with.
+#[derive(Message)]
+#[rtype(result = "Vec<ToolOutput>")]
+pub struct UseTools {
+    pub question_id: QuestionId,
+    pub name: String,
+    pub calls: Vec<ToolCall>,
+}
+
+/// Asks the [Coordinator](crate::Coordinator) for every version of the answer to a question in flight so far,
+/// oldest first, with the evaluations each has received. None if the question is not in flight; a finished
+/// question's versions are in its [ConsensusResult::rounds].
+#[derive(Message)]
+#[rtype(result = "Option<Vec<Round>>")]
+pub struct GetAnswerHistory {
+    pub question_id: QuestionId,
+}
+
+/// Asks the [Coordinator](crate::Coordinator) for the usage of every call made this session.
+#[derive(Message)]
+#[rtype(result = "UsageSummary")]
+pub struct GetUsage;
+
+/// Asks the [Coordinator](crate::Coordinator) how each actor has fared this session, by name.
+#[derive(Message)]
+#[rtype(result = "BTreeMap<String, ActorStats>")]
+pub struct GetActorStats;
+
+/// Starts (or, with `None`, stops) recording a [Transcript] of every deliberation.
+#[derive(Message)]
+#[rtype(result = "bool")]
+pub struct RecordTranscript(pub Option<Transcript>);
+
+/// Enables (or, with `None`, disables) stopping each question early once its refinements converge.
+#[derive(Message)]
+#[rtype(result = "bool")]
+pub struct DetectConvergence(pub Option<Convergence>);
+
+/// Enables (or, with `None`, disables) screening each question and version of its answer before it is evaluated.
+#[derive(Message)]
+#[rtype(result = "bool")]
+pub struct ModerateContent(pub Option<Moderation>);
+
+/// Sent to the [Coordinator](crate::Coordinator) once the moderator has screened a version of the answer.
+#[derive(Message)]
+#[rtype(result = "bool")]
+pub struct ContentScreened {
+    pub question_id: QuestionId,
+    /// The version of the answer that was screened, along with the question.
+    pub round: usize,
+    /// Why the content was flagged, None if it was not, or why it could not be screened.
+    pub verdict: Result<Option<String>, ProviderError>,
+}
+
+/// Enables (or, with `None`, disables) running the code blocks of each version of the answer before it is evaluated.
+#[derive(Message)]
+#[rtype(result = "bool")]
+pub struct TestCode(pub Option<Sandbox>);
+
+/// Sent to the [Coordinator](crate::Coordinator) once the sandbox has run the code blocks of a version of the answer.
+#[derive(Message)]
+#[rtype(result = "bool")]
+pub struct CodeTested {
+    pub question_id: QuestionId,
+    /// The version of the answer whose code was run.
+    pub round: usize,
+    pub runs: Vec<CodeRun>,
+}
+
+/// Sent to the [Coordinator](crate::Coordinator) with how similar a refined answer is to the version before it.
+#[derive(Message)]
+#[rtype(result = "bool")]
+pub struct AnswersCompared {
+    pub question_id: QuestionId,
+    /// The version of the answer that was refined, compared with the one before it.
+    pub round: usize,
+    pub similarity: f64,
+}
+
+/// Starts (or, with `None`, stops) sending every finished run to a [HistoryRecorder].
+#[derive(Message)]
+#[rtype(result = "bool")]
+pub struct RecordHistory(pub Option<Addr<HistoryRecorder>>);
+
+/// Sent by the [Coordinator](crate::Coordinator) to the [HistoryRecorder] when the panel settles on an answer.
+#[derive(Message)]
+#[rtype(result = "bool")]
+pub struct ConsensusReached(pub ConsensusResult);
+
+/// Subscribes the recipient to a [DeliberationUpdate] for every step the panel takes.
+/// Recipients whose actor has stopped are dropped.
+#[derive(Message)]
+#[rtype(result = "bool")]
+pub struct Subscribe(pub Recipient<DeliberationUpdate>);
+
+/// Registers a [ConsensusObserver] to be told about every draft, evaluation, refinement and final answer.
+#[derive(Message)]
+#[rtype(result = "bool")]
+pub struct Observe(pub Box<dyn ConsensusObserver>);
+
+/// A step of a deliberation in flight, sent by the [Coordinator](crate::Coordinator) to every subscriber.
+#[derive(Message, Debug, Clone, Serialize)]
+#[rtype(result = "()")]
+pub struct DeliberationUpdate {
+    pub question_id: QuestionId,
+    pub timestamp: DateTime<Utc>,
+    #[serde(flatten)]
+    pub event: TranscriptEvent,
+}
+
+/// A piece of an answer as the actor's model generates it. LLM actors stream drafts and refinements
+/// to the [Coordinator](crate::Coordinator), which passes on those of the answer the panel is working
+/// on to every recipient of [SubscribeTokens].
+#[derive(Message, Debug, Clone, Serialize)]
+#[rtype(result = "()")]
+pub struct AnswerToken {
+    pub question_id: QuestionId,
+    pub name: String,
+    /// [Stage::Drafting] for the first answer, [Stage::Refining] for a refinement.
+    pub stage: Stage,
+    pub token: String,
+}
+
+/// Subscribes the recipient to every [AnswerToken] of the answers the panel works on.
+/// Recipients whose actor has stopped are dropped.
+#[derive(Message)]
+#[rtype(result = "bool")]
+pub struct SubscribeTokens(pub Recipient<AnswerToken>);
+
+/// Replaces the [Coordinator](crate::Coordinator)'s session settings.
+#[derive(Message)]
+#[rtype(result = "bool")]
+pub struct Configure(pub ConsensusSettings);
+
+/// Replaces every actor on the panel, and the session settings, once the questions in flight have finished with the
+/// panel they started with. Questions asked in the meantime wait in the queue for the new panel, and a replacement
+/// sent while another is waiting takes its place.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct ReloadPanel {
+    pub actors: Vec<Register>,
+    pub settings: ConsensusSettings,
+}
+
+/// Numbers the questions asked from now on after the given id, such as when resuming a session whose answers took
+/// the ids up to it. Ids already handed out are never reused.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct NumberQuestionsAfter(pub QuestionId);
+
+/// Sent to an LLM actor to pick the best of several candidate answers.
+#[derive(Message)]
+#[rtype(result = "bool")]
+pub struct VoteOnCandidates {
+    pub question_id: QuestionId,
+    /// The span of the round the request belongs to, which the actor's call is traced under.
+    pub span: Span,
+    pub question: String,
+    pub candidates: Vec<String>
+}
+
+/// An actor's pick among the candidates in [VoteOnCandidates].
+#[derive(Message)]
+#[rtype(result = "bool")]
+pub struct CandidateVote {
+    pub question_id: QuestionId,
+    pub name: String,
+    /// Index of the chosen candidate, or None if the response could not be understood.
+    pub choice: Option<usize>,
+    pub reasoning: String
+}
+
+/// Abandons every question in flight.
+#[derive(Message)]
+#[rtype(result = "bool")]
+pub struct Reset;
+
+/// Stops deliberating on questions in flight and aborts their provider calls. Each asker's
+/// [AskQuestion] fails with [AskError::Cancelled](crate::AskError::Cancelled). Cancelling every question also
+/// empties the queue. Resolves with how many questions were cancelled, queued ones included.
+#[derive(Message)]
+#[rtype(result = "usize")]
+pub struct CancelQuestion {
+    /// The question to cancel, or None to cancel every question in flight.
+    pub question_id: Option<QuestionId>,
+}
+
+/// Sent by the [Coordinator](crate::Coordinator) to abort an actor's provider calls for a question,
+/// or for every question if `question_id` is None.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct CancelCalls {
+    pub question_id: Option<QuestionId>,
+}
+
+/// Cancels every question in flight and resolves once their transcripts and history are written,
+/// so that the actor system can be stopped without losing anything.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Shutdown;
+
+/// Resolves once the [HistoryRecorder] has stored every run sent to it before.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct FlushHistory;
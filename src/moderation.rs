@@ -0,0 +1,109 @@
+//! Screening questions and answers for harmful content before the panel deliberates on them.
+//!
+//! Each version of the answer is screened together with the question before the panel evaluates it, either by a
+//! provider's moderation API or by asking a model with the `moderate` prompt. Flagged content is not deliberated
+//! on: the question finishes at once with a refusal in place of the answer.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use crate::{
+    prompts::{PromptData, Prompts, Template},
+    provider::{CompletionRequest, CredentialsConfig, Limiter, LlmProvider, ModerationProvider, OpenAiProvider, ProviderConfig, ProviderError, ProviderKind, RetryPolicy},
+};
+
+/// What a flagged question is answered with.
+pub const REFUSAL: &str = "The panel will not deliberate on this question, because it was flagged as harmful.";
+
+/// The `[moderation]` section of the config file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum ModerationConfig {
+    /// OpenAI's moderation API, authenticated through `OPENAI_API_KEY`.
+    #[serde(rename = "openai")]
+    OpenAi {
+        /// Defaults to `omni-moderation-latest`.
+        #[serde(default)]
+        model: Option<String>,
+    },
+    /// A model asked whether the content is safe with the `moderate` prompt.
+    Prompt {
+        #[serde(flatten)]
+        provider: Box<ProviderConfig>,
+    },
+}
+
+impl ModerationConfig {
+    /// Checks the config, returning a description of the problem if it is unusable.
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            ModerationConfig::OpenAi { .. } => Ok(()),
+            ModerationConfig::Prompt { provider } if provider.provider == ProviderKind::Panel => {
+                Err("moderation: only actors can stand for a sub-panel".to_string())
+            },
+            ModerationConfig::Prompt { provider } => provider.validate().map_err(|reason| format!("moderation: {}", reason)),
+        }
+    }
+}
+
+/// Asks a model whether the content is safe, with the `moderate` prompt.
+struct PromptModerator {
+    provider: Arc<dyn LlmProvider>,
+    prompts: Arc<Prompts>,
+}
+
+#[async_trait]
+impl ModerationProvider for PromptModerator {
+    async fn moderate(&self, texts: &[String]) -> Result<Option<String>, ProviderError> {
+        let [question, answer] = texts else {
+            return Err(ProviderError::InvalidResponse(format!("the moderation prompt screens a question and an answer, not {} texts", texts.len())));
+        };
+        let data = PromptData { question: question.clone(), answer: answer.clone(), ..PromptData::default() };
+        let request = CompletionRequest { system: None, prompt: self.prompts.render(Template::Moderate, &data), images: Vec::new(), params: Default::default(), template: Template::Moderate, tools: Vec::new(), tool_rounds: Vec::new() };
+        let response = self.provider.complete(&request).await?.text;
+        parse_verdict(&response).ok_or_else(|| ProviderError::InvalidResponse(format!("expected SAFE or FLAGGED, got \"{}\"", response.trim())))
+    }
+}
+
+/// Reads SAFE as None and `FLAGGED: <reason>` as the reason, ignoring case and surrounding markdown.
+fn parse_verdict(response: &str) -> Option<Option<String>> {
+    let response = response.trim().trim_start_matches(['*', '`', '"']);
+    let upper = response.to_uppercase();
+    if upper.starts_with("SAFE") {
+        return Some(None);
+    }
+    if !upper.starts_with("FLAGGED") {
+        return None;
+    }
+    let reason = response["FLAGGED".len()..].trim_start_matches(['*', '`', '"', ':', ' ']).trim();
+    Some(Some(if reason.is_empty() { "flagged by the moderator".to_string() } else { reason.to_string() }))
+}
+
+/// Screens each question and version of its answer before the panel evaluates them.
+#[derive(Clone)]
+pub struct Moderation {
+    moderator: Arc<dyn ModerationProvider>,
+}
+
+impl Moderation {
+    pub fn new(moderator: Arc<dyn ModerationProvider>) -> Self {
+        Moderation { moderator }
+    }
+
+    /// Builds the configured moderator. A prompted model uses the configured keys, its calls follow the retry policy
+    /// and wait for the limiter, like the panel's, and are rendered from the given templates.
+    pub fn from_config(config: &ModerationConfig, credentials: &CredentialsConfig, retry: &RetryPolicy, limiter: Option<&Arc<Limiter>>, prompts: Arc<Prompts>) -> Result<Self, ProviderError> {
+        let moderator: Arc<dyn ModerationProvider> = match config {
+            ModerationConfig::OpenAi { model } => Arc::new(OpenAiProvider::moderation(model.clone())?),
+            ModerationConfig::Prompt { provider } => Arc::new(PromptModerator { provider: provider.build(credentials, retry, None, limiter)?, prompts }),
+        };
+        Ok(Moderation::new(moderator))
+    }
+
+    /// Screens the question and an answer to it, returning why if either is flagged.
+    pub async fn screen(&self, question: &str, answer: &str) -> Result<Option<String>, ProviderError> {
+        self.moderator.moderate(&[question.to_string(), answer.to_string()]).await
+    }
+}
@@ -0,0 +1,26 @@
+//! Hooks for library users to follow the panel's work, such as to log it, store it or show it, without handling
+//! the [Coordinator](crate::Coordinator)'s messages themselves.
+
+use crate::{result::{Evaluation, QuestionId}, ConsensusResult};
+
+/// Told about the steps of every deliberation, once registered with
+/// [ConsensusSystem::observe](crate::ConsensusSystem::observe). Every method does nothing unless overridden.
+///
+/// The methods are called on the [Coordinator](crate::Coordinator)'s thread as each step happens, so they must
+/// return quickly; slow work such as writing to a database belongs on another thread or actor. A
+/// [DeliberationUpdate](crate::messages::DeliberationUpdate) subscriber sees every step instead, as a message.
+pub trait ConsensusObserver: Send + 'static {
+    /// `author` drafted `answer`. Under best-of-N drafting, each candidate draft is reported as it arrives.
+    fn on_draft(&mut self, _question_id: QuestionId, _author: &str, _answer: &str) {}
+
+    /// An actor evaluated version `round` of the answer, starting at 0 for the first draft.
+    fn on_evaluation(&mut self, _question_id: QuestionId, _round: usize, _evaluation: &Evaluation) {}
+
+    /// `author` rewrote the answer as `answer`, alone, in turn, by synthesizing suggestions or as one of the
+    /// rewrites the panel votes on.
+    fn on_refinement(&mut self, _question_id: QuestionId, _author: &str, _answer: &str) {}
+
+    /// The panel settled on an answer, with or without consensus. Questions that fail, or are cancelled or
+    /// abandoned, are not reported.
+    fn on_final(&mut self, _result: &ConsensusResult) {}
+}
@@ -0,0 +1,216 @@
+//! Reading the actors' evaluations, and asking again for responses that cannot be used. Shared by the
+//! [LlmActor](crate::LlmActor)s and the [Engine](crate::engine::Engine), which each pass in how they call a provider.
+
+use async_trait::async_trait;
+use serde::{de::DeserializeOwned, Deserialize};
+use tracing::{debug, warn};
+
+use crate::{
+    constraints::AnswerConstraints,
+    error::ConsensusError,
+    provider::{CompletionRequest, LlmProvider, ProviderError},
+    result::Feedback,
+    strategy::EvaluationMode,
+};
+
+/// How many times an actor is asked for an evaluation before its response is given up on as unusable.
+pub(crate) const MAX_EVALUATION_ATTEMPTS: u32 = 3;
+
+/// How many times an actor is asked for an answer that meets the constraints before its last one is used as it is.
+pub(crate) const MAX_CONSTRAINT_ATTEMPTS: u32 = 3;
+
+/// The prompt asking again for an answer that broke the constraints, explaining how it broke them.
+pub(crate) fn constraint_reprompt(prompt: &str, answer: &str, problem: &str) -> String {
+    format!("{}\n---\nYour previous answer could not be used because {}:\n\n{}\n\nRespond again with only an answer that follows the instructions above.", prompt, problem, answer)
+}
+
+/// Asks the model to fix an evaluation that could not be parsed.
+pub(crate) fn evaluation_reprompt(prompt: &str, response: &str, problem: &str) -> String {
+    format!("{}\n---\nYour previous response could not be used because {}:\n\n{}\n\nRespond again with only the JSON object described above.", prompt, problem, response)
+}
+
+/// How an actor's provider is called, and what the call used accounted for, wherever the actor deliberates.
+#[async_trait]
+pub(crate) trait Caller: Send {
+    async fn complete(&mut self, provider: &dyn LlmProvider, request: &CompletionRequest) -> Result<String, ProviderError>;
+}
+
+/// Asks again for as long as the `name`d actor's `answer` to the `request` breaks the constraints, up to
+/// [MAX_CONSTRAINT_ATTEMPTS] times in all, counting the answer given. The last answer is used even if it still
+/// breaks them.
+pub(crate) async fn keep_to_constraints(provider: &dyn LlmProvider, mut request: CompletionRequest, mut answer: String, constraints: &AnswerConstraints, name: &str, caller: &mut impl Caller) -> Result<String, ProviderError> {
+    let prompt = request.prompt.clone();
+    for attempt in 1.. {
+        let Err(problem) = constraints.check(&answer) else { break };
+        if attempt >= MAX_CONSTRAINT_ATTEMPTS {
+            warn!("{}'s answer still breaks the constraints after {} attempts ({}), using it anyway", name, attempt, problem);
+            break;
+        }
+        debug!("{}'s answer breaks the constraints ({}), asking again", name, problem);
+        request.prompt = constraint_reprompt(&prompt, &answer, &problem);
+        answer = caller.complete(provider, &request).await?;
+    }
+    Ok(answer)
+}
+
+/// Asks the `name`d actor for its evaluation, asking again up to [MAX_EVALUATION_ATTEMPTS] times in all while it
+/// cannot be read under the `mode`. Returns its verdict, any score and its reasoning.
+pub(crate) async fn ask_for_evaluation(provider: &dyn LlmProvider, mut request: CompletionRequest, mode: EvaluationMode, name: &str, caller: &mut impl Caller) -> Result<(Feedback, Option<u8>, String), ConsensusError> {
+    let prompt = request.prompt.clone();
+    let mut attempt = 1;
+    loop {
+        let response = caller.complete(provider, &request).await
+            .map_err(|error| ConsensusError::Provider { actor: name.to_string(), call: "EvaluateAnswer", error })?;
+        let parsed = match mode {
+            EvaluationMode::Binary => parse_binary_evaluation(&response).map(|(feedback, reasoning)| (feedback, None, reasoning)),
+            EvaluationMode::Scored { threshold } => parse_scored_evaluation(&response, threshold),
+        };
+        let problem = match parsed {
+            Ok(parsed) => return Ok(parsed),
+            Err(problem) => problem,
+        };
+        if attempt >= MAX_EVALUATION_ATTEMPTS {
+            let reason = format!("{} after {} attempts: {}", problem, attempt, response);
+            return Err(ConsensusError::Parse { actor: name.to_string(), call: "EvaluateAnswer", reason });
+        }
+        warn!("{} gave an unusable evaluation ({}), asking again: {}", name, problem, response);
+        request.prompt = evaluation_reprompt(&prompt, &response, &problem);
+        attempt += 1;
+    }
+}
+
+/// The JSON object requested by the [Template::BinaryEvaluation](crate::prompts::Template::BinaryEvaluation) prompt.
+#[derive(Deserialize)]
+struct BinaryVerdict {
+    verdict: String,
+    reasoning: String,
+}
+
+/// The JSON object requested by the [Template::ScoredEvaluation](crate::prompts::Template::ScoredEvaluation) prompt,
+/// which has an Abstain verdict in place of the score if the evaluator abstains.
+#[derive(Deserialize)]
+struct ScoredVerdict {
+    #[serde(default)]
+    score: Option<f64>,
+    #[serde(default)]
+    verdict: Option<String>,
+    reasoning: String,
+}
+
+/// The verdict without its spaces, punctuation and case, such as `needsrefinement`.
+fn normalize(verdict: &str) -> String {
+    verdict.chars()
+        .filter(|c| c.is_ascii_alphabetic())
+        .collect::<String>()
+        .to_lowercase()
+}
+
+/// Finds the JSON object in a response, skipping any markdown fences or text the model wrapped it in.
+fn extract_json(response: &str) -> Result<&str, String> {
+    match (response.find('{'), response.rfind('}')) {
+        (Some(start), Some(end)) if start < end => Ok(&response[start..=end]),
+        _ => Err("it did not contain a JSON object".to_string()),
+    }
+}
+
+/// Reads the JSON object in a response. A model quoting the question or answer in its reasoning sometimes leaves the
+/// quotes unescaped, so an object that does not parse is read again with them escaped.
+fn read_object<T: DeserializeOwned>(response: &str) -> Result<T, String> {
+    let json = extract_json(response)?;
+    serde_json::from_str(json)
+        .or_else(|e| serde_json::from_str(&escape_stray_quotes(json)).map_err(|_| e))
+        .map_err(|e| format!("it was not a valid evaluation object ({})", e))
+}
+
+/// Escapes the quotes inside the object's strings that cannot end them, since they are not followed, past any
+/// whitespace, by a colon, a closing brace or bracket, or a comma and the next key.
+fn escape_stray_quotes(json: &str) -> String {
+    let mut escaped = String::with_capacity(json.len());
+    let (mut in_string, mut backslash) = (false, false);
+    for (index, c) in json.char_indices() {
+        match c {
+            _ if backslash => backslash = false,
+            '\\' if in_string => backslash = true,
+            '"' if !in_string => in_string = true,
+            '"' if ends_string(&json[index + 1..]) => in_string = false,
+            '"' => escaped.push('\\'),
+            _ => (),
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Whether a quote followed by `rest` can end a string of the object.
+fn ends_string(rest: &str) -> bool {
+    let rest = rest.trim_start();
+    match rest.chars().next() {
+        None | Some(':' | '}' | ']') => true,
+        Some(',') => rest[1..].trim_start().strip_prefix('"')
+            .and_then(|key| key.split_once('"'))
+            .is_some_and(|(_, after)| after.trim_start().starts_with(':')),
+        Some(_) => false,
+    }
+}
+
+pub(crate) fn parse_binary_evaluation(response: &str) -> Result<(Feedback, String), String> {
+    let verdict: BinaryVerdict = read_object(response)?;
+    let feedback = match normalize(&verdict.verdict).as_str() {
+        "good" => Feedback::Good,
+        "needsrefinement" => Feedback::NeedsRefinement,
+        "abstain" | "abstained" => Feedback::Abstain,
+        _ => return Err(format!("the verdict \"{}\" is not Good, NeedsRefinement or Abstain", verdict.verdict)),
+    };
+    Ok((feedback, verdict.reasoning))
+}
+
+/// Reads a scored evaluation, returning no score if the evaluator abstained.
+pub(crate) fn parse_scored_evaluation(response: &str, threshold: f64) -> Result<(Feedback, Option<u8>, String), String> {
+    let verdict: ScoredVerdict = read_object(response)?;
+    let score = match (verdict.score, verdict.verdict.as_deref().map(normalize)) {
+        (Some(score), _) => score,
+        (None, Some(abstain)) if abstain == "abstain" || abstain == "abstained" => return Ok((Feedback::Abstain, None, verdict.reasoning)),
+        (None, _) => return Err("it gave neither a score nor an Abstain verdict".to_string()),
+    };
+    if !(1.0..=10.0).contains(&score) {
+        return Err(format!("the score {} is not between 1 and 10", score));
+    }
+    let score = score.round() as u8;
+    let feedback = if score as f64 >= threshold { Feedback::Good } else { Feedback::NeedsRefinement };
+    Ok((feedback, Some(score), verdict.reasoning))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REASONING: &str = r#"It explains how "{}", "a" and "quoted" end up in the output, as it's "right"."#;
+
+    fn unescaped_evaluation() -> String {
+        format!(r#"{{"verdict": "Good", "reasoning": "{}"}}"#, REASONING)
+    }
+
+    #[test]
+    fn escape_stray_quotes_repairs_inner_quotes() {
+        let evaluation = unescaped_evaluation();
+        assert!(serde_json::from_str::<serde_json::Value>(&evaluation).is_err());
+
+        let repaired: serde_json::Value = serde_json::from_str(&escape_stray_quotes(&evaluation))
+            .expect("the escaped evaluation should be valid JSON");
+        assert_eq!(repaired["verdict"], "Good");
+        assert_eq!(repaired["reasoning"], REASONING);
+    }
+
+    #[test]
+    fn escape_stray_quotes_keeps_valid_json() {
+        let evaluation = serde_json::json!({ "verdict": "Good", "reasoning": REASONING }).to_string();
+        assert_eq!(escape_stray_quotes(&evaluation), evaluation);
+    }
+
+    #[test]
+    fn binary_evaluation_keeps_inner_quotes() {
+        let (feedback, reasoning) = parse_binary_evaluation(&unescaped_evaluation()).expect("the evaluation should parse");
+        assert_eq!(feedback, Feedback::Good);
+        assert_eq!(reasoning, REASONING);
+    }
+}
@@ -0,0 +1,117 @@
+//! The personas actors take on, and the built-in library of reusable ones, so that a panel can be assembled
+//! without describing every actor from scratch.
+//!
+//! A [Persona] is what an actor answers and judges from: its domain, the areas of it the actor is expert in, the
+//! rubric it checks every answer against, the topics it declines to judge and the tone it writes in. The persona
+//! and evaluation prompts are rendered from it.
+//!
+//! The library is the repository's `personas.toml`, embedded in the binary. An actor in the config file builds on
+//! a persona from it with `persona = "<key>"`, as [Persona::compose] describes, and keeps any name, role or tools it
+//! sets itself.
+
+use std::sync::OnceLock;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{config::ActorRole, tools::Tool};
+
+/// What an actor answers and judges from, set in the config file alongside its provider.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Persona {
+    /// The knowledge domain, such as `Computer Science`, which questions are routed by.
+    pub domain: String,
+    /// The areas of the domain the actor is expert in, one bullet each. Configs written before personas had a
+    /// rubric call them `tuning`.
+    #[serde(alias = "tuning")]
+    pub expertise: Vec<String>,
+    /// What the actor checks every answer for, such as `Does the code handle errors?`, whatever the question.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub rubric: Vec<String>,
+    /// Topics the actor declines to judge, leaving answers about them to the rest of the panel.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub refusals: Vec<String>,
+    /// How the actor writes, such as `blunt` or `patient and encouraging`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tone: Option<String>,
+    /// What the actor is told before a question it drafts an answer to, in place of the panel's preamble.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preamble: Option<String>,
+}
+
+impl Persona {
+    /// Checks the persona, returning what is wrong with it, worded to follow the actor's name, if it is unusable.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.domain.trim().is_empty() {
+            return Err("needs a non-empty domain".to_string());
+        }
+        if self.expertise.is_empty() {
+            return Err("needs at least one expertise item".to_string());
+        }
+        for (items, what) in [(&self.expertise, "expertise item"), (&self.rubric, "rubric item"), (&self.refusals, "refusal")] {
+            if items.iter().any(|item| item.trim().is_empty()) {
+                return Err(format!("has an empty {}", what));
+            }
+        }
+        if self.tone.as_ref().is_some_and(|tone| tone.trim().is_empty()) {
+            return Err("needs a non-empty tone if it sets one".to_string());
+        }
+        Ok(())
+    }
+
+    /// Builds the persona on another, such as one from the library: the domain, expertise, tone and preamble it
+    /// leaves out are taken from the other, and the other's rubric items and refusals come before its own, so that
+    /// an actor can add to what a library persona checks for without repeating it.
+    pub fn compose(&mut self, base: &Persona) {
+        if self.domain.trim().is_empty() {
+            self.domain = base.domain.clone();
+        }
+        if self.expertise.is_empty() {
+            self.expertise = base.expertise.clone();
+        }
+        self.tone = self.tone.take().or_else(|| base.tone.clone());
+        self.preamble = self.preamble.take().or_else(|| base.preamble.clone());
+        for (items, base_items) in [(&mut self.rubric, &base.rubric), (&mut self.refusals, &base.refusals)] {
+            let own = std::mem::take(items);
+            items.extend(base_items.iter().cloned());
+            items.extend(own.into_iter().filter(|item| !base_items.contains(item)));
+        }
+    }
+}
+
+/// A persona from the built-in library.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LibraryPersona {
+    /// What the persona is looked up by, such as `fact-checker`.
+    pub key: String,
+    /// The name actors taking the persona go by, unless they set their own.
+    pub name: String,
+    /// What the persona brings to a panel, for listing the library.
+    pub description: String,
+    #[serde(flatten)]
+    pub persona: Persona,
+    /// The part actors taking the persona play, unless they set their own.
+    #[serde(default)]
+    pub role: ActorRole,
+    /// The tools actors taking the persona may call, unless they list their own.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<Tool>,
+}
+
+/// Every persona in the library, in the order they are defined.
+pub fn library() -> &'static [LibraryPersona] {
+    #[derive(Deserialize)]
+    struct Library {
+        personas: Vec<LibraryPersona>,
+    }
+    static LIBRARY: OnceLock<Vec<LibraryPersona>> = OnceLock::new();
+    LIBRARY.get_or_init(|| {
+        let library: Library = toml::from_str(include_str!("../personas.toml")).expect("the built-in personas should be valid");
+        library.personas
+    })
+}
+
+/// The persona with the given key, ignoring case.
+pub fn find(key: &str) -> Option<&'static LibraryPersona> {
+    library().iter().find(|persona| persona.key.eq_ignore_ascii_case(key))
+}
@@ -0,0 +1,370 @@
+//! The handlebars templates the actors render their prompts from.
+//!
+//! The built-in templates are the files in the repository's `prompts` directory. A `[prompts]` section in the
+//! config file points at a directory of replacements, so that prompts can be reworded without recompiling.
+//! Each `<template>.hbs` file found there replaces the built-in template of that name, and every template
+//! not found there keeps the built-in version. The comment at the top of each built-in template lists the
+//! variables it can use.
+//!
+//! The section can also set the preamble every actor is told before a question it drafts an answer to, which
+//! the draft template renders as `preamble`. A persona's own preamble replaces it, and a question can add
+//! `instructions` of its own.
+
+use std::{fmt, fs, io, path::PathBuf, sync::{Arc, OnceLock}};
+
+use handlebars::Handlebars;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, error, warn};
+
+use crate::{config::ActorRole, conversation::Exchange, documents::Excerpt, personas::Persona, provider::SearchResult, search::FoundResult};
+
+/// The most characters of a question put to the panel, about 25,000 tokens, which leaves most models room for
+/// the rest of each prompt and the answer.
+pub const MAX_QUESTION_CHARS: usize = 100_000;
+
+/// What an actor is told before a question it drafts an answer to, unless the config file or its persona says
+/// otherwise.
+pub const DEFAULT_PREAMBLE: &str = "Please answer the following question without referring to yourself as a language model:";
+
+/// The `[prompts]` section of the config file.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct PromptsConfig {
+    /// Directory of `<template>.hbs` files that replace the built-in templates.
+    pub directory: Option<PathBuf>,
+    /// What every actor is told before a question it drafts an answer to, in place of [DEFAULT_PREAMBLE].
+    pub preamble: Option<String>,
+}
+
+/// The prompts an actor renders.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Template {
+    /// The system prompt describing the actor's persona.
+    Persona,
+    /// Picks the actor to draft the answer.
+    Route,
+    Draft,
+    Vote,
+    BinaryEvaluation,
+    ScoredEvaluation,
+    Refine,
+    Suggest,
+    Synthesize,
+    /// Asks the chair for guidance on refining the answer, from every evaluation of it.
+    Summarize,
+    /// Asks the chair to rule on which version of the answer to accept, in the final round.
+    Decide,
+    /// Screens the question and answer for harmful content, when moderation is configured.
+    Moderate,
+    /// Asks a fact checker for web searches that would check the answer's claims, when search is configured.
+    FactCheck,
+}
+
+impl Template {
+    pub const ALL: [Template; 13] = [
+        Template::Persona,
+        Template::Route,
+        Template::Draft,
+        Template::Vote,
+        Template::BinaryEvaluation,
+        Template::ScoredEvaluation,
+        Template::Refine,
+        Template::Suggest,
+        Template::Synthesize,
+        Template::Summarize,
+        Template::Decide,
+        Template::Moderate,
+        Template::FactCheck,
+    ];
+
+    /// The template's name, which is also the name of its file without the `.hbs` extension.
+    pub fn name(self) -> &'static str {
+        match self {
+            Template::Persona => "persona",
+            Template::Route => "route",
+            Template::Draft => "draft",
+            Template::Vote => "vote",
+            Template::BinaryEvaluation => "binary_evaluation",
+            Template::ScoredEvaluation => "scored_evaluation",
+            Template::Refine => "refine",
+            Template::Suggest => "suggest",
+            Template::Synthesize => "synthesize",
+            Template::Summarize => "summarize",
+            Template::Decide => "decide",
+            Template::Moderate => "moderate",
+            Template::FactCheck => "fact_check",
+        }
+    }
+
+    fn built_in(self) -> &'static str {
+        match self {
+            Template::Persona => include_str!("../prompts/persona.hbs"),
+            Template::Route => include_str!("../prompts/route.hbs"),
+            Template::Draft => include_str!("../prompts/draft.hbs"),
+            Template::Vote => include_str!("../prompts/vote.hbs"),
+            Template::BinaryEvaluation => include_str!("../prompts/binary_evaluation.hbs"),
+            Template::ScoredEvaluation => include_str!("../prompts/scored_evaluation.hbs"),
+            Template::Refine => include_str!("../prompts/refine.hbs"),
+            Template::Suggest => include_str!("../prompts/suggest.hbs"),
+            Template::Synthesize => include_str!("../prompts/synthesize.hbs"),
+            Template::Summarize => include_str!("../prompts/summarize.hbs"),
+            Template::Decide => include_str!("../prompts/decide.hbs"),
+            Template::Moderate => include_str!("../prompts/moderate.hbs"),
+            Template::FactCheck => include_str!("../prompts/fact_check.hbs"),
+        }
+    }
+}
+
+/// Errors raised while loading a directory of templates.
+#[derive(Debug)]
+pub enum PromptError {
+    /// The directory or a template in it could not be read.
+    Io { path: PathBuf, source: io::Error },
+    /// A template is not valid handlebars, or uses a variable that does not exist.
+    Template { path: PathBuf, reason: String },
+}
+
+impl fmt::Display for PromptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PromptError::Io { path, source } => write!(f, "unable to read {}: {}", path.display(), source),
+            PromptError::Template { path, reason } => write!(f, "invalid prompt template {}: {}", path.display(), reason),
+        }
+    }
+}
+
+impl std::error::Error for PromptError {}
+
+/// Every variable a template can use. Variables that do not apply to a stage are left empty.
+#[derive(Debug, Clone, Default, Serialize)]
+pub(crate) struct PromptData {
+    pub name: String,
+    pub domain: String,
+    pub expertise: Vec<String>,
+    /// The same as `expertise`, for templates written before personas had a rubric.
+    pub tuning: Vec<String>,
+    pub rubric: Vec<String>,
+    pub refusals: Vec<String>,
+    /// Empty if the persona does not set one.
+    pub tone: String,
+    /// What the actor is told before the question it drafts an answer to.
+    pub preamble: String,
+    /// Instructions the question adds to the preamble, if it has any.
+    pub instructions: String,
+    pub question: String,
+    /// Earlier questions and answers of the conversation, oldest first.
+    pub context: Vec<Exchange>,
+    /// Excerpts of the documents attached to the question.
+    pub documents: Vec<Excerpt>,
+    /// How many of the images attached to the question the actor's model cannot be shown.
+    pub unseen_images: usize,
+    pub answer: String,
+    /// The actor's own evaluation of the answer it is asked to refine.
+    pub reasoning: String,
+    /// Why the user rejected the answer the actor is asked to refine, if they did.
+    pub veto: String,
+    /// The chair's guidance on refining the answer, if the panel has a chair.
+    pub guidance: String,
+    /// Every actor on the panel, when routing the question.
+    pub panel: Vec<PromptActor>,
+    pub candidates: Vec<PromptCandidate>,
+    pub peer_evaluations: Vec<PromptEvaluation>,
+    /// Every version of the answer so far, when the chair rules on which to accept.
+    pub versions: Vec<PromptVersion>,
+    pub suggestions: Vec<PromptSuggestion>,
+    /// Whether the actor is the panel's devil's advocate.
+    pub devils_advocate: bool,
+    /// Instructions on the length and format of the answer, and on citing its sources, if it is constrained.
+    pub constraints: String,
+    /// Whether the answer to evaluate cites its sources, whose claims the actor is asked to check.
+    pub citations: bool,
+    /// Whether the actor is the panel's fact checker.
+    pub fact_checker: bool,
+    /// The most web searches the fact checker may ask for.
+    pub max_queries: usize,
+    /// What the fact checker's web searches found, to check the answer's claims against.
+    pub search_results: Vec<FoundResult>,
+    /// How running the answer's code blocks went, if the sandbox ran any.
+    pub code_runs: Vec<PromptCodeRun>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct PromptActor {
+    pub name: String,
+    pub domain: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct PromptCandidate {
+    /// Counts from 1, as the actor is asked to answer with it.
+    pub number: usize,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct PromptEvaluation {
+    pub actor: String,
+    /// The score, such as `score 7`, or the feedback, such as `NeedsRefinement`.
+    pub verdict: String,
+    pub reasoning: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct PromptVersion {
+    /// Counts from 1 for the first draft, as the chair is asked to answer with it.
+    pub number: usize,
+    pub text: String,
+    pub evaluations: Vec<PromptEvaluation>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct PromptCodeRun {
+    /// Counts the answer's code blocks from 1.
+    pub block: usize,
+    pub language: String,
+    /// How the run ended, such as `failed with exit status 1`.
+    pub outcome: String,
+    pub output: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct PromptSuggestion {
+    pub actor: String,
+    pub suggestion: String,
+}
+
+impl PromptData {
+    /// The variables describing an actor, to which each stage adds its own.
+    pub fn actor(name: &str, persona: &Persona, role: ActorRole) -> Self {
+        PromptData {
+            name: name.to_string(),
+            domain: persona.domain.clone(),
+            expertise: persona.expertise.clone(),
+            tuning: persona.expertise.clone(),
+            rubric: persona.rubric.clone(),
+            refusals: persona.refusals.clone(),
+            tone: persona.tone.clone().unwrap_or_default(),
+            devils_advocate: role == ActorRole::DevilsAdvocate,
+            fact_checker: role == ActorRole::FactChecker,
+            ..PromptData::default()
+        }
+    }
+
+    /// Values for every variable, used to check that a template renders before it is used.
+    fn sample() -> Self {
+        PromptData {
+            name: "Actor".to_string(),
+            domain: "Domain".to_string(),
+            expertise: vec!["Expertise".to_string()],
+            tuning: vec!["Expertise".to_string()],
+            rubric: vec!["Rubric".to_string()],
+            refusals: vec!["Refusal".to_string()],
+            tone: "Tone".to_string(),
+            preamble: "Preamble".to_string(),
+            instructions: "Instructions".to_string(),
+            question: "Question".to_string(),
+            context: vec![Exchange { question: "Earlier question".to_string(), answer: "Earlier answer".to_string() }],
+            documents: vec![Excerpt { source: "Document".to_string(), part: 1, text: "Excerpt".to_string() }],
+            unseen_images: 1,
+            answer: "Answer".to_string(),
+            reasoning: "Reasoning".to_string(),
+            veto: "Veto".to_string(),
+            guidance: "Guidance".to_string(),
+            panel: vec![PromptActor { name: "Peer".to_string(), domain: "Domain".to_string() }],
+            candidates: vec![PromptCandidate { number: 1, text: "Candidate".to_string() }],
+            peer_evaluations: vec![PromptEvaluation { actor: "Peer".to_string(), verdict: "Good".to_string(), reasoning: "Reasoning".to_string() }],
+            versions: vec![PromptVersion {
+                number: 1,
+                text: "Version".to_string(),
+                evaluations: vec![PromptEvaluation { actor: "Peer".to_string(), verdict: "Good".to_string(), reasoning: "Reasoning".to_string() }],
+            }],
+            suggestions: vec![PromptSuggestion { actor: "Peer".to_string(), suggestion: "Suggestion".to_string() }],
+            devils_advocate: true,
+            constraints: "Constraints".to_string(),
+            citations: true,
+            fact_checker: true,
+            max_queries: 3,
+            search_results: vec![FoundResult {
+                query: "Query".to_string(),
+                result: SearchResult { title: "Title".to_string(), url: "https://example.org".to_string(), snippet: "Snippet".to_string() },
+            }],
+            code_runs: vec![PromptCodeRun { block: 1, language: "Language".to_string(), outcome: "ran successfully".to_string(), output: "Output".to_string() }],
+        }
+    }
+}
+
+/// The registered templates, shared by every actor, and the preamble they draft answers after.
+pub struct Prompts {
+    registry: Handlebars<'static>,
+    preamble: String,
+}
+
+impl Prompts {
+    /// The built-in templates.
+    pub fn built_in() -> Arc<Prompts> {
+        static BUILT_IN: OnceLock<Arc<Prompts>> = OnceLock::new();
+        BUILT_IN.get_or_init(|| Arc::new(Prompts { registry: registry(), preamble: DEFAULT_PREAMBLE.to_string() })).clone()
+    }
+
+    /// Loads the templates in the configured directory, if any, over the built-in ones, checking that each renders,
+    /// with the configured preamble, if any.
+    pub fn load(config: &PromptsConfig) -> Result<Self, PromptError> {
+        let preamble = config.preamble.clone().unwrap_or_else(|| DEFAULT_PREAMBLE.to_string());
+        let Some(directory) = &config.directory else { return Ok(Prompts { registry: registry(), preamble }) };
+        let entries = fs::read_dir(directory).map_err(|source| PromptError::Io { path: directory.clone(), source })?;
+        let mut registry = registry();
+        for path in entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()) {
+            if path.extension().is_none_or(|extension| extension != "hbs") {
+                continue;
+            }
+            let stem = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or_default();
+            let Some(template) = Template::ALL.into_iter().find(|template| template.name() == stem) else {
+                warn!("Ignoring {}, which does not match the name of any prompt.", path.display());
+                continue;
+            };
+            let source = fs::read_to_string(&path).map_err(|source| PromptError::Io { path: path.clone(), source })?;
+            let invalid = |reason: String| PromptError::Template { path: path.clone(), reason };
+            registry.register_template_string(template.name(), source).map_err(|e| invalid(e.to_string()))?;
+            registry.render(template.name(), &PromptData::sample()).map_err(|e| invalid(e.to_string()))?;
+            debug!("Loaded the {} prompt from {}.", template.name(), path.display());
+        }
+        Ok(Prompts { registry, preamble })
+    }
+
+    /// What an actor with the persona is told before a question it drafts an answer to: the persona's preamble, or
+    /// else the panel's.
+    pub(crate) fn preamble(&self, persona: &Persona) -> String {
+        persona.preamble.clone().unwrap_or_else(|| self.preamble.clone())
+    }
+
+    /// Renders the template, trimming the whitespace around it. If it fails, which a template that was
+    /// checked when it was loaded should not, the built-in template is rendered instead.
+    pub(crate) fn render(&self, template: Template, data: &PromptData) -> String {
+        let rendered = self.registry.render(template.name(), data).unwrap_or_else(|e| {
+            error!("Unable to render the {} prompt, using the built-in one instead: {}", template.name(), e);
+            Prompts::built_in().registry.render(template.name(), data).expect("the built-in prompts should render")
+        });
+        rendered.trim().to_string()
+    }
+}
+
+/// The question, cut off at [MAX_QUESTION_CHARS] with a note saying so if it is longer. Every prompt is rendered
+/// with the question this returns.
+pub fn fit_question(question: String) -> String {
+    let Some((cut, _)) = question.char_indices().nth(MAX_QUESTION_CHARS) else { return question };
+    let dropped = question[cut..].chars().count();
+    warn!("The question is {} characters long, so only the first {} are put to the panel.", MAX_QUESTION_CHARS + dropped, MAX_QUESTION_CHARS);
+    format!("{}\n\n[The last {} characters of the question were cut off.]", &question[..cut], dropped)
+}
+
+/// A registry holding the built-in templates.
+fn registry() -> Handlebars<'static> {
+    let mut registry = Handlebars::new();
+    // Prompts are plain text, and a variable that does not exist is a typo in the template.
+    registry.register_escape_fn(handlebars::no_escape);
+    registry.set_strict_mode(true);
+    for template in Template::ALL {
+        registry.register_template_string(template.name(), template.built_in()).expect("the built-in prompts should be valid");
+    }
+    registry
+}
@@ -0,0 +1,246 @@
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+use super::{check_credential, read_lines, Completion, CompletionRequest, KeyRing, LlmProvider, ProviderError, TokenSink, ToolCall, Usage};
+
+const MESSAGES_URL: &str = "https://api.anthropic.com/v1/messages";
+const MODELS_URL: &str = "https://api.anthropic.com/v1/models";
+const API_VERSION: &str = "2023-06-01";
+const DEFAULT_MODEL: &str = "claude-3-5-haiku-latest";
+const MAX_TOKENS: u32 = 2048;
+pub(super) const API_KEY_VAR: &str = "ANTHROPIC_API_KEY";
+
+/// Anthropic's Messages API, authenticated with the configured keys or the `ANTHROPIC_API_KEY` environment variable.
+///
+/// The request's system instructions are sent in the top-level `system` field rather than as a message.
+pub struct AnthropicProvider {
+    client: Client,
+    keys: KeyRing,
+    model: String,
+}
+
+#[derive(Serialize)]
+pub(super) struct MessagesRequest<'a> {
+    model: &'a str,
+    max_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<&'a str>,
+    messages: Vec<Message<'a>>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<Tool<'a>>,
+}
+
+#[derive(Serialize)]
+struct Tool<'a> {
+    name: &'a str,
+    description: &'a str,
+    input_schema: &'a serde_json::Value,
+}
+
+#[derive(Serialize)]
+struct Message<'a> {
+    role: &'a str,
+    content: Vec<InputBlock<'a>>,
+}
+
+/// A block of a message's content: the images come before the text, as Anthropic recommends. The model's calls
+/// to tools are sent back to it as `tool_use` blocks, and what they returned as `tool_result` blocks.
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum InputBlock<'a> {
+    Text { text: &'a str },
+    Image { source: ImageSource<'a> },
+    ToolUse { id: &'a str, name: &'a str, input: &'a serde_json::Value },
+    ToolResult { tool_use_id: &'a str, content: &'a str },
+}
+
+#[derive(Serialize)]
+struct ImageSource<'a> {
+    #[serde(rename = "type")]
+    kind: &'a str,
+    media_type: &'a str,
+    data: String,
+}
+
+#[derive(Deserialize)]
+struct MessagesResponse {
+    content: Vec<ContentBlock>,
+    usage: Option<MessagesUsage>,
+}
+
+#[derive(Deserialize)]
+struct MessagesUsage {
+    input_tokens: u64,
+    output_tokens: u64,
+}
+
+/// A block of the response, or with streaming the piece of one a delta holds.
+#[derive(Deserialize)]
+struct ContentBlock {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    text: String,
+    /// Only given in a `tool_use` block, with `name` and `input`.
+    #[serde(default)]
+    id: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    input: serde_json::Value,
+}
+
+/// The server-sent events of a streamed response that carry text or token counts.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum StreamEvent {
+    MessageStart { message: StreamMessage },
+    ContentBlockDelta { delta: ContentBlock },
+    /// Carries the final output token count.
+    MessageDelta { usage: StreamUsage },
+    Error { error: StreamError },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+struct StreamMessage {
+    usage: MessagesUsage,
+}
+
+#[derive(Deserialize)]
+struct StreamUsage {
+    output_tokens: u64,
+}
+
+#[derive(Deserialize)]
+struct StreamError {
+    message: String,
+}
+
+impl AnthropicProvider {
+    pub fn new(model: Option<String>, keys: KeyRing) -> Self {
+        AnthropicProvider {
+            client: Client::new(),
+            keys,
+            model: model.unwrap_or_else(|| DEFAULT_MODEL.to_string()),
+        }
+    }
+
+    /// Sends the request, failing unless the API accepted it.
+    async fn send(&self, request: &CompletionRequest, stream: bool) -> Result<reqwest::Response, ProviderError> {
+        let response = self.client.post(MESSAGES_URL)
+            .header("x-api-key", self.keys.next())
+            .header("anthropic-version", API_VERSION)
+            .json(&MessagesRequest::new(&self.model, request, stream))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(ProviderError::Status { status: response.status(), body: response.text().await.unwrap_or_default() });
+        }
+        Ok(response)
+    }
+}
+
+impl<'a> MessagesRequest<'a> {
+    pub(super) fn new(model: &'a str, request: &'a CompletionRequest, stream: bool) -> Self {
+        let mut messages = vec![Message { role: "user", content: request.images.iter()
+            .map(|image| InputBlock::Image { source: ImageSource { kind: "base64", media_type: image.media_type, data: image.base64() } })
+            .chain([InputBlock::Text { text: &request.prompt }])
+            .collect() }];
+        for round in &request.tool_rounds {
+            let text = (!round.text.is_empty()).then_some(InputBlock::Text { text: &round.text });
+            let calls = round.calls.iter().map(|call| InputBlock::ToolUse { id: &call.id, name: &call.name, input: &call.arguments });
+            messages.push(Message { role: "assistant", content: text.into_iter().chain(calls).collect() });
+            let outputs = round.outputs.iter().map(|output| InputBlock::ToolResult { tool_use_id: &output.id, content: &output.content });
+            messages.push(Message { role: "user", content: outputs.collect() });
+        }
+        MessagesRequest {
+            model,
+            // The Messages API requires a limit.
+            max_tokens: request.params.max_tokens.unwrap_or(MAX_TOKENS),
+            temperature: request.params.temperature,
+            top_p: request.params.top_p,
+            system: request.system.as_deref(),
+            messages,
+            stream,
+            tools: request.tools.iter()
+                .map(|tool| Tool { name: &tool.name, description: &tool.description, input_schema: &tool.parameters })
+                .collect(),
+        }
+    }
+}
+
+#[async_trait]
+impl LlmProvider for AnthropicProvider {
+    async fn complete(&self, request: &CompletionRequest) -> Result<Completion, ProviderError> {
+        let response: MessagesResponse = self.send(request, false).await?.json().await?;
+        let usage = response.usage.map(|usage| Usage { prompt_tokens: usage.input_tokens, completion_tokens: usage.output_tokens });
+        let (mut text, mut tool_calls) = (String::new(), Vec::new());
+        for block in response.content {
+            match block.kind.as_str() {
+                "text" => text.push_str(&block.text),
+                "tool_use" => tool_calls.push(ToolCall { id: block.id, name: block.name, arguments: block.input }),
+                _ => (),
+            }
+        }
+        if text.is_empty() && tool_calls.is_empty() {
+            return Err(ProviderError::EmptyResponse);
+        }
+        Ok(Completion { text, usage, backend: None, tool_calls })
+    }
+
+    /// The response arrives as server-sent events: the prompt's token count first, then the text a
+    /// piece at a time, then the response's token count.
+    async fn stream(&self, request: &CompletionRequest, on_token: TokenSink<'_>) -> Result<Completion, ProviderError> {
+        let response = self.send(request, true).await?;
+        let (mut text, mut usage) = (String::new(), Usage::default());
+        read_lines(response, |line| {
+            let Some(data) = line.strip_prefix("data:").map(str::trim) else { return Ok(()) };
+            let event: StreamEvent = serde_json::from_str(data)
+                .map_err(|e| ProviderError::InvalidResponse(format!("unreadable stream event ({}): {}", e, data)))?;
+            match event {
+                StreamEvent::MessageStart { message } => usage.prompt_tokens = message.usage.input_tokens,
+                StreamEvent::ContentBlockDelta { delta } if delta.kind == "text_delta" => {
+                    on_token(&delta.text);
+                    text.push_str(&delta.text);
+                },
+                StreamEvent::MessageDelta { usage: delta } => usage.completion_tokens = delta.output_tokens,
+                StreamEvent::Error { error } => return Err(ProviderError::InvalidResponse(error.message)),
+                _ => (),
+            }
+            Ok(())
+        }).await?;
+        if text.is_empty() {
+            return Err(ProviderError::EmptyResponse);
+        }
+        Ok(Completion { text, usage: Some(usage), backend: None, tool_calls: Vec::new() })
+    }
+
+    fn max_images(&self) -> usize {
+        usize::MAX
+    }
+
+    fn supports_tools(&self) -> bool {
+        true
+    }
+
+    /// Looks the model up with each key, which checks both without generating anything.
+    async fn check(&self) -> Result<(), ProviderError> {
+        for (label, key) in self.keys.each() {
+            let request = self.client.get(format!("{}/{}", MODELS_URL, self.model))
+                .header("x-api-key", key)
+                .header("anthropic-version", API_VERSION);
+            check_credential(request, &label).await?;
+        }
+        Ok(())
+    }
+}
+
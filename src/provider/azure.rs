@@ -0,0 +1,143 @@
+use std::env;
+
+use async_trait::async_trait;
+use reqwest::{Client, RequestBuilder};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+
+use super::{check_credential, openai::{self, ChatRequest}, token::{self, TokenCache}, Completion, CompletionRequest, KeyRing, LlmProvider, ProviderError, TokenSink};
+
+const DEFAULT_API_VERSION: &str = "2024-10-21";
+pub(super) const API_KEY_VAR: &str = "AZURE_OPENAI_API_KEY";
+const TOKEN_URL: &str = "https://login.microsoftonline.com";
+/// The scope of a token for Azure OpenAI, and every other Azure AI service.
+const TOKEN_SCOPE: &str = "https://cognitiveservices.azure.com/.default";
+
+/// How requests to Azure OpenAI are authenticated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AzureAuth {
+    /// The resource's API keys, configured under `[credentials]` or from the `AZURE_OPENAI_API_KEY` environment
+    /// variable.
+    #[default]
+    Key,
+    /// A Microsoft Entra ID (AAD) token for the service principal in the `AZURE_TENANT_ID`, `AZURE_CLIENT_ID`
+    /// and `AZURE_CLIENT_SECRET` environment variables, which needs the Cognitive Services OpenAI User role.
+    Aad,
+}
+
+/// Azure OpenAI's Chat Completions API, which addresses a model by the name of its deployment on the resource
+/// at `endpoint`.
+pub struct AzureOpenAiProvider {
+    client: Client,
+    /// The deployment's chat completions URL, with the API version.
+    url: String,
+    /// The URL listing the resource's models, with the API version, which credentials are checked against.
+    models_url: String,
+    deployment: String,
+    credential: Credential,
+}
+
+enum Credential {
+    ApiKeys(KeyRing),
+    ServicePrincipal { tenant: String, client_id: String, client_secret: String, token: TokenCache },
+}
+
+impl AzureOpenAiProvider {
+    /// A provider authenticated with the resource's API keys.
+    pub fn new(endpoint: &str, deployment: String, api_version: Option<String>, keys: KeyRing) -> Self {
+        AzureOpenAiProvider::with_credential(endpoint, deployment, api_version, Credential::ApiKeys(keys))
+    }
+
+    /// A provider signed in as the service principal in the environment.
+    pub fn service_principal(endpoint: &str, deployment: String, api_version: Option<String>) -> Result<Self, ProviderError> {
+        let credential = Credential::ServicePrincipal {
+            tenant: var("AZURE_TENANT_ID")?,
+            client_id: var("AZURE_CLIENT_ID")?,
+            client_secret: var("AZURE_CLIENT_SECRET")?,
+            token: TokenCache::new(),
+        };
+        Ok(AzureOpenAiProvider::with_credential(endpoint, deployment, api_version, credential))
+    }
+
+    fn with_credential(endpoint: &str, deployment: String, api_version: Option<String>, credential: Credential) -> Self {
+        let endpoint = endpoint.trim_end_matches('/');
+        let api_version = api_version.as_deref().unwrap_or(DEFAULT_API_VERSION);
+        let url = format!("{}/openai/deployments/{}/chat/completions?api-version={}", endpoint, deployment, api_version);
+        let models_url = format!("{}/openai/models?api-version={}", endpoint, api_version);
+        AzureOpenAiProvider { client: Client::new(), url, models_url, deployment, credential }
+    }
+
+    /// Adds the API key or a current access token to the request.
+    async fn authorize(&self, request: RequestBuilder) -> Result<RequestBuilder, ProviderError> {
+        match &self.credential {
+            Credential::ApiKeys(keys) => Ok(request.header("api-key", keys.next())),
+            Credential::ServicePrincipal { tenant, client_id, client_secret, token } => {
+                // The service principal signs in with the client credentials flow.
+                let token = token.get(|| {
+                    debug!("Fetching an access token for the {} deployment.", self.deployment);
+                    async move {
+                        token::request(&self.client, &format!("{}/{}/oauth2/v2.0/token", TOKEN_URL, tenant), &[
+                            ("grant_type", "client_credentials"),
+                            ("client_id", client_id),
+                            ("client_secret", client_secret),
+                            ("scope", TOKEN_SCOPE),
+                        ]).await
+                    }
+                }).await?;
+                Ok(request.bearer_auth(token))
+            },
+        }
+    }
+
+    /// Sends the request, failing unless the API accepted it.
+    async fn send(&self, request: &CompletionRequest, stream: bool) -> Result<reqwest::Response, ProviderError> {
+        let response = self.authorize(self.client.post(&self.url)).await?
+            .json(&ChatRequest::new(&self.deployment, request, stream))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(ProviderError::Status { status: response.status(), body: response.text().await.unwrap_or_default() });
+        }
+        Ok(response)
+    }
+}
+
+fn var(name: &'static str) -> Result<String, ProviderError> {
+    env::var(name).map_err(|_| ProviderError::MissingCredential(name))
+}
+
+#[async_trait]
+impl LlmProvider for AzureOpenAiProvider {
+    async fn complete(&self, request: &CompletionRequest) -> Result<Completion, ProviderError> {
+        openai::read_completion(self.send(request, false).await?).await
+    }
+
+    async fn stream(&self, request: &CompletionRequest, on_token: TokenSink<'_>) -> Result<Completion, ProviderError> {
+        openai::read_stream(self.send(request, true).await?, on_token).await
+    }
+
+    fn max_images(&self) -> usize {
+        usize::MAX
+    }
+
+    fn supports_tools(&self) -> bool {
+        true
+    }
+
+    /// Lists the resource's models with each key, or with a token for the service principal, which checks the
+    /// credentials and the endpoint without generating anything.
+    async fn check(&self) -> Result<(), ProviderError> {
+        match &self.credential {
+            Credential::ApiKeys(keys) => {
+                for (label, key) in keys.each() {
+                    check_credential(self.client.get(&self.models_url).header("api-key", key), &label).await?;
+                }
+                Ok(())
+            },
+            Credential::ServicePrincipal { .. } => {
+                check_credential(self.authorize(self.client.get(&self.models_url)).await?, "the service principal").await
+            },
+        }
+    }
+}
@@ -0,0 +1,218 @@
+use std::{collections::HashMap, fs, io, path::PathBuf, sync::{Arc, Mutex}};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{debug, warn};
+
+use super::{Completion, CompletionRequest, LlmProvider, ProviderError, TokenSink, Usage};
+
+/// The `[cache]` section of the config file.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CacheConfig {
+    /// How many responses are kept in memory before the least recently used is evicted.
+    #[serde(default = "default_capacity")]
+    pub capacity: usize,
+    /// Also stores every response in this directory, so that it survives restarts. It is created if missing.
+    #[serde(default)]
+    pub directory: Option<PathBuf>,
+}
+
+fn default_capacity() -> usize {
+    256
+}
+
+impl CacheConfig {
+    /// Checks the config, returning a description of the problem if it is unusable.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.capacity == 0 {
+            return Err("cache capacity must be at least 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// A response as stored in the cache directory.
+#[derive(Deserialize, Serialize)]
+struct CachedResponse {
+    text: String,
+}
+
+struct CacheEntry {
+    text: String,
+    /// The value of [CacheState::clock] when the entry was last read or written.
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct CacheState {
+    entries: HashMap<String, CacheEntry>,
+    /// Counts lookups, ordering entries by how recently they were used.
+    clock: u64,
+    hits: u64,
+    misses: u64,
+}
+
+/// Responses keyed by a hash of the provider and prompt, kept in a least-recently-used map
+/// and optionally on disk. Shared by every actor's [CachingProvider].
+pub struct ResponseCache {
+    capacity: usize,
+    directory: Option<PathBuf>,
+    state: Mutex<CacheState>,
+}
+
+impl ResponseCache {
+    /// Creates the cache, creating its directory if one is configured.
+    pub fn open(config: &CacheConfig) -> io::Result<Self> {
+        if let Some(directory) = &config.directory {
+            fs::create_dir_all(directory)?;
+        }
+        Ok(ResponseCache { capacity: config.capacity, directory: config.directory.clone(), state: Mutex::new(CacheState::default()) })
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, CacheState> {
+        self.state.lock().expect("cache lock should not be poisoned")
+    }
+
+    fn path(&self, key: &str) -> Option<PathBuf> {
+        self.directory.as_ref().map(|directory| directory.join(format!("{}.json", key)))
+    }
+
+    /// Looks the key up in memory, then on disk, counting the lookup as a hit or a miss.
+    fn get(&self, key: &str) -> Option<String> {
+        let mut state = self.lock();
+        state.clock += 1;
+        let clock = state.clock;
+        if let Some(entry) = state.entries.get_mut(key) {
+            entry.last_used = clock;
+            let text = entry.text.clone();
+            state.hits += 1;
+            debug!("Response cache hit in memory ({} hits, {} misses).", state.hits, state.misses);
+            return Some(text);
+        }
+
+        let stored = self.path(key)
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|contents| serde_json::from_str::<CachedResponse>(&contents).ok());
+        match stored {
+            Some(CachedResponse { text }) => {
+                state.hits += 1;
+                debug!("Response cache hit on disk ({} hits, {} misses).", state.hits, state.misses);
+                self.remember(&mut state, key, text.clone());
+                Some(text)
+            },
+            None => {
+                state.misses += 1;
+                debug!("Response cache miss ({} hits, {} misses).", state.hits, state.misses);
+                None
+            },
+        }
+    }
+
+    /// Stores a fresh response in memory and, if configured, on disk.
+    fn insert(&self, key: &str, text: &str) {
+        if let Some(path) = self.path(key) {
+            let written = serde_json::to_string(&CachedResponse { text: text.to_string() })
+                .map_err(io::Error::from)
+                .and_then(|json| fs::write(&path, json));
+            if let Err(e) = written {
+                warn!("Unable to write the cached response to {}: {}", path.display(), e);
+            }
+        }
+        let mut state = self.lock();
+        state.clock += 1;
+        self.remember(&mut state, key, text.to_string());
+    }
+
+    fn remember(&self, state: &mut CacheState, key: &str, text: String) {
+        if !state.entries.contains_key(key) && state.entries.len() >= self.capacity {
+            let oldest = state.entries.iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                state.entries.remove(&oldest);
+            }
+        }
+        let last_used = state.clock;
+        state.entries.insert(key.to_string(), CacheEntry { text, last_used });
+    }
+}
+
+/// Wraps another provider, answering repeated requests from a [ResponseCache] instead of calling the model.
+pub struct CachingProvider {
+    inner: Arc<dyn LlmProvider>,
+    cache: Arc<ResponseCache>,
+    /// Identifies the backend and model, so that different models never share responses.
+    namespace: String,
+}
+
+impl CachingProvider {
+    pub fn new(inner: Arc<dyn LlmProvider>, cache: Arc<ResponseCache>, namespace: String) -> Self {
+        CachingProvider { inner, cache, namespace }
+    }
+
+    fn key(&self, request: &CompletionRequest) -> String {
+        let mut hasher = Sha256::new();
+        let params = format!("{:?}", request.params);
+        for part in [self.namespace.as_str(), params.as_str(), request.system.as_deref().unwrap_or_default(), request.prompt.as_str()] {
+            hasher.update((part.len() as u64).to_le_bytes());
+            hasher.update(part.as_bytes());
+        }
+        for image in &request.images {
+            hasher.update((image.data.len() as u64).to_le_bytes());
+            hasher.update(&image.data);
+        }
+        // The tools offered, and what the calls to them returned, change the response as much as the prompt does.
+        if !request.tools.is_empty() || !request.tool_rounds.is_empty() {
+            let tools = serde_json::to_string(&(&request.tools, &request.tool_rounds)).unwrap_or_default();
+            hasher.update((tools.len() as u64).to_le_bytes());
+            hasher.update(tools.as_bytes());
+        }
+        hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    /// Caches the response, unless it calls tools: only its text is kept, and the calls would be lost.
+    fn store(&self, key: &str, completion: &Completion) {
+        if completion.tool_calls.is_empty() {
+            self.cache.insert(key, &completion.text);
+        }
+    }
+}
+
+#[async_trait]
+impl LlmProvider for CachingProvider {
+    async fn complete(&self, request: &CompletionRequest) -> Result<Completion, ProviderError> {
+        let key = self.key(request);
+        if let Some(text) = self.cache.get(&key) {
+            // A cached response uses no tokens.
+            return Ok(Completion { text, usage: Some(Usage::default()), backend: None, tool_calls: Vec::new() });
+        }
+        let completion = self.inner.complete(request).await?;
+        self.store(&key, &completion);
+        Ok(completion)
+    }
+
+    async fn stream(&self, request: &CompletionRequest, on_token: TokenSink<'_>) -> Result<Completion, ProviderError> {
+        let key = self.key(request);
+        if let Some(text) = self.cache.get(&key) {
+            on_token(&text);
+            return Ok(Completion { text, usage: Some(Usage::default()), backend: None, tool_calls: Vec::new() });
+        }
+        let completion = self.inner.stream(request, on_token).await?;
+        self.store(&key, &completion);
+        Ok(completion)
+    }
+
+    fn max_images(&self) -> usize {
+        self.inner.max_images()
+    }
+
+    fn supports_tools(&self) -> bool {
+        self.inner.supports_tools()
+    }
+
+    /// Always reaches the model, since a cached response would say nothing about whether it can be reached now.
+    async fn check(&self) -> Result<(), ProviderError> {
+        self.inner.check().await
+    }
+}
@@ -0,0 +1,177 @@
+use std::{collections::{BTreeMap, HashMap}, env, process::Command, str::FromStr, sync::atomic::{AtomicUsize, Ordering}};
+
+use serde::Deserialize;
+
+use super::{ProviderError, ProviderKind};
+
+/// The `[credentials]` section of the config file: named API keys, and which of them each provider uses.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct CredentialsConfig {
+    /// Every named key, and where it is read from.
+    #[serde(default)]
+    pub keys: BTreeMap<String, KeySource>,
+    /// The keys each provider uses unless an actor names its own, by provider name. A provider without any reads
+    /// its usual environment variable.
+    #[serde(default)]
+    pub providers: HashMap<String, Vec<String>>,
+}
+
+/// Where a named key is read from.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeySource {
+    /// An environment variable.
+    Env(String),
+    /// The config file itself.
+    Value(String),
+    /// The OS keychain: the macOS login keychain, or the Secret Service on Linux.
+    Keychain { service: String, account: String },
+}
+
+/// The API keys a provider takes turns with, one request each, to spread its requests across their rate limits.
+#[derive(Debug)]
+pub struct KeyRing {
+    keys: Vec<String>,
+    next: AtomicUsize,
+}
+
+impl CredentialsConfig {
+    /// Checks the config, returning a description of the problem if it is unusable.
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, source) in &self.keys {
+            let empty = match source {
+                KeySource::Env(var) => var.trim().is_empty(),
+                KeySource::Value(value) => value.trim().is_empty(),
+                KeySource::Keychain { service, account } => service.trim().is_empty() || account.trim().is_empty(),
+            };
+            if empty {
+                return Err(format!("credentials key \"{}\" must say where to read it from", name));
+            }
+        }
+        for (provider, keys) in &self.providers {
+            let kind = ProviderKind::from_str(provider).map_err(|e| format!("credentials providers: {}", e))?;
+            if !kind.takes_keys() {
+                return Err(format!("credentials providers: {}", kind.keyless()));
+            }
+            self.check_names(keys).map_err(|e| format!("credentials providers {}: {}", provider, e))?;
+        }
+        Ok(())
+    }
+
+    /// Checks that each of the `names` is a configured key.
+    pub fn check_names(&self, names: &[String]) -> Result<(), String> {
+        match names.iter().find(|name| !self.keys.contains_key(*name)) {
+            Some(name) => Err(format!("no key named \"{}\" is configured under [credentials.keys]", name)),
+            None => Ok(()),
+        }
+    }
+
+    /// Reads the keys for a `provider`: the `names` given if there are any, otherwise those configured for the
+    /// provider, otherwise the one in `default_var`.
+    pub fn key_ring(&self, provider: ProviderKind, names: &[String], default_var: &'static str) -> Result<KeyRing, ProviderError> {
+        let names = match names {
+            [] => self.providers.iter()
+                .find(|(name, _)| ProviderKind::from_str(name).is_ok_and(|kind| kind == provider))
+                .map(|(_, keys)| keys.as_slice())
+                .unwrap_or_default(),
+            names => names,
+        };
+        self.named_key_ring(names, default_var)
+    }
+
+    /// Reads the keys called `names`, or the one in `default_var` if there are none, for a service that is not a
+    /// provider, such as a search backend.
+    pub fn named_key_ring(&self, names: &[String], default_var: &'static str) -> Result<KeyRing, ProviderError> {
+        if names.is_empty() {
+            return KeyRing::from_env(default_var);
+        }
+        let keys = names.iter()
+            .map(|name| match self.keys.get(name) {
+                Some(source) => source.read().map_err(|e| ProviderError::InvalidCredentials(format!("key \"{}\": {}", name, e))),
+                None => Err(ProviderError::InvalidCredentials(format!("no key named \"{}\" is configured", name))),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(KeyRing::new(keys))
+    }
+}
+
+impl KeySource {
+    /// Reads the key, returning a description of the problem if it cannot be.
+    fn read(&self) -> Result<String, String> {
+        match self {
+            KeySource::Env(var) => env::var(var).map_err(|_| format!("the {} environment variable is not set", var)),
+            KeySource::Value(value) => Ok(value.clone()),
+            KeySource::Keychain { service, account } => read_keychain(service, account),
+        }
+    }
+}
+
+/// Looks the key up in the login keychain with the `security` tool.
+#[cfg(target_os = "macos")]
+fn read_keychain(service: &str, account: &str) -> Result<String, String> {
+    run_keychain_tool(Command::new("security").args(["find-generic-password", "-s", service, "-a", account, "-w"]))
+}
+
+/// Looks the key up in the Secret Service, such as GNOME Keyring or KWallet, with `secret-tool`.
+#[cfg(all(unix, not(target_os = "macos")))]
+fn read_keychain(service: &str, account: &str) -> Result<String, String> {
+    run_keychain_tool(Command::new("secret-tool").args(["lookup", "service", service, "account", account]))
+}
+
+#[cfg(not(unix))]
+fn read_keychain(_service: &str, _account: &str) -> Result<String, String> {
+    Err("the OS keychain can only be read on macOS and Linux".to_string())
+}
+
+/// Runs the tool that reads the keychain, returning the key it prints.
+#[cfg(unix)]
+fn run_keychain_tool(command: &mut Command) -> Result<String, String> {
+    let tool = command.get_program().to_string_lossy().into_owned();
+    let output = command.output().map_err(|e| format!("unable to run {}: {}", tool, e))?;
+    let key = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if !output.status.success() || key.is_empty() {
+        return Err(format!("the keychain has no such key ({})", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(key)
+}
+
+impl KeyRing {
+    fn new(keys: Vec<String>) -> Self {
+        KeyRing { keys, next: AtomicUsize::new(0) }
+    }
+
+    /// The single key in the environment variable `var`.
+    pub fn from_env(var: &'static str) -> Result<Self, ProviderError> {
+        let key = env::var(var).map_err(|_| ProviderError::MissingApiKey(var))?;
+        Ok(KeyRing::new(vec![key]))
+    }
+
+    /// The key to send the next request with.
+    pub fn next(&self) -> &str {
+        &self.keys[self.next.fetch_add(1, Ordering::Relaxed) % self.keys.len()]
+    }
+
+    /// Every key, with how an error names it: "the API key", or "API key 2 of 3" when there are several.
+    pub fn each(&self) -> impl Iterator<Item = (String, &str)> {
+        let count = self.keys.len();
+        self.keys.iter().enumerate().map(move |(index, key)| match count {
+            1 => ("the API key".to_string(), key.as_str()),
+            count => (format!("API key {} of {}", index + 1, count), key.as_str()),
+        })
+    }
+}
+
+impl ProviderKind {
+    /// Whether the provider is authenticated with API keys that can be configured under `[credentials]`.
+    pub fn takes_keys(self) -> bool {
+        matches!(self, ProviderKind::OpenAi | ProviderKind::Anthropic | ProviderKind::Azure | ProviderKind::Gemini)
+    }
+
+    /// Why the provider cannot be given keys.
+    pub(super) fn keyless(self) -> String {
+        match self {
+            ProviderKind::Gemini => "the gemini provider signs in as a service account rather than using keys with vertex".to_string(),
+            kind => format!("the {} provider takes no API key", format!("{:?}", kind).to_lowercase()),
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use async_trait::async_trait;
+
+use super::{Completion, CompletionRequest, LlmProvider, ProviderError};
+
+/// What the stub answers every request with. Each stage takes what it needs from it: a vote for the first
+/// candidate from the first line, and an evaluation asking for refinement from the JSON object, so that a dry run
+/// goes through every stage until the panel runs out of rounds. A draft or refinement is the reply as it is.
+const REPLY: &str = "1\n{\"verdict\": \"NeedsRefinement\", \"score\": 1, \"reasoning\": \"This is a dry run, so no model was asked.\"}";
+
+/// A stub in place of a model, which prints every request it is sent to stderr and answers with [REPLY], so that
+/// prompts can be checked without calling any API.
+pub struct DryRunProvider {
+    /// Who the requests are printed as sent to.
+    label: String,
+}
+
+impl DryRunProvider {
+    pub fn new(label: Option<String>) -> Self {
+        DryRunProvider { label: label.unwrap_or_else(|| "dry run".to_string()) }
+    }
+}
+
+#[async_trait]
+impl LlmProvider for DryRunProvider {
+    async fn complete(&self, request: &CompletionRequest) -> Result<Completion, ProviderError> {
+        let mut printed = format!("===== Request to {} =====\n", self.label);
+        if let Some(system) = &request.system {
+            printed.push_str(&format!("----- System -----\n{}\n", system.trim_end()));
+        }
+        printed.push_str(&format!("----- Prompt -----\n{}\n", request.prompt.trim_end()));
+        if !request.images.is_empty() {
+            printed.push_str(&format!("----- With {} image(s) -----\n", request.images.len()));
+        }
+        if !request.tools.is_empty() {
+            let names: Vec<&str> = request.tools.iter().map(|tool| tool.name.as_str()).collect();
+            printed.push_str(&format!("----- With tools: {} -----\n", names.join(", ")));
+        }
+        // One write, so that requests sent at the same time are not interleaved.
+        eprint!("{}", printed);
+        Ok(Completion { text: REPLY.to_string(), usage: None, backend: None, tool_calls: Vec::new() })
+    }
+
+    fn max_images(&self) -> usize {
+        usize::MAX
+    }
+
+    /// Takes the tools so that the actors' requests are printed with them, though no call is ever made.
+    fn supports_tools(&self) -> bool {
+        true
+    }
+
+    /// Passes without printing anything, since there is no model to reach.
+    async fn check(&self) -> Result<(), ProviderError> {
+        Ok(())
+    }
+}
@@ -0,0 +1,61 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use super::{ollama, openai, KeyRing, OllamaProvider, OpenAiProvider, ProviderError, ProviderKind};
+
+/// A model that turns text into vectors, so that texts can be compared by meaning.
+#[async_trait]
+pub trait EmbeddingProvider: Send + Sync {
+    /// Embeds each text, returning the vectors in the same order.
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, ProviderError>;
+}
+
+/// Which backend embeds texts, and with which model.
+#[derive(Debug, Clone, Deserialize)]
+pub struct EmbeddingConfig {
+    /// Only `ollama` and `openai` have an embeddings API.
+    pub provider: ProviderKind,
+    /// Defaults to `nomic-embed-text` on Ollama and `text-embedding-3-small` on OpenAI.
+    pub model: Option<String>,
+    /// Endpoint override for self-hosted backends such as Ollama.
+    pub base_url: Option<String>,
+}
+
+impl EmbeddingConfig {
+    /// Checks the config, returning a description of the problem if it is unusable.
+    pub fn validate(&self) -> Result<(), String> {
+        match self.provider {
+            ProviderKind::Ollama | ProviderKind::OpenAi => Ok(()),
+            other => Err(format!("{:?} has no embeddings API; use ollama or openai", other)),
+        }
+    }
+
+    /// Builds the configured provider, reading its API key from the environment when it needs one.
+    pub fn build(&self) -> Result<Arc<dyn EmbeddingProvider>, ProviderError> {
+        match self.provider {
+            ProviderKind::Ollama => {
+                let model = self.model.clone().unwrap_or_else(|| ollama::DEFAULT_EMBEDDING_MODEL.to_string());
+                Ok(Arc::new(OllamaProvider::new(self.base_url.clone(), Some(model))))
+            },
+            ProviderKind::OpenAi => {
+                let model = self.model.clone().unwrap_or_else(|| openai::DEFAULT_EMBEDDING_MODEL.to_string());
+                Ok(Arc::new(OpenAiProvider::new(Some(model), KeyRing::from_env(openai::API_KEY_VAR)?)))
+            },
+            other => Err(ProviderError::NoEmbeddings(other)),
+        }
+    }
+}
+
+/// The cosine of the angle between two vectors: 1 for texts that mean the same, falling towards 0 (or below)
+/// the less they have in common. Vectors of different lengths, or without any length, are not similar at all.
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f64 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let dot: f64 = a.iter().zip(b).map(|(x, y)| *x as f64 * *y as f64).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| *x as f64 * *x as f64).sum::<f64>().sqrt();
+    let norms = norm(a) * norm(b);
+    if norms == 0.0 { 0.0 } else { dot / norms }
+}
@@ -0,0 +1,84 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use tracing::warn;
+
+use super::{Completion, CompletionRequest, LlmProvider, ProviderError, TokenSink};
+
+/// Tries each of an actor's backends in turn, moving on to the next when one fails for good, after any retries.
+/// A completion from any backend but the first names it in [Completion::backend].
+pub struct FailoverProvider {
+    /// Each backend with its description, first the actor's own provider and then its fallbacks in order.
+    backends: Vec<(String, Arc<dyn LlmProvider>)>,
+}
+
+impl FailoverProvider {
+    pub fn new(backends: Vec<(String, Arc<dyn LlmProvider>)>) -> Self {
+        FailoverProvider { backends }
+    }
+}
+
+/// Logs that the backend that failed is being replaced by the next one, if one did.
+fn fail_over(failure: &Option<(&str, ProviderError)>, next: &str) {
+    if let Some((failed, e)) = failure {
+        warn!("{} failed, failing over to {}: {}", failed, next, e);
+    }
+}
+
+/// What the last backend failed with, which is what the call fails with once every backend has.
+fn last_failure(failure: Option<(&str, ProviderError)>) -> ProviderError {
+    // Only an actor without any backend, which is never built, has no failure either.
+    failure.map_or(ProviderError::EmptyResponse, |(_, e)| e)
+}
+
+#[async_trait]
+impl LlmProvider for FailoverProvider {
+    async fn complete(&self, request: &CompletionRequest) -> Result<Completion, ProviderError> {
+        let mut failure = None;
+        for (index, (name, backend)) in self.backends.iter().enumerate() {
+            fail_over(&failure, name);
+            match backend.complete(request).await {
+                Ok(completion) => return Ok(Completion { backend: (index > 0).then(|| name.clone()), ..completion }),
+                Err(e) => failure = Some((name.as_str(), e)),
+            }
+        }
+        Err(last_failure(failure))
+    }
+
+    /// A fallback streams the response from the start, so `on_token` may see the beginning of a response that
+    /// was cut off before the full one.
+    async fn stream(&self, request: &CompletionRequest, on_token: TokenSink<'_>) -> Result<Completion, ProviderError> {
+        let mut failure = None;
+        for (index, (name, backend)) in self.backends.iter().enumerate() {
+            fail_over(&failure, name);
+            match backend.stream(request, on_token).await {
+                Ok(completion) => return Ok(Completion { backend: (index > 0).then(|| name.clone()), ..completion }),
+                Err(e) => failure = Some((name.as_str(), e)),
+            }
+        }
+        Err(last_failure(failure))
+    }
+
+    /// Only as many images as every backend can be shown, so that failing over does not hide any.
+    fn max_images(&self) -> usize {
+        self.backends.iter().map(|(_, backend)| backend.max_images()).min().unwrap_or(0)
+    }
+
+    /// Only if every backend takes tools, since a backend that failed over to would otherwise drop them.
+    fn supports_tools(&self) -> bool {
+        !self.backends.is_empty() && self.backends.iter().all(|(_, backend)| backend.supports_tools())
+    }
+
+    /// Passes if any backend does, since the actor can still answer, but warns about each one that failed first.
+    async fn check(&self) -> Result<(), ProviderError> {
+        let mut failure = None;
+        for (name, backend) in &self.backends {
+            fail_over(&failure, name);
+            match backend.check().await {
+                Ok(()) => return Ok(()),
+                Err(e) => failure = Some((name.as_str(), e)),
+            }
+        }
+        Err(last_failure(failure))
+    }
+}
@@ -0,0 +1,68 @@
+use async_trait::async_trait;
+use reqwest::Client;
+
+use super::{check_credential, vertex::{self, GenerateRequest}, Completion, CompletionRequest, KeyRing, LlmProvider, ProviderError, TokenSink};
+
+pub(super) const API_KEY_VAR: &str = "GEMINI_API_KEY";
+const DEFAULT_BASE_URL: &str = "https://generativelanguage.googleapis.com";
+
+/// Google Gemini through AI Studio, taking turns with its API keys. See [super::VertexProvider] for Gemini on
+/// Vertex AI, which takes requests of the same shape.
+pub struct GeminiProvider {
+    client: Client,
+    /// The model's URL, without the method.
+    url: String,
+    keys: KeyRing,
+}
+
+impl GeminiProvider {
+    /// A provider for `model`, calling `base_url` instead of AI Studio if one is given.
+    pub fn new(base_url: Option<String>, model: Option<String>, keys: KeyRing) -> Self {
+        let url = format!(
+            "{}/v1beta/models/{}",
+            base_url.as_deref().unwrap_or(DEFAULT_BASE_URL).trim_end_matches('/'),
+            model.as_deref().unwrap_or(vertex::DEFAULT_MODEL),
+        );
+        GeminiProvider { client: Client::new(), url, keys }
+    }
+
+    /// Sends the request to the model's `method`, failing unless the API accepted it.
+    async fn send(&self, request: &CompletionRequest, method: &str) -> Result<reqwest::Response, ProviderError> {
+        let response = self.client.post(format!("{}:{}", self.url, method))
+            .header("x-goog-api-key", self.keys.next())
+            .json(&GenerateRequest::new(request))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(ProviderError::Status { status: response.status(), body: response.text().await.unwrap_or_default() });
+        }
+        Ok(response)
+    }
+}
+
+#[async_trait]
+impl LlmProvider for GeminiProvider {
+    async fn complete(&self, request: &CompletionRequest) -> Result<Completion, ProviderError> {
+        vertex::read_completion(self.send(request, "generateContent").await?).await
+    }
+
+    async fn stream(&self, request: &CompletionRequest, on_token: TokenSink<'_>) -> Result<Completion, ProviderError> {
+        vertex::read_stream(self.send(request, "streamGenerateContent?alt=sse").await?, on_token).await
+    }
+
+    fn max_images(&self) -> usize {
+        usize::MAX
+    }
+
+    fn supports_tools(&self) -> bool {
+        true
+    }
+
+    /// Looks the model up with each key, which checks both without generating anything.
+    async fn check(&self) -> Result<(), ProviderError> {
+        for (label, key) in self.keys.each() {
+            check_credential(self.client.get(&self.url).header("x-goog-api-key", key), &label).await?;
+        }
+        Ok(())
+    }
+}
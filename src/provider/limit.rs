@@ -0,0 +1,139 @@
+use std::{collections::{HashMap, VecDeque}, str::FromStr, sync::{Arc, Mutex}, time::Duration};
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use tokio::{sync::Semaphore, time::Instant};
+use tracing::debug;
+
+use super::{Completion, CompletionRequest, LlmProvider, ProviderError, ProviderKind, TokenSink};
+
+/// The window [LimitsConfig::requests_per_minute] counts requests over.
+const WINDOW: Duration = Duration::from_secs(60);
+
+/// The `[limits]` section of the config file.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct LimitsConfig {
+    /// The most provider calls in flight at once across every actor. Further calls wait their turn.
+    #[serde(default)]
+    pub max_concurrent: Option<usize>,
+    /// The most requests sent to each provider in any minute, by provider name. Actors using the same
+    /// provider share its limit, whichever of its keys they use. Further requests wait until the limit allows them.
+    #[serde(default)]
+    pub requests_per_minute: HashMap<String, u32>,
+}
+
+impl LimitsConfig {
+    /// Checks the config, returning a description of the problem if it is unusable.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_concurrent == Some(0) {
+            return Err("limits max_concurrent must be at least 1".to_string());
+        }
+        for (provider, limit) in &self.requests_per_minute {
+            ProviderKind::from_str(provider).map_err(|e| format!("limits requests_per_minute: {}", e))?;
+            if *limit == 0 {
+                return Err(format!("limits requests_per_minute for {} must be at least 1", provider));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The requests a provider has been allowed to send in the last minute, or is scheduled to send.
+struct RateLimit {
+    per_minute: usize,
+    /// When each request was or will be sent, oldest first.
+    sent: Mutex<VecDeque<Instant>>,
+}
+
+impl RateLimit {
+    /// Reserves the earliest time a request can be sent within the limit, after every request reserved before it.
+    fn reserve(&self) -> Instant {
+        let now = Instant::now();
+        let mut sent = self.sent.lock().expect("rate limit lock should not be poisoned");
+        while sent.front().is_some_and(|time| *time + WINDOW <= now) {
+            sent.pop_front();
+        }
+        let mut slot = sent.back().map_or(now, |last| (*last).max(now));
+        if sent.len() >= self.per_minute {
+            slot = slot.max(sent[sent.len() - self.per_minute] + WINDOW);
+        }
+        sent.push_back(slot);
+        slot
+    }
+}
+
+/// The request rates and concurrency every actor's provider is held to. Shared by every [LimitedProvider].
+pub struct Limiter {
+    concurrency: Option<Semaphore>,
+    rates: HashMap<ProviderKind, RateLimit>,
+}
+
+impl Limiter {
+    pub fn new(config: &LimitsConfig) -> Self {
+        let rates = config.requests_per_minute.iter()
+            .filter_map(|(provider, limit)| {
+                let kind = ProviderKind::from_str(provider).ok()?;
+                Some((kind, RateLimit { per_minute: *limit as usize, sent: Mutex::new(VecDeque::new()) }))
+            })
+            .collect();
+        Limiter { concurrency: config.max_concurrent.map(Semaphore::new), rates }
+    }
+
+    /// Whether calls to the given provider are limited at all.
+    pub fn limits(&self, kind: ProviderKind) -> bool {
+        self.concurrency.is_some() || self.rates.contains_key(&kind)
+    }
+
+    /// Waits until the provider's rate limit allows another request, then until fewer than the maximum
+    /// calls are in flight, and runs the call.
+    async fn run<T>(&self, kind: ProviderKind, call: impl std::future::Future<Output = T>) -> T {
+        if let Some(rate) = self.rates.get(&kind) {
+            let slot = rate.reserve();
+            if slot > Instant::now() {
+                debug!("{:?} rate limit reached, waiting {:?}", kind, slot - Instant::now());
+                tokio::time::sleep_until(slot).await;
+            }
+        }
+        let _permit = match &self.concurrency {
+            Some(semaphore) => Some(semaphore.acquire().await.expect("the concurrency semaphore is never closed")),
+            None => None,
+        };
+        call.await
+    }
+}
+
+/// Wraps another provider, holding its calls to the [Limiter]'s limits. Calls beyond them are queued, not failed.
+pub struct LimitedProvider {
+    inner: Arc<dyn LlmProvider>,
+    limiter: Arc<Limiter>,
+    kind: ProviderKind,
+}
+
+impl LimitedProvider {
+    pub fn new(inner: Arc<dyn LlmProvider>, limiter: Arc<Limiter>, kind: ProviderKind) -> Self {
+        LimitedProvider { inner, limiter, kind }
+    }
+}
+
+#[async_trait]
+impl LlmProvider for LimitedProvider {
+    async fn complete(&self, request: &CompletionRequest) -> Result<Completion, ProviderError> {
+        self.limiter.run(self.kind, self.inner.complete(request)).await
+    }
+
+    async fn stream(&self, request: &CompletionRequest, on_token: TokenSink<'_>) -> Result<Completion, ProviderError> {
+        self.limiter.run(self.kind, self.inner.stream(request, on_token)).await
+    }
+
+    fn max_images(&self) -> usize {
+        self.inner.max_images()
+    }
+
+    fn supports_tools(&self) -> bool {
+        self.inner.supports_tools()
+    }
+
+    async fn check(&self) -> Result<(), ProviderError> {
+        self.limiter.run(self.kind, self.inner.check()).await
+    }
+}
@@ -0,0 +1,594 @@
+//! Backends an [crate::LlmActor] can use to reach a language model.
+
+mod anthropic;
+mod azure;
+mod cache;
+mod credentials;
+mod dry_run;
+mod embedding;
+mod failover;
+mod gemini;
+mod limit;
+mod moderation;
+mod ollama;
+mod openai;
+mod panel;
+mod retry;
+mod search;
+mod token;
+mod vertex;
+
+use std::{fmt, str::FromStr, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use reqwest::{RequestBuilder, StatusCode};
+use serde::{Deserialize, Serialize};
+
+use crate::{config::Config, prompts::Template};
+
+pub use anthropic::AnthropicProvider;
+pub use azure::{AzureAuth, AzureOpenAiProvider};
+pub use cache::{CacheConfig, CachingProvider, ResponseCache};
+pub use credentials::{CredentialsConfig, KeyRing, KeySource};
+pub use dry_run::DryRunProvider;
+pub use embedding::{cosine_similarity, EmbeddingConfig, EmbeddingProvider};
+pub use failover::FailoverProvider;
+pub use gemini::GeminiProvider;
+pub use limit::{LimitedProvider, Limiter, LimitsConfig};
+pub use moderation::ModerationProvider;
+pub use ollama::OllamaProvider;
+pub use openai::OpenAiProvider;
+pub use panel::PanelProvider;
+pub use retry::{RetryPolicy, RetryingProvider};
+pub use search::{BraveSearch, SearchProvider, SearchResult, SearxngSearch, SerpApiSearch, TavilySearch};
+pub use vertex::{VertexConfig, VertexProvider};
+
+/// Errors raised by an [LlmProvider] while completing a prompt.
+#[derive(Debug)]
+pub enum ProviderError {
+    /// The HTTP request to the provider failed.
+    Http(reqwest::Error),
+    /// The provider answered with a non-success status code.
+    Status { status: reqwest::StatusCode, body: String },
+    /// The provider did not answer within the retry policy's time limit for a call.
+    TimedOut(Duration),
+    /// The environment variable holding the provider's API key is not set.
+    MissingApiKey(&'static str),
+    /// An environment variable holding another of the provider's credentials is not set.
+    MissingCredential(&'static str),
+    /// The provider's credentials could not be read or used.
+    InvalidCredentials(String),
+    /// The model responded without any text.
+    EmptyResponse,
+    /// The model's response did not follow the requested format.
+    InvalidResponse(String),
+    /// Embeddings were asked of a provider without an embeddings API.
+    NoEmbeddings(ProviderKind),
+    /// A replayed call failed as the call it replays did, or because the transcript has nothing to answer it with.
+    Replay(String),
+    /// The sub-panel standing in for a model could not be built, or could not answer.
+    SubPanel { panel: String, reason: String },
+}
+
+impl fmt::Display for ProviderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProviderError::Http(e) => write!(f, "request failed: {}", e),
+            ProviderError::Status { status, body } => write!(f, "provider returned {}: {}", status, body),
+            ProviderError::TimedOut(limit) => write!(f, "provider did not answer within {:?}", limit),
+            ProviderError::MissingApiKey(var) => write!(f, "No API key has been set in the {0} environment variable. Generate an API key and set it with \"export {0}=<your API key>\".", var),
+            ProviderError::MissingCredential(var) => write!(f, "The {0} environment variable is not set. Set it with \"export {0}=<value>\".", var),
+            ProviderError::InvalidCredentials(reason) => write!(f, "unusable credentials: {}", reason),
+            ProviderError::EmptyResponse => write!(f, "model returned an empty response"),
+            ProviderError::InvalidResponse(reason) => write!(f, "model returned an unusable response: {}", reason),
+            ProviderError::NoEmbeddings(kind) => write!(f, "{:?} has no embeddings API", kind),
+            ProviderError::Replay(reason) => write!(f, "{}", reason),
+            ProviderError::SubPanel { panel, reason } => write!(f, "the \"{}\" sub-panel failed: {}", panel, reason),
+        }
+    }
+}
+
+impl std::error::Error for ProviderError {}
+
+impl From<reqwest::Error> for ProviderError {
+    fn from(e: reqwest::Error) -> Self {
+        ProviderError::Http(e)
+    }
+}
+
+/// A single prompt sent to a provider.
+#[derive(Debug, Clone)]
+pub struct CompletionRequest {
+    /// Instructions for the system role, such as the actor's persona.
+    /// Providers without a system role prepend it to the prompt.
+    pub system: Option<String>,
+    pub prompt: String,
+    /// Images to show the model along with the prompt, for providers whose [LlmProvider::max_images] allows it.
+    pub images: Vec<Image>,
+    pub params: GenerationParams,
+    /// The template the prompt was rendered from, which tells a provider standing in for a model, such as a
+    /// [ReplayProvider](crate::replay::ReplayProvider), what is asked of it.
+    pub template: Template,
+    /// Tools the model may call instead of responding, for providers whose [LlmProvider::supports_tools] allows it.
+    pub tools: Vec<ToolSpec>,
+    /// The tools the model called earlier in this request, and what they returned, oldest first.
+    pub tool_rounds: Vec<ToolRound>,
+}
+
+/// A tool a model may call, described as a function taking a JSON object.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ToolSpec {
+    pub name: String,
+    /// What the tool does, which the model decides whether to call it by.
+    pub description: String,
+    /// A JSON schema of the object of arguments the tool takes.
+    pub parameters: serde_json::Value,
+}
+
+/// A call to one of the request's tools that the model made instead of responding.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ToolCall {
+    /// Identifies the call's output to the model. Providers whose API does not identify calls number them.
+    pub id: String,
+    pub name: String,
+    /// The arguments the model called the tool with, which it was asked to give as an object.
+    pub arguments: serde_json::Value,
+}
+
+/// What a tool call returned, or why it failed, as the model is told.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ToolOutput {
+    /// The id of the call it answers.
+    pub id: String,
+    pub name: String,
+    pub content: String,
+}
+
+/// The calls a model made in one response, and what they returned.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ToolRound {
+    /// Any text the model wrote along with the calls.
+    pub text: String,
+    pub calls: Vec<ToolCall>,
+    pub outputs: Vec<ToolOutput>,
+}
+
+/// An image attached to a question, such as a screenshot or a diagram.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    /// The MIME type, such as `image/png`.
+    pub media_type: &'static str,
+    pub data: Arc<[u8]>,
+}
+
+impl Image {
+    /// Recognizes a PNG, JPEG, GIF or WebP image by its contents, returning None for anything else.
+    pub fn new(data: Vec<u8>) -> Option<Self> {
+        let media_type = match data.as_slice() {
+            [0x89, b'P', b'N', b'G', ..] => "image/png",
+            [0xff, 0xd8, 0xff, ..] => "image/jpeg",
+            [b'G', b'I', b'F', b'8', ..] => "image/gif",
+            [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => "image/webp",
+            _ => return None,
+        };
+        Some(Image { media_type, data: data.into() })
+    }
+
+    /// The image's data in base64, as the providers' APIs take it.
+    pub fn base64(&self) -> String {
+        STANDARD.encode(&self.data)
+    }
+}
+
+/// Sampling settings sent with each request. Unset values are left to the provider's defaults.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+pub struct GenerationParams {
+    /// Randomness of the output, from 0 (deterministic) to 2.
+    pub temperature: Option<f32>,
+    /// Nucleus sampling: only the most likely tokens making up this share of the probability are considered.
+    pub top_p: Option<f32>,
+    /// Upper bound on the length of the response.
+    pub max_tokens: Option<u32>,
+}
+
+impl GenerationParams {
+    /// Checks the settings, returning a description of the problem if they are unusable.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.temperature.is_some_and(|temperature| !(0.0..=2.0).contains(&temperature)) {
+            return Err("temperature must be between 0 and 2".to_string());
+        }
+        if self.top_p.is_some_and(|top_p| !(top_p > 0.0 && top_p <= 1.0)) {
+            return Err("top_p must be in (0, 1]".to_string());
+        }
+        if self.max_tokens == Some(0) {
+            return Err("max_tokens must be at least 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+impl CompletionRequest {
+    /// Joins the system instructions and the prompt for providers that take a single message.
+    pub fn flattened(&self) -> String {
+        match &self.system {
+            Some(system) => format!("{}\n\n{}", system, self.prompt),
+            None => self.prompt.clone(),
+        }
+    }
+}
+
+/// A provider's response to a [CompletionRequest].
+#[derive(Debug, Clone)]
+pub struct Completion {
+    pub text: String,
+    /// Token counts reported by the provider, if it reports them.
+    pub usage: Option<Usage>,
+    /// Which of an actor's fallbacks produced the completion, if its own provider failed.
+    pub backend: Option<String>,
+    /// The tools the model called instead of responding, whose outputs it expects in a further request.
+    pub tool_calls: Vec<ToolCall>,
+}
+
+/// Tokens consumed by one provider call.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Usage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+/// Receives each piece of a streamed response as it arrives.
+pub type TokenSink<'a> = &'a (dyn Fn(&str) + Send + Sync);
+
+/// A language model backend that turns a prompt into a text completion.
+///
+/// Each [crate::LlmActor] owns its own provider, so a single panel can mix models.
+#[async_trait]
+pub trait LlmProvider: Send + Sync {
+    /// Sends the request to the model and returns its text response with the tokens it used.
+    async fn complete(&self, request: &CompletionRequest) -> Result<Completion, ProviderError>;
+
+    /// Like [LlmProvider::complete], but also passes each piece of the response to `on_token` as the model
+    /// generates it. Providers without a streaming API pass the whole response at once when it is complete.
+    async fn stream(&self, request: &CompletionRequest, on_token: TokenSink<'_>) -> Result<Completion, ProviderError> {
+        let completion = self.complete(request).await?;
+        on_token(&completion.text);
+        Ok(completion)
+    }
+
+    /// How many of a request's images the model is shown. Providers for models that cannot see images keep the
+    /// default of none, and their actors are told about the images they were not shown instead.
+    fn max_images(&self) -> usize {
+        0
+    }
+
+    /// Whether the model can be given a request's tools to call. Providers whose API has no tool calling keep the
+    /// default, and their actors answer without tools. A request with tools is sent with [LlmProvider::complete],
+    /// since streamed responses are only read for their text.
+    fn supports_tools(&self) -> bool {
+        false
+    }
+
+    /// Checks that the model can be reached with the provider's credentials, so that a misconfigured provider is
+    /// found before the first question rather than on it. Providers whose API can check every key they were given
+    /// without generating anything, such as by looking the model up, do so. The default asks for a completion a
+    /// few tokens long, which succeeds even if it comes back empty, since the API accepted the call.
+    async fn check(&self) -> Result<(), ProviderError> {
+        let request = CompletionRequest {
+            system: None,
+            prompt: "Reply with OK.".to_string(),
+            images: Vec::new(),
+            params: GenerationParams { max_tokens: Some(8), ..GenerationParams::default() },
+            template: Template::Draft,
+            tools: Vec::new(),
+            tool_rounds: Vec::new(),
+        };
+        match self.complete(&request).await {
+            Ok(_) | Err(ProviderError::EmptyResponse) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Sends a request that generates nothing and only needs the `credential` it carries to be accepted, such as one
+/// looking up the model, failing with [ProviderError::InvalidCredentials] naming the credential if it was rejected.
+async fn check_credential(request: RequestBuilder, credential: &str) -> Result<(), ProviderError> {
+    let response = request.send().await?;
+    let status = response.status();
+    if status.is_success() {
+        return Ok(());
+    }
+    let body = response.text().await.unwrap_or_default();
+    Err(match status {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => ProviderError::InvalidCredentials(format!("{} was rejected with {}: {}", credential, status, body)),
+        status => ProviderError::Status { status, body },
+    })
+}
+
+/// Reads a streamed response body, passing each non-empty line to `on_line` as soon as it is complete.
+async fn read_lines(mut response: reqwest::Response, mut on_line: impl FnMut(&str) -> Result<(), ProviderError> + Send) -> Result<(), ProviderError> {
+    let mut buffer = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        buffer.extend_from_slice(&chunk);
+        while let Some(end) = buffer.iter().position(|byte| *byte == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            if !line.trim().is_empty() {
+                on_line(line.trim())?;
+            }
+        }
+    }
+    let rest = String::from_utf8_lossy(&buffer);
+    if !rest.trim().is_empty() {
+        on_line(rest.trim())?;
+    }
+    Ok(())
+}
+
+/// The supported provider backends.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderKind {
+    #[default]
+    Gemini,
+    OpenAi,
+    Anthropic,
+    Ollama,
+    /// OpenAI models deployed on an Azure OpenAI resource.
+    Azure,
+    /// A stub that prints each request instead of calling a model. See [DryRunProvider].
+    #[serde(rename = "dry_run")]
+    DryRun,
+    /// A whole panel from one of the config file's profiles, whose consensus answer is the response. See
+    /// [PanelProvider].
+    Panel,
+}
+
+impl FromStr for ProviderKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "gemini" => Ok(ProviderKind::Gemini),
+            "openai" => Ok(ProviderKind::OpenAi),
+            "anthropic" => Ok(ProviderKind::Anthropic),
+            "ollama" => Ok(ProviderKind::Ollama),
+            "azure" => Ok(ProviderKind::Azure),
+            "dry_run" => Ok(ProviderKind::DryRun),
+            "panel" => Ok(ProviderKind::Panel),
+            other => Err(format!("unknown provider \"{}\"", other)),
+        }
+    }
+}
+
+impl ProviderKind {
+    /// Whether the provider calls the model it is configured with, so that an actor can evaluate with another one
+    /// of the provider's models. A sub-panel answers with its own actors' models.
+    pub fn takes_model(self) -> bool {
+        self != ProviderKind::Panel
+    }
+
+    /// Whether the provider reports the tokens each call uses, which token and cost budgets are held to.
+    pub fn metered(self) -> bool {
+        self != ProviderKind::DryRun
+    }
+}
+
+/// Which backend an actor talks to, and which model it asks for.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ProviderConfig {
+    #[serde(default)]
+    pub provider: ProviderKind,
+    pub model: Option<String>,
+    /// Endpoint override for self-hosted backends such as Ollama and for Gemini and Vertex AI, and the resource's
+    /// endpoint for Azure, such as `https://<resource>.openai.azure.com`.
+    pub base_url: Option<String>,
+    /// The Azure deployment to call, defaulting to the model name.
+    pub deployment: Option<String>,
+    /// The Azure OpenAI API version, defaulting to 2024-10-21.
+    pub api_version: Option<String>,
+    /// How requests to Azure are authenticated.
+    #[serde(default)]
+    pub auth: AzureAuth,
+    /// Reaches Gemini through Vertex AI in this project and region, rather than through AI Studio.
+    pub vertex: Option<VertexConfig>,
+    /// The names of the keys under `[credentials]` to take turns with, in place of those configured for the provider.
+    #[serde(default)]
+    pub keys: Vec<String>,
+    /// The profile whose panel deliberates on each request in place of a model, for the panel provider.
+    pub panel: Option<String>,
+    /// The config of the [panel](ProviderConfig::panel)'s profile, which [Config::parse] fills in from the rest of
+    /// the config file.
+    #[serde(skip)]
+    pub subpanel: Option<Arc<Config>>,
+    #[serde(flatten)]
+    pub params: GenerationParams,
+}
+
+impl ProviderConfig {
+    /// Checks the config, returning a description of the problem if it is unusable.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.provider == ProviderKind::Panel {
+            if self.panel.as_ref().is_none_or(|panel| panel.trim().is_empty()) {
+                return Err("the panel provider needs the profile whose panel it stands for as panel".to_string());
+            }
+            if self.model.is_some() || self.base_url.is_some() || self.deployment.is_some() || self.vertex.is_some() {
+                return Err("the panel provider takes its models from the profile's actors, so model, base_url, deployment and vertex do not apply".to_string());
+            }
+        } else if self.panel.is_some() {
+            return Err("panel only applies to the panel provider".to_string());
+        }
+        if self.provider == ProviderKind::Azure {
+            if self.base_url.is_none() {
+                return Err("the azure provider needs the resource's endpoint as base_url".to_string());
+            }
+            if self.deployment.is_none() && self.model.is_none() {
+                return Err("the azure provider needs a deployment".to_string());
+            }
+        }
+        if let Some(vertex) = &self.vertex {
+            if self.provider != ProviderKind::Gemini {
+                return Err("vertex only applies to the gemini provider".to_string());
+            }
+            vertex.validate()?;
+        }
+        if !self.keys.is_empty() {
+            if !self.provider.takes_keys() || self.vertex.is_some() {
+                return Err(format!("keys do not apply: {}", self.provider.keyless()));
+            }
+            if self.provider == ProviderKind::Azure && self.auth == AzureAuth::Aad {
+                return Err("keys do not apply: the azure provider signs in rather than using keys with auth = \"aad\"".to_string());
+            }
+        }
+        self.params.validate()
+    }
+
+    /// The backend and model, e.g. "openai gpt-4o", or just the backend if it uses its default model.
+    pub fn describe(&self) -> String {
+        let backend = format!("{:?}", self.provider).to_lowercase();
+        match self.deployment.as_ref().or(self.model.as_ref()).or(self.panel.as_ref()) {
+            Some(model) => format!("{} {}", backend, model),
+            None => backend,
+        }
+    }
+
+    /// Builds the configured provider, reading the API keys it needs from `credentials` or the environment.
+    /// Each call, and each retry of it, waits for the `limiter` if one is given. Failed calls are retried according
+    /// to `retry`, and responses are looked up in `cache` first if one is given.
+    pub fn build(&self, credentials: &CredentialsConfig, retry: &RetryPolicy, cache: Option<&Arc<ResponseCache>>, limiter: Option<&Arc<Limiter>>) -> Result<Arc<dyn LlmProvider>, ProviderError> {
+        self.build_with_fallback(&[], credentials, retry, cache, limiter)
+    }
+
+    /// Like [ProviderConfig::build], but fails over to each of the `fallback` providers in turn when the ones before
+    /// it fail for good, after any retries. Responses are cached as the configured provider's, whichever answered.
+    pub fn build_with_fallback(&self, fallback: &[ProviderConfig], credentials: &CredentialsConfig, retry: &RetryPolicy, cache: Option<&Arc<ResponseCache>>, limiter: Option<&Arc<Limiter>>) -> Result<Arc<dyn LlmProvider>, ProviderError> {
+        let provider = self.build_uncached(credentials, retry, limiter)?;
+        let provider: Arc<dyn LlmProvider> = match fallback {
+            [] => provider,
+            fallback => {
+                let mut backends = vec![(self.describe(), provider)];
+                for config in fallback {
+                    backends.push((config.describe(), config.build_uncached(credentials, retry, limiter)?));
+                }
+                Arc::new(FailoverProvider::new(backends))
+            },
+        };
+        Ok(match cache {
+            Some(cache) => {
+                let model = self.deployment.as_deref().or(self.model.as_deref()).or(self.panel.as_deref()).unwrap_or_default();
+                let location = self.vertex.as_ref().map(|vertex| format!(" {}/{}", vertex.project, vertex.region)).unwrap_or_default();
+                let namespace = format!("{:?} {} {}{}", self.provider, model, self.base_url.as_deref().unwrap_or_default(), location);
+                Arc::new(CachingProvider::new(provider, cache.clone(), namespace))
+            },
+            None => provider,
+        })
+    }
+
+    /// The [DryRunProvider], printing the requests it is sent as sent to `label`, with the same sampling settings.
+    pub fn dry_run(&self, label: String) -> ProviderConfig {
+        ProviderConfig {
+            provider: ProviderKind::DryRun,
+            model: Some(label),
+            base_url: None,
+            deployment: None,
+            api_version: None,
+            auth: AzureAuth::default(),
+            vertex: None,
+            keys: Vec::new(),
+            panel: None,
+            subpanel: None,
+            params: self.params,
+        }
+    }
+
+    /// The same provider with another model, which for Azure is the deployment called.
+    pub fn with_model(&self, model: &str) -> ProviderConfig {
+        ProviderConfig {
+            model: Some(model.to_string()),
+            deployment: self.deployment.as_ref().map(|_| model.to_string()),
+            ..self.clone()
+        }
+    }
+
+    /// Builds the configured provider, limited and retrying, but without a cache.
+    fn build_uncached(&self, credentials: &CredentialsConfig, retry: &RetryPolicy, limiter: Option<&Arc<Limiter>>) -> Result<Arc<dyn LlmProvider>, ProviderError> {
+        let keys = |default_var| credentials.key_ring(self.provider, &self.keys, default_var);
+        let provider: Arc<dyn LlmProvider> = match self.provider {
+            ProviderKind::Gemini => match &self.vertex {
+                Some(vertex) => Arc::new(VertexProvider::new(vertex, self.base_url.as_deref(), self.model.clone())?),
+                None => Arc::new(GeminiProvider::new(self.base_url.clone(), self.model.clone(), keys(gemini::API_KEY_VAR)?)),
+            },
+            ProviderKind::OpenAi => Arc::new(OpenAiProvider::new(self.model.clone(), keys(openai::API_KEY_VAR)?)),
+            ProviderKind::Anthropic => Arc::new(AnthropicProvider::new(self.model.clone(), keys(anthropic::API_KEY_VAR)?)),
+            ProviderKind::Ollama => Arc::new(OllamaProvider::new(self.base_url.clone(), self.model.clone())),
+            ProviderKind::DryRun => Arc::new(DryRunProvider::new(self.model.clone())),
+            ProviderKind::Panel => {
+                // The sub-panel's own providers are limited and retried as its profile says.
+                let panel = self.panel.clone().unwrap_or_default();
+                return match &self.subpanel {
+                    Some(config) => Ok(Arc::new(PanelProvider::from_config(panel, config)?)),
+                    None => Err(ProviderError::SubPanel { panel, reason: "only actors in the config file can stand for a sub-panel".to_string() }),
+                };
+            },
+            ProviderKind::Azure => {
+                let endpoint = self.base_url.as_deref().unwrap_or_default();
+                let deployment = self.deployment.clone().or_else(|| self.model.clone()).unwrap_or_default();
+                match self.auth {
+                    AzureAuth::Key => Arc::new(AzureOpenAiProvider::new(endpoint, deployment, self.api_version.clone(), keys(azure::API_KEY_VAR)?)),
+                    AzureAuth::Aad => Arc::new(AzureOpenAiProvider::service_principal(endpoint, deployment, self.api_version.clone())?),
+                }
+            },
+        };
+        let provider: Arc<dyn LlmProvider> = match limiter {
+            Some(limiter) if limiter.limits(self.provider) => Arc::new(LimitedProvider::new(provider, limiter.clone(), self.provider)),
+            _ => provider,
+        };
+        Ok(match (retry.attempts, retry.call_timeout_secs) {
+            (0 | 1, None) => provider,
+            _ => Arc::new(RetryingProvider::new(provider, *retry)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+
+    /// A question holding both kinds of quotes, as code does.
+    const QUESTION: &str = r#"Why does `println!("{}", "it's \"quoted\"")` print it's "quoted"?"#;
+    /// A persona holding both kinds of quotes, sent as the system instructions.
+    const SYSTEM: &str = r#"You are "Ada", the panel's expert on Rust."#;
+
+    /// The body as the provider's API receives it: serialized, then read as JSON.
+    fn sent(body: impl Serialize) -> Value {
+        let body = serde_json::to_string(&body).expect("the request body should serialize");
+        serde_json::from_str(&body).expect("the request body should be valid JSON")
+    }
+
+    #[test]
+    fn request_bodies_keep_the_prompts_quotes() {
+        let request = CompletionRequest {
+            system: Some(SYSTEM.to_string()),
+            prompt: QUESTION.to_string(),
+            images: Vec::new(),
+            params: GenerationParams::default(),
+            template: Template::Draft,
+            tools: Vec::new(),
+            tool_rounds: Vec::new(),
+        };
+        // Each body builder, with where its body holds the system instructions and the question. Azure sends
+        // OpenAI's body, and Gemini Vertex's.
+        let bodies = [
+            ("openai", sent(openai::ChatRequest::new("model", &request, false)), "/messages/0/content", "/messages/1/content"),
+            ("anthropic", sent(anthropic::MessagesRequest::new("model", &request, false)), "/system", "/messages/0/content/0/text"),
+            ("ollama", sent(ollama::ChatRequest::new("model", &request, false)), "/messages/0/content", "/messages/1/content"),
+            ("vertex", sent(vertex::GenerateRequest::new(&request)), "/systemInstruction/parts/0/text", "/contents/0/parts/0/text"),
+        ];
+        for (provider, body, system, question) in bodies {
+            assert_eq!(body.pointer(system).and_then(Value::as_str), Some(SYSTEM), "{} sent the wrong system instructions: {}", provider, body);
+            assert_eq!(body.pointer(question).and_then(Value::as_str), Some(QUESTION), "{} sent the wrong question: {}", provider, body);
+        }
+    }
+}
@@ -0,0 +1,10 @@
+use async_trait::async_trait;
+
+use super::ProviderError;
+
+/// A safety classifier that screens text for harmful content.
+#[async_trait]
+pub trait ModerationProvider: Send + Sync {
+    /// Screens the texts together, returning why if any of them is flagged, or None if they are all acceptable.
+    async fn moderate(&self, texts: &[String]) -> Result<Option<String>, ProviderError>;
+}
@@ -0,0 +1,242 @@
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+use super::{openai::FunctionTool, read_lines, Completion, CompletionRequest, EmbeddingProvider, Image, LlmProvider, ProviderError, TokenSink, ToolCall, Usage};
+
+const DEFAULT_BASE_URL: &str = "http://localhost:11434";
+const DEFAULT_MODEL: &str = "llama3";
+pub(super) const DEFAULT_EMBEDDING_MODEL: &str = "nomic-embed-text";
+
+/// A local Ollama server. No API key is needed.
+pub struct OllamaProvider {
+    client: Client,
+    base_url: String,
+    model: String,
+}
+
+#[derive(Serialize)]
+pub(super) struct ChatRequest<'a> {
+    model: &'a str,
+    messages: Vec<ChatMessage<'a>>,
+    stream: bool,
+    options: ChatOptions,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<FunctionTool<'a>>,
+}
+
+/// Ollama's name for each of the [super::GenerationParams].
+#[derive(Serialize)]
+struct ChatOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    num_predict: Option<u32>,
+}
+
+#[derive(Serialize)]
+struct ChatMessage<'a> {
+    role: &'a str,
+    content: &'a str,
+    /// In base64, for multimodal models such as llava.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    images: Vec<String>,
+    /// The calls to tools an assistant message made.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tool_calls: Vec<MessageToolCall<'a>>,
+    /// The tool a `tool` message holds the output of.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_name: Option<&'a str>,
+}
+
+impl<'a> ChatMessage<'a> {
+    fn new(role: &'a str, content: &'a str) -> Self {
+        ChatMessage { role, content, images: Vec::new(), tool_calls: Vec::new(), tool_name: None }
+    }
+}
+
+#[derive(Serialize)]
+struct MessageToolCall<'a> {
+    function: MessageFunctionCall<'a>,
+}
+
+#[derive(Serialize)]
+struct MessageFunctionCall<'a> {
+    name: &'a str,
+    arguments: &'a serde_json::Value,
+}
+
+/// The response, or with streaming one line of it.
+#[derive(Deserialize)]
+struct ChatResponse {
+    message: ResponseMessage,
+    /// Only given in the last line of a streamed response, with the token counts.
+    #[serde(default)]
+    done: bool,
+    prompt_eval_count: Option<u64>,
+    eval_count: Option<u64>,
+}
+
+impl ChatResponse {
+    fn usage(&self) -> Option<Usage> {
+        match (self.prompt_eval_count, self.eval_count) {
+            (Some(prompt_tokens), Some(completion_tokens)) => Some(Usage { prompt_tokens, completion_tokens }),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ResponseMessage {
+    #[serde(default)]
+    content: String,
+    /// Ollama gives the calls no ids, so they are numbered in the order they come.
+    #[serde(default)]
+    tool_calls: Vec<ResponseToolCall>,
+}
+
+#[derive(Deserialize)]
+struct ResponseToolCall {
+    function: ResponseFunctionCall,
+}
+
+#[derive(Deserialize)]
+struct ResponseFunctionCall {
+    name: String,
+    #[serde(default)]
+    arguments: serde_json::Value,
+}
+
+#[derive(Serialize)]
+struct EmbedRequest<'a> {
+    model: &'a str,
+    input: &'a [String],
+}
+
+#[derive(Deserialize)]
+struct EmbedResponse {
+    embeddings: Vec<Vec<f32>>,
+}
+
+impl<'a> ChatRequest<'a> {
+    pub(super) fn new(model: &'a str, request: &'a CompletionRequest, stream: bool) -> Self {
+        let mut messages = Vec::with_capacity(2);
+        if let Some(system) = &request.system {
+            messages.push(ChatMessage::new("system", system));
+        }
+        messages.push(ChatMessage { images: request.images.iter().map(Image::base64).collect(), ..ChatMessage::new("user", &request.prompt) });
+        for round in &request.tool_rounds {
+            let tool_calls = round.calls.iter()
+                .map(|call| MessageToolCall { function: MessageFunctionCall { name: &call.name, arguments: &call.arguments } })
+                .collect();
+            messages.push(ChatMessage { tool_calls, ..ChatMessage::new("assistant", &round.text) });
+            messages.extend(round.outputs.iter().map(|output| ChatMessage { tool_name: Some(&output.name), ..ChatMessage::new("tool", &output.content) }));
+        }
+        let options = ChatOptions { temperature: request.params.temperature, top_p: request.params.top_p, num_predict: request.params.max_tokens };
+        ChatRequest { model, messages, stream, options, tools: request.tools.iter().map(FunctionTool::new).collect() }
+    }
+}
+
+impl OllamaProvider {
+    pub fn new(base_url: Option<String>, model: Option<String>) -> Self {
+        OllamaProvider {
+            client: Client::new(),
+            base_url: base_url.unwrap_or_else(|| DEFAULT_BASE_URL.to_string()).trim_end_matches('/').to_string(),
+            model: model.unwrap_or_else(|| DEFAULT_MODEL.to_string()),
+        }
+    }
+
+    /// Sends the request, failing unless the server accepted it.
+    async fn send(&self, request: &CompletionRequest, stream: bool) -> Result<reqwest::Response, ProviderError> {
+        let response = self.client.post(format!("{}/api/chat", self.base_url))
+            .json(&ChatRequest::new(&self.model, request, stream))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(ProviderError::Status { status: response.status(), body: response.text().await.unwrap_or_default() });
+        }
+        Ok(response)
+    }
+}
+
+#[async_trait]
+impl LlmProvider for OllamaProvider {
+    async fn complete(&self, request: &CompletionRequest) -> Result<Completion, ProviderError> {
+        let response: ChatResponse = self.send(request, false).await?.json().await?;
+        if response.message.content.is_empty() && response.message.tool_calls.is_empty() {
+            return Err(ProviderError::EmptyResponse);
+        }
+        let usage = response.usage();
+        let tool_calls = response.message.tool_calls.into_iter()
+            .enumerate()
+            .map(|(n, call)| ToolCall { id: format!("call_{}", n), name: call.function.name, arguments: call.function.arguments })
+            .collect();
+        Ok(Completion { text: response.message.content, usage, backend: None, tool_calls })
+    }
+
+    /// Ollama streams one JSON object per line, each holding the next piece of the message.
+    async fn stream(&self, request: &CompletionRequest, on_token: TokenSink<'_>) -> Result<Completion, ProviderError> {
+        let response = self.send(request, true).await?;
+        let (mut text, mut usage) = (String::new(), None);
+        read_lines(response, |line| {
+            let chunk: ChatResponse = serde_json::from_str(line)
+                .map_err(|e| ProviderError::InvalidResponse(format!("unreadable stream line ({}): {}", e, line)))?;
+            if !chunk.message.content.is_empty() {
+                on_token(&chunk.message.content);
+                text.push_str(&chunk.message.content);
+            }
+            if chunk.done {
+                usage = chunk.usage();
+            }
+            Ok(())
+        }).await?;
+        if text.is_empty() {
+            return Err(ProviderError::EmptyResponse);
+        }
+        Ok(Completion { text, usage, backend: None, tool_calls: Vec::new() })
+    }
+
+    /// Only multimodal models such as llava look at the images; other models ignore them.
+    fn max_images(&self) -> usize {
+        usize::MAX
+    }
+
+    /// Only models trained for tool calling, such as llama3.1, take the tools; the server rejects the request for
+    /// others.
+    fn supports_tools(&self) -> bool {
+        true
+    }
+
+    /// Asks the server about the model, which fails if the server is down or the model has not been pulled.
+    async fn check(&self) -> Result<(), ProviderError> {
+        let response = self.client.post(format!("{}/api/show", self.base_url))
+            .json(&serde_json::json!({ "model": self.model }))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(ProviderError::Status { status: response.status(), body: response.text().await.unwrap_or_default() });
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for OllamaProvider {
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, ProviderError> {
+        let response = self.client.post(format!("{}/api/embed", self.base_url))
+            .json(&EmbedRequest { model: &self.model, input: texts })
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(ProviderError::Status { status: response.status(), body: response.text().await.unwrap_or_default() });
+        }
+        let response: EmbedResponse = response.json().await?;
+        if response.embeddings.len() != texts.len() {
+            return Err(ProviderError::InvalidResponse(format!("{} embeddings for {} texts", response.embeddings.len(), texts.len())));
+        }
+        Ok(response.embeddings)
+    }
+}
+
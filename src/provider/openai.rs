@@ -0,0 +1,404 @@
+use std::collections::BTreeMap;
+
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+use super::{check_credential, read_lines, Completion, CompletionRequest, EmbeddingProvider, KeyRing, LlmProvider, ModerationProvider, ProviderError, TokenSink, ToolCall, ToolSpec, Usage};
+
+const CHAT_COMPLETIONS_URL: &str = "https://api.openai.com/v1/chat/completions";
+const EMBEDDINGS_URL: &str = "https://api.openai.com/v1/embeddings";
+const MODERATIONS_URL: &str = "https://api.openai.com/v1/moderations";
+const MODELS_URL: &str = "https://api.openai.com/v1/models";
+pub(super) const API_KEY_VAR: &str = "OPENAI_API_KEY";
+const DEFAULT_MODEL: &str = "gpt-4o-mini";
+pub(super) const DEFAULT_EMBEDDING_MODEL: &str = "text-embedding-3-small";
+const DEFAULT_MODERATION_MODEL: &str = "omni-moderation-latest";
+
+/// OpenAI's Chat Completions API, authenticated with the configured keys or the `OPENAI_API_KEY` environment
+/// variable.
+pub struct OpenAiProvider {
+    client: Client,
+    keys: KeyRing,
+    model: String,
+}
+
+/// The body of a Chat Completions request, which Azure OpenAI takes too.
+#[derive(Serialize)]
+pub(super) struct ChatRequest<'a> {
+    model: &'a str,
+    messages: Vec<ChatMessage<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<StreamOptions>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<FunctionTool<'a>>,
+}
+
+/// A tool as Chat Completions, and Ollama, take it.
+#[derive(Serialize)]
+pub(super) struct FunctionTool<'a> {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    function: &'a ToolSpec,
+}
+
+impl<'a> FunctionTool<'a> {
+    pub(super) fn new(spec: &'a ToolSpec) -> Self {
+        FunctionTool { kind: "function", function: spec }
+    }
+}
+
+#[derive(Serialize)]
+struct StreamOptions {
+    /// Asks for a final chunk with the token counts, which streamed responses otherwise leave out.
+    include_usage: bool,
+}
+
+#[derive(Serialize)]
+struct ChatMessage<'a> {
+    role: &'a str,
+    /// Left out of an assistant message that only calls tools.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content: Option<MessageContent<'a>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tool_calls: Vec<MessageToolCall<'a>>,
+    /// The call a tool message gives the output of.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_call_id: Option<&'a str>,
+}
+
+impl<'a> ChatMessage<'a> {
+    fn new(role: &'a str, content: MessageContent<'a>) -> Self {
+        ChatMessage { role, content: Some(content), tool_calls: Vec::new(), tool_call_id: None }
+    }
+}
+
+#[derive(Serialize)]
+struct MessageToolCall<'a> {
+    id: &'a str,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    function: FunctionCall,
+}
+
+#[derive(Serialize, Deserialize)]
+struct FunctionCall {
+    name: String,
+    /// The arguments as a JSON string.
+    arguments: String,
+}
+
+/// A message's text, or its text and images as a list of parts when it has any.
+#[derive(Serialize)]
+#[serde(untagged)]
+enum MessageContent<'a> {
+    Text(&'a str),
+    Parts(Vec<ContentPart<'a>>),
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ContentPart<'a> {
+    Text { text: &'a str },
+    ImageUrl { image_url: ImageUrl },
+}
+
+#[derive(Serialize)]
+struct ImageUrl {
+    /// The image as a `data:` URL.
+    url: String,
+}
+
+#[derive(Deserialize)]
+struct ChatResponse {
+    choices: Vec<Choice>,
+    usage: Option<ChatUsage>,
+}
+
+#[derive(Deserialize)]
+struct ChatUsage {
+    prompt_tokens: u64,
+    completion_tokens: u64,
+}
+
+#[derive(Deserialize)]
+struct Choice {
+    message: ResponseMessage,
+}
+
+#[derive(Deserialize)]
+struct ResponseMessage {
+    content: Option<String>,
+    #[serde(default)]
+    tool_calls: Vec<ResponseToolCall>,
+}
+
+#[derive(Deserialize)]
+struct ResponseToolCall {
+    id: String,
+    function: FunctionCall,
+}
+
+impl From<ResponseToolCall> for ToolCall {
+    /// Arguments that are not valid JSON are passed on as a string, for the tool to reject.
+    fn from(call: ResponseToolCall) -> Self {
+        let arguments = serde_json::from_str(&call.function.arguments).unwrap_or(serde_json::Value::String(call.function.arguments));
+        ToolCall { id: call.id, name: call.function.name, arguments }
+    }
+}
+
+/// The piece of the message a streamed chunk holds.
+#[derive(Deserialize)]
+struct ChunkDelta {
+    content: Option<String>,
+}
+
+/// One server-sent event of a streamed response.
+#[derive(Deserialize)]
+struct ChatChunk {
+    #[serde(default)]
+    choices: Vec<ChunkChoice>,
+    /// Only given in the final chunk.
+    usage: Option<ChatUsage>,
+}
+
+#[derive(Deserialize)]
+struct ChunkChoice {
+    delta: ChunkDelta,
+}
+
+#[derive(Serialize)]
+struct EmbeddingRequest<'a> {
+    model: &'a str,
+    input: &'a [String],
+}
+
+#[derive(Deserialize)]
+struct EmbeddingResponse {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingData {
+    /// Which of the input texts this is the embedding of.
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+#[derive(Serialize)]
+struct ModerationRequest<'a> {
+    model: &'a str,
+    input: &'a [String],
+}
+
+#[derive(Deserialize)]
+struct ModerationResponse {
+    results: Vec<ModerationResult>,
+}
+
+#[derive(Deserialize)]
+struct ModerationResult {
+    flagged: bool,
+    /// Whether the text falls into each category, such as `violence` or `self-harm`.
+    #[serde(default)]
+    categories: BTreeMap<String, bool>,
+}
+
+impl ChatUsage {
+    fn into_usage(self) -> Usage {
+        Usage { prompt_tokens: self.prompt_tokens, completion_tokens: self.completion_tokens }
+    }
+}
+
+impl OpenAiProvider {
+    pub fn new(model: Option<String>, keys: KeyRing) -> Self {
+        OpenAiProvider {
+            client: Client::new(),
+            keys,
+            model: model.unwrap_or_else(|| DEFAULT_MODEL.to_string()),
+        }
+    }
+
+    /// A client for the moderation API, with `model` defaulting to `omni-moderation-latest`.
+    pub fn moderation(model: Option<String>) -> Result<Self, ProviderError> {
+        Ok(OpenAiProvider::new(Some(model.unwrap_or_else(|| DEFAULT_MODERATION_MODEL.to_string())), KeyRing::from_env(API_KEY_VAR)?))
+    }
+
+    /// Sends the request, failing unless the API accepted it.
+    async fn send(&self, request: &CompletionRequest, stream: bool) -> Result<reqwest::Response, ProviderError> {
+        let response = self.client.post(CHAT_COMPLETIONS_URL)
+            .bearer_auth(self.keys.next())
+            .json(&ChatRequest::new(&self.model, request, stream))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(ProviderError::Status { status: response.status(), body: response.text().await.unwrap_or_default() });
+        }
+        Ok(response)
+    }
+}
+
+impl<'a> ChatRequest<'a> {
+    pub(super) fn new(model: &'a str, request: &'a CompletionRequest, stream: bool) -> Self {
+        let mut messages = Vec::with_capacity(2 + request.tool_rounds.len() * 2);
+        if let Some(system) = &request.system {
+            messages.push(ChatMessage::new("system", MessageContent::Text(system)));
+        }
+        let content = match request.images.as_slice() {
+            [] => MessageContent::Text(&request.prompt),
+            images => MessageContent::Parts([ContentPart::Text { text: &request.prompt }].into_iter()
+                .chain(images.iter().map(|image| ContentPart::ImageUrl {
+                    image_url: ImageUrl { url: format!("data:{};base64,{}", image.media_type, image.base64()) },
+                }))
+                .collect()),
+        };
+        messages.push(ChatMessage::new("user", content));
+        for round in &request.tool_rounds {
+            messages.push(ChatMessage {
+                role: "assistant",
+                content: (!round.text.is_empty()).then_some(MessageContent::Text(&round.text)),
+                tool_calls: round.calls.iter()
+                    .map(|call| MessageToolCall { id: &call.id, kind: "function", function: FunctionCall { name: call.name.clone(), arguments: call.arguments.to_string() } })
+                    .collect(),
+                tool_call_id: None,
+            });
+            messages.extend(round.outputs.iter().map(|output| ChatMessage {
+                tool_call_id: Some(&output.id),
+                ..ChatMessage::new("tool", MessageContent::Text(&output.content))
+            }));
+        }
+        let params = &request.params;
+        ChatRequest {
+            model,
+            messages,
+            temperature: params.temperature,
+            top_p: params.top_p,
+            max_tokens: params.max_tokens,
+            stream,
+            stream_options: stream.then_some(StreamOptions { include_usage: true }),
+            tools: request.tools.iter().map(FunctionTool::new).collect(),
+        }
+    }
+}
+
+/// Reads the completion from a Chat Completions response, which holds text, calls to the request's tools, or both.
+pub(super) async fn read_completion(response: reqwest::Response) -> Result<Completion, ProviderError> {
+    let response: ChatResponse = response.json().await?;
+    let usage = response.usage.map(ChatUsage::into_usage);
+    let message = response.choices.into_iter().next().ok_or(ProviderError::EmptyResponse)?.message;
+    let tool_calls: Vec<ToolCall> = message.tool_calls.into_iter().map(ToolCall::from).collect();
+    match message.content {
+        None if tool_calls.is_empty() => Err(ProviderError::EmptyResponse),
+        text => Ok(Completion { text: text.unwrap_or_default(), usage, backend: None, tool_calls }),
+    }
+}
+
+/// Reads a streamed Chat Completions response, which arrives as server-sent events, each holding the next
+/// piece of the message.
+pub(super) async fn read_stream(response: reqwest::Response, on_token: TokenSink<'_>) -> Result<Completion, ProviderError> {
+    let (mut text, mut usage) = (String::new(), None);
+    read_lines(response, |line| {
+        let Some(data) = line.strip_prefix("data:").map(str::trim) else { return Ok(()) };
+        if data == "[DONE]" {
+            return Ok(());
+        }
+        let chunk: ChatChunk = serde_json::from_str(data)
+            .map_err(|e| ProviderError::InvalidResponse(format!("unreadable stream event ({}): {}", e, data)))?;
+        if let Some(content) = chunk.choices.into_iter().next().and_then(|choice| choice.delta.content) {
+            on_token(&content);
+            text.push_str(&content);
+        }
+        if let Some(chunk_usage) = chunk.usage {
+            usage = Some(chunk_usage.into_usage());
+        }
+        Ok(())
+    }).await?;
+    if text.is_empty() {
+        return Err(ProviderError::EmptyResponse);
+    }
+    Ok(Completion { text, usage, backend: None, tool_calls: Vec::new() })
+}
+
+#[async_trait]
+impl LlmProvider for OpenAiProvider {
+    async fn complete(&self, request: &CompletionRequest) -> Result<Completion, ProviderError> {
+        read_completion(self.send(request, false).await?).await
+    }
+
+    async fn stream(&self, request: &CompletionRequest, on_token: TokenSink<'_>) -> Result<Completion, ProviderError> {
+        read_stream(self.send(request, true).await?, on_token).await
+    }
+
+    fn max_images(&self) -> usize {
+        usize::MAX
+    }
+
+    fn supports_tools(&self) -> bool {
+        true
+    }
+
+    /// Looks the model up with each key, which checks both without generating anything.
+    async fn check(&self) -> Result<(), ProviderError> {
+        for (label, key) in self.keys.each() {
+            check_credential(self.client.get(format!("{}/{}", MODELS_URL, self.model)).bearer_auth(key), &label).await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for OpenAiProvider {
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, ProviderError> {
+        let response = self.client.post(EMBEDDINGS_URL)
+            .bearer_auth(self.keys.next())
+            .json(&EmbeddingRequest { model: &self.model, input: texts })
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(ProviderError::Status { status: response.status(), body: response.text().await.unwrap_or_default() });
+        }
+        let mut response: EmbeddingResponse = response.json().await?;
+        if response.data.len() != texts.len() {
+            return Err(ProviderError::InvalidResponse(format!("{} embeddings for {} texts", response.data.len(), texts.len())));
+        }
+        response.data.sort_by_key(|data| data.index);
+        Ok(response.data.into_iter().map(|data| data.embedding).collect())
+    }
+}
+
+#[async_trait]
+impl ModerationProvider for OpenAiProvider {
+    async fn moderate(&self, texts: &[String]) -> Result<Option<String>, ProviderError> {
+        let response = self.client.post(MODERATIONS_URL)
+            .bearer_auth(self.keys.next())
+            .json(&ModerationRequest { model: &self.model, input: texts })
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(ProviderError::Status { status: response.status(), body: response.text().await.unwrap_or_default() });
+        }
+        let response: ModerationResponse = response.json().await?;
+        let flagged: Vec<ModerationResult> = response.results.into_iter().filter(|result| result.flagged).collect();
+        if flagged.is_empty() {
+            return Ok(None);
+        }
+        let mut categories: Vec<String> = flagged.into_iter()
+            .flat_map(|result| result.categories.into_iter().filter(|(_, flagged)| *flagged).map(|(category, _)| category))
+            .collect();
+        categories.sort();
+        categories.dedup();
+        Ok(Some(match categories.as_slice() {
+            [] => "flagged by OpenAI's moderation".to_string(),
+            categories => format!("flagged by OpenAI's moderation as {}", categories.join(", ")),
+        }))
+    }
+}
+
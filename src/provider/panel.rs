@@ -0,0 +1,50 @@
+use async_trait::async_trait;
+use tracing::debug;
+
+use crate::{config::Config, engine::Engine};
+
+use super::{Completion, CompletionRequest, LlmProvider, ProviderError, Usage};
+
+/// A whole panel standing in for one actor's model, so that a committee can sit on a committee: every request the
+/// actor sends is asked of the sub-panel as a question, and the answer it agrees on is the actor's response, be it a
+/// draft, an evaluation or a vote. The sub-panel's actors may stand for panels of their own in turn.
+///
+/// The sub-panel deliberates on an [Engine], so it offers only what the engine does, and its token usage is
+/// reported as the actor's.
+pub struct PanelProvider {
+    /// The profile the sub-panel was built from.
+    name: String,
+    engine: Engine,
+}
+
+impl PanelProvider {
+    /// Builds the sub-panel from the config of the profile called `name`.
+    pub fn from_config(name: String, config: &Config) -> Result<Self, ProviderError> {
+        match Engine::from_config(config) {
+            Ok(engine) => Ok(PanelProvider { name, engine }),
+            Err(e) => Err(ProviderError::SubPanel { panel: name, reason: e.to_string() }),
+        }
+    }
+}
+
+#[async_trait]
+impl LlmProvider for PanelProvider {
+    async fn complete(&self, request: &CompletionRequest) -> Result<Completion, ProviderError> {
+        let question = match &request.system {
+            Some(system) => format!("{}\n\n{}", system, request.prompt),
+            None => request.prompt.clone(),
+        };
+        let result = self.engine.ask(&question).await
+            .map_err(|e| ProviderError::SubPanel { panel: self.name.clone(), reason: e.to_string() })?;
+        let agreement = if result.consensus_reached { "with consensus" } else { "without consensus" };
+        debug!("The \"{}\" sub-panel answered {} after {} round(s).", self.name, agreement, result.rounds.len());
+        let usage = Usage { prompt_tokens: result.usage.total.prompt_tokens, completion_tokens: result.usage.total.completion_tokens };
+        Ok(Completion { text: result.answer, usage: Some(usage), backend: None, tool_calls: Vec::new() })
+    }
+
+    /// Checks every provider on the sub-panel, rather than asking it a question.
+    async fn check(&self) -> Result<(), ProviderError> {
+        self.engine.check().await
+            .map_err(|e| ProviderError::SubPanel { panel: self.name.clone(), reason: e.to_string() })
+    }
+}
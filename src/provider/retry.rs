@@ -0,0 +1,153 @@
+use std::{future::Future, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use rand::Rng;
+use serde::Deserialize;
+use tracing::warn;
+
+use super::{Completion, CompletionRequest, LlmProvider, ProviderError, TokenSink};
+
+/// How failed provider calls are retried. Read from the `[retry]` section of the config file.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// Total attempts per call, including the first. 1 disables retrying.
+    pub attempts: u32,
+    /// Delay before the first retry, doubled after each further failure.
+    pub initial_backoff_ms: u64,
+    /// Upper bound on the delay between attempts.
+    pub max_backoff_ms: u64,
+    /// Randomize each delay between half and all of its value, so that actors hitting the
+    /// same rate limit do not retry in lockstep.
+    pub jitter: bool,
+    /// How long each attempt may take before it fails as timed out, and is retried. Unlimited if unset.
+    pub call_timeout_secs: Option<u64>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy { attempts: 3, initial_backoff_ms: 500, max_backoff_ms: 8_000, jitter: true, call_timeout_secs: None }
+    }
+}
+
+impl RetryPolicy {
+    /// Checks the policy, returning a description of the problem if it is unusable.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.attempts == 0 {
+            return Err("retry attempts must be at least 1".to_string());
+        }
+        if self.initial_backoff_ms > self.max_backoff_ms {
+            return Err("retry initial_backoff_ms must not exceed max_backoff_ms".to_string());
+        }
+        if self.call_timeout_secs == Some(0) {
+            return Err("retry call_timeout_secs must be at least 1".to_string());
+        }
+        Ok(())
+    }
+
+    /// The delay before the given retry, counting the first retry as 1.
+    fn backoff(&self, retry: u32) -> Duration {
+        let exponential = self.initial_backoff_ms.saturating_mul(1u64.checked_shl(retry - 1).unwrap_or(u64::MAX));
+        let capped = exponential.min(self.max_backoff_ms);
+        let millis = if self.jitter && capped > 0 {
+            rand::thread_rng().gen_range(capped / 2..=capped)
+        } else {
+            capped
+        };
+        Duration::from_millis(millis)
+    }
+}
+
+impl ProviderError {
+    /// Whether the call might succeed if it is repeated, e.g. after a rate limit or a server error.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            ProviderError::Http(_) | ProviderError::TimedOut(_) | ProviderError::EmptyResponse => true,
+            ProviderError::Status { status, .. } => status.as_u16() == 429 || status.is_server_error(),
+            ProviderError::MissingApiKey(_) | ProviderError::MissingCredential(_) | ProviderError::InvalidCredentials(_)
+            | ProviderError::InvalidResponse(_) | ProviderError::NoEmbeddings(_) | ProviderError::Replay(_) => false,
+            // The sub-panel's own providers have already been retried.
+            ProviderError::SubPanel { .. } => false,
+        }
+    }
+}
+
+/// Wraps another provider, retrying retryable failures according to a [RetryPolicy], and failing attempts that
+/// take longer than it allows.
+pub struct RetryingProvider {
+    inner: Arc<dyn LlmProvider>,
+    policy: RetryPolicy,
+}
+
+impl RetryingProvider {
+    pub fn new(inner: Arc<dyn LlmProvider>, policy: RetryPolicy) -> Self {
+        RetryingProvider { inner, policy }
+    }
+
+    /// Runs one attempt, failing it if it outlasts the policy's time limit.
+    async fn attempt<T>(&self, call: impl Future<Output = Result<T, ProviderError>>) -> Result<T, ProviderError> {
+        match self.policy.call_timeout_secs.map(Duration::from_secs) {
+            Some(limit) => tokio::time::timeout(limit, call).await.unwrap_or(Err(ProviderError::TimedOut(limit))),
+            None => call.await,
+        }
+    }
+
+    /// Waits out the backoff after the given failed attempt.
+    async fn wait(&self, attempt: u32, e: &ProviderError) {
+        let delay = self.policy.backoff(attempt);
+        warn!("Provider call failed on attempt {} of {}, retrying in {:?}: {}", attempt, self.policy.attempts, delay, e);
+        tokio::time::sleep(delay).await;
+    }
+}
+
+#[async_trait]
+impl LlmProvider for RetryingProvider {
+    async fn complete(&self, request: &CompletionRequest) -> Result<Completion, ProviderError> {
+        let mut attempt = 1;
+        loop {
+            match self.attempt(self.inner.complete(request)).await {
+                Err(e) if e.is_retryable() && attempt < self.policy.attempts => {
+                    self.wait(attempt, &e).await;
+                    attempt += 1;
+                },
+                result => return result,
+            }
+        }
+    }
+
+    /// A retry streams the response again from the start, so `on_token` may see the beginning of a
+    /// response that was cut off before the full one.
+    async fn stream(&self, request: &CompletionRequest, on_token: TokenSink<'_>) -> Result<Completion, ProviderError> {
+        let mut attempt = 1;
+        loop {
+            match self.attempt(self.inner.stream(request, on_token)).await {
+                Err(e) if e.is_retryable() && attempt < self.policy.attempts => {
+                    self.wait(attempt, &e).await;
+                    attempt += 1;
+                },
+                result => return result,
+            }
+        }
+    }
+
+    fn max_images(&self) -> usize {
+        self.inner.max_images()
+    }
+
+    fn supports_tools(&self) -> bool {
+        self.inner.supports_tools()
+    }
+
+    async fn check(&self) -> Result<(), ProviderError> {
+        let mut attempt = 1;
+        loop {
+            match self.attempt(self.inner.check()).await {
+                Err(e) if e.is_retryable() && attempt < self.policy.attempts => {
+                    self.wait(attempt, &e).await;
+                    attempt += 1;
+                },
+                result => return result,
+            }
+        }
+    }
+}
@@ -0,0 +1,203 @@
+use async_trait::async_trait;
+use reqwest::{Client, RequestBuilder};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use super::{KeyRing, ProviderError};
+
+const BRAVE_URL: &str = "https://api.search.brave.com/res/v1/web/search";
+const SERPAPI_URL: &str = "https://serpapi.com/search.json";
+const TAVILY_URL: &str = "https://api.tavily.com/search";
+
+/// A web search API, which the panel's fact checkers look claims up with.
+#[async_trait]
+pub trait SearchProvider: Send + Sync {
+    /// Searches for the query, returning at most `max_results` results, best first.
+    async fn search(&self, query: &str, max_results: usize) -> Result<Vec<SearchResult>, ProviderError>;
+}
+
+/// A page a search found.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct SearchResult {
+    pub title: String,
+    pub url: String,
+    /// The part of the page the search matched, as the search API quotes it.
+    pub snippet: String,
+}
+
+/// Brave's Web Search API, authenticated with the configured keys or the `BRAVE_SEARCH_API_KEY` environment variable.
+pub struct BraveSearch {
+    client: Client,
+    keys: KeyRing,
+    url: String,
+}
+
+/// Google results through SerpApi, authenticated with the configured keys or the `SERPAPI_API_KEY` environment
+/// variable.
+pub struct SerpApiSearch {
+    client: Client,
+    keys: KeyRing,
+    url: String,
+}
+
+/// Tavily's search API, authenticated with the configured keys or the `TAVILY_API_KEY` environment variable.
+pub struct TavilySearch {
+    client: Client,
+    keys: KeyRing,
+    url: String,
+}
+
+/// A SearXNG instance's JSON API, which needs no key but must have the `json` format enabled.
+pub struct SearxngSearch {
+    client: Client,
+    url: String,
+}
+
+impl BraveSearch {
+    /// The environment variable the key is read from unless keys are configured.
+    pub const API_KEY_VAR: &'static str = "BRAVE_SEARCH_API_KEY";
+
+    pub fn new(base_url: Option<String>, keys: KeyRing) -> Self {
+        BraveSearch { client: Client::new(), keys, url: base_url.unwrap_or_else(|| BRAVE_URL.to_string()) }
+    }
+}
+
+impl SerpApiSearch {
+    /// The environment variable the key is read from unless keys are configured.
+    pub const API_KEY_VAR: &'static str = "SERPAPI_API_KEY";
+
+    pub fn new(base_url: Option<String>, keys: KeyRing) -> Self {
+        SerpApiSearch { client: Client::new(), keys, url: base_url.unwrap_or_else(|| SERPAPI_URL.to_string()) }
+    }
+}
+
+impl TavilySearch {
+    /// The environment variable the key is read from unless keys are configured.
+    pub const API_KEY_VAR: &'static str = "TAVILY_API_KEY";
+
+    pub fn new(base_url: Option<String>, keys: KeyRing) -> Self {
+        TavilySearch { client: Client::new(), keys, url: base_url.unwrap_or_else(|| TAVILY_URL.to_string()) }
+    }
+}
+
+impl SearxngSearch {
+    /// A client for the instance at `base_url`, such as `http://localhost:8080`.
+    pub fn new(base_url: &str) -> Self {
+        SearxngSearch { client: Client::new(), url: format!("{}/search", base_url.trim_end_matches('/')) }
+    }
+}
+
+#[derive(Deserialize)]
+struct BraveResponse {
+    #[serde(default)]
+    web: Option<BraveResults>,
+}
+
+#[derive(Deserialize)]
+struct BraveResults {
+    results: Vec<BraveResult>,
+}
+
+#[derive(Deserialize)]
+struct BraveResult {
+    title: String,
+    url: String,
+    #[serde(default)]
+    description: String,
+}
+
+#[derive(Deserialize)]
+struct SerpApiResponse {
+    #[serde(default)]
+    organic_results: Vec<SerpApiResult>,
+}
+
+#[derive(Deserialize)]
+struct SerpApiResult {
+    title: String,
+    link: String,
+    #[serde(default)]
+    snippet: String,
+}
+
+#[derive(Serialize)]
+struct TavilyRequest<'a> {
+    query: &'a str,
+    max_results: usize,
+}
+
+/// The results of Tavily and SearXNG, which both quote each page as its `content`.
+#[derive(Deserialize)]
+struct ContentResponse {
+    #[serde(default)]
+    results: Vec<ContentResult>,
+}
+
+#[derive(Deserialize)]
+struct ContentResult {
+    title: String,
+    url: String,
+    #[serde(default)]
+    content: String,
+}
+
+impl From<ContentResult> for SearchResult {
+    fn from(result: ContentResult) -> Self {
+        SearchResult { title: result.title, url: result.url, snippet: result.content }
+    }
+}
+
+/// Sends the request, failing unless the API accepted it, and reads its JSON response.
+async fn send<T: DeserializeOwned>(request: RequestBuilder) -> Result<T, ProviderError> {
+    let response = request.send().await?;
+    if !response.status().is_success() {
+        return Err(ProviderError::Status { status: response.status(), body: response.text().await.unwrap_or_default() });
+    }
+    Ok(response.json().await?)
+}
+
+#[async_trait]
+impl SearchProvider for BraveSearch {
+    async fn search(&self, query: &str, max_results: usize) -> Result<Vec<SearchResult>, ProviderError> {
+        let request = self.client.get(&self.url)
+            .header("X-Subscription-Token", self.keys.next())
+            .query(&[("q", query), ("count", &max_results.to_string())]);
+        let response: BraveResponse = send(request).await?;
+        Ok(response.web.map(|web| web.results).unwrap_or_default().into_iter()
+            .take(max_results)
+            .map(|result| SearchResult { title: result.title, url: result.url, snippet: result.description })
+            .collect())
+    }
+}
+
+#[async_trait]
+impl SearchProvider for SerpApiSearch {
+    async fn search(&self, query: &str, max_results: usize) -> Result<Vec<SearchResult>, ProviderError> {
+        let request = self.client.get(&self.url)
+            .query(&[("engine", "google"), ("q", query), ("num", &max_results.to_string()), ("api_key", self.keys.next())]);
+        let response: SerpApiResponse = send(request).await?;
+        Ok(response.organic_results.into_iter()
+            .take(max_results)
+            .map(|result| SearchResult { title: result.title, url: result.link, snippet: result.snippet })
+            .collect())
+    }
+}
+
+#[async_trait]
+impl SearchProvider for TavilySearch {
+    async fn search(&self, query: &str, max_results: usize) -> Result<Vec<SearchResult>, ProviderError> {
+        let request = self.client.post(&self.url)
+            .bearer_auth(self.keys.next())
+            .json(&TavilyRequest { query, max_results });
+        let response: ContentResponse = send(request).await?;
+        Ok(response.results.into_iter().take(max_results).map(SearchResult::from).collect())
+    }
+}
+
+#[async_trait]
+impl SearchProvider for SearxngSearch {
+    async fn search(&self, query: &str, max_results: usize) -> Result<Vec<SearchResult>, ProviderError> {
+        let request = self.client.get(&self.url).query(&[("q", query), ("format", "json")]);
+        let response: ContentResponse = send(request).await?;
+        Ok(response.results.into_iter().take(max_results).map(SearchResult::from).collect())
+    }
+}
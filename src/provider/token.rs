@@ -0,0 +1,62 @@
+use std::{future::Future, time::Duration};
+
+use reqwest::Client;
+use serde::Deserialize;
+use tokio::{sync::Mutex, time::Instant};
+
+use super::ProviderError;
+
+/// How long before a token expires it is replaced, so that it does not expire while a request is on its way.
+const TOKEN_MARGIN: Duration = Duration::from_secs(300);
+
+/// The OAuth access token a provider signs in for, fetched when first needed and again shortly before it
+/// expires.
+pub(super) struct TokenCache {
+    token: Mutex<Option<Token>>,
+}
+
+/// An access token and when it should be replaced.
+struct Token {
+    value: String,
+    refresh_at: Instant,
+}
+
+/// A token endpoint's response.
+#[derive(Deserialize)]
+pub(super) struct TokenResponse {
+    access_token: String,
+    /// Seconds until the token expires.
+    expires_in: u64,
+}
+
+impl TokenCache {
+    pub(super) fn new() -> Self {
+        TokenCache { token: Mutex::new(None) }
+    }
+
+    /// Returns the current token, first fetching a new one with `fetch` if there is none or it is about to expire.
+    pub(super) async fn get<F, Fut>(&self, fetch: F) -> Result<String, ProviderError>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<TokenResponse, ProviderError>>,
+    {
+        // Held while a new token is fetched, so that concurrent calls wait for it rather than each fetching one.
+        let mut token = self.token.lock().await;
+        if let Some(current) = token.as_ref().filter(|current| current.refresh_at > Instant::now()) {
+            return Ok(current.value.clone());
+        }
+        let response = fetch().await?;
+        let lifetime = Duration::from_secs(response.expires_in).saturating_sub(TOKEN_MARGIN);
+        let fetched = token.insert(Token { value: response.access_token, refresh_at: Instant::now() + lifetime });
+        Ok(fetched.value.clone())
+    }
+}
+
+/// Posts `form` to the token endpoint at `url`, failing unless it grants a token.
+pub(super) async fn request(client: &Client, url: &str, form: &[(&str, &str)]) -> Result<TokenResponse, ProviderError> {
+    let response = client.post(url).form(form).send().await?;
+    if !response.status().is_success() {
+        return Err(ProviderError::Status { status: response.status(), body: response.text().await.unwrap_or_default() });
+    }
+    Ok(response.json().await?)
+}
@@ -0,0 +1,403 @@
+use std::{env, fs};
+
+use async_trait::async_trait;
+use base64::{engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD}, Engine};
+use reqwest::Client;
+use ring::{rand::SystemRandom, signature::{RsaKeyPair, RSA_PKCS1_SHA256}};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::debug;
+
+use super::{read_lines, token::{self, TokenCache}, Completion, CompletionRequest, LlmProvider, ProviderError, TokenSink, ToolCall, Usage};
+
+pub(super) const DEFAULT_MODEL: &str = "gemini-2.0-flash";
+const DEFAULT_REGION: &str = "us-central1";
+/// The scope of a token for Vertex AI.
+const TOKEN_SCOPE: &str = "https://www.googleapis.com/auth/cloud-platform";
+/// How long the signed assertion exchanged for a token is valid, which is the most Google accepts.
+const ASSERTION_LIFETIME_SECS: i64 = 3600;
+
+/// Where a Gemini actor reaches Gemini through Vertex AI, authenticated as a service account, rather than through
+/// AI Studio with an API key.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct VertexConfig {
+    /// The Google Cloud project the requests are made in and billed to.
+    pub project: String,
+    /// The region serving the requests, such as `europe-west4`, or `global`.
+    #[serde(default = "default_region")]
+    pub region: String,
+}
+
+fn default_region() -> String {
+    DEFAULT_REGION.to_string()
+}
+
+impl VertexConfig {
+    /// Checks the config, returning a description of the problem if it is unusable.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.project.trim().is_empty() {
+            return Err("vertex.project must not be empty".to_string());
+        }
+        if self.region.trim().is_empty() {
+            return Err("vertex.region must not be empty".to_string());
+        }
+        Ok(())
+    }
+
+    /// The regional endpoint, or the global one for the `global` region.
+    fn endpoint(&self) -> String {
+        match self.region.as_str() {
+            "global" => "https://aiplatform.googleapis.com".to_string(),
+            region => format!("https://{}-aiplatform.googleapis.com", region),
+        }
+    }
+}
+
+/// Gemini on Vertex AI, authenticated as the service account whose key file is named by the
+/// `GOOGLE_APPLICATION_CREDENTIALS` environment variable. The account needs the Vertex AI User role.
+pub struct VertexProvider {
+    client: Client,
+    /// The model's URL, without the method.
+    url: String,
+    account: ServiceAccount,
+    token: TokenCache,
+}
+
+/// The parts of a service account key file needed to sign in as the account.
+#[derive(Deserialize)]
+struct ServiceAccountKey {
+    client_email: String,
+    private_key: String,
+    token_uri: String,
+}
+
+struct ServiceAccount {
+    email: String,
+    key: RsaKeyPair,
+    token_uri: String,
+}
+
+/// The body of a request to generate content, which Vertex AI and AI Studio both take.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct GenerateRequest<'a> {
+    contents: Vec<Content<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system_instruction: Option<Content<'a>>,
+    generation_config: GenerationConfig,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<ToolSet<'a>>,
+}
+
+/// The functions the model may call instead of responding.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ToolSet<'a> {
+    function_declarations: Vec<FunctionDeclaration<'a>>,
+}
+
+#[derive(Serialize)]
+struct FunctionDeclaration<'a> {
+    name: &'a str,
+    description: &'a str,
+    parameters: &'a serde_json::Value,
+}
+
+#[derive(Serialize)]
+struct Content<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    role: Option<&'a str>,
+    parts: Vec<Part<'a>>,
+}
+
+/// A part of a turn's content. The model's calls to functions are sent back to it as `functionCall` parts of its
+/// own turn, and what they returned as `functionResponse` parts of the next.
+#[derive(Serialize)]
+#[serde(untagged)]
+enum Part<'a> {
+    Text { text: &'a str },
+    Image {
+        #[serde(rename = "inlineData")]
+        inline_data: InlineData<'a>,
+    },
+    Call {
+        #[serde(rename = "functionCall")]
+        function_call: FunctionCall<'a>,
+    },
+    Output {
+        #[serde(rename = "functionResponse")]
+        function_response: FunctionResponse<'a>,
+    },
+}
+
+#[derive(Serialize)]
+struct FunctionCall<'a> {
+    name: &'a str,
+    args: &'a serde_json::Value,
+}
+
+/// What a function returned, which the API takes as an object.
+#[derive(Serialize)]
+struct FunctionResponse<'a> {
+    name: &'a str,
+    response: serde_json::Value,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct InlineData<'a> {
+    mime_type: &'a str,
+    /// In base64.
+    data: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GenerationConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_output_tokens: Option<u32>,
+}
+
+/// A response, or one server-sent event of a streamed response holding the next piece of it.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GenerateResponse {
+    #[serde(default)]
+    candidates: Vec<Candidate>,
+    usage_metadata: Option<UsageMetadata>,
+}
+
+#[derive(Deserialize)]
+struct Candidate {
+    /// Left out when the response was blocked.
+    content: Option<CandidateContent>,
+}
+
+#[derive(Deserialize)]
+struct CandidateContent {
+    #[serde(default)]
+    parts: Vec<CandidatePart>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CandidatePart {
+    #[serde(default)]
+    text: String,
+    function_call: Option<CandidateCall>,
+}
+
+#[derive(Deserialize)]
+struct CandidateCall {
+    name: String,
+    #[serde(default)]
+    args: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UsageMetadata {
+    #[serde(default)]
+    prompt_token_count: u64,
+    #[serde(default)]
+    candidates_token_count: u64,
+}
+
+impl<'a> GenerateRequest<'a> {
+    pub(super) fn new(request: &'a CompletionRequest) -> Self {
+        let params = &request.params;
+        let mut contents = vec![Content { role: Some("user"), parts: [Part::Text { text: &request.prompt }].into_iter()
+            .chain(request.images.iter().map(|image| Part::Image { inline_data: InlineData { mime_type: image.media_type, data: image.base64() } }))
+            .collect() }];
+        for round in &request.tool_rounds {
+            let text = (!round.text.is_empty()).then_some(Part::Text { text: &round.text });
+            let calls = round.calls.iter().map(|call| Part::Call { function_call: FunctionCall { name: &call.name, args: &call.arguments } });
+            contents.push(Content { role: Some("model"), parts: text.into_iter().chain(calls).collect() });
+            let outputs = round.outputs.iter().map(|output| Part::Output {
+                function_response: FunctionResponse { name: &output.name, response: json!({ "content": output.content }) },
+            });
+            contents.push(Content { role: Some("user"), parts: outputs.collect() });
+        }
+        let declarations: Vec<_> = request.tools.iter()
+            .map(|tool| FunctionDeclaration { name: &tool.name, description: &tool.description, parameters: &tool.parameters })
+            .collect();
+        GenerateRequest {
+            contents,
+            system_instruction: request.system.as_deref().map(|system| Content { role: None, parts: vec![Part::Text { text: system }] }),
+            generation_config: GenerationConfig { temperature: params.temperature, top_p: params.top_p, max_output_tokens: params.max_tokens },
+            tools: (!declarations.is_empty()).then_some(ToolSet { function_declarations: declarations }).into_iter().collect(),
+        }
+    }
+}
+
+impl GenerateResponse {
+    /// The parts of the first candidate, which are none if there is none.
+    fn parts(&self) -> &[CandidatePart] {
+        self.candidates.first()
+            .and_then(|candidate| candidate.content.as_ref())
+            .map(|content| content.parts.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// The text of the first candidate, which is empty if there is none.
+    fn text(&self) -> String {
+        self.parts().iter().map(|part| part.text.as_str()).collect()
+    }
+
+    /// Adds the first candidate's calls to functions to `tool_calls`, numbered on from those already there, since
+    /// the API does not identify them.
+    fn collect_tool_calls(&self, tool_calls: &mut Vec<ToolCall>) {
+        for call in self.parts().iter().filter_map(|part| part.function_call.as_ref()) {
+            tool_calls.push(ToolCall { id: format!("call_{}", tool_calls.len()), name: call.name.clone(), arguments: call.args.clone() });
+        }
+    }
+
+    fn usage(&self) -> Option<Usage> {
+        self.usage_metadata.as_ref()
+            .map(|usage| Usage { prompt_tokens: usage.prompt_token_count, completion_tokens: usage.candidates_token_count })
+    }
+}
+
+/// Reads the completion from a response to a request to generate content, which holds text, calls to the
+/// request's tools, or both.
+pub(super) async fn read_completion(response: reqwest::Response) -> Result<Completion, ProviderError> {
+    let response: GenerateResponse = response.json().await?;
+    let (text, mut tool_calls) = (response.text(), Vec::new());
+    response.collect_tool_calls(&mut tool_calls);
+    if text.is_empty() && tool_calls.is_empty() {
+        return Err(ProviderError::EmptyResponse);
+    }
+    Ok(Completion { text, usage: response.usage(), backend: None, tool_calls })
+}
+
+/// Reads a streamed response, which arrives as server-sent events, each holding the next piece of the text or
+/// whole calls to functions.
+pub(super) async fn read_stream(response: reqwest::Response, on_token: TokenSink<'_>) -> Result<Completion, ProviderError> {
+    let (mut text, mut usage, mut tool_calls) = (String::new(), None, Vec::new());
+    read_lines(response, |line| {
+        let Some(data) = line.strip_prefix("data:").map(str::trim) else { return Ok(()) };
+        let chunk: GenerateResponse = serde_json::from_str(data)
+            .map_err(|e| ProviderError::InvalidResponse(format!("unreadable stream event ({}): {}", e, data)))?;
+        let piece = chunk.text();
+        if !piece.is_empty() {
+            on_token(&piece);
+            text.push_str(&piece);
+        }
+        chunk.collect_tool_calls(&mut tool_calls);
+        // Each event counts the tokens so far, so the last one holds the totals.
+        usage = chunk.usage().or(usage);
+        Ok(())
+    }).await?;
+    if text.is_empty() && tool_calls.is_empty() {
+        return Err(ProviderError::EmptyResponse);
+    }
+    Ok(Completion { text, usage, backend: None, tool_calls })
+}
+
+impl VertexProvider {
+    /// A provider for `model` in the configured project and region, calling `base_url` instead of the region's
+    /// endpoint if one is given.
+    pub fn new(vertex: &VertexConfig, base_url: Option<&str>, model: Option<String>) -> Result<Self, ProviderError> {
+        let path = env::var("GOOGLE_APPLICATION_CREDENTIALS").map_err(|_| ProviderError::MissingCredential("GOOGLE_APPLICATION_CREDENTIALS"))?;
+        let account = ServiceAccount::load(&path)?;
+        let url = format!(
+            "{}/v1/projects/{}/locations/{}/publishers/google/models/{}",
+            base_url.map(str::to_string).unwrap_or_else(|| vertex.endpoint()).trim_end_matches('/'),
+            vertex.project,
+            vertex.region,
+            model.as_deref().unwrap_or(DEFAULT_MODEL),
+        );
+        Ok(VertexProvider { client: Client::new(), url, account, token: TokenCache::new() })
+    }
+
+    /// A current access token for the service account, signing in again once the last one has expired.
+    async fn token(&self) -> Result<String, ProviderError> {
+        self.token.get(|| {
+            debug!("Fetching an access token for {}.", self.account.email);
+            async move {
+                let assertion = self.account.assertion()?;
+                token::request(&self.client, &self.account.token_uri, &[
+                    ("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"),
+                    ("assertion", &assertion),
+                ]).await
+            }
+        }).await
+    }
+
+    /// Sends the request to the model's `method`, failing unless the API accepted it.
+    async fn send(&self, request: &CompletionRequest, method: &str) -> Result<reqwest::Response, ProviderError> {
+        let token = self.token().await?;
+        let response = self.client.post(format!("{}:{}", self.url, method))
+            .bearer_auth(token)
+            .json(&GenerateRequest::new(request))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(ProviderError::Status { status: response.status(), body: response.text().await.unwrap_or_default() });
+        }
+        Ok(response)
+    }
+}
+
+impl ServiceAccount {
+    /// Reads the account's key file.
+    fn load(path: &str) -> Result<Self, ProviderError> {
+        let file = fs::read_to_string(path)
+            .map_err(|e| ProviderError::InvalidCredentials(format!("unable to read the service account key {}: {}", path, e)))?;
+        let key: ServiceAccountKey = serde_json::from_str(&file)
+            .map_err(|e| ProviderError::InvalidCredentials(format!("{} is not a service account key: {}", path, e)))?;
+        // The private key is a PKCS #8 PEM block.
+        let der: String = key.private_key.lines().filter(|line| !line.starts_with("-----")).collect();
+        let pair = STANDARD.decode(der.trim()).ok()
+            .and_then(|der| RsaKeyPair::from_pkcs8(&der).ok())
+            .ok_or_else(|| ProviderError::InvalidCredentials(format!("the private key in {} is not an RSA key", path)))?;
+        Ok(ServiceAccount { email: key.client_email, key: pair, token_uri: key.token_uri })
+    }
+
+    /// A JWT signed with the account's key, which the token endpoint exchanges for an access token.
+    fn assertion(&self) -> Result<String, ProviderError> {
+        let now = chrono::Utc::now().timestamp();
+        let header = URL_SAFE_NO_PAD.encode(json!({ "alg": "RS256", "typ": "JWT" }).to_string());
+        let claims = URL_SAFE_NO_PAD.encode(json!({
+            "iss": self.email,
+            "scope": TOKEN_SCOPE,
+            "aud": self.token_uri,
+            "iat": now,
+            "exp": now + ASSERTION_LIFETIME_SECS,
+        }).to_string());
+        let message = format!("{}.{}", header, claims);
+        let mut signature = vec![0; self.key.public().modulus_len()];
+        self.key.sign(&RSA_PKCS1_SHA256, &SystemRandom::new(), message.as_bytes(), &mut signature)
+            .map_err(|_| ProviderError::InvalidCredentials(format!("unable to sign in as {}", self.email)))?;
+        Ok(format!("{}.{}", message, URL_SAFE_NO_PAD.encode(signature)))
+    }
+}
+
+#[async_trait]
+impl LlmProvider for VertexProvider {
+    async fn complete(&self, request: &CompletionRequest) -> Result<Completion, ProviderError> {
+        read_completion(self.send(request, "generateContent").await?).await
+    }
+
+    async fn stream(&self, request: &CompletionRequest, on_token: TokenSink<'_>) -> Result<Completion, ProviderError> {
+        read_stream(self.send(request, "streamGenerateContent?alt=sse").await?, on_token).await
+    }
+
+    fn max_images(&self) -> usize {
+        usize::MAX
+    }
+
+    fn supports_tools(&self) -> bool {
+        true
+    }
+
+    /// Signs in as the service account, which checks its key without generating anything.
+    async fn check(&self) -> Result<(), ProviderError> {
+        self.token().await.map(|_| ())
+    }
+}
+
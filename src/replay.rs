@@ -0,0 +1,423 @@
+//! Replaying deliberations from a transcript, to reproduce and debug how the panel settled on an answer.
+//!
+//! Each question in the transcript is asked again, and every actor answers through a [ReplayProvider] with what
+//! the transcript records it answering instead of calling its model, so that the panel goes through the same
+//! stages to the same answer. A [RoundGate] holds the responses of each round back until it is let through, for
+//! stepping through a replay round by round.
+//!
+//! The transcript records what the panel made of each response, such as a verdict and its reasoning, rather than the
+//! response itself, so the replayed responses are written out again in a form the actors read back the same way.
+//! Convergence and moderation are replayed from the transcript too, but spending is not recorded, so a question
+//! stopped by its budget is replayed until the transcript has nothing more for the panel.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    future,
+    sync::{atomic::{AtomicUsize, Ordering}, Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::json;
+use tokio::sync::watch;
+use tracing::warn;
+
+use crate::{
+    convergence::Convergence,
+    moderation::Moderation,
+    prompts::Template,
+    provider::{Completion, CompletionRequest, EmbeddingProvider, LlmProvider, ModerationProvider, ProviderError},
+    result::QuestionId,
+    strategy::{ConsensusStrategy, Stage},
+    transcript::{TranscriptEvent, COORDINATOR, MODERATOR, REVIEWER, SANDBOX},
+};
+
+/// How much less similar than recorded a replayed comparison may come out, from the rounding of the embeddings
+/// standing in for the recorded similarity.
+const SIMILARITY_TOLERANCE: f64 = 1e-6;
+
+/// A line of a transcript file.
+#[derive(Deserialize)]
+struct Entry {
+    question_id: QuestionId,
+    #[serde(flatten)]
+    event: TranscriptEvent,
+}
+
+/// One question's deliberation, as a transcript recorded it.
+#[derive(Debug, Clone)]
+pub struct Recording {
+    /// The id the question was asked under in the session the transcript recorded.
+    pub question_id: QuestionId,
+    pub question: String,
+    pub strategy: ConsensusStrategy,
+    /// Every step the panel took on the question, from the question on.
+    pub events: Vec<TranscriptEvent>,
+}
+
+/// Reads the questions in a transcript, in the order they were asked. Steps recorded for a question whose start
+/// the transcript does not have are left out.
+pub fn read(transcript: &str) -> Result<Vec<Recording>, String> {
+    let mut recordings: Vec<Recording> = Vec::new();
+    for (index, line) in transcript.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry: Entry = serde_json::from_str(line).map_err(|e| format!("line {} is not a transcript entry: {}", index + 1, e))?;
+        if let TranscriptEvent::Question { question, strategy } = &entry.event {
+            recordings.push(Recording { question_id: entry.question_id, question: question.clone(), strategy: *strategy, events: Vec::new() });
+        }
+        match recordings.iter_mut().rev().find(|recording| recording.question_id == entry.question_id) {
+            Some(recording) => recording.events.push(entry.event),
+            None => warn!("Leaving out line {} of the transcript, about question {}, whose start it does not record.", index + 1, entry.question_id),
+        }
+    }
+    Ok(recordings)
+}
+
+impl Recording {
+    /// The answer the panel settled on, whether or not it agreed on it, if the question got that far.
+    pub fn answer(&self) -> Option<&str> {
+        self.events.iter().find_map(|event| match event {
+            TranscriptEvent::Consensus { answer, .. } => Some(answer.as_str()),
+            _ => None,
+        })
+    }
+
+    /// The actors the panel waited on or heard from, by name.
+    pub fn actors(&self) -> BTreeSet<&str> {
+        let mut actors = BTreeSet::new();
+        for event in &self.events {
+            match event {
+                TranscriptEvent::StageStarted { actors: names, .. } | TranscriptEvent::Redispatched { actors: names, .. } => {
+                    actors.extend(names.iter().map(String::as_str));
+                },
+                TranscriptEvent::Routed { router: name, .. }
+                | TranscriptEvent::Draft { author: name, .. }
+                | TranscriptEvent::CandidateVote { actor: name, .. }
+                | TranscriptEvent::Evaluation { actor: name, .. }
+                | TranscriptEvent::Suggestion { actor: name, .. }
+                | TranscriptEvent::ChairGuidance { chair: name, .. }
+                | TranscriptEvent::ChairRuling { chair: name, .. }
+                | TranscriptEvent::Refinement { author: name, .. }
+                | TranscriptEvent::Failed { actor: name, .. } => {
+                    actors.insert(name.as_str());
+                },
+                _ => (),
+            }
+        }
+        actors.remove(MODERATOR);
+        actors.remove(COORDINATOR);
+        actors.remove(REVIEWER);
+        actors.remove(SANDBOX);
+        actors
+    }
+
+    /// The responses the actors gave, for their [ReplayProvider]s to share. With a gate, each round's responses
+    /// wait until it lets the round through.
+    pub fn script(&self, gate: Option<Arc<RoundGate>>) -> Arc<Script> {
+        let mut responses = Vec::new();
+        let mut failures = HashMap::new();
+        let mut stalled = false;
+        let mut round = 0;
+        let mut respond = |part, round, actor: &String, text: String| responses.push(Response { part, round, actor: actor.clone(), text, replayed: false });
+        for event in &self.events {
+            match event {
+                TranscriptEvent::StageStarted { round: started, .. } => round = *started,
+                TranscriptEvent::Routed { router, choice } => respond(Part::Route, round, router, choice.clone().unwrap_or_default()),
+                TranscriptEvent::Draft { author, answer } => respond(Part::Draft, round, author, answer.clone()),
+                TranscriptEvent::CandidateVote { actor, choice, reasoning } => {
+                    let choice = choice.map_or_else(|| "none".to_string(), |index| (index + 1).to_string());
+                    respond(Part::Vote, round, actor, format!("{}\n{}", choice, reasoning));
+                },
+                TranscriptEvent::Evaluation { round, actor, feedback, score, reasoning } if actor != REVIEWER => {
+                    let evaluation = match score {
+                        Some(score) => json!({ "score": score, "reasoning": reasoning }),
+                        None => json!({ "verdict": format!("{:?}", feedback), "reasoning": reasoning }),
+                    };
+                    respond(Part::Evaluation, *round, actor, evaluation.to_string());
+                },
+                TranscriptEvent::Suggestion { actor, suggestion } => respond(Part::Suggestion, round, actor, suggestion.clone()),
+                TranscriptEvent::ChairGuidance { chair, guidance, .. } => respond(Part::Chair, round, chair, guidance.clone()),
+                TranscriptEvent::ChairRuling { round: version, chair, reasoning } => {
+                    respond(Part::Chair, round, chair, format!("{}\n{}", version + 1, reasoning));
+                },
+                TranscriptEvent::Refinement { author, answer } => respond(Part::Refinement, round, author, answer.clone()),
+                TranscriptEvent::Failed { actor, error } => {
+                    // The error is recorded after the step that failed, which failing again adds back.
+                    let error = error.split_once(": ").map_or(error.as_str(), |(_, error)| error);
+                    failures.insert(actor.clone(), error.to_string());
+                },
+                // Calls left unanswered when the question converged or timed out wait to be cancelled again.
+                TranscriptEvent::Converged { .. } | TranscriptEvent::TimedOut { .. } => stalled = true,
+                _ => (),
+            }
+        }
+        Arc::new(Script { responses: Mutex::new(responses), failures, stalled, gate })
+    }
+
+    /// Stops the question where the transcript records that its refinements converged, if they did, in place of
+    /// the configured convergence detection.
+    pub fn convergence(&self) -> Option<Convergence> {
+        let mut latest_refinement = None;
+        let mut versions = HashMap::new();
+        let mut converged = Vec::new();
+        for event in &self.events {
+            match event {
+                TranscriptEvent::Refinement { answer, .. } => latest_refinement = Some(answer.clone()),
+                TranscriptEvent::Revised { round, .. } => {
+                    if let Some(answer) = latest_refinement.take() {
+                        versions.insert(*round, answer);
+                    }
+                },
+                TranscriptEvent::Converged { round, similarity } => {
+                    if let Some(answer) = versions.get(round) {
+                        converged.push((answer.clone(), *similarity));
+                    }
+                },
+                _ => (),
+            }
+        }
+        let threshold = converged.iter().map(|(_, similarity)| *similarity).reduce(f64::min)?;
+        Some(Convergence::new(Arc::new(ReplayEmbedder { converged }), (threshold - SIMILARITY_TOLERANCE).max(SIMILARITY_TOLERANCE)))
+    }
+
+    /// Flags what the transcript records the moderator flagging, if the question was moderated, in place of the
+    /// configured moderation.
+    pub fn moderation(&self) -> Option<Moderation> {
+        let moderated = self.events.iter().any(|event| matches!(event, TranscriptEvent::StageStarted { stage: Stage::Moderating, .. }));
+        let flagged = self.events.iter().find_map(|event| match event {
+            TranscriptEvent::Flagged { round, reason } => Some((*round, reason.clone())),
+            _ => None,
+        });
+        moderated.then(|| Moderation::new(Arc::new(ReplayModerator { flagged, screened: AtomicUsize::new(0) })))
+    }
+}
+
+/// What a response was to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Part {
+    Route,
+    Draft,
+    Vote,
+    Evaluation,
+    Suggestion,
+    /// A refinement, a link of a chain of them or a synthesis of suggestions.
+    Refinement,
+    /// The chair's guidance or ruling.
+    Chair,
+}
+
+impl Part {
+    /// What a request rendered from the template asks for, or None for templates actors are not asked with.
+    fn of(template: Template) -> Option<Part> {
+        match template {
+            Template::Route => Some(Part::Route),
+            Template::Draft => Some(Part::Draft),
+            Template::Vote => Some(Part::Vote),
+            Template::BinaryEvaluation | Template::ScoredEvaluation => Some(Part::Evaluation),
+            Template::Suggest => Some(Part::Suggestion),
+            Template::Refine | Template::Synthesize => Some(Part::Refinement),
+            Template::Summarize | Template::Decide => Some(Part::Chair),
+            Template::Persona | Template::Moderate | Template::FactCheck => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Part::Route => "routing",
+            Part::Draft => "draft",
+            Part::Vote => "vote",
+            Part::Evaluation => "evaluation",
+            Part::Suggestion => "suggestion",
+            Part::Refinement => "refinement",
+            Part::Chair => "chair's decision",
+        }
+    }
+
+    /// Whether the panel picks who gives it, rather than asking every actor.
+    fn is_picked(self) -> bool {
+        matches!(self, Part::Route | Part::Draft | Part::Suggestion | Part::Refinement)
+    }
+}
+
+/// A response the transcript records.
+#[derive(Debug)]
+struct Response {
+    part: Part,
+    /// The round it belongs to, counting from 0 for the first draft.
+    round: usize,
+    actor: String,
+    text: String,
+    replayed: bool,
+}
+
+/// The responses a recorded panel gave, which the [ReplayProvider]s of the replaying panel share, and how the
+/// calls the transcript has no response for end.
+pub struct Script {
+    responses: Mutex<Vec<Response>>,
+    /// The error the calls of each actor whose call failed the question fail with, once it has nothing more to
+    /// replay.
+    failures: HashMap<String, String>,
+    /// Whether the question converged or timed out with calls unanswered, so that calls with nothing to replay never
+    /// finish either.
+    stalled: bool,
+    gate: Option<Arc<RoundGate>>,
+}
+
+impl Script {
+    /// The actor's next response of the part, with the round it belongs to. The earliest round's responses go
+    /// first. When the replaying panel picks a different actor than the recorded one did, as it may unless both
+    /// picked with the same seed, the actor gives the response the recorded one gave in its place.
+    fn next(&self, actor: &str, part: Part) -> Result<Option<(usize, String)>, ProviderError> {
+        let mut responses = self.responses.lock().expect("no replayed call panics holding the script");
+        let round = responses.iter().filter(|response| response.part == part && !response.replayed).map(|response| response.round).min();
+        let mut pending: Vec<&mut Response> = responses.iter_mut()
+            .filter(|response| response.part == part && !response.replayed && Some(response.round) == round)
+            .collect();
+        let index = match pending.iter().position(|response| response.actor == actor) {
+            Some(own) => Some(own),
+            None if part.is_picked() && !pending.is_empty() => Some(0),
+            None => None,
+        };
+        if let Some(response) = index.map(|index| pending.swap_remove(index)) {
+            if response.actor != actor {
+                warn!("{} gives the {} {} gave in the transcript, since the panel picked differently this time.", actor, part.name(), response.actor);
+            }
+            response.replayed = true;
+            return Ok(Some((response.round, response.text.clone())));
+        }
+        if let Some(error) = self.failures.get(actor) {
+            return Err(ProviderError::Replay(error.clone()));
+        }
+        if self.stalled {
+            return Ok(None);
+        }
+        Err(ProviderError::Replay(format!("the transcript has no further {} by {}", part.name(), actor)))
+    }
+}
+
+/// Answers an actor's requests with the responses a [Script] has for it, instead of calling a model.
+pub struct ReplayProvider {
+    actor: String,
+    script: Arc<Script>,
+}
+
+impl ReplayProvider {
+    pub fn new(actor: String, script: Arc<Script>) -> Self {
+        ReplayProvider { actor, script }
+    }
+}
+
+#[async_trait]
+impl LlmProvider for ReplayProvider {
+    async fn complete(&self, request: &CompletionRequest) -> Result<Completion, ProviderError> {
+        let part = Part::of(request.template)
+            .ok_or_else(|| ProviderError::Replay(format!("actors are not asked with the {} prompt", request.template.name())))?;
+        let Some((round, text)) = self.script.next(&self.actor, part)? else {
+            return future::pending().await;
+        };
+        if let Some(gate) = &self.script.gate {
+            gate.enter(round).await;
+        }
+        Ok(Completion { text, usage: None, backend: None, tool_calls: Vec::new() })
+    }
+
+    fn max_images(&self) -> usize {
+        usize::MAX
+    }
+
+    /// Passes without taking anything from the script, since there is no model to reach.
+    async fn check(&self) -> Result<(), ProviderError> {
+        Ok(())
+    }
+}
+
+/// Holds replayed responses back until their round is let through. The first draft's round is let through from
+/// the start.
+pub struct RoundGate {
+    /// The latest round let through.
+    open: watch::Sender<usize>,
+    /// The latest round a response is held back for.
+    held: watch::Sender<usize>,
+}
+
+impl Default for RoundGate {
+    fn default() -> Self {
+        RoundGate::new()
+    }
+}
+
+impl RoundGate {
+    pub fn new() -> Self {
+        RoundGate { open: watch::channel(0).0, held: watch::channel(0).0 }
+    }
+
+    /// Waits until the round is let through.
+    async fn enter(&self, round: usize) {
+        if round <= *self.open.borrow() {
+            return;
+        }
+        self.held.send_if_modified(|held| {
+            let later = round > *held;
+            if later {
+                *held = round;
+            }
+            later
+        });
+        // The gate holds the sender, so the round is waited on for as long as the gate is.
+        let _ = self.open.subscribe().wait_for(|open| *open >= round).await;
+    }
+
+    /// Waits until a response is held back for a round not yet let through, returning the round.
+    pub async fn held(&self) -> usize {
+        let open = *self.open.borrow();
+        let mut held = self.held.subscribe();
+        let round = *held.wait_for(|held| *held > open).await.expect("the gate holds the sender");
+        round
+    }
+
+    /// Lets the responses of every round up to this one through.
+    pub fn open(&self, round: usize) {
+        self.open.send_modify(|open| *open = (*open).max(round));
+    }
+}
+
+/// Embeds versions of an answer so that each version the transcript records converging comes out as similar to
+/// the one before it as recorded, and every other version as unlike it.
+struct ReplayEmbedder {
+    /// The converged versions, with how similar each was to the one before it.
+    converged: Vec<(String, f64)>,
+}
+
+#[async_trait]
+impl EmbeddingProvider for ReplayEmbedder {
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, ProviderError> {
+        let similarity = match texts {
+            [_, latest] => self.converged.iter().find(|(answer, _)| answer == latest).map_or(0.0, |(_, similarity)| *similarity),
+            _ => 0.0,
+        } as f32;
+        Ok(texts.iter()
+            .enumerate()
+            .map(|(index, _)| match index {
+                0 => vec![1.0, 0.0],
+                _ => vec![similarity, (1.0 - similarity * similarity).max(0.0).sqrt()],
+            })
+            .collect())
+    }
+}
+
+/// Flags the version of the answer the transcript records the moderator flagging, and passes every other.
+struct ReplayModerator {
+    flagged: Option<(usize, String)>,
+    /// How many versions have been screened, which is the round of the next.
+    screened: AtomicUsize,
+}
+
+#[async_trait]
+impl ModerationProvider for ReplayModerator {
+    async fn moderate(&self, _texts: &[String]) -> Result<Option<String>, ProviderError> {
+        let round = self.screened.fetch_add(1, Ordering::Relaxed);
+        Ok(self.flagged.as_ref().filter(|(flagged, _)| *flagged == round).map(|(_, reason)| reason.clone()))
+    }
+}
@@ -0,0 +1,125 @@
+//! Markdown reports of a deliberation, for reading how the panel settled on an answer.
+//!
+//! A report covers the question, the competing drafts if the panel drafted best-of-N, every version of the
+//! answer with each actor's verdict and reasoning on it, and the result: the final answer, how firmly the
+//! panel stands behind it, who still objected and what it cost.
+
+use std::fmt::Write;
+
+use crate::result::{ConsensusResult, Evaluation, Feedback};
+
+/// Renders the deliberation that produced the result as a Markdown document.
+pub fn markdown(result: &ConsensusResult) -> String {
+    let mut report = String::new();
+    // Writing to a String cannot fail.
+    let _ = write_report(&mut report, result);
+    report
+}
+
+fn write_report(report: &mut String, result: &ConsensusResult) -> std::fmt::Result {
+    writeln!(report, "# Deliberation on question {}", result.question_id)?;
+    writeln!(report)?;
+    writeln!(report, "## Question")?;
+    writeln!(report)?;
+    writeln!(report, "{}", quote(&result.question))?;
+
+    if !result.candidates.is_empty() {
+        writeln!(report)?;
+        writeln!(report, "## Candidate drafts")?;
+        for candidate in &result.candidates {
+            writeln!(report)?;
+            writeln!(report, "### By {} ({} votes)", candidate.author, candidate.votes)?;
+            writeln!(report)?;
+            writeln!(report, "{}", candidate.answer.trim())?;
+        }
+    }
+
+    for (index, round) in result.rounds.iter().enumerate() {
+        let verb = if index == 0 { "drafted" } else { "refined" };
+        writeln!(report)?;
+        writeln!(report, "## Round {}, {} by {}", index + 1, verb, round.author)?;
+        writeln!(report)?;
+        writeln!(report, "{}", round.answer.trim())?;
+        if round.evaluations.is_empty() {
+            continue;
+        }
+        writeln!(report)?;
+        writeln!(report, "### Evaluations")?;
+        writeln!(report)?;
+        for evaluation in &round.evaluations {
+            writeln!(report, "{}", describe_evaluation(evaluation))?;
+        }
+    }
+
+    writeln!(report)?;
+    writeln!(report, "## Result")?;
+    writeln!(report)?;
+    let outcome = match (&result.flagged, result.consensus_reached, result.converged) {
+        (Some(reason), _, _) => format!("The moderator refused the question: {}", reason),
+        (None, true, _) => match &result.cached {
+            Some(cached) => format!("The panel reached consensus on {} on a question {:.0}% similar: \"{}\".", cached.finished_at, cached.similarity * 100.0, cached.question),
+            None => "The panel reached consensus.".to_string(),
+        },
+        (None, false, true) => "The panel's refinements stopped changing the answer before it agreed on it.".to_string(),
+        (None, false, false) => match (&result.budget_exceeded, &result.deadlock) {
+            (Some(reason), _) => format!("The panel stopped before agreeing on the answer, since {}.", reason),
+            (None, Some(reason)) => format!("The panel was going in circles, since {}, so it settled on the answer without agreeing on it.", reason),
+            (None, None) if result.ruling.is_some() => "The panel ran out of rounds before agreeing, so its chair ruled on the answer.".to_string(),
+            (None, None) => "The panel ran out of rounds before agreeing on the answer.".to_string(),
+        },
+    };
+    writeln!(report, "{}", outcome)?;
+    if let Some(reasoning) = &result.ruling {
+        writeln!(report)?;
+        writeln!(report, "The chair's ruling: {}", reasoning)?;
+    }
+    writeln!(report)?;
+    writeln!(report, "- First drafted by: {}", result.answered_by)?;
+    writeln!(report, "- Refinement rounds: {}", result.refinement_rounds)?;
+    writeln!(report, "- Confidence: {:.0}%", result.confidence * 100.0)?;
+    writeln!(report, "- Elapsed: {:.1}s", result.elapsed.as_secs_f64())?;
+    for (actor, backend) in &result.fallbacks {
+        writeln!(report, "- {}'s provider failed, so its fallback {} answered for it", actor, backend)?;
+    }
+    let usage = &result.usage.total;
+    writeln!(report, "- Usage: {} calls, {} prompt and {} completion tokens, costing about {:.4}",
+        usage.calls, usage.prompt_tokens, usage.completion_tokens, usage.estimated_cost)?;
+    writeln!(report)?;
+    writeln!(report, "### Final answer")?;
+    writeln!(report)?;
+    writeln!(report, "{}", result.answer.trim())?;
+
+    if !result.dissent.is_empty() {
+        writeln!(report)?;
+        writeln!(report, "### Dissent")?;
+        writeln!(report)?;
+        for dissent in &result.dissent {
+            writeln!(report, "- **{}**, on round {}: {}", dissent.actor, dissent.round + 1, indent(&dissent.reasoning))?;
+        }
+    }
+    Ok(())
+}
+
+/// A list item with the actor's verdict and reasoning, e.g. "- **A**: needs refinement (score 4). Too vague."
+fn describe_evaluation(evaluation: &Evaluation) -> String {
+    let verdict = match evaluation.feedback {
+        Feedback::Good => "good",
+        Feedback::NeedsRefinement => "needs refinement",
+        Feedback::Abstain => "abstained",
+    };
+    let score = evaluation.score.map(|score| format!(" (score {})", score)).unwrap_or_default();
+    match evaluation.reasoning.trim() {
+        "" => format!("- **{}**: {}{}", evaluation.actor, verdict, score),
+        reasoning => format!("- **{}**: {}{}. {}", evaluation.actor, verdict, score, indent(reasoning)),
+    }
+}
+
+/// Indents every line after the first, so that text spanning several lines stays within its list item.
+fn indent(text: &str) -> String {
+    text.trim().lines().collect::<Vec<_>>().join("\n  ")
+}
+
+/// The text as a Markdown block quote.
+fn quote(text: &str) -> String {
+    text.trim().lines().map(|line| format!("> {}", line).trim_end().to_string()).collect::<Vec<_>>().join("\n")
+}
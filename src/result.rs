@@ -0,0 +1,190 @@
+//! The structured outcome of a consensus run.
+
+use std::{collections::BTreeMap, fmt, time::Duration};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{citations::Citation, usage::UsageSummary};
+
+/// How much each refinement round lowers the [ConsensusResult::confidence], as a share of what is left.
+const REFINEMENT_PENALTY: f64 = 0.1;
+
+/// How much running out of rounds without consensus lowers the [ConsensusResult::confidence].
+const CUTOFF_PENALTY: f64 = 0.5;
+
+/// Identifies one question the panel is deliberating on. Every message about a question carries its id,
+/// so that several questions can be in flight at once.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct QuestionId(pub u64);
+
+impl fmt::Display for QuestionId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// An evaluator's verdict on a version of the answer.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub enum Feedback {
+    Good,
+    NeedsRefinement,
+    /// The question is outside the evaluator's domain, so it leaves the verdict to the rest of the panel. Consensus
+    /// is decided among those that did not abstain.
+    Abstain,
+}
+
+/// The outcome of asking the panel a question.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ConsensusResult {
+    pub question_id: QuestionId,
+    pub question: String,
+    /// The answer the panel settled on.
+    pub answer: String,
+    /// Whether the panel agreed on the answer, rather than running out of rounds and accepting the latest version.
+    pub consensus_reached: bool,
+    /// Whether the panel stopped without agreeing because the refinements had stopped changing what the answer says.
+    pub converged: bool,
+    /// Why the moderator flagged the question or its answer, if it did. The answer is then a refusal.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flagged: Option<String>,
+    /// Why the panel stopped refining the answer before it agreed on it, if another round would have overrun the
+    /// question's budget or the session's.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub budget_exceeded: Option<String>,
+    /// Why the panel stopped refining the answer before it agreed on it, if it was going in circles. The answer is
+    /// then the version the tie-break picked, which may be an earlier one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deadlock: Option<String>,
+    /// The chair's reasoning, if the chair ruled on which version of the answer to accept because the panel ran
+    /// out of rounds or deadlocked. The answer is then the version it ruled for.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ruling: Option<String>,
+    /// The earlier run the answer was reused from instead of deliberating, if the question meant nearly the same as
+    /// one the panel had already agreed on. The question then has no id of its own, and its only round is the final
+    /// evaluation of the answer.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cached: Option<CachedAnswer>,
+    /// The actor that drafted the first answer.
+    pub answered_by: String,
+    /// Competing drafts, when the panel drafted best-of-N.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub candidates: Vec<Candidate>,
+    /// Every version of the answer and how the panel evaluated it, in order.
+    pub rounds: Vec<Round>,
+    /// How many times the answer was refined after the first draft.
+    pub refinement_rounds: u32,
+    /// From 0 to 1, how firmly the panel stands behind the answer: 1 for an answer every actor approved
+    /// as first drafted, less the more of them objected, the longer it took them to agree, and if they never did.
+    pub confidence: f64,
+    /// Wall time from receiving the question to reaching consensus.
+    #[serde(rename = "elapsed_secs", serialize_with = "serialize_secs", deserialize_with = "deserialize_secs")]
+    pub elapsed: Duration,
+    /// If the panel ran out of rounds or budget, converged or deadlocked, the actors that still objected to the last
+    /// version they evaluated, and why.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dissent: Vec<Dissent>,
+    /// The sources the answer cites, with the claims citing each, if answers had to cite their sources.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub citations: Vec<Citation>,
+    /// The actors whose own provider failed, with the fallback that last answered for each instead.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub fallbacks: BTreeMap<String, String>,
+    /// Tokens used and estimated cost of the provider calls made for this question.
+    pub usage: UsageSummary,
+}
+
+impl ConsensusResult {
+    /// Version `round` of the answer, starting at 0 for the first draft, to fall back on in place of the one the
+    /// panel settled on.
+    pub fn version(&self, round: usize) -> Option<&str> {
+        self.rounds.get(round).map(|round| round.answer.as_str())
+    }
+}
+
+/// The run in the history database an answer was reused from, because its question meant nearly the same.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CachedAnswer {
+    pub run_id: i64,
+    /// The question the panel answered then.
+    pub question: String,
+    /// When the panel settled on the answer, as an RFC 3339 timestamp.
+    pub finished_at: String,
+    /// How similar, from 0 to 1, the two questions are in meaning.
+    pub similarity: f64,
+}
+
+/// A draft answer competing under best-of-N drafting.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Candidate {
+    pub author: String,
+    pub answer: String,
+    /// The weighted votes it received from the panel.
+    pub votes: f64,
+}
+
+/// One version of the answer and the evaluations it received.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Round {
+    /// The actor that drafted or refined this version.
+    pub author: String,
+    pub answer: String,
+    pub evaluations: Vec<Evaluation>,
+}
+
+/// An actor's objection to one version of the answer.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Dissent {
+    /// The version of the answer objected to, starting at 0 for the first draft.
+    pub round: usize,
+    pub actor: String,
+    pub reasoning: String,
+}
+
+/// A single actor's verdict on one version of the answer.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Evaluation {
+    pub actor: String,
+    pub feedback: Feedback,
+    /// The 1 to 10 score, for strategies that score answers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub score: Option<u8>,
+    pub reasoning: String,
+}
+
+/// The share of Good votes in the last round that was evaluated, leaving out abstentions, lowered by
+/// [REFINEMENT_PENALTY] for every refinement and by [CUTOFF_PENALTY] if the panel ran out of rounds or converged. An
+/// answer accepted without consensus was never evaluated itself, so the votes on the version before it count.
+pub fn confidence(rounds: &[Round], consensus_reached: bool) -> f64 {
+    let Some(evaluations) = rounds.iter().rev().map(|round| &round.evaluations).find(|evaluations| !evaluations.is_empty()) else {
+        return 0.0;
+    };
+    let evaluations: Vec<&Evaluation> = evaluations.iter().filter(|evaluation| evaluation.feedback != Feedback::Abstain).collect();
+    if evaluations.is_empty() {
+        return 0.0;
+    }
+    let good = evaluations.iter().filter(|evaluation| evaluation.feedback == Feedback::Good).count();
+    let refinements = rounds.len().saturating_sub(1) as i32;
+    let cutoff = if consensus_reached { 1.0 } else { 1.0 - CUTOFF_PENALTY };
+    good as f64 / evaluations.len() as f64 * (1.0 - REFINEMENT_PENALTY).powi(refinements) * cutoff
+}
+
+/// The objections to the last version of the answer that was evaluated.
+pub fn final_dissent(rounds: &[Round]) -> Vec<Dissent> {
+    let Some((round, version)) = rounds.iter().enumerate().rev().find(|(_, version)| !version.evaluations.is_empty()) else {
+        return Vec::new();
+    };
+    version.evaluations.iter()
+        .filter(|evaluation| evaluation.feedback == Feedback::NeedsRefinement)
+        .map(|evaluation| Dissent { round, actor: evaluation.actor.clone(), reasoning: evaluation.reasoning.clone() })
+        .collect()
+}
+
+fn serialize_secs<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64())
+}
+
+fn deserialize_secs<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    let secs = f64::deserialize(deserializer)?;
+    Duration::try_from_secs_f64(secs).map_err(serde::de::Error::custom)
+}
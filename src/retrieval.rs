@@ -0,0 +1,274 @@
+//! Retrieval of the passages of a local corpus most relevant to each question, which are quoted in its prompts
+//! alongside any documents attached to it.
+//!
+//! The corpus is cut into excerpts like attached documents are, and each excerpt is embedded and kept in a SQLite
+//! vector store. At startup only the files added or changed since they were last embedded, or embedded with
+//! another model, are embedded again, and those deleted are dropped from the store. Each question is then embedded
+//! and the `top_k` excerpts most similar to it are added to the question's documents.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::UNIX_EPOCH,
+};
+
+use rusqlite::{params, Connection};
+use serde::Deserialize;
+use tokio::sync::RwLock;
+use tracing::{debug, error, info, warn};
+
+use crate::{
+    documents::{self, Excerpt},
+    provider::{cosine_similarity, EmbeddingConfig, EmbeddingProvider, ProviderError},
+};
+
+/// How many excerpts are embedded in one request while indexing.
+const EMBEDDING_BATCH: usize = 32;
+
+/// The `[retrieval]` section of the config file.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RetrievalConfig {
+    /// The directory of files to retrieve passages from, searched recursively. Hidden files, and files that are
+    /// not text, are skipped.
+    pub corpus: PathBuf,
+    /// The SQLite database the embedded excerpts are kept in. It is created if missing.
+    pub store: PathBuf,
+    /// How many excerpts are retrieved for each question.
+    #[serde(default = "default_top_k")]
+    pub top_k: usize,
+    #[serde(flatten)]
+    pub embedding: EmbeddingConfig,
+}
+
+fn default_top_k() -> usize {
+    4
+}
+
+impl RetrievalConfig {
+    /// Checks the config, returning a description of the problem if it is unusable.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.top_k == 0 {
+            return Err("retrieval top_k must be at least 1".to_string());
+        }
+        self.embedding.validate().map_err(|reason| format!("retrieval: {}", reason))
+    }
+
+    /// Names the embedding model, so that excerpts embedded with another one are embedded again.
+    fn embedding_model(&self) -> String {
+        format!("{:?}/{}", self.embedding.provider, self.embedding.model.as_deref().unwrap_or("default")).to_lowercase()
+    }
+}
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS files (
+    path TEXT PRIMARY KEY,
+    modified INTEGER NOT NULL,
+    model TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS excerpts (
+    path TEXT NOT NULL REFERENCES files(path),
+    part INTEGER NOT NULL,
+    text TEXT NOT NULL,
+    embedding BLOB NOT NULL
+);
+CREATE INDEX IF NOT EXISTS excerpts_path ON excerpts(path);
+";
+
+/// A connection to the vector store.
+pub struct VectorStore {
+    connection: Connection,
+}
+
+/// An excerpt of the corpus with its embedding.
+struct IndexedExcerpt {
+    excerpt: Excerpt,
+    embedding: Vec<f32>,
+}
+
+impl VectorStore {
+    /// Opens the store at `path`, creating it and its tables if needed.
+    pub fn open(path: &Path) -> rusqlite::Result<Self> {
+        let connection = Connection::open(path)?;
+        connection.execute_batch(SCHEMA)?;
+        Ok(VectorStore { connection })
+    }
+
+    /// When each stored file was last modified, and the model its excerpts were embedded with.
+    fn files(&self) -> rusqlite::Result<HashMap<String, (i64, String)>> {
+        let mut statement = self.connection.prepare("SELECT path, modified, model FROM files")?;
+        let files = statement.query_map([], |row| Ok((row.get(0)?, (row.get(1)?, row.get(2)?))))?.collect();
+        files
+    }
+
+    /// Replaces the stored excerpts of a file.
+    fn store(&mut self, path: &str, modified: i64, model: &str, excerpts: &[(String, Vec<f32>)]) -> rusqlite::Result<()> {
+        let transaction = self.connection.transaction()?;
+        transaction.execute("DELETE FROM excerpts WHERE path = ?1", params![path])?;
+        transaction.execute("INSERT OR REPLACE INTO files (path, modified, model) VALUES (?1, ?2, ?3)", params![path, modified, model])?;
+        for (index, (text, embedding)) in excerpts.iter().enumerate() {
+            let embedding: Vec<u8> = embedding.iter().flat_map(|value| value.to_le_bytes()).collect();
+            transaction.execute(
+                "INSERT INTO excerpts (path, part, text, embedding) VALUES (?1, ?2, ?3, ?4)",
+                params![path, index + 1, text, embedding],
+            )?;
+        }
+        transaction.commit()
+    }
+
+    /// Drops a file that is no longer in the corpus.
+    fn remove(&mut self, path: &str) -> rusqlite::Result<()> {
+        let transaction = self.connection.transaction()?;
+        transaction.execute("DELETE FROM excerpts WHERE path = ?1", params![path])?;
+        transaction.execute("DELETE FROM files WHERE path = ?1", params![path])?;
+        transaction.commit()
+    }
+
+    /// Every stored excerpt with its embedding.
+    fn load(&self) -> rusqlite::Result<Vec<IndexedExcerpt>> {
+        let mut statement = self.connection.prepare("SELECT path, part, text, embedding FROM excerpts ORDER BY path, part")?;
+        let excerpts = statement.query_map([], |row| {
+            let embedding: Vec<u8> = row.get(3)?;
+            Ok(IndexedExcerpt {
+                excerpt: Excerpt { source: row.get(0)?, part: row.get(1)?, text: row.get(2)? },
+                embedding: embedding.chunks_exact(4).map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])).collect(),
+            })
+        })?.collect();
+        excerpts
+    }
+}
+
+/// Finds the excerpts of the corpus most relevant to a question.
+#[derive(Clone)]
+pub struct Retriever {
+    embedder: Arc<dyn EmbeddingProvider>,
+    top_k: usize,
+    /// Every excerpt of the corpus, write-locked until the corpus is indexed so that questions wait for it.
+    index: Arc<RwLock<Vec<IndexedExcerpt>>>,
+}
+
+impl Retriever {
+    /// Starts indexing the corpus into the store in the background, cutting its files into excerpts of at most
+    /// `chunk_chars` characters. Questions asked before the index is ready wait for it.
+    pub fn start(config: &RetrievalConfig, chunk_chars: usize, store: VectorStore, embedder: Arc<dyn EmbeddingProvider>) -> Self {
+        let index = Arc::new(RwLock::new(Vec::new()));
+        let mut guard = index.clone().try_write_owned().expect("a new index should not be locked");
+        let indexer = Indexer { corpus: config.corpus.clone(), model: config.embedding_model(), chunk_chars, store, embedder: embedder.clone() };
+        let indexing = async move {
+            *guard = indexer.run().await;
+        };
+        #[cfg(feature = "actix")]
+        actix::spawn(indexing);
+        #[cfg(not(feature = "actix"))]
+        tokio::spawn(indexing);
+        Retriever { embedder, top_k: config.top_k, index }
+    }
+
+    /// The `top_k` excerpts most similar in meaning to the question, most similar first.
+    pub async fn retrieve(&self, question: &str) -> Result<Vec<Excerpt>, ProviderError> {
+        let index = self.index.read().await;
+        if index.is_empty() {
+            return Ok(Vec::new());
+        }
+        let embedding = self.embedder.embed(&[question.to_string()]).await?.into_iter().next()
+            .ok_or_else(|| ProviderError::InvalidResponse("no embedding for the question".to_string()))?;
+        let mut scored: Vec<(f64, &IndexedExcerpt)> = index.iter()
+            .map(|indexed| (cosine_similarity(&embedding, &indexed.embedding), indexed))
+            .collect();
+        scored.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+        Ok(scored.into_iter().take(self.top_k).map(|(_, indexed)| indexed.excerpt.clone()).collect())
+    }
+}
+
+/// Brings the store up to date with the corpus.
+struct Indexer {
+    corpus: PathBuf,
+    model: String,
+    chunk_chars: usize,
+    store: VectorStore,
+    embedder: Arc<dyn EmbeddingProvider>,
+}
+
+impl Indexer {
+    /// Embeds the files that changed since they were stored, drops those that were deleted, and returns every
+    /// excerpt in the store. A file that cannot be embedded keeps the excerpts stored for it before, if any.
+    async fn run(mut self) -> Vec<IndexedExcerpt> {
+        let stored = match self.store.files() {
+            Ok(stored) => stored,
+            Err(e) => {
+                error!("Unable to read the vector store: {}", e);
+                return Vec::new();
+            }
+        };
+        let mut files = Vec::new();
+        if let Err(e) = list_files(&self.corpus, &mut files) {
+            error!("Unable to read the corpus {}: {}", self.corpus.display(), e);
+        }
+        let mut seen = HashSet::new();
+        let mut embedded = 0;
+        for file in files {
+            let name = file.strip_prefix(&self.corpus).unwrap_or(&file).display().to_string();
+            seen.insert(name.clone());
+            let modified = fs::metadata(&file)
+                .and_then(|metadata| metadata.modified())
+                .map(|modified| modified.duration_since(UNIX_EPOCH).map(|since| since.as_secs() as i64).unwrap_or_default())
+                .unwrap_or_default();
+            if stored.get(&name).is_some_and(|(stored_modified, model)| *stored_modified == modified && *model == self.model) {
+                continue;
+            }
+            let Ok(text) = fs::read_to_string(&file) else {
+                debug!("Skipping {}, which is not text.", file.display());
+                continue;
+            };
+            match self.embed(&name, modified, &text).await {
+                Ok(()) => embedded += 1,
+                Err(e) => warn!("Unable to index {}: {}", file.display(), e),
+            }
+        }
+        for name in stored.keys().filter(|name| !seen.contains(*name)) {
+            if let Err(e) = self.store.remove(name) {
+                warn!("Unable to drop {} from the vector store: {}", name, e);
+            }
+        }
+        match self.store.load() {
+            Ok(excerpts) => {
+                info!("Indexed {} new or changed files of {}; {} excerpts can be retrieved.", embedded, self.corpus.display(), excerpts.len());
+                excerpts
+            },
+            Err(e) => {
+                error!("Unable to read the vector store: {}", e);
+                Vec::new()
+            }
+        }
+    }
+
+    /// Cuts the file into excerpts, embeds them and stores them in place of the file's earlier excerpts.
+    async fn embed(&mut self, name: &str, modified: i64, text: &str) -> Result<(), String> {
+        let chunks = documents::chunk(text, self.chunk_chars);
+        let mut embeddings = Vec::with_capacity(chunks.len());
+        for batch in chunks.chunks(EMBEDDING_BATCH) {
+            embeddings.extend(self.embedder.embed(batch).await.map_err(|e| e.to_string())?);
+        }
+        let excerpts: Vec<(String, Vec<f32>)> = chunks.into_iter().zip(embeddings).collect();
+        self.store.store(name, modified, &self.model, &excerpts).map_err(|e| e.to_string())
+    }
+}
+
+/// Adds every file under the directory that is not hidden, or in a hidden directory, to `files`.
+fn list_files(directory: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    let mut entries = fs::read_dir(directory)?.collect::<io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            list_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,62 @@
+//! Asks on the terminal whether to accept the answer the panel agreed on.
+
+use std::{io, thread};
+
+use actix::prelude::*;
+use llm_consensus::messages::{Review, ReviewAnswer};
+use tokio::sync::oneshot;
+
+use crate::display::{ClearStatus, TerminalDisplay};
+
+/// Shows each answer the panel agrees on and reads the user's verdict from stdin.
+pub struct TerminalReviewer {
+    /// Whose status line is cleared before asking.
+    display: Option<Addr<TerminalDisplay>>,
+}
+
+impl TerminalReviewer {
+    pub fn new(display: Option<Addr<TerminalDisplay>>) -> Self {
+        TerminalReviewer { display }
+    }
+}
+
+impl Actor for TerminalReviewer {
+    type Context = Context<Self>;
+}
+
+impl Handler<ReviewAnswer> for TerminalReviewer {
+    type Result = ResponseFuture<Review>;
+
+    fn handle(&mut self, msg: ReviewAnswer, _ctx: &mut Self::Context) -> Self::Result {
+        let display = self.display.clone();
+        Box::pin(async move {
+            if let Some(display) = display {
+                let _ = display.send(ClearStatus).await;
+            }
+            // Reading stdin blocks, so it happens on a thread of its own.
+            let (sender, review) = oneshot::channel();
+            thread::spawn(move || sender.send(ask(&msg.answer)));
+            review.await.unwrap_or(Review::Approve)
+        })
+    }
+}
+
+/// Prints the answer and asks whether to accept it. An empty line or "yes" approves it and anything else
+/// is taken as the reason for rejecting it. The answer is approved if stdin is closed.
+fn ask(answer: &str) -> Review {
+    eprintln!("The panel agreed on this answer:\n\n{}\n", answer);
+    loop {
+        eprint!("Press Enter to accept it, or type why you reject it: ");
+        let mut line = String::new();
+        if io::stdin().read_line(&mut line).unwrap_or(0) == 0 {
+            eprintln!();
+            return Review::Approve;
+        }
+        let line = line.trim();
+        match line.to_lowercase().as_str() {
+            "" | "y" | "yes" => return Review::Approve,
+            "n" | "no" => eprintln!("Say what is wrong with it, so that the panel can address it."),
+            _ => return Review::Reject { reason: line.to_string() },
+        }
+    }
+}
@@ -0,0 +1,310 @@
+//! Running the code in programming answers, so that the panel evaluates them on what the compiler and the
+//! interpreter make of the code rather than on how it reads.
+//!
+//! With a `[sandbox]` section, each fenced code block of an answer tagged with one of the configured languages is
+//! saved to a fresh temporary directory and run with that language's command before the panel evaluates the
+//! version. How each run ended, and what it printed, are quoted in the evaluation prompts as evidence. Answers
+//! without such blocks are evaluated as usual.
+//!
+//! The commands run with the panel's own permissions, with nothing but `PATH` in their environment so that the
+//! code cannot read the API keys. A command that wraps the run in a container or a jail, such as `docker run` or
+//! `firejail`, keeps the code the models write off the host.
+
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::Path,
+    process::{self, Stdio},
+    sync::{atomic::{AtomicU64, Ordering}, Arc},
+    time::Duration,
+};
+
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+use tokio::{process::Command, time::timeout};
+use tracing::{debug, warn};
+
+/// Numbers the scratch directories the runs of this process make, so that no two share one.
+static NEXT_RUN: AtomicU64 = AtomicU64::new(0);
+
+/// The `[sandbox]` section of the config file.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SandboxConfig {
+    /// How long each code block may take to compile and run before it is killed.
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    /// The most code blocks of each version of the answer that are run, first to last.
+    #[serde(default = "default_max_blocks")]
+    pub max_blocks: usize,
+    /// The most characters of each run's output quoted in the evaluation prompts.
+    #[serde(default = "default_max_output_chars")]
+    pub max_output_chars: usize,
+    /// How to run each language, by the tag code blocks in it are fenced with, such as `python` or `rust`.
+    pub languages: BTreeMap<String, LanguageConfig>,
+}
+
+/// How the code blocks of one language are run.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LanguageConfig {
+    /// Other tags the language's code blocks may be fenced with, such as `py`.
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    /// The name the code is saved under, such as `main.py`.
+    pub file: String,
+    /// The program to run and its arguments, in which `{file}` stands for the path of the saved code and `{dir}`
+    /// for the directory it is saved in, such as `["python3", "{file}"]`. The command runs in that directory, and
+    /// the block passes if it exits with status 0.
+    pub command: Vec<String>,
+}
+
+fn default_timeout_secs() -> u64 {
+    10
+}
+
+fn default_max_blocks() -> usize {
+    3
+}
+
+fn default_max_output_chars() -> usize {
+    2_000
+}
+
+impl SandboxConfig {
+    /// Checks the config, returning a description of the problem if it is unusable.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.timeout_secs == 0 {
+            return Err("sandbox timeout_secs must be at least 1".to_string());
+        }
+        if self.max_blocks == 0 {
+            return Err("sandbox max_blocks must be at least 1".to_string());
+        }
+        if self.max_output_chars == 0 {
+            return Err("sandbox max_output_chars must be at least 1".to_string());
+        }
+        if self.languages.is_empty() {
+            return Err("sandbox: at least one language must be configured".to_string());
+        }
+        for (name, language) in &self.languages {
+            if language.command.first().is_none_or(|program| program.trim().is_empty()) {
+                return Err(format!("sandbox language \"{}\" must have a command", name));
+            }
+            if language.file.trim().is_empty() || language.file.contains(['/', '\\']) {
+                return Err(format!("sandbox language \"{}\" must save its code under a file name, not a path", name));
+            }
+        }
+        Ok(())
+    }
+
+    /// The configured language the tag of a code block names, if any, and its name.
+    fn language(&self, tag: &str) -> Option<(&str, &LanguageConfig)> {
+        self.languages.iter()
+            .find(|(name, language)| name.eq_ignore_ascii_case(tag) || language.aliases.iter().any(|alias| alias.eq_ignore_ascii_case(tag)))
+            .map(|(name, language)| (name.as_str(), language))
+    }
+}
+
+/// A fenced code block of an answer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodeBlock {
+    /// Counts the answer's code blocks from 1.
+    pub number: usize,
+    /// The first word after the opening fence, such as `rust`, or empty if there is none.
+    pub tag: String,
+    pub code: String,
+}
+
+/// How running one of an answer's code blocks went.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct CodeRun {
+    /// Counts the answer's code blocks from 1, including any that were not run.
+    pub block: usize,
+    /// The configured language it was run as.
+    pub language: String,
+    /// The status the command exited with, or None if it was killed.
+    pub exit_code: Option<i32>,
+    /// Whether it was killed for taking longer than `timeout_secs`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub timed_out: bool,
+    /// What it printed, standard output first, cut to `max_output_chars`.
+    pub output: String,
+}
+
+impl CodeRun {
+    /// Whether the block compiled and ran to a successful exit.
+    pub fn passed(&self) -> bool {
+        self.exit_code == Some(0)
+    }
+
+    /// How the run ended, as the evaluation prompts put it.
+    pub fn outcome(&self) -> String {
+        match self.exit_code {
+            Some(0) => "ran successfully".to_string(),
+            Some(code) => format!("failed with exit status {}", code),
+            None if self.timed_out => "was killed for running too long".to_string(),
+            None => "was killed by a signal".to_string(),
+        }
+    }
+}
+
+/// Runs the code blocks of each version of the answer, as the `[sandbox]` section says.
+#[derive(Debug, Clone)]
+pub struct Sandbox {
+    config: Arc<SandboxConfig>,
+}
+
+impl Sandbox {
+    pub fn new(config: SandboxConfig) -> Self {
+        Sandbox { config: Arc::new(config) }
+    }
+
+    /// The answer's code blocks in the configured languages, up to `max_blocks` of them.
+    pub fn runnable(&self, answer: &str) -> Vec<CodeBlock> {
+        code_blocks(answer).into_iter()
+            .filter(|block| self.config.language(&block.tag).is_some())
+            .take(self.config.max_blocks)
+            .collect()
+    }
+
+    /// Runs the blocks at once, each in a directory of its own, in the order given. A block whose command cannot be
+    /// started is left out, since that says nothing about the code.
+    pub async fn run(&self, blocks: &[CodeBlock]) -> Vec<CodeRun> {
+        join_all(blocks.iter().map(|block| self.run_block(block))).await.into_iter().flatten().collect()
+    }
+
+    async fn run_block(&self, block: &CodeBlock) -> Option<CodeRun> {
+        let (name, language) = self.config.language(&block.tag)?;
+        let dir = env::temp_dir().join(format!("llm-consensus-{}-{}", process::id(), NEXT_RUN.fetch_add(1, Ordering::Relaxed)));
+        let run = self.run_in(&dir, block, name, language).await;
+        if let Err(e) = fs::remove_dir_all(&dir) {
+            warn!("Unable to remove {}, where code block {} ran: {}", dir.display(), block.number, e);
+        }
+        match run {
+            Ok(run) => {
+                debug!("Code block {} ({}) {}.", block.number, name, run.outcome());
+                Some(run)
+            },
+            Err(e) => {
+                warn!("Unable to run code block {} as {}, so it is evaluated without running: {}", block.number, name, e);
+                None
+            },
+        }
+    }
+
+    async fn run_in(&self, dir: &Path, block: &CodeBlock, name: &str, language: &LanguageConfig) -> Result<CodeRun, String> {
+        fs::create_dir_all(dir).map_err(|e| format!("unable to create {}: {}", dir.display(), e))?;
+        let file = dir.join(&language.file);
+        fs::write(&file, &block.code).map_err(|e| format!("unable to write {}: {}", file.display(), e))?;
+        let args: Vec<String> = language.command.iter().map(|arg| substitute(arg, &file, dir)).collect();
+        let mut command = Command::new(&args[0]);
+        command.args(&args[1..])
+            .current_dir(dir)
+            .env_clear()
+            .stdin(Stdio::null())
+            .kill_on_drop(true);
+        if let Some(path) = env::var_os("PATH") {
+            command.env("PATH", path);
+        }
+        let limit = Duration::from_secs(self.config.timeout_secs);
+        let (exit_code, timed_out, output) = match timeout(limit, command.output()).await {
+            Ok(output) => {
+                let output = output.map_err(|e| format!("unable to start {}: {}", args[0], e))?;
+                let printed = format!("{}{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+                (output.status.code(), false, printed)
+            },
+            Err(_) => (None, true, String::new()),
+        };
+        Ok(CodeRun { block: block.number, language: name.to_string(), exit_code, timed_out, output: cut(output.trim(), self.config.max_output_chars) })
+    }
+}
+
+/// The fenced code blocks of the answer, in order. A block left open runs to the end of the answer.
+pub fn code_blocks(answer: &str) -> Vec<CodeBlock> {
+    let mut blocks = Vec::new();
+    let mut open: Option<(String, Vec<&str>)> = None;
+    for line in answer.lines() {
+        let fence = line.trim_start().strip_prefix("```");
+        match (open.take(), fence) {
+            (None, Some(info)) => open = Some((info.split_whitespace().next().unwrap_or_default().to_lowercase(), Vec::new())),
+            (None, None) => {},
+            (Some((tag, lines)), Some(_)) => blocks.push(CodeBlock { number: blocks.len() + 1, tag, code: lines.join("\n") + "\n" }),
+            (Some((tag, mut lines)), None) => {
+                lines.push(line);
+                open = Some((tag, lines));
+            },
+        }
+    }
+    if let Some((tag, lines)) = open {
+        blocks.push(CodeBlock { number: blocks.len() + 1, tag, code: lines.join("\n") + "\n" });
+    }
+    blocks
+}
+
+/// The argument with `{file}` and `{dir}` replaced by the paths they stand for.
+fn substitute(arg: &str, file: &Path, dir: &Path) -> String {
+    arg.replace("{file}", &file.to_string_lossy()).replace("{dir}", &dir.to_string_lossy())
+}
+
+/// The output, cut to `max_chars` characters with a note saying how many more there were.
+fn cut(output: &str, max_chars: usize) -> String {
+    match output.char_indices().nth(max_chars) {
+        Some((end, _)) => format!("{}\n[{} more characters cut]", &output[..end], output[end..].chars().count()),
+        None => output.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> SandboxConfig {
+        let python = LanguageConfig { aliases: vec!["py".to_string()], file: "main.py".to_string(), command: vec!["python3".to_string(), "{file}".to_string()] };
+        SandboxConfig {
+            timeout_secs: default_timeout_secs(),
+            max_blocks: default_max_blocks(),
+            max_output_chars: default_max_output_chars(),
+            languages: BTreeMap::from([("python".to_string(), python)]),
+        }
+    }
+
+    #[test]
+    fn code_blocks_are_numbered_with_their_tags() {
+        let answer = "Try this:\n```Python title=\"demo\"\nprint(1)\n\nprint(2)\n```\nOr:\n  ```\nls\n  ```";
+        assert_eq!(code_blocks(answer), vec![
+            CodeBlock { number: 1, tag: "python".to_string(), code: "print(1)\n\nprint(2)\n".to_string() },
+            CodeBlock { number: 2, tag: String::new(), code: "ls\n".to_string() },
+        ]);
+    }
+
+    #[test]
+    fn unterminated_block_runs_to_the_end() {
+        assert_eq!(code_blocks("```rust\nfn main() {}\n"), vec![CodeBlock { number: 1, tag: "rust".to_string(), code: "fn main() {}\n".to_string() }]);
+        assert_eq!(code_blocks("```py"), vec![CodeBlock { number: 1, tag: "py".to_string(), code: "\n".to_string() }]);
+    }
+
+    #[test]
+    fn only_configured_languages_are_runnable() {
+        let sandbox = Sandbox::new(config());
+        let runnable = sandbox.runnable("```rust\nfn main() {}\n```\n```py\nprint(1)\n```");
+        assert_eq!(runnable.iter().map(|block| block.number).collect::<Vec<_>>(), vec![2]);
+    }
+
+    #[test]
+    fn cut_counts_characters_not_bytes() {
+        assert_eq!(cut("h\u{e9}llo", 5), "h\u{e9}llo");
+        assert_eq!(cut("h\u{e9}llo w\u{f6}rld", 2), "h\u{e9}\n[9 more characters cut]");
+        assert_eq!(cut("\u{1f980}\u{1f980}\u{1f980}", 1), "\u{1f980}\n[2 more characters cut]");
+    }
+
+    #[test]
+    fn unusable_configs_are_rejected() {
+        assert!(config().validate().is_ok());
+        let mut no_command = config();
+        no_command.languages.get_mut("python").unwrap().command = vec![" ".to_string()];
+        assert!(no_command.validate().unwrap_err().contains("must have a command"));
+        let mut path = config();
+        path.languages.get_mut("python").unwrap().file = "../main.py".to_string();
+        assert!(path.validate().unwrap_err().contains("not a path"));
+        assert!(SandboxConfig { languages: BTreeMap::new(), ..config() }.validate().is_err());
+        assert!(SandboxConfig { timeout_secs: 0, ..config() }.validate().is_err());
+    }
+}
@@ -0,0 +1,157 @@
+//! Web searches the panel's fact checkers ground their evaluations in.
+//!
+//! An actor with the `fact_checker` role does not evaluate an answer from what it knows alone. It is first asked,
+//! with the `fact_check` prompt, for web searches that would check the answer's factual claims, which are run on
+//! the `[search]` section's backend. The results are quoted in its evaluation prompt, so that its verdict, and the
+//! reasoning a refiner works from, name the claims the results contradict and the pages that say so.
+
+use std::{collections::HashSet, sync::Arc};
+
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+use crate::provider::{BraveSearch, CredentialsConfig, ProviderError, SearchProvider, SearchResult, SearxngSearch, SerpApiSearch, TavilySearch};
+
+/// The response to the `fact_check` prompt when the answer makes no claims worth searching for.
+const NO_QUERIES: &str = "NONE";
+
+/// The `[search]` section of the config file.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SearchConfig {
+    pub backend: SearchBackend,
+    /// Endpoint override, required for a SearXNG instance.
+    #[serde(default)]
+    pub base_url: Option<String>,
+    /// Names of keys from `[credentials.keys]` to take turns with, in place of the backend's environment variable.
+    #[serde(default)]
+    pub keys: Vec<String>,
+    /// The most searches a fact checker runs for each version of the answer.
+    #[serde(default = "default_max_queries")]
+    pub max_queries: usize,
+    /// The most results of each search quoted in the fact checker's prompt.
+    #[serde(default = "default_max_results")]
+    pub max_results: usize,
+}
+
+/// The web search APIs a fact checker can search with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchBackend {
+    /// Brave's Web Search API, reading its key from `BRAVE_SEARCH_API_KEY`.
+    Brave,
+    /// Google results through SerpApi, reading its key from `SERPAPI_API_KEY`.
+    SerpApi,
+    /// Tavily's search API, reading its key from `TAVILY_API_KEY`.
+    Tavily,
+    /// A self-hosted SearXNG instance at `base_url`, which needs no key.
+    Searxng,
+}
+
+fn default_max_queries() -> usize {
+    3
+}
+
+fn default_max_results() -> usize {
+    3
+}
+
+impl SearchConfig {
+    /// Checks the config, returning a description of the problem if it is unusable.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_queries == 0 {
+            return Err("search max_queries must be at least 1".to_string());
+        }
+        if self.max_results == 0 {
+            return Err("search max_results must be at least 1".to_string());
+        }
+        if self.backend == SearchBackend::Searxng {
+            if self.base_url.is_none() {
+                return Err("search: searxng needs the base_url of the instance".to_string());
+            }
+            if !self.keys.is_empty() {
+                return Err("search: searxng takes no keys".to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A search result quoted in a fact checker's prompt, with the query that found it.
+#[derive(Debug, Clone, Serialize)]
+pub struct FoundResult {
+    pub query: String,
+    #[serde(flatten)]
+    pub result: SearchResult,
+}
+
+/// The search backend fact checkers use, and how much they may search.
+#[derive(Clone)]
+pub struct WebSearch {
+    provider: Arc<dyn SearchProvider>,
+    max_queries: usize,
+    max_results: usize,
+}
+
+impl WebSearch {
+    pub fn new(provider: Arc<dyn SearchProvider>, max_queries: usize, max_results: usize) -> Self {
+        WebSearch { provider, max_queries, max_results }
+    }
+
+    /// Builds the configured backend with the configured keys.
+    pub fn from_config(config: &SearchConfig, credentials: &CredentialsConfig) -> Result<Self, ProviderError> {
+        let base_url = config.base_url.clone();
+        let provider: Arc<dyn SearchProvider> = match config.backend {
+            SearchBackend::Brave => Arc::new(BraveSearch::new(base_url, credentials.named_key_ring(&config.keys, BraveSearch::API_KEY_VAR)?)),
+            SearchBackend::SerpApi => Arc::new(SerpApiSearch::new(base_url, credentials.named_key_ring(&config.keys, SerpApiSearch::API_KEY_VAR)?)),
+            SearchBackend::Tavily => Arc::new(TavilySearch::new(base_url, credentials.named_key_ring(&config.keys, TavilySearch::API_KEY_VAR)?)),
+            SearchBackend::Searxng => Arc::new(SearxngSearch::new(base_url.as_deref().unwrap_or_default())),
+        };
+        Ok(WebSearch::new(provider, config.max_queries, config.max_results))
+    }
+
+    /// The most searches a fact checker may ask for.
+    pub fn max_queries(&self) -> usize {
+        self.max_queries
+    }
+
+    /// Runs the searches at once, returning their results in the order of the queries, each page only once. A
+    /// search that fails is left out, so that the fact checker evaluates with whatever the others found.
+    pub async fn search_all(&self, queries: &[String]) -> Vec<FoundResult> {
+        let searches = queries.iter().map(|query| async move { (query, self.provider.search(query, self.max_results).await) });
+        let mut seen = HashSet::new();
+        let mut found = Vec::new();
+        for (query, outcome) in join_all(searches).await {
+            match outcome {
+                Ok(results) => {
+                    debug!("The search for \"{}\" found {} results.", query, results.len());
+                    found.extend(results.into_iter()
+                        .filter(|result| seen.insert(result.url.clone()))
+                        .map(|result| FoundResult { query: query.clone(), result }));
+                },
+                Err(e) => warn!("The search for \"{}\" failed, so the fact checker goes without its results: {}", query, e),
+            }
+        }
+        found
+    }
+}
+
+/// The search queries in the response to the `fact_check` prompt, one a line, without any numbering, bullets or
+/// quotes around them, and at most `max_queries` of them. None if the response is NONE.
+pub fn parse_queries(response: &str, max_queries: usize) -> Vec<String> {
+    response.lines()
+        .map(|line| without_marker(line).trim_matches(['"', '`']).trim())
+        .filter(|line| !line.is_empty() && !line.eq_ignore_ascii_case(NO_QUERIES))
+        .take(max_queries)
+        .map(str::to_string)
+        .collect()
+}
+
+/// The line without the bullet or number it may be listed with, such as `- ` or `2. `.
+fn without_marker(line: &str) -> &str {
+    let line = line.trim().trim_start_matches(['-', '*', '•']).trim_start();
+    match line.find(|c: char| !c.is_ascii_digit()) {
+        Some(digits) if digits > 0 && line[digits..].starts_with(['.', ')']) => line[digits + 1..].trim_start(),
+        _ => line,
+    }
+}
@@ -0,0 +1,105 @@
+//! How the [Coordinator](crate::Coordinator) picks which actors draft, refine and synthesize answers.
+
+use std::collections::HashMap;
+
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+/// Which of the eligible actors are picked for a task.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SelectionPolicy {
+    /// Picks at random.
+    #[default]
+    Random,
+    /// Takes turns in order of name, carrying on after the last actor picked.
+    RoundRobin,
+    /// Picks the actors that have gone longest without being picked, or were never picked.
+    LeastRecentlyUsed,
+}
+
+/// The `[selection]` section of the config file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SelectionSettings {
+    #[serde(default)]
+    pub policy: SelectionPolicy,
+    /// Seeds the random number generator, so that the same seed picks the same actors in the same order.
+    /// Without one, every session picks differently.
+    #[serde(default)]
+    pub seed: Option<u64>,
+}
+
+/// Picks actors under a [SelectionPolicy], remembering whom it picked before.
+///
+/// Eligible actors are considered in order of name, so a seeded selector picks the same actors
+/// however the caller happened to collect them.
+pub struct Selector {
+    policy: SelectionPolicy,
+    rng: StdRng,
+    /// The actor picked most recently, where round-robin selection carries on from.
+    last: Option<String>,
+    /// When each actor was last picked, by the value of [Selector::clock] at the time.
+    last_picked: HashMap<String, u64>,
+    /// Counts picks.
+    clock: u64,
+}
+
+impl Default for Selector {
+    fn default() -> Self {
+        Selector::new(&SelectionSettings::default())
+    }
+}
+
+impl Selector {
+    pub fn new(settings: &SelectionSettings) -> Self {
+        let rng = match settings.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        Selector::with_rng(settings.policy, rng)
+    }
+
+    /// A selector drawing its random picks from the given generator.
+    pub fn with_rng(policy: SelectionPolicy, rng: StdRng) -> Self {
+        Selector { policy, rng, last: None, last_picked: HashMap::new(), clock: 0 }
+    }
+
+    /// Picks up to `count` different actors from `eligible`.
+    pub fn select<'a>(&mut self, eligible: impl IntoIterator<Item = &'a String>, count: usize) -> Vec<String> {
+        let mut eligible: Vec<&String> = eligible.into_iter().collect();
+        eligible.sort();
+        eligible.dedup();
+        let count = count.min(eligible.len());
+        let picked: Vec<String> = match self.policy {
+            SelectionPolicy::Random => eligible.choose_multiple(&mut self.rng, count).map(|name| (*name).clone()).collect(),
+            SelectionPolicy::RoundRobin => {
+                let start = self.last.as_ref().map_or(0, |last| eligible.partition_point(|name| *name <= last));
+                eligible.iter().cycle().skip(start).take(count).map(|name| (*name).clone()).collect()
+            },
+            SelectionPolicy::LeastRecentlyUsed => {
+                // Sorting is stable, so ties go to the first name.
+                eligible.sort_by_key(|name| self.last_picked.get(*name).copied().unwrap_or(0));
+                eligible.into_iter().take(count).cloned().collect()
+            },
+        };
+        for name in &picked {
+            self.clock += 1;
+            self.last_picked.insert(name.clone(), self.clock);
+        }
+        if let Some(name) = picked.last() {
+            self.last = Some(name.clone());
+        }
+        picked
+    }
+
+    /// Picks one actor from `eligible`, or None if there are none.
+    pub fn select_one<'a>(&mut self, eligible: impl IntoIterator<Item = &'a String>) -> Option<String> {
+        self.select(eligible, 1).pop()
+    }
+
+    /// Puts the items in a random order, drawn like a random pick, so that a seeded selector shuffles them the same
+    /// way every session.
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        items.shuffle(&mut self.rng);
+    }
+}
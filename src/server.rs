@@ -0,0 +1,230 @@
+//! HTTP API that lets other applications put questions to the panel.
+//!
+//! * `POST /questions` with `{"question": "...", "strategy": {...}}` submits a question and returns its id.
+//!   An optional `"context": [{"question": "...", "answer": "..."}]` lists earlier exchanges of the
+//!   conversation, oldest first, for a follow-up question, and an optional
+//!   `"constraints": {"max_words": 100, "format": "bullets", "citations": true}` replaces the configured answer
+//!   constraints. An optional `"actors": ["...", "..."]` asks only the named actors, leaving the rest of the panel
+//!   out of it, `"fresh": true` has the panel deliberate even if the answer to a similar question is cached, and an
+//!   optional `"instructions": "..."` is added to the preamble the answer is drafted after.
+//! * `GET /questions/{id}` reports whether the question is being deliberated, answered or failed.
+//! * `GET /questions/{id}/transcript` returns the full [ConsensusResult] once the question is answered.
+//! * `GET /events` upgrades to a WebSocket that streams every [DeliberationUpdate] as JSON text messages.
+//!
+//! The panel deliberates on every submitted question at once.
+
+use std::{collections::HashMap, io, sync::{atomic::{AtomicU64, Ordering}, Mutex}};
+
+use actix::prelude::*;
+use actix_web::{web, App, Error, HttpRequest, HttpResponse, HttpServer};
+use actix_web_actors::ws;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, error, info};
+
+use crate::{constraints::AnswerConstraints, conversation::Exchange, messages::{DeliberationUpdate, QuestionOptions}, result::ConsensusResult, strategy::ConsensusStrategy, ConsensusSystem};
+
+/// Body of `POST /questions`.
+#[derive(Debug, Deserialize)]
+pub struct SubmitQuestion {
+    pub question: String,
+    /// Overrides the configured strategy for this question.
+    #[serde(default)]
+    pub strategy: Option<ConsensusStrategy>,
+    /// Earlier questions and answers of the conversation, oldest first, to draft the answer in the context of.
+    #[serde(default)]
+    pub context: Vec<Exchange>,
+    /// Overrides the configured answer constraints for this question.
+    #[serde(default)]
+    pub constraints: Option<AnswerConstraints>,
+    /// The actors to ask, if only some of the panel.
+    #[serde(default)]
+    pub actors: Vec<String>,
+    /// Deliberates even if the answer to a similar question is cached.
+    #[serde(default)]
+    pub fresh: bool,
+    /// Instructions added to the preamble the answer is drafted after.
+    #[serde(default)]
+    pub instructions: Option<String>,
+}
+
+/// Where a submitted question is in its lifecycle.
+#[derive(Debug, Clone)]
+enum QuestionStatus {
+    Deliberating,
+    Answered(Box<ConsensusResult>),
+    Failed(String),
+}
+
+/// Body of `POST /questions` and `GET /questions/{id}` responses.
+#[derive(Debug, Serialize)]
+struct QuestionSummary<'a> {
+    id: u64,
+    question: &'a str,
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    answer: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    refinement_rounds: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    confidence: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'a str>,
+}
+
+struct QuestionRecord {
+    question: String,
+    status: QuestionStatus,
+}
+
+impl QuestionRecord {
+    fn summary(&self, id: u64) -> QuestionSummary<'_> {
+        let (status, answer, refinement_rounds, confidence, error) = match &self.status {
+            QuestionStatus::Deliberating => ("deliberating", None, None, None, None),
+            QuestionStatus::Answered(result) => ("answered", Some(result.answer.as_str()), Some(result.refinement_rounds), Some(result.confidence), None),
+            QuestionStatus::Failed(error) => ("failed", None, None, None, Some(error.as_str())),
+        };
+        QuestionSummary { id, question: &self.question, status, answer, refinement_rounds, confidence, error }
+    }
+}
+
+/// State shared by every server worker.
+struct ServerState {
+    system: ConsensusSystem,
+    questions: Mutex<HashMap<u64, QuestionRecord>>,
+    last_id: AtomicU64,
+}
+
+impl ServerState {
+    fn set_status(&self, id: u64, status: QuestionStatus) {
+        if let Some(record) = self.questions.lock().expect("questions lock should not be poisoned").get_mut(&id) {
+            record.status = status;
+        }
+    }
+
+    async fn deliberate(&self, id: u64, question: String, options: QuestionOptions) {
+        debug!("Deliberating on question {}.", id);
+        let status = match self.system.ask_with(question, options).await {
+            Ok(result) => QuestionStatus::Answered(Box::new(result)),
+            Err(e) => QuestionStatus::Failed(e.to_string()),
+        };
+        self.set_status(id, status);
+    }
+}
+
+/// Serves the HTTP API on `address` until the server is stopped.
+pub async fn serve(system: ConsensusSystem, address: &str) -> io::Result<()> {
+    let state = web::Data::new(ServerState {
+        system,
+        questions: Mutex::new(HashMap::new()),
+        last_id: AtomicU64::new(0),
+    });
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(state.clone())
+            .route("/questions", web::post().to(submit_question))
+            .route("/questions/{id}", web::get().to(question_status))
+            .route("/questions/{id}/transcript", web::get().to(question_transcript))
+            .route("/events", web::get().to(deliberation_events))
+            .route("/healthz", web::get().to(health))
+    })
+    .bind(address)?;
+    info!("Serving the consensus API on {}.", address);
+    server.run().await
+}
+
+async fn submit_question(state: web::Data<ServerState>, body: web::Json<SubmitQuestion>) -> HttpResponse {
+    let SubmitQuestion { question, strategy, context, constraints, actors, fresh, instructions } = body.into_inner();
+    if question.trim().is_empty() {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": "the question must not be empty" }));
+    }
+    if let Some(Err(reason)) = strategy.as_ref().map(ConsensusStrategy::validate) {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": reason }));
+    }
+    if let Some(Err(reason)) = constraints.as_ref().map(AnswerConstraints::validate) {
+        return HttpResponse::BadRequest().json(serde_json::json!({ "error": reason }));
+    }
+
+    let id = state.last_id.fetch_add(1, Ordering::Relaxed) + 1;
+    let record = QuestionRecord { question: question.clone(), status: QuestionStatus::Deliberating };
+    let response = HttpResponse::Accepted().json(record.summary(id));
+    state.questions.lock().expect("questions lock should not be poisoned").insert(id, record);
+
+    let options = QuestionOptions { strategy, context, constraints, actors, fresh, instructions, ..QuestionOptions::default() };
+    actix_web::rt::spawn(async move { state.deliberate(id, question, options).await });
+    response
+}
+
+async fn question_status(state: web::Data<ServerState>, path: web::Path<u64>) -> HttpResponse {
+    let id = path.into_inner();
+    let questions = state.questions.lock().expect("questions lock should not be poisoned");
+    match questions.get(&id) {
+        Some(record) => HttpResponse::Ok().json(record.summary(id)),
+        None => HttpResponse::NotFound().json(serde_json::json!({ "error": format!("no question with id {}", id) })),
+    }
+}
+
+async fn question_transcript(state: web::Data<ServerState>, path: web::Path<u64>) -> HttpResponse {
+    let id = path.into_inner();
+    let questions = state.questions.lock().expect("questions lock should not be poisoned");
+    match questions.get(&id) {
+        Some(QuestionRecord { status: QuestionStatus::Answered(result), .. }) => HttpResponse::Ok().json(result),
+        // The deliberation is only complete, and its transcript final, once the question is answered.
+        Some(record) => HttpResponse::Conflict().json(record.summary(id)),
+        None => HttpResponse::NotFound().json(serde_json::json!({ "error": format!("no question with id {}", id) })),
+    }
+}
+
+/// Responds with 200 if every actor on the panel can reach its models, or with 503 if any cannot or the panel is
+/// empty, listing how each actor fared either way.
+async fn health(state: web::Data<ServerState>) -> HttpResponse {
+    match state.system.check_health().await {
+        Ok(actors) => {
+            let healthy = !actors.is_empty() && actors.iter().all(|actor| actor.error.is_none());
+            let body = serde_json::json!({ "status": if healthy { "ok" } else { "unhealthy" }, "actors": actors });
+            match healthy {
+                true => HttpResponse::Ok().json(body),
+                false => HttpResponse::ServiceUnavailable().json(body),
+            }
+        },
+        Err(e) => HttpResponse::ServiceUnavailable().json(serde_json::json!({ "status": "unhealthy", "error": e.to_string() })),
+    }
+}
+
+async fn deliberation_events(state: web::Data<ServerState>, request: HttpRequest, stream: web::Payload) -> Result<HttpResponse, Error> {
+    let (session, response) = ws::WsResponseBuilder::new(EventSession, &request, stream).start_with_addr()?;
+    state.system.subscribe(session.recipient());
+    Ok(response)
+}
+
+/// WebSocket connection that forwards every [DeliberationUpdate] to the client.
+struct EventSession;
+
+impl Actor for EventSession {
+    type Context = ws::WebsocketContext<Self>;
+}
+
+impl Handler<DeliberationUpdate> for EventSession {
+    type Result = ();
+
+    fn handle(&mut self, msg: DeliberationUpdate, ctx: &mut Self::Context) -> Self::Result {
+        match serde_json::to_string(&msg) {
+            Ok(json) => ctx.text(json),
+            Err(e) => error!("Unable to serialize a deliberation update: {}", e),
+        }
+    }
+}
+
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for EventSession {
+    fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        // The stream is one-way; only keep-alives and close frames from the client matter.
+        match msg {
+            Ok(ws::Message::Ping(bytes)) => ctx.pong(&bytes),
+            Ok(ws::Message::Close(reason)) => {
+                ctx.close(reason);
+                ctx.stop();
+            },
+            Err(_) => ctx.stop(),
+            _ => (),
+        }
+    }
+}
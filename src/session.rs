@@ -0,0 +1,59 @@
+//! REPL sessions saved to disk, so that a session can be quit and later resumed with the same panel, the
+//! conversation it remembered and the answers it gave.
+
+use std::{env, fs, io, path::{Path, PathBuf}};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{config::Profile, conversation::Exchange, result::ConsensusResult};
+
+/// The directory in the home directory that sessions are saved in, a file each.
+const SESSIONS_DIR: &str = ".llm_consensus_sessions";
+
+/// Everything a REPL session needs to pick up where it left off.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Session {
+    /// The panel in use, with the actors added and removed and the settings changed during the session.
+    pub panel: Profile,
+    /// The exchanges the conversation remembers, oldest first, or None while conversation memory is off.
+    pub conversation: Option<Vec<Exchange>>,
+    /// Every question answered in the session, oldest first.
+    pub answered: Vec<ConsensusResult>,
+}
+
+impl Session {
+    /// The file the session called `name` is saved in, under the home directory, or the working directory if there
+    /// is none.
+    pub fn path(name: &str) -> Result<PathBuf, String> {
+        if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_') {
+            return Err(format!("\"{}\" is not a session name, which may only have letters, digits, - and _", name));
+        }
+        let dir = match env::var_os("HOME") {
+            Some(home) => PathBuf::from(home).join(SESSIONS_DIR),
+            None => PathBuf::from(SESSIONS_DIR),
+        };
+        Ok(dir.join(format!("{}.json", name)))
+    }
+
+    /// Reads the session saved in `path`, or None if none has been saved there yet.
+    pub fn load(path: &Path) -> Result<Option<Session>, String> {
+        let json = match fs::read_to_string(path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(format!("unable to read {}: {}", path.display(), e)),
+        };
+        serde_json::from_str(&json).map(Some).map_err(|e| format!("{} does not hold a saved session: {}", path.display(), e))
+    }
+
+    /// Saves the session in `path`, in place of whatever was saved there. The file is written in full beside the old
+    /// one before replacing it, so that a session is never left half-saved.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir).map_err(|e| format!("unable to create {}: {}", dir.display(), e))?;
+        }
+        let json = serde_json::to_string_pretty(self).map_err(|e| format!("unable to serialize the session: {}", e))?;
+        let partial = path.with_extension("json.partial");
+        fs::write(&partial, json).map_err(|e| format!("unable to write {}: {}", partial.display(), e))?;
+        fs::rename(&partial, path).map_err(|e| format!("unable to replace {}: {}", path.display(), e))
+    }
+}
@@ -0,0 +1,117 @@
+//! How each actor has fared over the session: how long its calls take, how it votes, and how often the panel
+//! agrees on its rewrites.
+
+use std::{collections::BTreeMap, time::Duration};
+
+use serde::Serialize;
+
+use crate::{prompts::Template, result::Feedback};
+
+/// One actor's record since the session started.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct ActorStats {
+    /// Calls that wrote an answer: drafts, refinements and syntheses.
+    pub answer_calls: u32,
+    /// How long those calls took, in milliseconds, together.
+    pub answer_ms: u64,
+    /// Calls that judged answers: evaluations, votes and routing.
+    pub evaluation_calls: u32,
+    /// How long those calls took, in milliseconds, together.
+    pub evaluation_ms: u64,
+    /// Evaluations that found an answer Good.
+    pub good: u32,
+    /// Evaluations that found an answer in need of refinement.
+    pub needs_refinement: u32,
+    /// Evaluations the actor abstained from, the question being outside its domain.
+    pub abstained: u32,
+    /// Rewrites of an answer the panel went on to evaluate.
+    pub refinements: u32,
+    /// Rewrites the panel reached consensus on.
+    pub refinements_agreed: u32,
+}
+
+impl ActorStats {
+    /// How long a call writing an answer takes on average, if the actor has made any.
+    pub fn mean_answer_latency(&self) -> Option<Duration> {
+        mean(self.answer_ms, self.answer_calls)
+    }
+
+    /// How long a call judging answers takes on average, if the actor has made any.
+    pub fn mean_evaluation_latency(&self) -> Option<Duration> {
+        mean(self.evaluation_ms, self.evaluation_calls)
+    }
+
+    /// The share of the actor's evaluations that found the answer Good, if it has evaluated any without abstaining.
+    pub fn good_share(&self) -> Option<f64> {
+        share(self.good, self.good + self.needs_refinement)
+    }
+
+    /// The share of the actor's rewrites the panel reached consensus on, if it has rewritten any.
+    pub fn refinement_success(&self) -> Option<f64> {
+        share(self.refinements_agreed, self.refinements)
+    }
+}
+
+fn mean(total_ms: u64, calls: u32) -> Option<Duration> {
+    (calls > 0).then(|| Duration::from_millis(total_ms / calls as u64))
+}
+
+fn share(part: u32, whole: u32) -> Option<f64> {
+    (whole > 0).then(|| part as f64 / whole as f64)
+}
+
+/// Keeps every actor's [ActorStats] for the session, by name.
+#[derive(Debug, Default)]
+pub struct StatsTracker {
+    actors: BTreeMap<String, ActorStats>,
+}
+
+impl StatsTracker {
+    /// Counts a provider call the actor made with the template, which took `latency`. Calls that neither write nor
+    /// judge an answer, such as suggestions, are not timed.
+    pub fn record_call(&mut self, actor: &str, template: Template, latency: Duration) {
+        let ms = latency.as_millis() as u64;
+        match template {
+            Template::Draft | Template::Refine | Template::Synthesize => {
+                let stats = self.actor(actor);
+                stats.answer_calls += 1;
+                stats.answer_ms += ms;
+            },
+            Template::Route | Template::Vote | Template::BinaryEvaluation | Template::ScoredEvaluation | Template::Decide => {
+                let stats = self.actor(actor);
+                stats.evaluation_calls += 1;
+                stats.evaluation_ms += ms;
+            },
+            Template::Persona | Template::Suggest | Template::Summarize | Template::Moderate | Template::FactCheck => {},
+        }
+    }
+
+    /// Counts the actor's verdict on an answer.
+    pub fn record_verdict(&mut self, actor: &str, feedback: Feedback) {
+        let stats = self.actor(actor);
+        match feedback {
+            Feedback::Good => stats.good += 1,
+            Feedback::NeedsRefinement => stats.needs_refinement += 1,
+            Feedback::Abstain => stats.abstained += 1,
+        }
+    }
+
+    /// Counts a rewrite by the actor that the panel went on to evaluate.
+    pub fn record_refinement(&mut self, actor: &str) {
+        self.actor(actor).refinements += 1;
+    }
+
+    /// Counts a rewrite by the actor that the panel reached consensus on.
+    pub fn record_agreed(&mut self, actor: &str) {
+        self.actor(actor).refinements_agreed += 1;
+    }
+
+    /// Every actor's stats, by name, including actors that have since left the panel.
+    pub fn actors(&self) -> &BTreeMap<String, ActorStats> {
+        &self.actors
+    }
+
+    fn actor(&mut self, actor: &str) -> &mut ActorStats {
+        self.actors.entry(actor.to_string()).or_default()
+    }
+}
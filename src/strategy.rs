@@ -0,0 +1,611 @@
+//! Rules the [Coordinator](crate::Coordinator) uses to draft answers and decide whether the panel agrees.
+
+use std::{fmt, str::FromStr, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{budget::BudgetSettings, constraints::AnswerConstraints, deadlock::DeadlockSettings, result::Feedback, selection::SelectionSettings};
+
+/// How a round of evaluations is turned into a consensus decision.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ConsensusStrategy {
+    /// Every actor must evaluate the answer as Good.
+    #[default]
+    Unanimous,
+    /// More than half of the actors must evaluate the answer as Good.
+    Majority,
+    /// At least `fraction` (between 0 and 1) of the actors must evaluate the answer as Good.
+    SuperMajority { fraction: f64 },
+    /// The weighted share of Good votes must reach `threshold` (between 0 and 1).
+    Weighted { threshold: f64 },
+    /// Evaluators score the answer from 1 to 10, and the `aggregate` of the scores must reach `threshold`.
+    Scored {
+        threshold: f64,
+        #[serde(default)]
+        aggregate: ScoreAggregate,
+    },
+}
+
+/// The kind of a [ConsensusStrategy], without its parameters, named as in its `kind` field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StrategyKind {
+    Unanimous,
+    Majority,
+    SuperMajority,
+    Weighted,
+    Scored,
+}
+
+/// How the first answer to a question is drafted.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum DraftMode {
+    /// A single actor, picked by the [SelectionPolicy](crate::selection::SelectionPolicy), drafts the answer.
+    #[default]
+    Single,
+    /// `candidates` actors draft answers in parallel and the panel votes on which one to evaluate.
+    BestOfN { candidates: usize },
+    /// The `router` actor reads every actor's domain and picks the one best suited to the question, who drafts
+    /// the answer. Without a router, an actor picked by the selection policy routes the question. If the router
+    /// names nobody on the panel, the selection policy picks the drafter instead.
+    Routed {
+        #[serde(default)]
+        router: Option<String>,
+    },
+    /// A single actor, picked like a [DraftMode::Single] one, writes several answers at a higher temperature, and
+    /// the one that agrees most with the others is drafted, so that the draft does not hinge on one lucky generation.
+    SelfConsistent(Sampling),
+}
+
+/// How many answers a self-consistent drafter writes, and how randomly.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Sampling {
+    pub samples: usize,
+    /// The temperature each answer is written at, in place of the actor's own.
+    pub temperature: f32,
+}
+
+impl Default for Sampling {
+    fn default() -> Self {
+        Sampling { samples: 5, temperature: 1.0 }
+    }
+}
+
+/// How an answer that did not reach consensus is refined.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum RefinementMode {
+    /// A dissenting actor, picked by the [SelectionPolicy](crate::selection::SelectionPolicy), rewrites the answer alone.
+    /// Dissenters that have not yet refined the answer are picked first, so that they take turns.
+    #[default]
+    Single,
+    /// Every dissenting actor suggests changes, and the `synthesizer` actor merges them into one
+    /// revised answer. Without a synthesizer, a dissenter picked by the selection policy merges them, taking turns
+    /// like under [RefinementMode::Single].
+    Synthesize {
+        #[serde(default)]
+        synthesizer: Option<String>,
+    },
+    /// Every dissenting actor rewrites the answer, and the rewrites are combined as `combine` says.
+    Every {
+        #[serde(default)]
+        combine: RefinementCombine,
+    },
+}
+
+/// How the rewrites are combined under [RefinementMode::Every].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RefinementCombine {
+    /// The dissenters take turns, in the order the selection policy picks them, each rewriting the
+    /// version the one before them wrote.
+    #[default]
+    Chain,
+    /// The dissenters each rewrite the answer at once, and the panel votes for the rewrite to evaluate.
+    Vote,
+}
+
+/// Session-wide settings for the [Coordinator](crate::Coordinator).
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ConsensusSettings {
+    #[serde(default)]
+    pub strategy: ConsensusStrategy,
+    #[serde(default)]
+    pub draft: DraftMode,
+    #[serde(default)]
+    pub refinement: RefinementMode,
+    /// Show each evaluator the other actors' reasoning from the previous round.
+    #[serde(default)]
+    pub debate: bool,
+    /// Leave the actor that wrote each version of the answer out of evaluating it, so that the strategy decides
+    /// among the rest of the panel: under every strategy, or only under some.
+    #[serde(default)]
+    pub exclude_author: ExcludeAuthor,
+    /// Hide which actor wrote each answer from the actors judging it, by stripping hints of the author from the
+    /// answers they evaluate or vote on and asking them in a random order.
+    #[serde(default)]
+    pub blind: bool,
+    /// Decide each round of evaluations as soon as the verdicts in settle it under the strategy, however the rest
+    /// would go, and stop waiting for the rest.
+    #[serde(default)]
+    pub early_decision: bool,
+    #[serde(default)]
+    pub timeouts: StageTimeouts,
+    /// How many times the panel evaluates an answer before the latest version is accepted without consensus.
+    #[serde(default = "default_max_rounds")]
+    pub max_rounds: u32,
+    /// The fewest actors the panel takes questions with.
+    #[serde(default = "default_min_actors")]
+    pub min_actors: usize,
+    /// The most questions the panel deliberates on at once. Questions asked while it is at the limit wait in a
+    /// queue and are taken up in the order they were asked. Unset, there is no limit.
+    #[serde(default)]
+    pub max_concurrent_questions: Option<usize>,
+    /// How the actors that draft, refine and synthesize are picked.
+    #[serde(default)]
+    pub selection: SelectionSettings,
+    #[serde(default)]
+    pub devils_advocate: DevilsAdvocateSettings,
+    /// The length and format every answer is held to.
+    #[serde(default)]
+    pub constraints: AnswerConstraints,
+    /// What each question, and the session, may spend.
+    #[serde(default)]
+    pub budget: BudgetSettings,
+    /// How the panel notices it is going in circles and breaks the tie, if it does.
+    #[serde(default)]
+    pub deadlock: Option<DeadlockSettings>,
+}
+
+/// The strategies under which the author of each version of the answer sits out evaluating it.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum ExcludeAuthor {
+    /// Under every strategy, or under none.
+    Always(bool),
+    /// Only under the strategies of these kinds, such as `majority`, whatever their parameters.
+    Under(Vec<StrategyKind>),
+}
+
+impl Default for ExcludeAuthor {
+    fn default() -> Self {
+        ExcludeAuthor::Always(false)
+    }
+}
+
+impl ExcludeAuthor {
+    /// Whether the author sits out under the `strategy`.
+    pub fn applies_to(&self, strategy: &ConsensusStrategy) -> bool {
+        match self {
+            ExcludeAuthor::Always(exclude) => *exclude,
+            ExcludeAuthor::Under(kinds) => kinds.contains(&strategy.kind()),
+        }
+    }
+}
+
+/// The `[devils_advocate]` section of the config file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DevilsAdvocateSettings {
+    /// How many rounds of evaluation a devil's advocate's vote counts towards consensus in. In later rounds the
+    /// rest of the panel decides without it. 0 makes its vote advisory from the start.
+    pub blocking_rounds: u32,
+}
+
+impl Default for DevilsAdvocateSettings {
+    fn default() -> Self {
+        DevilsAdvocateSettings { blocking_rounds: 1 }
+    }
+}
+
+fn default_max_rounds() -> u32 {
+    5
+}
+
+fn default_min_actors() -> usize {
+    1
+}
+
+impl Default for ConsensusSettings {
+    fn default() -> Self {
+        ConsensusSettings {
+            strategy: ConsensusStrategy::default(),
+            draft: DraftMode::default(),
+            refinement: RefinementMode::default(),
+            debate: false,
+            exclude_author: ExcludeAuthor::default(),
+            blind: false,
+            early_decision: false,
+            timeouts: StageTimeouts::default(),
+            max_rounds: default_max_rounds(),
+            min_actors: default_min_actors(),
+            max_concurrent_questions: None,
+            selection: SelectionSettings::default(),
+            devils_advocate: DevilsAdvocateSettings::default(),
+            constraints: AnswerConstraints::default(),
+            budget: BudgetSettings::default(),
+            deadlock: None,
+        }
+    }
+}
+
+/// A phase of the deliberation on one question.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    /// Waiting for the first answer, or for every best-of-N candidate.
+    Drafting,
+    /// Waiting for the panel to vote on the best-of-N candidates.
+    Voting,
+    /// Waiting for the moderator to screen the question and the answer about to be evaluated.
+    Moderating,
+    /// Waiting for the sandbox to run the code in the answer about to be evaluated.
+    Testing,
+    /// Waiting for the panel to evaluate the current answer.
+    Evaluating,
+    /// Waiting for the chair's guidance on refining the answer, or its ruling on which version to accept.
+    Chairing,
+    /// Waiting for a refined answer, including any suggestions and synthesis.
+    Refining,
+    /// Waiting for the asker's reviewer to approve the answer the panel agreed on.
+    Reviewing,
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stage::Drafting => write!(f, "drafting"),
+            Stage::Voting => write!(f, "voting"),
+            Stage::Moderating => write!(f, "moderation"),
+            Stage::Testing => write!(f, "testing"),
+            Stage::Evaluating => write!(f, "evaluation"),
+            Stage::Chairing => write!(f, "chairing"),
+            Stage::Refining => write!(f, "refinement"),
+            Stage::Reviewing => write!(f, "review"),
+        }
+    }
+}
+
+/// How long each stage may take before the [Coordinator](crate::Coordinator)'s watchdog steps in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct StageTimeouts {
+    /// Limit for drafting the first answer.
+    pub draft_secs: u64,
+    /// Limit for a round of evaluations, for screening and running the answer before it, and for voting on
+    /// best-of-N candidates.
+    pub evaluation_secs: u64,
+    /// Limit for refining the answer.
+    pub refinement_secs: u64,
+    /// How many times a stalled stage is sent again to the actors that have not responded before the
+    /// question fails. 0 fails the question on its first timeout. A stage an actor was restarted during counts
+    /// as stalled.
+    pub redispatches: u32,
+}
+
+impl Default for StageTimeouts {
+    fn default() -> Self {
+        StageTimeouts { draft_secs: 180, evaluation_secs: 180, refinement_secs: 180, redispatches: 1 }
+    }
+}
+
+impl StageTimeouts {
+    /// How long the stage may take.
+    pub fn limit(&self, stage: Stage) -> Duration {
+        Duration::from_secs(match stage {
+            Stage::Drafting => self.draft_secs,
+            Stage::Voting | Stage::Moderating | Stage::Testing | Stage::Evaluating => self.evaluation_secs,
+            Stage::Chairing | Stage::Refining => self.refinement_secs,
+            // A person reviewing the answer is never hurried.
+            Stage::Reviewing => return Duration::MAX,
+        })
+    }
+}
+
+impl ConsensusSettings {
+    /// Checks every setting, returning a description of the first problem found.
+    pub fn validate(&self) -> Result<(), String> {
+        self.strategy.validate()?;
+        match self.draft {
+            DraftMode::BestOfN { candidates: 0 } => return Err("best-of-N drafting needs at least one candidate".to_string()),
+            DraftMode::SelfConsistent(sampling) if sampling.samples < 2 => return Err("self-consistent drafting needs at least two samples".to_string()),
+            DraftMode::SelfConsistent(sampling) if !(0.0..=2.0).contains(&sampling.temperature) => {
+                return Err("the self-consistent sampling temperature must be between 0 and 2".to_string());
+            },
+            _ => {},
+        }
+        if self.timeouts.draft_secs == 0 || self.timeouts.evaluation_secs == 0 || self.timeouts.refinement_secs == 0 {
+            return Err("stage timeouts must be at least one second".to_string());
+        }
+        if self.max_rounds == 0 {
+            return Err("max_rounds must be at least 1".to_string());
+        }
+        if self.min_actors == 0 {
+            return Err("min_actors must be at least 1".to_string());
+        }
+        if self.max_concurrent_questions == Some(0) {
+            return Err("max_concurrent_questions must be at least 1".to_string());
+        }
+        self.constraints.validate()?;
+        self.budget.validate()?;
+        self.deadlock.as_ref().map_or(Ok(()), DeadlockSettings::validate)
+    }
+}
+
+/// How the evaluators' scores are combined under [ConsensusStrategy::Scored].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScoreAggregate {
+    #[default]
+    Mean,
+    Min,
+}
+
+/// What kind of verdict evaluators are asked for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EvaluationMode {
+    /// Good, NeedsRefinement or Abstain.
+    Binary,
+    /// A score from 1 to 10, or an abstention. Scores below `threshold` count as NeedsRefinement.
+    Scored { threshold: f64 },
+}
+
+/// One actor's evaluation, as seen by a [ConsensusStrategy].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Vote {
+    pub feedback: Feedback,
+    pub weight: f64,
+    /// The actor's 1 to 10 score, when evaluating in [EvaluationMode::Scored].
+    pub score: Option<u8>,
+}
+
+impl ConsensusStrategy {
+    /// The strategy's kind, without its parameters.
+    pub fn kind(&self) -> StrategyKind {
+        match self {
+            ConsensusStrategy::Unanimous => StrategyKind::Unanimous,
+            ConsensusStrategy::Majority => StrategyKind::Majority,
+            ConsensusStrategy::SuperMajority { .. } => StrategyKind::SuperMajority,
+            ConsensusStrategy::Weighted { .. } => StrategyKind::Weighted,
+            ConsensusStrategy::Scored { .. } => StrategyKind::Scored,
+        }
+    }
+
+    /// Checks the strategy's parameters, returning a description of the problem if they are unusable.
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            ConsensusStrategy::Unanimous | ConsensusStrategy::Majority => Ok(()),
+            ConsensusStrategy::SuperMajority { fraction } if *fraction > 0.5 && *fraction <= 1.0 => Ok(()),
+            ConsensusStrategy::SuperMajority { fraction } => Err(format!("super-majority fraction must be in (0.5, 1], got {}", fraction)),
+            ConsensusStrategy::Weighted { threshold } if *threshold > 0.0 && *threshold <= 1.0 => Ok(()),
+            ConsensusStrategy::Weighted { threshold } => Err(format!("weighted threshold must be in (0, 1], got {}", threshold)),
+            ConsensusStrategy::Scored { threshold, .. } if (1.0..=10.0).contains(threshold) => Ok(()),
+            ConsensusStrategy::Scored { threshold, .. } => Err(format!("score threshold must be in [1, 10], got {}", threshold)),
+        }
+    }
+
+    /// The kind of verdict evaluators should give under this strategy.
+    pub fn evaluation_mode(&self) -> EvaluationMode {
+        match self {
+            ConsensusStrategy::Scored { threshold, .. } => EvaluationMode::Scored { threshold: *threshold },
+            _ => EvaluationMode::Binary,
+        }
+    }
+
+    /// Decides a round before every vote is in: Some of the outcome once the votes still to come, of the given
+    /// weights, could not change it however they went, or None while they could.
+    pub fn decided(&self, votes: &[Vote], pending: &[f64]) -> Option<bool> {
+        let with_pending = |feedback, score| -> Vec<Vote> {
+            votes.iter().copied().chain(pending.iter().map(|&weight| Vote { feedback, weight, score: Some(score) })).collect()
+        };
+        let best = self.is_reached(&with_pending(Feedback::Good, 10));
+        let worst = self.is_reached(&with_pending(Feedback::NeedsRefinement, 1));
+        (best == worst).then_some(best)
+    }
+
+    /// Decides whether a complete round of votes reaches consensus. Abstentions are left out, so that consensus is
+    /// decided among the actors that gave a verdict, and a round without votes, or in which every actor abstained,
+    /// reaches none.
+    pub fn is_reached(&self, votes: &[Vote]) -> bool {
+        if all_abstained(votes) {
+            return false;
+        }
+        let votes: Vec<Vote> = votes.iter().copied().filter(|vote| vote.feedback != Feedback::Abstain).collect();
+        let votes = votes.as_slice();
+        match self {
+            ConsensusStrategy::Unanimous => !votes.is_empty() && votes.iter().all(|vote| vote.feedback == Feedback::Good),
+            ConsensusStrategy::Majority => good_votes(votes) * 2 > votes.len(),
+            ConsensusStrategy::SuperMajority { fraction } => {
+                !votes.is_empty() && good_votes(votes) as f64 / votes.len() as f64 >= *fraction
+            }
+            ConsensusStrategy::Weighted { threshold } => {
+                let total: f64 = votes.iter().map(|vote| vote.weight).sum();
+                let good: f64 = votes.iter()
+                    .filter(|vote| vote.feedback == Feedback::Good)
+                    .map(|vote| vote.weight)
+                    .sum();
+                total > 0.0 && good / total >= *threshold
+            }
+            ConsensusStrategy::Scored { threshold, aggregate } => {
+                // Evaluators that did not give a score count as the lowest or highest score by their verdict.
+                let scores: Vec<f64> = votes.iter()
+                    .map(|vote| match (vote.score, vote.feedback) {
+                        (Some(score), _) => score as f64,
+                        (None, Feedback::Good) => 10.0,
+                        (None, _) => 1.0,
+                    })
+                    .collect();
+                if scores.is_empty() {
+                    return false;
+                }
+                let combined = match aggregate {
+                    ScoreAggregate::Mean => scores.iter().sum::<f64>() / scores.len() as f64,
+                    ScoreAggregate::Min => scores.iter().copied().fold(f64::INFINITY, f64::min),
+                };
+                combined >= *threshold
+            }
+        }
+    }
+}
+
+impl FromStr for ConsensusStrategy {
+    type Err = String;
+
+    /// Parses the compact form used on the command line: `unanimous`, `majority`, `super_majority:<fraction>`,
+    /// `weighted:<threshold>` or `scored:<threshold>[:min|mean]`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(':');
+        let kind = parts.next().unwrap_or_default().to_lowercase().replace('-', "_");
+        let mut number = |name: &str| -> Result<f64, String> {
+            let value = parts.next().ok_or_else(|| format!("the {} strategy needs a {}, e.g. {}:0.75", kind, name, kind))?;
+            value.parse().map_err(|_| format!("\"{}\" is not a valid {}", value, name))
+        };
+        let strategy = match kind.as_str() {
+            "unanimous" => ConsensusStrategy::Unanimous,
+            "majority" => ConsensusStrategy::Majority,
+            "super_majority" => ConsensusStrategy::SuperMajority { fraction: number("fraction")? },
+            "weighted" => ConsensusStrategy::Weighted { threshold: number("threshold")? },
+            "scored" => {
+                let threshold = number("threshold")?;
+                let aggregate = match parts.next() {
+                    None | Some("mean") => ScoreAggregate::Mean,
+                    Some("min") => ScoreAggregate::Min,
+                    Some(other) => return Err(format!("unknown score aggregate \"{}\"", other)),
+                };
+                ConsensusStrategy::Scored { threshold, aggregate }
+            },
+            other => return Err(format!("unknown strategy \"{}\"", other)),
+        };
+        if parts.next().is_some() {
+            return Err(format!("too many parameters for the {} strategy", kind));
+        }
+        strategy.validate()?;
+        Ok(strategy)
+    }
+}
+
+impl fmt::Display for ConsensusStrategy {
+    /// Writes the compact form [FromStr] parses.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConsensusStrategy::Unanimous => write!(f, "unanimous"),
+            ConsensusStrategy::Majority => write!(f, "majority"),
+            ConsensusStrategy::SuperMajority { fraction } => write!(f, "super_majority:{}", fraction),
+            ConsensusStrategy::Weighted { threshold } => write!(f, "weighted:{}", threshold),
+            ConsensusStrategy::Scored { threshold, aggregate: ScoreAggregate::Mean } => write!(f, "scored:{}:mean", threshold),
+            ConsensusStrategy::Scored { threshold, aggregate: ScoreAggregate::Min } => write!(f, "scored:{}:min", threshold),
+        }
+    }
+}
+
+/// Whether every actor that voted abstained, leaving nobody on the panel to decide the round.
+pub fn all_abstained(votes: &[Vote]) -> bool {
+    !votes.is_empty() && votes.iter().all(|vote| vote.feedback == Feedback::Abstain)
+}
+
+fn good_votes(votes: &[Vote]) -> usize {
+    votes.iter().filter(|vote| vote.feedback == Feedback::Good).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vote(feedback: Feedback, weight: f64) -> Vote {
+        Vote { feedback, weight, score: None }
+    }
+
+    fn good() -> Vote {
+        vote(Feedback::Good, 1.0)
+    }
+
+    fn needs_refinement() -> Vote {
+        vote(Feedback::NeedsRefinement, 1.0)
+    }
+
+    fn abstain() -> Vote {
+        vote(Feedback::Abstain, 1.0)
+    }
+
+    const STRATEGIES: [ConsensusStrategy; 5] = [
+        ConsensusStrategy::Unanimous,
+        ConsensusStrategy::Majority,
+        ConsensusStrategy::SuperMajority { fraction: 0.75 },
+        ConsensusStrategy::Weighted { threshold: 0.6 },
+        ConsensusStrategy::Scored { threshold: 7.0, aggregate: ScoreAggregate::Mean },
+    ];
+
+    #[test]
+    fn no_strategy_is_reached_without_votes() {
+        for strategy in STRATEGIES {
+            assert!(!strategy.is_reached(&[]), "{} reached consensus without votes", strategy);
+            assert!(!strategy.is_reached(&[abstain(), abstain()]), "{} reached consensus on abstentions", strategy);
+        }
+    }
+
+    #[test]
+    fn every_strategy_is_reached_when_all_agree() {
+        for strategy in STRATEGIES {
+            assert!(strategy.is_reached(&[good(), good(), abstain()]), "{} missed a unanimous panel", strategy);
+        }
+    }
+
+    #[test]
+    fn unanimous_needs_every_verdict() {
+        assert!(!ConsensusStrategy::Unanimous.is_reached(&[good(), good(), needs_refinement()]));
+    }
+
+    #[test]
+    fn a_tied_majority_is_not_reached() {
+        assert!(!ConsensusStrategy::Majority.is_reached(&[good(), needs_refinement()]));
+        assert!(ConsensusStrategy::Majority.is_reached(&[good(), good(), needs_refinement()]));
+    }
+
+    #[test]
+    fn super_majority_needs_its_fraction() {
+        let strategy = ConsensusStrategy::SuperMajority { fraction: 0.75 };
+        assert!(!strategy.is_reached(&[good(), good(), needs_refinement()]));
+        assert!(strategy.is_reached(&[good(), good(), good(), needs_refinement()]));
+    }
+
+    #[test]
+    fn weighted_counts_weights_not_heads() {
+        let strategy = ConsensusStrategy::Weighted { threshold: 0.6 };
+        assert!(strategy.is_reached(&[vote(Feedback::Good, 3.0), needs_refinement(), needs_refinement()]));
+        assert!(!strategy.is_reached(&[good(), good(), vote(Feedback::NeedsRefinement, 3.0)]));
+        // A tie in weight falls short of a threshold above half.
+        assert!(!strategy.is_reached(&[vote(Feedback::Good, 2.0), vote(Feedback::NeedsRefinement, 2.0)]));
+    }
+
+    fn scored(score: u8) -> Vote {
+        let feedback = if score >= 7 { Feedback::Good } else { Feedback::NeedsRefinement };
+        Vote { feedback, weight: 1.0, score: Some(score) }
+    }
+
+    const SCORED: ConsensusStrategy = ConsensusStrategy::Scored { threshold: 7.0, aggregate: ScoreAggregate::Mean };
+
+    #[test]
+    fn scores_that_reach_the_threshold_reach_consensus() {
+        assert!(SCORED.is_reached(&[scored(9), scored(6)]));
+        assert_eq!(SCORED.decided(&[scored(9), scored(6)], &[]), Some(true));
+    }
+
+    #[test]
+    fn scores_below_the_threshold_do_not() {
+        assert!(!SCORED.is_reached(&[scored(8), scored(5)]));
+        assert_eq!(SCORED.decided(&[scored(8), scored(5)], &[]), Some(false));
+        let min = ConsensusStrategy::Scored { threshold: 7.0, aggregate: ScoreAggregate::Min };
+        assert!(!min.is_reached(&[scored(10), scored(6)]));
+    }
+
+    #[test]
+    fn scores_decide_a_round_once_the_rest_could_not_change_it() {
+        // Whatever the third evaluator scores, a mean of 7 is out of reach.
+        assert_eq!(SCORED.decided(&[scored(2), scored(3)], &[1.0]), Some(false));
+        // A 10 from the third would bring the mean to 7, a 1 would leave it at 4.
+        assert_eq!(SCORED.decided(&[scored(5), scored(6)], &[1.0]), None);
+    }
+}
@@ -0,0 +1,458 @@
+use std::{collections::BTreeMap, fmt, sync::{Arc, RwLock}};
+
+use actix::prelude::*;
+use tracing::warn;
+
+use crate::{
+    actors::LlmActor,
+    answer_cache::AnswerCache,
+    config::{ActorConfig, Config, ConfigError},
+    convergence::Convergence,
+    coordinator::Coordinator,
+    error::ConsensusError,
+    history::{History, HistoryRecorder},
+    moderation::Moderation,
+    observer::ConsensusObserver,
+    messages::{ActorHealth, ActorInfo, AnswerToken, AskQuestion, CancelQuestion, Configure, DeliberationUpdate, DetectConvergence, GetActorStats, GetAnswerHistory, GetUsage, HealthCheck, ListActors, ModerateContent, NumberQuestionsAfter, Observe, QuestionId, QuestionOptions, RecordHistory, RecordTranscript, Register, RegisterAll, ReloadPanel, Shutdown, Subscribe, SubscribeTokens, TestCode, Unregister},
+    prompts::Prompts,
+    provider::{CredentialsConfig, Limiter, LlmProvider, ProviderError, ResponseCache, RetryPolicy},
+    result::{ConsensusResult, Round},
+    retrieval::{Retriever, VectorStore},
+    sandbox::Sandbox,
+    search::WebSearch,
+    stats::ActorStats,
+    strategy::ConsensusSettings,
+    transcript::Transcript,
+    usage::{Pricing, UsageSummary},
+};
+
+/// Errors raised by [ConsensusSystem::ask].
+#[derive(Debug)]
+pub enum AskError {
+    /// The [Coordinator] could not be reached.
+    Mailbox(MailboxError),
+    /// The question was dropped before the panel reached consensus, e.g. by a [crate::messages::Reset].
+    Abandoned,
+    /// The panel could not answer the question, or could not take it up at all.
+    Failed(ConsensusError),
+    /// The question was cancelled with a [crate::messages::CancelQuestion] or by shutting down.
+    Cancelled,
+    /// The session's budget was already overrun, so the question was not taken.
+    BudgetExceeded(String),
+}
+
+impl fmt::Display for AskError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AskError::Mailbox(e) => write!(f, "unable to reach the Coordinator: {}", e),
+            AskError::Abandoned => write!(f, "the question was abandoned before the panel reached consensus"),
+            AskError::Failed(e) => write!(f, "{}", e),
+            AskError::Cancelled => write!(f, "the question was cancelled"),
+            AskError::BudgetExceeded(reason) => write!(f, "the session's budget is spent, at {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for AskError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AskError::Mailbox(e) => Some(e),
+            AskError::Failed(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<MailboxError> for AskError {
+    fn from(e: MailboxError) -> Self {
+        AskError::Mailbox(e)
+    }
+}
+
+impl From<ConsensusError> for AskError {
+    fn from(e: ConsensusError) -> Self {
+        AskError::Failed(e)
+    }
+}
+
+/// Handle to the consensus engine running in the current actix system.
+///
+/// Must be created and used from within a running actix `System`.
+#[derive(Clone)]
+pub struct ConsensusSystem {
+    coordinator: Addr<Coordinator>,
+    /// The API keys the providers of actors added with [ConsensusSystem::add_actor] use.
+    credentials: Arc<CredentialsConfig>,
+    /// How the providers of actors added with [ConsensusSystem::add_actor] retry failed calls.
+    retry: RetryPolicy,
+    /// Shared by the providers of actors added with [ConsensusSystem::add_actor].
+    cache: Option<Arc<ResponseCache>>,
+    /// Holds the providers of actors added with [ConsensusSystem::add_actor] to the configured request limits.
+    limiter: Option<Arc<Limiter>>,
+    /// The templates actors added with [ConsensusSystem::add_actor] render their prompts from, which
+    /// [ConsensusSystem::reload] replaces.
+    prompts: Arc<RwLock<Arc<Prompts>>>,
+    /// The web search fact checkers added with [ConsensusSystem::add_actor] check answers' claims with, if any.
+    search: Option<WebSearch>,
+    /// Finds the passages of the corpus relevant to each question, if there is one.
+    retriever: Option<Retriever>,
+    /// Finds the answers of past runs to questions meaning nearly the same as each one, if they are reused.
+    answer_cache: Option<Arc<AnswerCache>>,
+}
+
+impl Default for ConsensusSystem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ConsensusSystem {
+    /// Connects to the system's [Coordinator] without registering any actors.
+    pub fn new() -> Self {
+        ConsensusSystem { coordinator: Coordinator::from_registry(), credentials: Arc::default(), retry: RetryPolicy::default(), cache: None, limiter: None, prompts: Arc::new(RwLock::new(Prompts::built_in())), search: None, retriever: None, answer_cache: None }
+    }
+
+    /// Starts an [LlmActor] for every actor in the config with the configured keys, registers it with the
+    /// [Coordinator], applies the configured settings and opens the configured response cache, request limits,
+    /// prompts, web search, convergence detection, moderation, code sandbox, retrieval, transcript, history and answer
+    /// cache. Resolves
+    /// once every actor is registered, so that no question reaches a panel still being assembled.
+    pub async fn from_config(config: &Config) -> Result<Self, ConfigError> {
+        let cache = match &config.cache {
+            Some(cache_config) => {
+                let cache = ResponseCache::open(cache_config).map_err(|source| ConfigError::Io {
+                    path: cache_config.directory.clone().unwrap_or_default(),
+                    source,
+                })?;
+                Some(Arc::new(cache))
+            },
+            None => None,
+        };
+        let prompts = load_prompts(config)?;
+        let limiter = config.limits.as_ref().map(|limits| Arc::new(Limiter::new(limits)));
+        let search = match &config.search {
+            Some(search_config) => Some(WebSearch::from_config(search_config, &config.credentials)
+                .map_err(|source| ConfigError::Provider { actor: "search".to_string(), source })?),
+            None => None,
+        };
+        let mut system = ConsensusSystem { coordinator: Coordinator::from_registry(), credentials: Arc::new(config.credentials.clone()), retry: config.retry, cache, limiter, prompts: Arc::new(RwLock::new(prompts.clone())), search, retriever: None, answer_cache: None };
+        let mut actors = Vec::with_capacity(config.actors.len());
+        for actor_config in &config.actors {
+            let actor = system.build_actor(actor_config, prompts.clone())
+                .map_err(|source| ConfigError::Provider { actor: actor_config.name.clone(), source })?;
+            actors.push((actor_config, actor));
+        }
+        system.register_all(actors).await.map_err(ConfigError::Registration)?;
+        system.configure(config.settings.clone());
+        if let Some(convergence_config) = &config.convergence {
+            let convergence = Convergence::from_config(convergence_config)
+                .map_err(|source| ConfigError::Provider { actor: "convergence detection".to_string(), source })?;
+            system.detect_convergence(Some(convergence));
+        }
+        if let Some(moderation_config) = &config.moderation {
+            let moderation = Moderation::from_config(moderation_config, &system.credentials, &system.retry, system.limiter.as_ref(), prompts)
+                .map_err(|source| ConfigError::Provider { actor: "moderation".to_string(), source })?;
+            system.moderate(Some(moderation));
+        }
+        if let Some(sandbox_config) = &config.sandbox {
+            system.run_code(Some(Sandbox::new(sandbox_config.clone())));
+        }
+        if let Some(retrieval_config) = &config.retrieval {
+            let store = VectorStore::open(&retrieval_config.store)
+                .map_err(|source| ConfigError::VectorStore { path: retrieval_config.store.clone(), source })?;
+            let embedder = retrieval_config.embedding.build()
+                .map_err(|source| ConfigError::Provider { actor: "retrieval".to_string(), source })?;
+            system.retrieve(Some(Retriever::start(retrieval_config, config.documents.chunk_chars, store, embedder)));
+        }
+        if let Some(transcript_config) = &config.transcript {
+            let transcript = Transcript::open(transcript_config)
+                .map_err(|source| ConfigError::Io { path: transcript_config.directory.clone(), source })?;
+            system.record_transcript(Some(transcript));
+        }
+        if let Some(history_config) = &config.history {
+            let history = History::open(&history_config.path)
+                .map_err(|source| ConfigError::History { path: history_config.path.clone(), source })?;
+            system.record_history(Some(HistoryRecorder::new(history)));
+            if let Some(answer_cache_config) = &history_config.answer_cache {
+                let history = History::open(&history_config.path)
+                    .map_err(|source| ConfigError::History { path: history_config.path.clone(), source })?;
+                let embedder = answer_cache_config.embedding.build()
+                    .map_err(|source| ConfigError::Provider { actor: "answer cache".to_string(), source })?;
+                system.reuse_answers(Some(AnswerCache::new(answer_cache_config, history, embedder)));
+            }
+        }
+        Ok(system)
+    }
+
+    /// Starts the actor and adds it to the panel under the given name with the given voting weight
+    /// and the prices its token usage is costed at.
+    pub fn register(&self, name: String, actor: LlmActor, weight: f64, pricing: Pricing) {
+        self.coordinator.do_send(registration(name, actor, weight, pricing));
+    }
+
+    /// Starts each actor and adds it to the panel with the voting weight and prices of its config entry,
+    /// resolving with how many actors the panel has once they are all registered.
+    async fn register_all(&self, actors: Vec<(&ActorConfig, LlmActor)>) -> Result<usize, MailboxError> {
+        let registrations = actors.into_iter()
+            .map(|(config, actor)| registration(config.name.clone(), actor, config.weight, config.pricing))
+            .collect();
+        self.coordinator.send(RegisterAll(registrations)).await
+    }
+
+    /// Builds an [LlmActor] from its config entry and adds it to the panel, replacing any actor with the same name.
+    /// Its provider uses the keys, retry policy, response cache and request limits the system was configured with, its
+    /// prompts the templates, and a fact checker the web search.
+    pub fn add_actor(&self, config: &ActorConfig) -> Result<(), ProviderError> {
+        let actor = self.build_actor(config, self.prompts())?;
+        self.register(config.name.clone(), actor, config.weight, config.pricing);
+        Ok(())
+    }
+
+    /// Builds an [LlmActor] from its config entry with the given templates and the keys, retry policy, response cache,
+    /// request limits and web search the system was configured with.
+    fn build_actor(&self, config: &ActorConfig, prompts: Arc<Prompts>) -> Result<LlmActor, ProviderError> {
+        let actor = LlmActor::from_config(config, &self.credentials, &self.retry, self.cache.as_ref(), self.limiter.as_ref())?.with_prompts(prompts);
+        Ok(match &self.search {
+            Some(search) => actor.with_search(search.clone()),
+            None => actor,
+        })
+    }
+
+    /// Like [ConsensusSystem::add_actor], but the actor answers through the given provider instead of the one its config
+    /// entry describes, such as a [ReplayProvider](crate::replay::ReplayProvider).
+    pub fn add_actor_with(&self, config: &ActorConfig, provider: Arc<dyn LlmProvider>) {
+        let actor = LlmActor::new(config.name.clone(), config.persona.clone(), provider)
+            .with_params(config.provider.params)
+            .with_role(config.role.unwrap_or_default())
+            .with_prompts(self.prompts());
+        let actor = match &self.search {
+            Some(search) => actor.with_search(search.clone()),
+            None => actor,
+        };
+        self.register(config.name.clone(), actor, config.weight, config.pricing);
+    }
+
+    /// Replaces every actor on the panel with the given ones and applies the given settings, such as those of a
+    /// [Profile](crate::config::Profile). Every new actor's provider is created before any actor is replaced, so the
+    /// panel is left as it was if one cannot be. Questions in flight are abandoned.
+    pub async fn replace_panel(&self, actors: &[ActorConfig], settings: ConsensusSettings) -> Result<(), ConfigError> {
+        let mut replacements = Vec::with_capacity(actors.len());
+        for config in actors {
+            let actor = self.build_actor(config, self.prompts()).map_err(|source| ConfigError::Provider { actor: config.name.clone(), source })?;
+            replacements.push((config, actor));
+        }
+        // Like registering, removing the old actors only fails if the Coordinator has stopped, along with the system.
+        for actor in self.actors().await.unwrap_or_default() {
+            let _ = self.unregister(actor.name).await;
+        }
+        let _ = self.register_all(replacements).await;
+        self.configure(settings);
+        Ok(())
+    }
+
+    /// Replaces the panel, its settings and the prompt templates with those of the config, such as when its file has
+    /// been edited, the way [ConsensusSystem::replace_panel] does, except that the questions in flight finish with
+    /// the panel they started with and questions asked in the meantime wait for the new one. The templates are loaded,
+    /// and every new actor's provider created, before anything is replaced, so the panel is left as it was if either
+    /// cannot be. The keys, response cache, request limits and the config's other sections keep what the system
+    /// started with.
+    pub async fn reload(&self, config: &Config) -> Result<(), ConfigError> {
+        let prompts = load_prompts(config)?;
+        let mut actors = Vec::with_capacity(config.actors.len());
+        for actor_config in &config.actors {
+            let actor = self.build_actor(actor_config, prompts.clone())
+                .map_err(|source| ConfigError::Provider { actor: actor_config.name.clone(), source })?;
+            actors.push((actor_config, actor));
+        }
+        *self.prompts.write().expect("the prompts lock should not be poisoned") = prompts;
+        let actors = actors.into_iter()
+            .map(|(actor_config, actor)| registration(actor_config.name.clone(), actor, actor_config.weight, actor_config.pricing))
+            .collect();
+        self.coordinator.send(ReloadPanel { actors, settings: config.settings.clone() }).await.map_err(ConfigError::Registration)
+    }
+
+    /// The templates actors added from now on render their prompts from.
+    fn prompts(&self) -> Arc<Prompts> {
+        self.prompts.read().expect("the prompts lock should not be poisoned").clone()
+    }
+
+    /// Removes the named actor from the panel, returning false if there is no such actor.
+    /// Questions in flight carry on without it.
+    pub async fn unregister(&self, name: impl Into<String>) -> Result<bool, MailboxError> {
+        self.coordinator.send(Unregister { name: name.into() }).await
+    }
+
+    /// The actors on the panel, by name.
+    pub async fn actors(&self) -> Result<Vec<ActorInfo>, MailboxError> {
+        self.coordinator.send(ListActors).await
+    }
+
+    /// How many actors are registered, counting every registration sent before it.
+    pub async fn registered(&self) -> Result<usize, MailboxError> {
+        Ok(self.actors().await?.len())
+    }
+
+    /// Checks that every actor on the panel can reach its models, with a tiny call to each, and returns how each
+    /// one fared, by name.
+    pub async fn check_health(&self) -> Result<Vec<ActorHealth>, MailboxError> {
+        self.coordinator.send(HealthCheck).await
+    }
+
+    /// Changes how the panel drafts answers and decides that it has reached consensus.
+    pub fn configure(&self, settings: ConsensusSettings) {
+        self.coordinator.do_send(Configure(settings));
+    }
+
+    /// Numbers the questions asked from now on after `last`, so that they do not take the ids of answers from an
+    /// earlier run, such as those of a resumed session.
+    pub fn number_questions_after(&self, last: QuestionId) {
+        self.coordinator.do_send(NumberQuestionsAfter(last));
+    }
+
+    /// Stops each subsequent question once a refinement is as similar to the version before it as the
+    /// [Convergence] requires, or never with `None`.
+    pub fn detect_convergence(&self, convergence: Option<Convergence>) {
+        self.coordinator.do_send(DetectConvergence(convergence));
+    }
+
+    /// Screens each subsequent question and every version of its answer with the [Moderation] before the panel
+    /// evaluates it, refusing flagged content, or stops screening with `None`.
+    pub fn moderate(&self, moderation: Option<Moderation>) {
+        self.coordinator.do_send(ModerateContent(moderation));
+    }
+
+    /// Runs the code blocks of every version of each subsequent question's answer in the [Sandbox] before the panel
+    /// evaluates it, quoting how they ran in the evaluation prompts, or stops running them with `None`.
+    pub fn run_code(&self, sandbox: Option<Sandbox>) {
+        self.coordinator.do_send(TestCode(sandbox));
+    }
+
+    /// Quotes the passages the [Retriever] finds for each subsequent question asked through this handle in its
+    /// prompts, or stops retrieving with `None`.
+    pub fn retrieve(&mut self, retriever: Option<Retriever>) {
+        self.retriever = retriever;
+    }
+
+    /// Answers each subsequent question asked through this handle with the [AnswerCache]'s answer to one meaning
+    /// nearly the same, if it has one, or stops reusing answers with `None`.
+    pub fn reuse_answers(&mut self, answer_cache: Option<AnswerCache>) {
+        self.answer_cache = answer_cache.map(Arc::new);
+    }
+
+    /// Records every subsequent deliberation to the transcript, or stops recording with `None`.
+    pub fn record_transcript(&self, transcript: Option<Transcript>) {
+        self.coordinator.do_send(RecordTranscript(transcript));
+    }
+
+    /// Starts the recorder and stores every subsequent run in its history, or stops recording with `None`.
+    pub fn record_history(&self, recorder: Option<HistoryRecorder>) {
+        self.coordinator.do_send(RecordHistory(recorder.map(Actor::start)));
+    }
+
+    /// Sends the recipient a [DeliberationUpdate] for every step the panel takes from now on.
+    pub fn subscribe(&self, recipient: Recipient<DeliberationUpdate>) {
+        self.coordinator.do_send(Subscribe(recipient));
+    }
+
+    /// Tells the observer about every draft, evaluation, refinement and final answer from now on.
+    pub fn observe(&self, observer: impl ConsensusObserver) {
+        self.coordinator.do_send(Observe(Box::new(observer)));
+    }
+
+    /// Sends the recipient each piece of the first answer and of every refinement as the actor's model writes it.
+    pub fn subscribe_tokens(&self, recipient: Recipient<AnswerToken>) {
+        self.coordinator.do_send(SubscribeTokens(recipient));
+    }
+
+    /// Every version so far of the answer to a question in flight, oldest first, with the evaluations each has
+    /// received. None once the question is over, when [ConsensusResult::rounds] has them all.
+    pub async fn answer_history(&self, question_id: QuestionId) -> Result<Option<Vec<Round>>, MailboxError> {
+        self.coordinator.send(GetAnswerHistory { question_id }).await
+    }
+
+    /// Version `round` of the answer to a question in flight, starting at 0 for the first draft, or the latest
+    /// version with None. None if the question is not in flight or has no such version yet.
+    pub async fn answer(&self, question_id: QuestionId, round: Option<usize>) -> Result<Option<String>, MailboxError> {
+        let history = self.answer_history(question_id).await?.unwrap_or_default();
+        let version = match round {
+            Some(round) => history.get(round),
+            None => history.last(),
+        };
+        Ok(version.map(|version| version.answer.clone()))
+    }
+
+    /// The tokens used and estimated cost of every provider call made so far this session.
+    pub async fn usage(&self) -> Result<UsageSummary, MailboxError> {
+        self.coordinator.send(GetUsage).await
+    }
+
+    /// How each actor has fared so far this session, by name: how long its calls take, how it votes and how often
+    /// the panel agrees on its rewrites.
+    pub async fn actor_stats(&self) -> Result<BTreeMap<String, ActorStats>, MailboxError> {
+        self.coordinator.send(GetActorStats).await
+    }
+
+    /// Asks the panel a question and waits until it reaches consensus.
+    pub async fn ask(&self, question: impl Into<String>) -> Result<ConsensusResult, AskError> {
+        self.ask_with(question, QuestionOptions::default()).await
+    }
+
+    /// Asks the panel a question with per-question overrides, such as a different strategy. With an [AnswerCache],
+    /// the answer to a question meaning nearly the same is given instead, unless the options ask for a fresh
+    /// deliberation or change what the answer is drafted from or held to. With a [Retriever], the passages of its
+    /// corpus most relevant to the question are added to the question's documents first; if they cannot be
+    /// retrieved, the question is asked without them.
+    pub async fn ask_with(&self, question: impl Into<String>, mut options: QuestionOptions) -> Result<ConsensusResult, AskError> {
+        let question = question.into();
+        let reusable = !options.fresh && options.context.is_empty() && options.documents.is_empty() && options.images.is_empty()
+            && options.actors.is_empty() && options.constraints.is_none() && options.instructions.is_none();
+        if let Some(answer_cache) = self.answer_cache.as_ref().filter(|_| reusable) {
+            match answer_cache.lookup(&question).await {
+                Ok(Some(result)) => return Ok(result),
+                Ok(None) => {},
+                Err(e) => warn!("Unable to look for an answer to a similar question, so the panel deliberates on it: {}", e),
+            }
+        }
+        if let Some(retriever) = &self.retriever {
+            match retriever.retrieve(&question).await {
+                Ok(excerpts) => options.documents.extend(excerpts),
+                Err(e) => warn!("Unable to retrieve passages for the question, which is asked without them: {}", e),
+            }
+        }
+        self.coordinator.send(AskQuestion { question, options }).await?
+    }
+
+    /// Stops the panel deliberating on the question and aborts its provider calls, so that its asker gets
+    /// [AskError::Cancelled]. Returns false if the question is not in flight.
+    pub async fn cancel(&self, question_id: QuestionId) -> Result<bool, MailboxError> {
+        self.coordinator.send(CancelQuestion { question_id: Some(question_id) }).await.map(|cancelled| cancelled > 0)
+    }
+
+    /// Cancels every question in flight, returning how many there were.
+    pub async fn cancel_all(&self) -> Result<usize, MailboxError> {
+        self.coordinator.send(CancelQuestion { question_id: None }).await
+    }
+
+    /// Cancels every question in flight and waits until the transcript and history have everything
+    /// recorded so far, after which the actor system can be stopped.
+    pub async fn shutdown(&self) -> Result<(), MailboxError> {
+        self.coordinator.send(Shutdown).await
+    }
+}
+
+/// Starts the actor under a [Supervisor], so that it is restarted if a call panics, and describes it to the
+/// [Coordinator].
+fn registration(name: String, actor: LlmActor, weight: f64, pricing: Pricing) -> Register {
+    let domain = actor.domain().to_string();
+    let role = actor.role();
+    let tools = actor.tools().to_vec();
+    Register { name, actor: Supervisor::start(|_| actor), domain, role, weight, pricing, tools }
+}
+
+/// The templates in the config's `[prompts]` directory, or the built-in ones if it has none.
+fn load_prompts(config: &Config) -> Result<Arc<Prompts>, ConfigError> {
+    match &config.prompts {
+        Some(prompts_config) => Ok(Arc::new(Prompts::load(prompts_config).map_err(ConfigError::Prompts)?)),
+        None => Ok(Prompts::built_in()),
+    }
+}
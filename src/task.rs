@@ -0,0 +1,134 @@
+//! The [Engine] run as a tokio task, which takes its questions over a channel the way the
+//! [Coordinator](crate::Coordinator) takes messages, for applications that want the panel as a long-running service
+//! without an actor framework in their dependency tree.
+//!
+//! [ConsensusTask::spawn] starts the task and returns a handle to it, which can be cloned and shared. Each question
+//! runs on a task of its own, so the panel deliberates on several at once, and one dropped before the panel answers
+//! it is cancelled.
+
+use std::{fmt, sync::Arc};
+
+use futures::channel::mpsc::UnboundedReceiver;
+use tokio::{sync::{mpsc, oneshot}, task::JoinSet};
+use tracing::debug;
+
+use crate::{engine::Engine, error::ConsensusError, result::{ConsensusResult, QuestionId}, transcript::TranscriptEvent};
+
+/// Errors raised by [ConsensusTask::ask].
+#[derive(Debug)]
+pub enum TaskError {
+    /// The task has stopped, so the question was not taken.
+    Stopped,
+    /// The panel could not answer the question, or could not take it up at all.
+    Failed(ConsensusError),
+    /// The task was shut down before the panel answered the question.
+    Cancelled,
+}
+
+impl fmt::Display for TaskError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TaskError::Stopped => write!(f, "the consensus task has stopped"),
+            TaskError::Failed(e) => write!(f, "{}", e),
+            TaskError::Cancelled => write!(f, "the question was cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for TaskError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TaskError::Failed(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// What the task is asked to do.
+enum Command {
+    Ask { question: String, reply: oneshot::Sender<Result<ConsensusResult, ConsensusError>> },
+    Subscribe { reply: oneshot::Sender<UnboundedReceiver<(QuestionId, TranscriptEvent)>> },
+    /// Cancels every question in flight and stops the task, replying once it has.
+    Shutdown { reply: oneshot::Sender<()> },
+}
+
+/// A handle to an [Engine] running as a tokio task.
+#[derive(Clone)]
+pub struct ConsensusTask {
+    commands: mpsc::UnboundedSender<Command>,
+}
+
+impl ConsensusTask {
+    /// Starts the engine on a task of its own. Must be called from within a tokio runtime.
+    pub fn spawn(engine: Engine) -> Self {
+        let (commands, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(run(Arc::new(engine), receiver));
+        ConsensusTask { commands }
+    }
+
+    /// Asks the panel the question, resolving with its answer.
+    pub async fn ask(&self, question: impl Into<String>) -> Result<ConsensusResult, TaskError> {
+        let (reply, answer) = oneshot::channel();
+        self.send(Command::Ask { question: question.into(), reply })?;
+        match answer.await {
+            Ok(Ok(result)) => Ok(result),
+            Ok(Err(e)) => Err(TaskError::Failed(e)),
+            Err(_) => Err(TaskError::Cancelled),
+        }
+    }
+
+    /// A channel receiving every step of every question asked from now on, with the question it belongs to.
+    pub async fn subscribe(&self) -> Result<UnboundedReceiver<(QuestionId, TranscriptEvent)>, TaskError> {
+        let (reply, subscription) = oneshot::channel();
+        self.send(Command::Subscribe { reply })?;
+        subscription.await.map_err(|_| TaskError::Stopped)
+    }
+
+    /// Cancels the questions in flight, whose askers get [TaskError::Cancelled], and stops the task. Resolves once
+    /// it has stopped, at once if it already had.
+    pub async fn shutdown(&self) {
+        let (reply, stopped) = oneshot::channel();
+        if self.send(Command::Shutdown { reply }).is_ok() {
+            let _ = stopped.await;
+        }
+    }
+
+    fn send(&self, command: Command) -> Result<(), TaskError> {
+        self.commands.send(command).map_err(|_| TaskError::Stopped)
+    }
+}
+
+/// Takes commands until told to shut down or every handle is dropped, starting a task for each question.
+async fn run(engine: Arc<Engine>, mut commands: mpsc::UnboundedReceiver<Command>) {
+    let mut questions = JoinSet::new();
+    loop {
+        let command = tokio::select! {
+            command = commands.recv() => command,
+            // Finished questions are reaped as they go, so that the set does not grow for the whole session.
+            Some(_) = questions.join_next() => continue,
+        };
+        match command {
+            Some(Command::Ask { question, mut reply }) => {
+                let engine = engine.clone();
+                questions.spawn(async move {
+                    tokio::select! {
+                        result = engine.ask(&question) => { let _ = reply.send(result); },
+                        // The asker stopped waiting, so the deliberation is dropped along with its provider calls.
+                        () = reply.closed() => debug!("Dropped a question whose asker stopped waiting for it."),
+                    }
+                });
+            },
+            Some(Command::Subscribe { reply }) => {
+                let _ = reply.send(engine.subscribe());
+            },
+            Some(Command::Shutdown { reply }) => {
+                debug!("Shutting down the consensus task with {} questions in flight.", questions.len());
+                questions.shutdown().await;
+                let _ = reply.send(());
+                return;
+            },
+            None => break,
+        }
+    }
+    questions.shutdown().await;
+}
@@ -0,0 +1,109 @@
+//! Where the log and the trace of each deliberation go.
+//!
+//! The log is written to stderr, filtered by `RUST_LOG` (errors only by default). Every question is traced
+//! in a span, with a child span per round and one per model call under it. Built with the `otel` feature, the
+//! spans are also exported over OTLP when `OTEL_EXPORTER_OTLP_ENDPOINT` is set, e.g. to
+//! `http://localhost:4318` for a local Jaeger.
+
+use std::{io::{self, IsTerminal}, sync::{atomic::{AtomicBool, Ordering}, OnceLock}};
+
+use tracing::{level_filters::LevelFilter, Level};
+use tracing_subscriber::{filter::{filter_fn, FilterExt}, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer};
+
+/// Whether the log is held back, while the TUI has the terminal.
+static SILENCED: AtomicBool = AtomicBool::new(false);
+
+/// The most verbose level `RUST_LOG` lets through.
+static LOG_LEVEL: OnceLock<LevelFilter> = OnceLock::new();
+
+/// Keeps the exporter running. Dropping it sends the spans that have not been exported yet.
+pub struct Telemetry {
+    #[cfg(feature = "otel")]
+    provider: Option<opentelemetry_sdk::trace::SdkTracerProvider>,
+}
+
+/// Installs the log and, if it is configured, the span exporter.
+pub fn init() -> Telemetry {
+    let filter = EnvFilter::builder().with_default_directive(LevelFilter::ERROR.into()).from_env_lossy();
+    let _ = LOG_LEVEL.set(filter.max_level_hint().unwrap_or(LevelFilter::TRACE));
+    let log = tracing_subscriber::fmt::layer()
+        .with_writer(io::stderr)
+        .with_ansi(io::stderr().is_terminal())
+        .with_filter(filter.and(filter_fn(|_| !SILENCED.load(Ordering::Relaxed))));
+    let registry = tracing_subscriber::registry().with(log);
+
+    #[cfg(feature = "otel")]
+    {
+        let (layer, provider) = match otel::layer() {
+            Some(Ok((layer, provider))) => (Some(layer), Some(provider)),
+            Some(Err(e)) => {
+                eprintln!("Unable to export traces: {}", e);
+                (None, None)
+            },
+            None => (None, None),
+        };
+        registry.with(layer).init();
+        Telemetry { provider }
+    }
+    #[cfg(not(feature = "otel"))]
+    {
+        registry.init();
+        Telemetry {}
+    }
+}
+
+/// Whether `RUST_LOG` lets messages at the level through, at least for some modules.
+pub fn log_enabled(level: Level) -> bool {
+    LOG_LEVEL.get().is_some_and(|max| *max >= level)
+}
+
+/// Holds back the log, or lets it through again. Spans are still exported.
+pub fn silence(silenced: bool) {
+    SILENCED.store(silenced, Ordering::Relaxed);
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        #[cfg(feature = "otel")]
+        if let Some(provider) = self.provider.take() {
+            if let Err(e) = provider.shutdown() {
+                eprintln!("Unable to export the last traces: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "otel")]
+mod otel {
+    use std::env;
+
+    use opentelemetry::trace::TracerProvider;
+    use opentelemetry_otlp::{ExporterBuildError, SpanExporter};
+    use opentelemetry_sdk::{trace::{SdkTracer, SdkTracerProvider}, Resource};
+    use tracing::{level_filters::LevelFilter, Subscriber};
+    use tracing_opentelemetry::OpenTelemetryLayer;
+    use tracing_subscriber::{filter::{Filtered, Targets}, registry::LookupSpan, Layer};
+
+    const SERVICE_NAME: &str = "llm-consensus";
+
+    /// The spans of the deliberations and the debug messages within them, as OpenTelemetry sees them.
+    type OtelLayer<S> = Filtered<OpenTelemetryLayer<S, SdkTracer>, Targets, S>;
+
+    /// A layer exporting spans to the collector at `OTEL_EXPORTER_OTLP_ENDPOINT`, or None if it is not set.
+    pub fn layer<S>() -> Option<Result<(OtelLayer<S>, SdkTracerProvider), ExporterBuildError>>
+    where
+        S: Subscriber + for<'span> LookupSpan<'span>,
+    {
+        env::var_os("OTEL_EXPORTER_OTLP_ENDPOINT")?;
+        Some(SpanExporter::builder().with_http().build().map(|exporter| {
+            let provider = SdkTracerProvider::builder()
+                .with_batch_exporter(exporter)
+                .with_resource(Resource::builder().with_service_name(SERVICE_NAME).build())
+                .build();
+            let layer = tracing_opentelemetry::layer()
+                .with_tracer(provider.tracer(SERVICE_NAME))
+                .with_filter(Targets::new().with_target("llm_consensus", LevelFilter::DEBUG));
+            (layer, provider)
+        }))
+    }
+}
@@ -0,0 +1,472 @@
+//! Tools the panel's actors may call while they answer and evaluate, for the arithmetic, conversions and date
+//! calculations models get wrong when they work them out in their heads.
+//!
+//! An actor lists the tools it may use with `tools = [...]` in the config file, or takes its persona's. Its
+//! drafts, refinements, syntheses and evaluations are then sent with the tools' JSON schemas, for providers whose
+//! API has tool calling. The model calls them instead of responding, and the
+//! [Coordinator](crate::Coordinator) runs each call the actor may make, records it in the transcript and hands
+//! back what it returned, until the model responds.
+
+use chrono::{Datelike, Local, Months, NaiveDate, TimeDelta};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::provider::ToolSpec;
+
+/// A tool an actor may call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Tool {
+    /// Evaluates an arithmetic expression.
+    Calculator,
+    /// Converts a quantity from one unit to another of the same kind.
+    UnitConverter,
+    /// Adds to dates, counts the days between them and names their weekdays.
+    DateMath,
+}
+
+impl Tool {
+    pub const ALL: [Tool; 3] = [Tool::Calculator, Tool::UnitConverter, Tool::DateMath];
+
+    /// The name the model calls the tool by.
+    pub fn name(self) -> &'static str {
+        match self {
+            Tool::Calculator => "calculator",
+            Tool::UnitConverter => "unit_converter",
+            Tool::DateMath => "date_math",
+        }
+    }
+
+    /// The tool the model called by name, if it is one.
+    pub fn from_name(name: &str) -> Option<Tool> {
+        Tool::ALL.into_iter().find(|tool| tool.name() == name)
+    }
+
+    /// The tool as the model is given it.
+    pub fn spec(self) -> ToolSpec {
+        let (description, parameters) = match self {
+            Tool::Calculator => (
+                "Evaluates an arithmetic expression exactly, such as \"(17.5 * 12) / 3 + 2^10\". Supports + - * / % ^, \
+                 parentheses, the constants pi and e, and the functions sqrt, abs, exp, ln, log (base 10, or log(x, base)), \
+                 sin, cos, tan, asin, acos, atan (in radians), round, floor, ceil, min and max.",
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "expression": { "type": "string", "description": "The expression to evaluate." },
+                    },
+                    "required": ["expression"],
+                }),
+            ),
+            Tool::UnitConverter => (
+                "Converts a quantity between units of length, area, volume, mass, time, speed, temperature, energy, \
+                 pressure or data, such as 5 km to mi, 350 F to C or 2 GiB to MB. US customary units are used for \
+                 gallons, quarts, pints, cups and fluid ounces.",
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "value": { "type": "number", "description": "The quantity to convert." },
+                        "from": { "type": "string", "description": "The unit it is in, such as \"km\", \"lb\" or \"C\"." },
+                        "to": { "type": "string", "description": "The unit to convert it to." },
+                    },
+                    "required": ["value", "from", "to"],
+                }),
+            ),
+            Tool::DateMath => (
+                "Calculates with calendar dates, given as YYYY-MM-DD or \"today\". \"add\" adds years, months, weeks \
+                 and days (negative to subtract) to the date; \"difference\" counts the days from the date to the \
+                 other date; \"weekday\" names the date's day of the week.",
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "operation": { "type": "string", "enum": ["add", "difference", "weekday"] },
+                        "date": { "type": "string", "description": "The date, as YYYY-MM-DD or \"today\"." },
+                        "other": { "type": "string", "description": "For difference, the date to count to." },
+                        "years": { "type": "integer" },
+                        "months": { "type": "integer" },
+                        "weeks": { "type": "integer" },
+                        "days": { "type": "integer" },
+                    },
+                    "required": ["operation", "date"],
+                }),
+            ),
+        };
+        ToolSpec { name: self.name().to_string(), description: description.to_string(), parameters }
+    }
+
+    /// Runs the tool with the arguments the model called it with, returning what it found or why it could not.
+    pub fn run(self, arguments: &Value) -> Result<String, String> {
+        match self {
+            Tool::Calculator => {
+                let expression = string_arg(arguments, "expression")?;
+                Ok(format!("{} = {}", expression.trim(), format_number(calculate(expression)?)))
+            },
+            Tool::UnitConverter => {
+                let value = number_arg(arguments, "value")?;
+                let (from, to) = (string_arg(arguments, "from")?, string_arg(arguments, "to")?);
+                Ok(format!("{} {} = {} {}", format_number(value), from.trim(), format_number(convert(value, from, to)?), to.trim()))
+            },
+            Tool::DateMath => date_math(arguments),
+        }
+    }
+}
+
+fn string_arg<'a>(arguments: &'a Value, name: &str) -> Result<&'a str, String> {
+    arguments.get(name).and_then(Value::as_str).ok_or_else(|| format!("\"{}\" must be given as a string", name))
+}
+
+fn number_arg(arguments: &Value, name: &str) -> Result<f64, String> {
+    match arguments.get(name) {
+        Some(Value::Number(number)) => number.as_f64().ok_or_else(|| format!("\"{}\" is out of range", name)),
+        Some(Value::String(text)) => text.trim().parse().map_err(|_| format!("\"{}\" must be a number", name)),
+        _ => Err(format!("\"{}\" must be given as a number", name)),
+    }
+}
+
+/// The integer argument, or 0 if it was left out.
+fn integer_arg(arguments: &Value, name: &str) -> Result<i64, String> {
+    match arguments.get(name) {
+        None | Some(Value::Null) => Ok(0),
+        Some(Value::Number(number)) => number.as_i64().ok_or_else(|| format!("\"{}\" must be a whole number", name)),
+        Some(Value::String(text)) => text.trim().parse().map_err(|_| format!("\"{}\" must be a whole number", name)),
+        Some(_) => Err(format!("\"{}\" must be a whole number", name)),
+    }
+}
+
+/// The number to 12 significant digits, which hides the rounding errors of floating point, and without a
+/// fractional part if it is whole.
+fn format_number(value: f64) -> String {
+    let rounded: f64 = format!("{:.11e}", value).parse().unwrap_or(value);
+    if rounded == rounded.trunc() && rounded.abs() < 1e15 {
+        format!("{:.0}", rounded)
+    } else {
+        rounded.to_string()
+    }
+}
+
+/// Evaluates an arithmetic expression.
+fn calculate(expression: &str) -> Result<f64, String> {
+    let mut parser = Parser { chars: expression.chars().filter(|c| !c.is_whitespace()).collect(), position: 0 };
+    let value = parser.expression()?;
+    if let Some(c) = parser.peek() {
+        return Err(format!("unexpected '{}' at position {}", c, parser.position + 1));
+    }
+    if !value.is_finite() {
+        return Err("the result is not a finite number".to_string());
+    }
+    Ok(value)
+}
+
+/// A recursive descent parser over an expression with its whitespace removed, evaluating as it parses.
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    /// Moves past the character if it is next.
+    fn eat(&mut self, c: char) -> bool {
+        let next = self.peek() == Some(c);
+        if next {
+            self.position += 1;
+        }
+        next
+    }
+
+    /// Whether `**`, which raises like `^`, is next.
+    fn at_double_star(&self) -> bool {
+        self.peek() == Some('*') && self.chars.get(self.position + 1) == Some(&'*')
+    }
+
+    /// Terms joined by `+` and `-`.
+    fn expression(&mut self) -> Result<f64, String> {
+        let mut value = self.term()?;
+        loop {
+            if self.eat('+') {
+                value += self.term()?;
+            } else if self.eat('-') {
+                value -= self.term()?;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    /// Factors joined by `*`, `/` and `%`.
+    fn term(&mut self) -> Result<f64, String> {
+        let mut value = self.factor()?;
+        loop {
+            if !self.at_double_star() && self.eat('*') {
+                value *= self.factor()?;
+            } else if self.eat('/') {
+                let divisor = self.factor()?;
+                if divisor == 0.0 {
+                    return Err("division by zero".to_string());
+                }
+                value /= divisor;
+            } else if self.eat('%') {
+                value %= self.factor()?;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    /// A signed power.
+    fn factor(&mut self) -> Result<f64, String> {
+        if self.eat('-') {
+            return Ok(-self.factor()?);
+        }
+        if self.eat('+') {
+            return self.factor();
+        }
+        self.power()
+    }
+
+    /// A primary raised by `^` or `**`, which binds to the right.
+    fn power(&mut self) -> Result<f64, String> {
+        let base = self.primary()?;
+        if self.at_double_star() {
+            self.position += 2;
+        } else if !self.eat('^') {
+            return Ok(base);
+        }
+        Ok(base.powf(self.factor()?))
+    }
+
+    /// A number, a constant, a function call or a parenthesized expression.
+    fn primary(&mut self) -> Result<f64, String> {
+        match self.peek() {
+            Some('(') => {
+                self.position += 1;
+                let value = self.expression()?;
+                if !self.eat(')') {
+                    return Err("missing ')'".to_string());
+                }
+                Ok(value)
+            },
+            Some(c) if c.is_ascii_digit() || c == '.' => self.number(),
+            Some(c) if c.is_alphabetic() => self.name(),
+            Some(c) => Err(format!("unexpected '{}' at position {}", c, self.position + 1)),
+            None => Err("the expression ends too soon".to_string()),
+        }
+    }
+
+    fn number(&mut self) -> Result<f64, String> {
+        let start = self.position;
+        while self.peek().is_some_and(|c| c.is_ascii_digit() || c == '.') {
+            self.position += 1;
+        }
+        // An exponent, such as the e-3 of 1.5e-3.
+        if matches!(self.peek(), Some('e' | 'E')) {
+            let digits_at = match self.chars.get(self.position + 1) {
+                Some('+' | '-') => self.position + 2,
+                _ => self.position + 1,
+            };
+            if self.chars.get(digits_at).is_some_and(char::is_ascii_digit) {
+                self.position = digits_at;
+                while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                    self.position += 1;
+                }
+            }
+        }
+        let text: String = self.chars[start..self.position].iter().collect();
+        text.parse().map_err(|_| format!("\"{}\" is not a number", text))
+    }
+
+    fn name(&mut self) -> Result<f64, String> {
+        let start = self.position;
+        while self.peek().is_some_and(|c| c.is_alphanumeric()) {
+            self.position += 1;
+        }
+        let name: String = self.chars[start..self.position].iter().collect::<String>().to_lowercase();
+        if !self.eat('(') {
+            return match name.as_str() {
+                "pi" => Ok(std::f64::consts::PI),
+                "e" => Ok(std::f64::consts::E),
+                _ => Err(format!("unknown constant \"{}\"", name)),
+            };
+        }
+        let mut arguments = vec![self.expression()?];
+        while self.eat(',') {
+            arguments.push(self.expression()?);
+        }
+        if !self.eat(')') {
+            return Err(format!("missing ')' after the arguments of {}", name));
+        }
+        let value = match (name.as_str(), arguments.as_slice()) {
+            ("sqrt", [x]) if *x < 0.0 => return Err("sqrt of a negative number".to_string()),
+            ("sqrt", [x]) => x.sqrt(),
+            ("abs", [x]) => x.abs(),
+            ("exp", [x]) => x.exp(),
+            ("ln", [x]) => x.ln(),
+            ("log", [x]) => x.log10(),
+            ("log", [x, base]) => x.log(*base),
+            ("sin", [x]) => x.sin(),
+            ("cos", [x]) => x.cos(),
+            ("tan", [x]) => x.tan(),
+            ("asin", [x]) => x.asin(),
+            ("acos", [x]) => x.acos(),
+            ("atan", [x]) => x.atan(),
+            ("round", [x]) => x.round(),
+            ("floor", [x]) => x.floor(),
+            ("ceil", [x]) => x.ceil(),
+            ("min", [first, rest @ ..]) => rest.iter().fold(*first, |min, x| min.min(*x)),
+            ("max", [first, rest @ ..]) => rest.iter().fold(*first, |max, x| max.max(*x)),
+            (name, arguments) => return Err(format!("unknown function {} of {} argument(s)", name, arguments.len())),
+        };
+        Ok(value)
+    }
+}
+
+/// A unit the converter knows: a quantity in it is `factor` times, plus `offset`, the quantity in the base unit of
+/// its kind.
+struct Unit {
+    names: &'static [&'static str],
+    kind: &'static str,
+    factor: f64,
+    offset: f64,
+}
+
+const fn unit(names: &'static [&'static str], kind: &'static str, factor: f64) -> Unit {
+    Unit { names, kind, factor, offset: 0.0 }
+}
+
+const UNITS: &[Unit] = &[
+    unit(&["m", "meter", "meters", "metre", "metres"], "length", 1.0),
+    unit(&["km", "kilometer", "kilometers", "kilometre", "kilometres"], "length", 1_000.0),
+    unit(&["cm", "centimeter", "centimeters", "centimetre", "centimetres"], "length", 0.01),
+    unit(&["mm", "millimeter", "millimeters", "millimetre", "millimetres"], "length", 0.001),
+    unit(&["um", "µm", "micrometer", "micrometers", "micron", "microns"], "length", 1e-6),
+    unit(&["nm", "nanometer", "nanometers"], "length", 1e-9),
+    unit(&["mi", "mile", "miles"], "length", 1_609.344),
+    unit(&["yd", "yard", "yards"], "length", 0.9144),
+    unit(&["ft", "foot", "feet"], "length", 0.3048),
+    unit(&["in", "inch", "inches"], "length", 0.0254),
+    unit(&["nmi", "nautical mile", "nautical miles"], "length", 1_852.0),
+    unit(&["au", "astronomical unit", "astronomical units"], "length", 1.495_978_707e11),
+    unit(&["ly", "light year", "light years", "light-year", "light-years"], "length", 9.460_730_472_580_8e15),
+    unit(&["m2", "m^2", "sq m", "square meter", "square meters", "square metre", "square metres"], "area", 1.0),
+    unit(&["km2", "km^2", "sq km", "square kilometer", "square kilometers"], "area", 1e6),
+    unit(&["cm2", "cm^2", "sq cm", "square centimeter", "square centimeters"], "area", 1e-4),
+    unit(&["ha", "hectare", "hectares"], "area", 1e4),
+    unit(&["acre", "acres"], "area", 4_046.856_422_4),
+    unit(&["ft2", "ft^2", "sq ft", "square foot", "square feet"], "area", 0.092_903_04),
+    unit(&["mi2", "mi^2", "sq mi", "square mile", "square miles"], "area", 2_589_988.110_336),
+    unit(&["m3", "m^3", "cubic meter", "cubic meters", "cubic metre", "cubic metres"], "volume", 1.0),
+    unit(&["l", "liter", "liters", "litre", "litres"], "volume", 0.001),
+    unit(&["ml", "milliliter", "milliliters", "millilitre", "millilitres", "cm3", "cc"], "volume", 1e-6),
+    unit(&["gal", "gallon", "gallons"], "volume", 0.003_785_411_784),
+    unit(&["qt", "quart", "quarts"], "volume", 0.000_946_352_946),
+    unit(&["pt", "pint", "pints"], "volume", 0.000_473_176_473),
+    unit(&["cup", "cups"], "volume", 0.000_236_588_236_5),
+    unit(&["floz", "fl oz", "fluid ounce", "fluid ounces"], "volume", 2.957_352_956_25e-5),
+    unit(&["tbsp", "tablespoon", "tablespoons"], "volume", 1.478_676_478_125e-5),
+    unit(&["tsp", "teaspoon", "teaspoons"], "volume", 4.928_921_593_75e-6),
+    unit(&["kg", "kilogram", "kilograms"], "mass", 1.0),
+    unit(&["g", "gram", "grams"], "mass", 0.001),
+    unit(&["mg", "milligram", "milligrams"], "mass", 1e-6),
+    unit(&["t", "tonne", "tonnes", "metric ton", "metric tons"], "mass", 1_000.0),
+    unit(&["lb", "lbs", "pound", "pounds"], "mass", 0.453_592_37),
+    unit(&["oz", "ounce", "ounces"], "mass", 0.028_349_523_125),
+    unit(&["st", "stone", "stones"], "mass", 6.350_293_18),
+    unit(&["s", "sec", "secs", "second", "seconds"], "time", 1.0),
+    unit(&["ms", "millisecond", "milliseconds"], "time", 0.001),
+    unit(&["min", "mins", "minute", "minutes"], "time", 60.0),
+    unit(&["h", "hr", "hrs", "hour", "hours"], "time", 3_600.0),
+    unit(&["d", "day", "days"], "time", 86_400.0),
+    unit(&["wk", "week", "weeks"], "time", 604_800.0),
+    unit(&["yr", "year", "years"], "time", 31_557_600.0),
+    unit(&["m/s", "mps", "meters per second", "metres per second"], "speed", 1.0),
+    unit(&["km/h", "kph", "kmh", "kilometers per hour", "kilometres per hour"], "speed", 1.0 / 3.6),
+    unit(&["mph", "mi/h", "miles per hour"], "speed", 0.447_04),
+    unit(&["kn", "kt", "knot", "knots"], "speed", 1_852.0 / 3_600.0),
+    unit(&["ft/s", "fps", "feet per second"], "speed", 0.3048),
+    Unit { names: &["k", "kelvin"], kind: "temperature", factor: 1.0, offset: 0.0 },
+    Unit { names: &["c", "°c", "celsius", "centigrade"], kind: "temperature", factor: 1.0, offset: 273.15 },
+    Unit { names: &["f", "°f", "fahrenheit"], kind: "temperature", factor: 5.0 / 9.0, offset: 273.15 - 32.0 * 5.0 / 9.0 },
+    unit(&["j", "joule", "joules"], "energy", 1.0),
+    unit(&["kj", "kilojoule", "kilojoules"], "energy", 1_000.0),
+    unit(&["cal", "calorie", "calories"], "energy", 4.184),
+    unit(&["kcal", "kilocalorie", "kilocalories"], "energy", 4_184.0),
+    unit(&["wh", "watt hour", "watt hours"], "energy", 3_600.0),
+    unit(&["kwh", "kilowatt hour", "kilowatt hours"], "energy", 3.6e6),
+    unit(&["btu"], "energy", 1_055.055_852_62),
+    unit(&["ev", "electronvolt", "electronvolts"], "energy", 1.602_176_634e-19),
+    unit(&["pa", "pascal", "pascals"], "pressure", 1.0),
+    unit(&["kpa", "kilopascal", "kilopascals"], "pressure", 1_000.0),
+    unit(&["bar", "bars"], "pressure", 1e5),
+    unit(&["atm", "atmosphere", "atmospheres"], "pressure", 101_325.0),
+    unit(&["psi"], "pressure", 6_894.757_293_168),
+    unit(&["mmhg"], "pressure", 133.322_387_415),
+    unit(&["b", "byte", "bytes"], "data", 1.0),
+    unit(&["bit", "bits"], "data", 0.125),
+    unit(&["kb", "kilobyte", "kilobytes"], "data", 1e3),
+    unit(&["mb", "megabyte", "megabytes"], "data", 1e6),
+    unit(&["gb", "gigabyte", "gigabytes"], "data", 1e9),
+    unit(&["tb", "terabyte", "terabytes"], "data", 1e12),
+    unit(&["kib", "kibibyte", "kibibytes"], "data", 1_024.0),
+    unit(&["mib", "mebibyte", "mebibytes"], "data", 1_048_576.0),
+    unit(&["gib", "gibibyte", "gibibytes"], "data", 1_073_741_824.0),
+    unit(&["tib", "tebibyte", "tebibytes"], "data", 1_099_511_627_776.0),
+];
+
+fn find_unit(name: &str) -> Result<&'static Unit, String> {
+    let name = name.trim().to_lowercase();
+    UNITS.iter().find(|unit| unit.names.contains(&name.as_str())).ok_or_else(|| format!("unknown unit \"{}\"", name))
+}
+
+/// Converts the value between units of the same kind.
+fn convert(value: f64, from: &str, to: &str) -> Result<f64, String> {
+    let (from_unit, to_unit) = (find_unit(from)?, find_unit(to)?);
+    if from_unit.kind != to_unit.kind {
+        return Err(format!("{} is a unit of {} but {} is a unit of {}", from.trim(), from_unit.kind, to.trim(), to_unit.kind));
+    }
+    let base = value * from_unit.factor + from_unit.offset;
+    Ok((base - to_unit.offset) / to_unit.factor)
+}
+
+fn parse_date(text: &str) -> Result<NaiveDate, String> {
+    let text = text.trim();
+    if text.eq_ignore_ascii_case("today") {
+        return Ok(Local::now().date_naive());
+    }
+    NaiveDate::parse_from_str(text, "%Y-%m-%d").map_err(|_| format!("\"{}\" is not a date of the form YYYY-MM-DD", text))
+}
+
+/// The date with its weekday, such as `2024-03-01 (Friday)`.
+fn describe_date(date: NaiveDate) -> String {
+    format!("{} ({})", date.format("%Y-%m-%d"), date.format("%A"))
+}
+
+fn date_math(arguments: &Value) -> Result<String, String> {
+    let date = parse_date(string_arg(arguments, "date")?)?;
+    match string_arg(arguments, "operation")? {
+        "add" => {
+            let (years, months) = (integer_arg(arguments, "years")?, integer_arg(arguments, "months")?);
+            let (weeks, days) = (integer_arg(arguments, "weeks")?, integer_arg(arguments, "days")?);
+            let months = years.checked_mul(12).and_then(|years| years.checked_add(months)).ok_or("the years and months to add are out of range")?;
+            let days = weeks.checked_mul(7).and_then(|weeks| weeks.checked_add(days)).ok_or("the weeks and days to add are out of range")?;
+            // Months are added first, so that adding a month to January 31 ends on the last day of February.
+            let shifted = match u32::try_from(months.unsigned_abs()) {
+                Ok(count) if months >= 0 => date.checked_add_months(Months::new(count)),
+                Ok(count) => date.checked_sub_months(Months::new(count)),
+                Err(_) => None,
+            };
+            let result = shifted
+                .and_then(|date| TimeDelta::try_days(days).and_then(|days| date.checked_add_signed(days)))
+                .ok_or("the resulting date is out of range")?;
+            Ok(describe_date(result))
+        },
+        "difference" => {
+            let other = parse_date(string_arg(arguments, "other")?)?;
+            let days = (other - date).num_days();
+            Ok(format!("{} days from {} to {} ({} weeks and {} days)", days, describe_date(date), describe_date(other), days / 7, days % 7))
+        },
+        "weekday" => Ok(format!("{}, day {} of {}", describe_date(date), date.ordinal(), date.year())),
+        other => Err(format!("unknown operation \"{}\"; use add, difference or weekday", other)),
+    }
+}
@@ -0,0 +1,198 @@
+//! Timestamped JSONL records of every step the panel takes, for auditing how it settled on an answer.
+
+use std::{collections::HashMap, fs::{self, File, OpenOptions}, io::{self, Write}, path::PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+use crate::{diff::Change, result::{Feedback, QuestionId}, sandbox::CodeRun, strategy::{ConsensusStrategy, Stage}};
+
+/// The `[transcript]` section of the config file.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TranscriptConfig {
+    /// Directory the JSONL files are written to. It is created if missing.
+    pub directory: PathBuf,
+    #[serde(default)]
+    pub per: TranscriptGranularity,
+}
+
+/// How transcript entries are split across files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TranscriptGranularity {
+    /// One file for every question asked while the program runs.
+    #[default]
+    Session,
+    /// A new file for each question.
+    Question,
+}
+
+/// The name the reviewer's rejections are recorded under, as if it were an actor on the panel.
+pub const REVIEWER: &str = "User";
+
+/// The name the moderator is waited on and fails under, as if it were an actor on the panel.
+pub const MODERATOR: &str = "Moderator";
+
+/// The name the sandbox running the answer's code is waited on under, as if it were an actor on the panel.
+pub const SANDBOX: &str = "Sandbox";
+
+/// The name a question that failed on no one's call is recorded as failing under, such as one that could not
+/// go on with the panel it had.
+pub const COORDINATOR: &str = "Coordinator";
+
+/// One step of a deliberation.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum TranscriptEvent {
+    Question { question: String, strategy: ConsensusStrategy },
+    Draft { author: String, answer: String },
+    CandidateVote { actor: String, choice: Option<usize>, reasoning: String },
+    /// `router` picked `choice` to draft the answer. When it named nobody on the panel, the selection policy
+    /// picks the drafter instead.
+    Routed { router: String, choice: Option<String> },
+    /// The panel's vote picked `author`'s draft as the first version of the answer.
+    CandidateChosen { author: String, votes: f64 },
+    Evaluation {
+        /// The version of the answer being evaluated, starting at 0 for the first draft.
+        round: usize,
+        actor: String,
+        feedback: Feedback,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        score: Option<u8>,
+        reasoning: String,
+    },
+    /// The question moved on to `stage`, in which it waits on `actors`. `round` is the version of the answer
+    /// the stage works on, starting at 0 for the first draft, so a refinement's round is the version it writes.
+    /// Announced again when a synthesizer takes over from the actors suggesting refinements.
+    StageStarted { stage: Stage, round: usize, actors: Vec<String> },
+    Suggestion { actor: String, suggestion: String },
+    /// The panel's `chair` read every evaluation of version `round` of the answer and wrote `guidance` for refining it.
+    ChairGuidance { round: usize, chair: String, guidance: String },
+    /// The panel's `chair` ruled that version `round` of the answer be accepted without agreement, for `reasoning`.
+    ChairRuling { round: usize, chair: String, reasoning: String },
+    Refinement { author: String, answer: String },
+    /// `author` wrote version `round` of the answer, making `changes` to the version before it word by word.
+    /// Follows the [TranscriptEvent::Refinement] it was taken from, or the vote that chose it.
+    Revised { round: usize, author: String, changes: Vec<Change> },
+    /// The refinement writing version `round` of the answer was `similarity` (from 0 to 1) like the version
+    /// before it, close enough for the panel to stop refining.
+    Converged { round: usize, similarity: f64 },
+    /// The panel stopped refining version `round` of the answer, which is accepted without agreement, because
+    /// another round would have overrun a budget as `reason` describes.
+    BudgetExceeded { round: usize, reason: String },
+    /// The panel was going in circles, as `reason` describes, so it stopped refining the answer and the tie-break
+    /// accepted version `round` without agreement.
+    Deadlocked { round: usize, reason: String },
+    /// The moderator flagged the question or version `round` of its answer, so the panel refused to deliberate on it.
+    Flagged { round: usize, reason: String },
+    /// The sandbox ran the code blocks of version `round` of the answer, before the panel evaluated it.
+    CodeRan { round: usize, runs: Vec<CodeRun> },
+    /// `actor`'s model called `tool` with `arguments`, and the Coordinator ran it, giving the model `output`.
+    ToolUsed { actor: String, tool: String, arguments: serde_json::Value, output: String },
+    /// The panel settled on `answer`. `reached` is false when it ran out of rounds and the latest
+    /// version was accepted without agreement.
+    Consensus { answer: String, reached: bool, refinement_rounds: u32, elapsed_secs: f64 },
+    /// The question was dropped before the panel reached consensus.
+    Abandoned,
+    /// The question was cancelled before the panel reached consensus.
+    Cancelled,
+    /// The question failed because `actor` could not reach its model.
+    Failed { actor: String, error: String },
+    /// The stage stalled and was sent again to `actors`, which had not responded.
+    Redispatched { stage: Stage, actors: Vec<String> },
+    /// The question failed because the stage stalled.
+    TimedOut { stage: Stage },
+}
+
+impl TranscriptEvent {
+    /// Whether the event ends the question's deliberation.
+    pub fn is_final(&self) -> bool {
+        matches!(self, TranscriptEvent::Consensus { .. } | TranscriptEvent::Abandoned | TranscriptEvent::Cancelled | TranscriptEvent::Failed { .. } | TranscriptEvent::TimedOut { .. })
+    }
+}
+
+/// A line of the transcript file.
+#[derive(Serialize)]
+struct TranscriptEntry<'a> {
+    timestamp: DateTime<Utc>,
+    question_id: QuestionId,
+    #[serde(flatten)]
+    event: &'a TranscriptEvent,
+}
+
+/// Appends [TranscriptEvent]s to JSONL files in the configured directory.
+#[derive(Debug)]
+pub struct Transcript {
+    directory: PathBuf,
+    granularity: TranscriptGranularity,
+    /// Start time of the session, used to name its files.
+    session: String,
+    /// The session's file, for per-session transcripts.
+    session_file: Option<File>,
+    /// The file of each question in flight, for per-question transcripts.
+    question_files: HashMap<QuestionId, File>,
+}
+
+impl Transcript {
+    /// Creates the transcript directory and, for per-session transcripts, the session's file.
+    pub fn open(config: &TranscriptConfig) -> io::Result<Self> {
+        fs::create_dir_all(&config.directory)?;
+        let mut transcript = Transcript {
+            directory: config.directory.clone(),
+            granularity: config.per,
+            session: Utc::now().format("%Y%m%dT%H%M%S%.3fZ").to_string(),
+            session_file: None,
+            question_files: HashMap::new(),
+        };
+        if transcript.granularity == TranscriptGranularity::Session {
+            transcript.session_file = Some(transcript.create_file(format!("{}.jsonl", transcript.session))?);
+        }
+        Ok(transcript)
+    }
+
+    /// Appends an event about a question. For per-question transcripts, [TranscriptEvent::Question]
+    /// opens the question's file and [TranscriptEvent::Consensus], [TranscriptEvent::Abandoned],
+    /// [TranscriptEvent::Cancelled], [TranscriptEvent::Failed] or [TranscriptEvent::TimedOut] closes it.
+    ///
+    /// Failures are logged rather than returned so that a full disk never interrupts the panel.
+    pub fn record(&mut self, question_id: QuestionId, event: TranscriptEvent) {
+        if let Err(e) = self.try_record(question_id, &event) {
+            error!("Unable to write to the transcript in {}: {}", self.directory.display(), e);
+        }
+    }
+
+    fn try_record(&mut self, question_id: QuestionId, event: &TranscriptEvent) -> io::Result<()> {
+        if let (TranscriptGranularity::Question, TranscriptEvent::Question { .. }) = (self.granularity, event) {
+            let file = self.create_file(format!("{}-q{}.jsonl", self.session, question_id))?;
+            self.question_files.insert(question_id, file);
+        }
+        let entry = TranscriptEntry { timestamp: Utc::now(), question_id, event };
+        let mut line = serde_json::to_string(&entry)?;
+        line.push('\n');
+        let file = match self.granularity {
+            TranscriptGranularity::Session => self.session_file.as_mut(),
+            TranscriptGranularity::Question => self.question_files.get_mut(&question_id),
+        };
+        if let Some(file) = file {
+            file.write_all(line.as_bytes())?;
+        }
+        if event.is_final() {
+            self.question_files.remove(&question_id);
+        }
+        Ok(())
+    }
+
+    /// Waits until everything recorded so far has reached the disk.
+    pub fn flush(&mut self) {
+        for file in self.session_file.iter().chain(self.question_files.values()) {
+            if let Err(e) = file.sync_data() {
+                error!("Unable to flush the transcript in {}: {}", self.directory.display(), e);
+            }
+        }
+    }
+
+    fn create_file(&self, name: String) -> io::Result<File> {
+        OpenOptions::new().create(true).append(true).open(self.directory.join(name))
+    }
+}
@@ -0,0 +1,489 @@
+//! A full-screen view of the panel at work: the question, the answer as it evolves, and a pane per actor
+//! with its latest verdict and reasoning.
+
+use std::{collections::BTreeMap, io, thread};
+
+use actix::prelude::*;
+use llm_consensus::{
+    diff::{self, Change},
+    messages::{ActorInfo, AnswerToken, DeliberationUpdate, Feedback, QuestionId},
+    strategy::Stage,
+    transcript::TranscriptEvent,
+    AskError, ConsensusResult, ConsensusSystem,
+};
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Direction, Layout},
+    style::{Color, Modifier, Style},
+    text::{Line, Span, Text},
+    widgets::{Block, Paragraph, Wrap},
+    DefaultTerminal, Frame,
+};
+use tokio::sync::oneshot;
+use tracing::error;
+
+use crate::{describe_usage, display::{Status, SPINNER_FRAMES, SPINNER_INTERVAL}, telemetry};
+
+/// How many lines Page Up and Page Down scroll the answer by.
+const SCROLL_STEP: u16 = 5;
+
+/// What one actor last did.
+#[derive(Default)]
+struct ActorPane {
+    /// What the actor is working on, if anything.
+    activity: Option<&'static str>,
+    verdict: Option<Span<'static>>,
+    reasoning: String,
+}
+
+/// The full-screen interface, redrawn after every change. Questions typed at the bottom are asked one at a time.
+pub struct Tui {
+    system: ConsensusSystem,
+    /// Taken while a frame is drawn.
+    terminal: Option<DefaultTerminal>,
+    /// Resolved when the user quits.
+    quit: Option<oneshot::Sender<()>>,
+    input: String,
+    question: String,
+    /// The question being shown, once the Coordinator has announced it.
+    question_id: Option<QuestionId>,
+    /// Whether a question is in flight.
+    asking: bool,
+    status: Option<Status>,
+    answer: String,
+    /// Who wrote the answer being shown, and whether it is a refinement.
+    author: Option<(String, bool)>,
+    /// The round of evaluation the panel is on, counting from 1, or 0 before the first.
+    round: usize,
+    /// How many times the answer has been refined.
+    refinements: usize,
+    /// What the refinement being shown changed in the version before it, once it was taken forward.
+    changes: Option<Vec<Change>>,
+    /// Whether the answer is shown as the changes to it rather than as it reads.
+    show_changes: bool,
+    scroll: u16,
+    actors: BTreeMap<String, ActorPane>,
+    /// The outcome of the last question.
+    outcome: Option<Line<'static>>,
+    frame: usize,
+}
+
+impl Tui {
+    pub fn new(system: ConsensusSystem, terminal: DefaultTerminal, actors: Vec<ActorInfo>, quit: oneshot::Sender<()>) -> Self {
+        Tui {
+            system,
+            terminal: Some(terminal),
+            quit: Some(quit),
+            input: String::new(),
+            question: String::new(),
+            question_id: None,
+            asking: false,
+            status: None,
+            answer: String::new(),
+            author: None,
+            round: 0,
+            refinements: 0,
+            changes: None,
+            show_changes: false,
+            scroll: 0,
+            actors: actors.into_iter().map(|actor| (actor.name, ActorPane::default())).collect(),
+            outcome: None,
+            frame: 0,
+        }
+    }
+
+    fn pane(&mut self, name: &str) -> &mut ActorPane {
+        self.actors.entry(name.to_string()).or_default()
+    }
+
+    fn redraw(&mut self) {
+        self.frame = (self.frame + 1) % SPINNER_FRAMES.len();
+        let Some(mut terminal) = self.terminal.take() else { return };
+        if let Err(e) = terminal.draw(|frame| self.render(frame)) {
+            error!("Unable to draw the interface: {}", e);
+        }
+        self.terminal = Some(terminal);
+    }
+
+    fn render(&self, frame: &mut Frame) {
+        let [question_area, answer_area, actors_area, status_area, input_area] = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(3), Constraint::Min(5), Constraint::Length(9), Constraint::Length(1), Constraint::Length(3)])
+            .areas(frame.area());
+
+        let mut block = Block::bordered().title(" Question ");
+        if self.round > 0 {
+            block = block.title(Line::raw(format!(" Round {} ", self.round)).right_aligned());
+        }
+        let question = Paragraph::new(self.question.as_str()).wrap(Wrap { trim: true }).block(block);
+        frame.render_widget(question, question_area);
+
+        let title = match &self.author {
+            Some((author, false)) => format!(" Answer · drafted by {} ", author),
+            Some((author, true)) => format!(" Answer · refinement {} by {} ", self.refinements, author),
+            None => " Answer ".to_string(),
+        };
+        let mut block = Block::bordered().title(title);
+        let text = match &self.changes {
+            Some(changes) => {
+                let (added, removed) = diff::count_changed_words(changes);
+                let toggle = if self.show_changes { "Tab hides them" } else { "Tab shows them" };
+                block = block.title_bottom(Line::styled(format!(" +{} −{} words · {} ", added, removed, toggle), Style::new().add_modifier(Modifier::DIM)));
+                if self.show_changes { change_lines(changes) } else { Text::raw(self.answer.as_str()) }
+            },
+            None => Text::raw(self.answer.as_str()),
+        };
+        let answer = Paragraph::new(text)
+            .wrap(Wrap { trim: false })
+            .scroll((self.scroll, 0))
+            .block(block);
+        frame.render_widget(answer, answer_area);
+
+        if !self.actors.is_empty() {
+            let areas = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints(vec![Constraint::Ratio(1, self.actors.len() as u32); self.actors.len()])
+                .split(actors_area);
+            for ((name, pane), area) in self.actors.iter().zip(areas.iter()) {
+                let mut lines = Vec::new();
+                if let Some(verdict) = &pane.verdict {
+                    lines.push(Line::from(verdict.clone()));
+                }
+                lines.push(Line::raw(pane.reasoning.as_str()));
+                let mut block = Block::bordered().title(format!(" {} ", name));
+                if let Some(activity) = pane.activity {
+                    block = block.title_bottom(Line::styled(format!(" {}… ", activity), Style::new().fg(Color::Yellow)));
+                }
+                frame.render_widget(Paragraph::new(lines).wrap(Wrap { trim: true }).block(block), *area);
+            }
+        }
+
+        let status = match (&self.status, &self.outcome) {
+            (Some(status), _) if self.asking => Line::raw(format!("{} {}", SPINNER_FRAMES[self.frame], status.describe())),
+            (_, Some(outcome)) => outcome.clone(),
+            _ if self.asking => Line::raw(format!("{} Asking the panel…", SPINNER_FRAMES[self.frame])),
+            _ => Line::styled("Type a question and press Enter. Page Up and Page Down scroll the answer; Tab shows what a refinement changed; Esc cancels the question or quits.", Style::new().add_modifier(Modifier::DIM)),
+        };
+        frame.render_widget(status, status_area);
+
+        let input = Paragraph::new(format!("> {}", self.input)).block(Block::bordered().title(" Ask "));
+        frame.render_widget(input, input_area);
+        if !self.asking {
+            frame.set_cursor_position((input_area.x + 3 + self.input.chars().count() as u16, input_area.y + 1));
+        }
+    }
+
+    /// Asks the panel the typed question.
+    fn ask(&mut self, ctx: &mut Context<Self>) {
+        let question = std::mem::take(&mut self.input).trim().to_string();
+        if question.is_empty() {
+            return;
+        }
+        self.question = question.clone();
+        self.question_id = None;
+        self.asking = true;
+        self.status = None;
+        self.answer.clear();
+        self.author = None;
+        self.round = 0;
+        self.refinements = 0;
+        self.changes = None;
+        self.scroll = 0;
+        self.outcome = None;
+        self.actors.values_mut().for_each(|pane| *pane = ActorPane::default());
+        let system = self.system.clone();
+        ctx.spawn(async move { system.ask(question).await }
+            .into_actor(self)
+            .map(|result, tui, _ctx| tui.finish(result)));
+    }
+
+    /// Cancels the question being asked, which then finishes with [AskError::Cancelled].
+    fn cancel(&self) {
+        let system = self.system.clone();
+        let question_id = self.question_id;
+        actix::spawn(async move {
+            let cancelled = match question_id {
+                Some(question_id) => system.cancel(question_id).await.map(|_| ()),
+                // The question has not been announced yet, but it is the only one this view asks.
+                None => system.cancel_all().await.map(|_| ()),
+            };
+            if let Err(e) = cancelled {
+                error!("Unable to cancel the question: {}", e);
+            }
+        });
+    }
+
+    fn finish(&mut self, result: Result<ConsensusResult, AskError>) {
+        self.asking = false;
+        self.status = None;
+        self.actors.values_mut().for_each(|pane| pane.activity = None);
+        self.outcome = Some(match result {
+            Ok(result) => {
+                let summary = format!(" after {} refinements, with {:.0}% confidence. It {}", result.refinement_rounds, result.confidence * 100.0, describe_usage(&result.usage.total));
+                let (verdict, color) = if let Some(cached) = &result.cached {
+                    (format!("Reused the consensus on \"{}\" ({:.0}% similar)", cached.question, cached.similarity * 100.0), Color::Green)
+                } else if result.consensus_reached {
+                    ("Consensus reached".to_string(), Color::Green)
+                } else if let Some(reason) = &result.flagged {
+                    (format!("Refused by the moderator ({})", reason), Color::Red)
+                } else {
+                    let dissenters: Vec<&str> = result.dissent.iter().map(|dissent| dissent.actor.as_str()).collect();
+                    let outcome = match (result.converged, &result.budget_exceeded, &result.deadlock) {
+                        (true, _, _) => "Converged",
+                        (false, Some(_), _) => "Out of budget",
+                        (false, None, Some(_)) => "Deadlocked",
+                        (false, None, None) if result.ruling.is_some() => "Ruled by the chair",
+                        (false, None, None) => "Out of rounds",
+                    };
+                    (format!("{} without consensus ({} still objected)", outcome, dissenters.join(", ")), Color::Yellow)
+                };
+                self.answer = result.answer;
+                // The changes only describe the answer if it is the last version the panel refined.
+                if self.changes.as_ref().is_some_and(|changes| diff::after(changes) != self.answer) {
+                    self.changes = None;
+                }
+                Line::from(vec![Span::styled(verdict, Style::new().fg(color).add_modifier(Modifier::BOLD)), Span::raw(summary)])
+            },
+            Err(AskError::Cancelled) => Line::styled("The question was cancelled.", Style::new().fg(Color::Yellow)),
+            Err(e) => Line::styled(format!("Unable to answer the question: {}", e), Style::new().fg(Color::Red)),
+        });
+        self.redraw();
+    }
+
+    /// Updates the view for a step of the question being shown.
+    fn record(&mut self, question_id: QuestionId, event: TranscriptEvent) {
+        match event {
+            TranscriptEvent::StageStarted { stage, round, ref actors } => {
+                match stage {
+                    Stage::Evaluating => self.round = round + 1,
+                    Stage::Refining => self.refinements = round,
+                    _ => (),
+                }
+                let activity = match stage {
+                    Stage::Drafting => "drafting",
+                    Stage::Voting => "voting",
+                    Stage::Moderating => "screening",
+                    Stage::Testing => "testing",
+                    Stage::Evaluating => "evaluating",
+                    Stage::Chairing => "chairing",
+                    Stage::Refining => "refining",
+                    Stage::Reviewing => "reviewing",
+                };
+                self.actors.values_mut().for_each(|pane| pane.activity = None);
+                for name in actors {
+                    self.pane(name).activity = Some(activity);
+                }
+                self.status = Status::started(question_id, &event);
+                return;
+            },
+            TranscriptEvent::Draft { ref author, ref answer } => {
+                self.pane(author).activity = None;
+                if self.status.as_ref().is_some_and(|status| status.actors.len() == 1) {
+                    self.show_answer(author, answer, false);
+                }
+            },
+            TranscriptEvent::Routed { ref router, ref choice } => {
+                let pane = self.pane(router);
+                pane.verdict = Some(Span::raw(match choice {
+                    Some(name) => format!("Routed the question to {}", name),
+                    None => "Did not route the question".to_string(),
+                }));
+            },
+            TranscriptEvent::CandidateChosen { ref author, votes } => {
+                let pane = self.pane(author);
+                pane.verdict = Some(Span::styled(format!("Draft chosen with {} votes", votes), Style::new().fg(Color::Cyan)));
+            },
+            TranscriptEvent::CandidateVote { ref actor, choice, ref reasoning } => {
+                let pane = self.pane(actor);
+                pane.activity = None;
+                pane.verdict = Some(Span::raw(match choice {
+                    Some(index) => format!("Voted for draft {}", index + 1),
+                    None => "Did not vote".to_string(),
+                }));
+                pane.reasoning = reasoning.clone();
+            },
+            TranscriptEvent::Evaluation { ref actor, feedback, score, ref reasoning, .. } => {
+                let pane = self.pane(actor);
+                pane.activity = None;
+                let (verdict, color) = match feedback {
+                    Feedback::Good => ("Good", Color::Green),
+                    Feedback::NeedsRefinement => ("Needs refinement", Color::Red),
+                    Feedback::Abstain => ("Abstained", Color::DarkGray),
+                };
+                let verdict = match score {
+                    Some(score) => format!("{} ({}/10)", verdict, score),
+                    None => verdict.to_string(),
+                };
+                pane.verdict = Some(Span::styled(verdict, Style::new().fg(color).add_modifier(Modifier::BOLD)));
+                pane.reasoning = reasoning.clone();
+            },
+            TranscriptEvent::Suggestion { ref actor, ref suggestion } => {
+                let pane = self.pane(actor);
+                pane.activity = None;
+                pane.verdict = Some(Span::styled("Suggested changes", Style::new().fg(Color::Yellow)));
+                pane.reasoning = suggestion.clone();
+            },
+            TranscriptEvent::ChairGuidance { ref chair, ref guidance, .. } => {
+                let pane = self.pane(chair);
+                pane.activity = None;
+                pane.verdict = Some(Span::styled("Guided the refinement", Style::new().fg(Color::Cyan)));
+                pane.reasoning = guidance.clone();
+            },
+            TranscriptEvent::ChairRuling { ref chair, round, ref reasoning } => {
+                let pane = self.pane(chair);
+                pane.activity = None;
+                pane.verdict = Some(Span::styled(format!("Ruled for version {}", round + 1), Style::new().fg(Color::Cyan).add_modifier(Modifier::BOLD)));
+                pane.reasoning = reasoning.clone();
+            },
+            TranscriptEvent::Refinement { ref author, ref answer } => {
+                self.pane(author).activity = None;
+                self.show_answer(author, answer, true);
+            },
+            TranscriptEvent::Revised { ref author, ref changes, .. } => {
+                self.show_answer(author, &diff::after(changes), true);
+                self.changes = Some(changes.clone());
+            },
+            _ => (),
+        }
+        if let Some(status) = self.status.as_mut() {
+            status.record(question_id, &event);
+        }
+    }
+
+    fn show_answer(&mut self, author: &str, answer: &str, refinement: bool) {
+        self.answer = answer.to_string();
+        self.author = Some((author.to_string(), refinement));
+        self.changes = None;
+    }
+}
+
+/// The answer as the changes made to it: removed words struck through in red and added ones in green.
+fn change_lines(changes: &[Change]) -> Text<'static> {
+    let mut lines = vec![Line::default()];
+    for change in changes {
+        let style = match change {
+            Change::Kept(_) => Style::new(),
+            Change::Removed(_) => Style::new().fg(Color::Red).add_modifier(Modifier::CROSSED_OUT),
+            Change::Added(_) => Style::new().fg(Color::Green),
+        };
+        for (index, piece) in change.text().split('\n').enumerate() {
+            if index > 0 {
+                lines.push(Line::default());
+            }
+            if !piece.is_empty() {
+                lines.last_mut().expect("there is always a line").push_span(Span::styled(piece.to_string(), style));
+            }
+        }
+    }
+    Text::from(lines)
+}
+
+impl Actor for Tui {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(SPINNER_INTERVAL, |tui, _ctx| if tui.asking { tui.redraw() });
+        // Reading the terminal blocks, so it happens on a thread of its own.
+        let addr = ctx.address();
+        thread::spawn(move || loop {
+            match event::read() {
+                Ok(event) => if addr.try_send(TerminalInput(event)).is_err() {
+                    break;
+                },
+                Err(e) => {
+                    error!("Unable to read from the terminal: {}", e);
+                    break;
+                },
+            }
+        });
+        self.redraw();
+    }
+}
+
+/// A key press or resize read from the terminal.
+#[derive(Message)]
+#[rtype(result = "()")]
+struct TerminalInput(Event);
+
+impl Handler<TerminalInput> for Tui {
+    type Result = ();
+
+    fn handle(&mut self, msg: TerminalInput, ctx: &mut Self::Context) -> Self::Result {
+        let key = match msg.0 {
+            Event::Key(key) if key.kind == KeyEventKind::Press => key,
+            Event::Resize(..) => return self.redraw(),
+            _ => return,
+        };
+        match key.code {
+            KeyCode::Esc if self.asking => self.cancel(),
+            KeyCode::Esc => {
+                self.quit.take().map(|quit| quit.send(()));
+                return ctx.stop();
+            },
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.quit.take().map(|quit| quit.send(()));
+                return ctx.stop();
+            },
+            KeyCode::Enter if !self.asking => self.ask(ctx),
+            KeyCode::Tab => self.show_changes = !self.show_changes,
+            KeyCode::Backspace => {
+                self.input.pop();
+            },
+            KeyCode::Char(c) => self.input.push(c),
+            KeyCode::PageUp => self.scroll = self.scroll.saturating_sub(SCROLL_STEP),
+            KeyCode::PageDown => self.scroll = self.scroll.saturating_add(SCROLL_STEP),
+            _ => return,
+        }
+        self.redraw();
+    }
+}
+
+impl Handler<DeliberationUpdate> for Tui {
+    type Result = ();
+
+    fn handle(&mut self, msg: DeliberationUpdate, _ctx: &mut Self::Context) -> Self::Result {
+        // The first question announced after asking is the one that was asked.
+        if self.asking && self.question_id.is_none() && matches!(msg.event, TranscriptEvent::Question { .. }) {
+            self.question_id = Some(msg.question_id);
+        }
+        if self.question_id != Some(msg.question_id) {
+            return;
+        }
+        self.record(msg.question_id, msg.event);
+        self.redraw();
+    }
+}
+
+impl Handler<AnswerToken> for Tui {
+    type Result = ();
+
+    fn handle(&mut self, msg: AnswerToken, _ctx: &mut Self::Context) -> Self::Result {
+        if self.question_id != Some(msg.question_id) {
+            return;
+        }
+        let refinement = msg.stage == Stage::Refining;
+        if self.author.as_ref() != Some(&(msg.name.clone(), refinement)) || self.answer.is_empty() || self.changes.is_some() {
+            self.answer.clear();
+            self.author = Some((msg.name, refinement));
+            self.changes = None;
+        }
+        self.answer.push_str(&msg.token);
+        self.redraw();
+    }
+}
+
+/// Runs the interface until the user quits, restoring the terminal afterwards.
+pub async fn run(system: &ConsensusSystem) -> io::Result<()> {
+    let actors = system.actors().await.map_err(io::Error::other)?;
+    let terminal = ratatui::try_init()?;
+    // The log would scroll over the view, so it is silenced until the terminal is restored.
+    telemetry::silence(true);
+    let (quit, quitted) = oneshot::channel();
+    let tui = Tui::new(system.clone(), terminal, actors, quit).start();
+    system.subscribe(tui.clone().recipient());
+    system.subscribe_tokens(tui.recipient());
+    let _ = quitted.await;
+    let restored = ratatui::try_restore();
+    telemetry::silence(false);
+    restored
+}
@@ -0,0 +1,135 @@
+//! Token usage and estimated cost, per actor, per question and per session.
+
+use std::{collections::{BTreeMap, HashMap}, ops::Add};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{provider::Usage, result::QuestionId};
+
+/// What an actor's model costs, in the currency of your choice per million tokens.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+pub struct Pricing {
+    #[serde(default)]
+    pub input_cost_per_million: f64,
+    #[serde(default)]
+    pub output_cost_per_million: f64,
+    /// What the actor's evaluation model costs, if it has one, defaulting to the prices above.
+    #[serde(default)]
+    pub evaluation_input_cost_per_million: Option<f64>,
+    #[serde(default)]
+    pub evaluation_output_cost_per_million: Option<f64>,
+}
+
+impl Pricing {
+    /// The cost of a call, made with the evaluation model if `evaluation` is set.
+    fn cost(&self, usage: &Usage, evaluation: bool) -> f64 {
+        let (input, output) = if evaluation {
+            (
+                self.evaluation_input_cost_per_million.unwrap_or(self.input_cost_per_million),
+                self.evaluation_output_cost_per_million.unwrap_or(self.output_cost_per_million),
+            )
+        } else {
+            (self.input_cost_per_million, self.output_cost_per_million)
+        };
+        (usage.prompt_tokens as f64 * input + usage.completion_tokens as f64 * output) / 1_000_000.0
+    }
+}
+
+/// Token counts and estimated cost of a set of provider calls.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+pub struct UsageTotals {
+    pub calls: u32,
+    /// Calls whose provider did not report token counts, which are missing from the totals.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub unmetered_calls: u32,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub estimated_cost: f64,
+}
+
+fn is_zero(count: &u32) -> bool {
+    *count == 0
+}
+
+impl Add for UsageTotals {
+    type Output = UsageTotals;
+
+    fn add(self, other: UsageTotals) -> UsageTotals {
+        UsageTotals {
+            calls: self.calls + other.calls,
+            unmetered_calls: self.unmetered_calls + other.unmetered_calls,
+            prompt_tokens: self.prompt_tokens + other.prompt_tokens,
+            completion_tokens: self.completion_tokens + other.completion_tokens,
+            estimated_cost: self.estimated_cost + other.estimated_cost,
+        }
+    }
+}
+
+impl UsageTotals {
+    fn count(&mut self, usage: Option<&Usage>, cost: impl Fn(&Usage) -> f64) {
+        self.calls += 1;
+        match usage {
+            Some(usage) => {
+                self.prompt_tokens += usage.prompt_tokens;
+                self.completion_tokens += usage.completion_tokens;
+                self.estimated_cost += cost(usage);
+            },
+            None => self.unmetered_calls += 1,
+        }
+    }
+}
+
+/// Usage broken down by actor, with the overall total.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct UsageSummary {
+    pub total: UsageTotals,
+    pub actors: BTreeMap<String, UsageTotals>,
+}
+
+impl UsageSummary {
+    fn add(&mut self, actor: &str, usage: Option<&Usage>, cost: impl Fn(&Usage) -> f64) {
+        self.total.count(usage, &cost);
+        self.actors.entry(actor.to_string()).or_default().count(usage, &cost);
+    }
+}
+
+/// Adds up the usage reported by the actors, for each question in flight and for the whole session.
+#[derive(Debug, Default)]
+pub struct UsageTracker {
+    pricing: HashMap<String, Pricing>,
+    questions: HashMap<QuestionId, UsageSummary>,
+    session: UsageSummary,
+}
+
+impl UsageTracker {
+    /// Sets the prices used to estimate the cost of the actor's calls.
+    pub fn set_pricing(&mut self, actor: &str, pricing: Pricing) {
+        self.pricing.insert(actor.to_string(), pricing);
+    }
+
+    /// Counts one provider call toward the session and, if given, the question it was made for. `evaluation` is
+    /// set if the call was made with the actor's evaluation model.
+    pub fn record(&mut self, question_id: Option<QuestionId>, actor: &str, usage: Option<&Usage>, evaluation: bool) {
+        let pricing = self.pricing.get(actor).copied().unwrap_or_default();
+        let cost = |usage: &Usage| pricing.cost(usage, evaluation);
+        self.session.add(actor, usage, cost);
+        if let Some(question_id) = question_id {
+            self.questions.entry(question_id).or_default().add(actor, usage, cost);
+        }
+    }
+
+    /// The usage of the question so far.
+    pub fn question(&self, question_id: QuestionId) -> UsageTotals {
+        self.questions.get(&question_id).map(|summary| summary.total).unwrap_or_default()
+    }
+
+    /// Returns the question's usage and stops tracking it.
+    pub fn finish(&mut self, question_id: QuestionId) -> UsageSummary {
+        self.questions.remove(&question_id).unwrap_or_default()
+    }
+
+    /// Usage of every call made since the session started.
+    pub fn session(&self) -> &UsageSummary {
+        &self.session
+    }
+}
@@ -0,0 +1,65 @@
+//! Watching the config file, so that a long-running panel can be reloaded with
+//! [ConsensusSystem::reload](crate::ConsensusSystem::reload) while its personas and prompts are being worked on.
+//!
+//! Editors save files in different ways: some write them in place, while others write a new file and rename it over
+//! the old one, which ends a watch set on the file itself. [ConfigWatcher] therefore watches the directory the file
+//! is in, and the `[prompts]` directory of templates, for changes to the file and to the templates. One save is
+//! often several events, so a change is only reported once they have stopped for [SETTLE].
+
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::{sync::mpsc::{self, UnboundedReceiver}, time::timeout};
+use tracing::warn;
+
+/// How long the files must go unchanged before a change is reported.
+const SETTLE: Duration = Duration::from_millis(300);
+
+/// Watches the config file, and the templates it points at, for changes.
+pub struct ConfigWatcher {
+    /// Stops watching when dropped.
+    _watcher: RecommendedWatcher,
+    changes: UnboundedReceiver<()>,
+}
+
+impl ConfigWatcher {
+    /// Starts watching the config file, and the directory of templates if it has one.
+    pub fn new(config: &Path, prompts: Option<&Path>) -> notify::Result<Self> {
+        let directory = match config.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let file = directory.join(config.file_name().unwrap_or_default());
+        let templates = prompts.map(Path::to_path_buf);
+        let watched = {
+            let templates = templates.clone();
+            move |path: &Path| path == file || templates.as_ref().is_some_and(|templates| path.starts_with(templates) && path.extension() == Some(OsStr::new("hbs")))
+        };
+        let (sender, changes) = mpsc::unbounded_channel();
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| match event {
+            Ok(event) if !event.kind.is_access() && event.paths.iter().any(|path| watched(path)) => {
+                let _ = sender.send(());
+            },
+            Ok(_) => {},
+            Err(e) => warn!("Unable to watch the config file: {}", e),
+        })?;
+        watcher.watch(&directory, RecursiveMode::NonRecursive)?;
+        if let Some(templates) = &templates {
+            watcher.watch(templates, RecursiveMode::NonRecursive)?;
+        }
+        Ok(ConfigWatcher { _watcher: watcher, changes })
+    }
+
+    /// Waits for the files to change and then to settle, returning false if the watch has ended.
+    pub async fn changed(&mut self) -> bool {
+        if self.changes.recv().await.is_none() {
+            return false;
+        }
+        while let Ok(Some(())) = timeout(SETTLE, self.changes.recv()).await {}
+        true
+    }
+}
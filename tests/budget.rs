@@ -0,0 +1,38 @@
+//! A question stops being refined once another round would overrun its token budget, by the usage providers report.
+
+#![cfg(feature = "actix")]
+
+mod common;
+
+use common::{actor, panel, reply, OBJECTION};
+use llm_consensus::{
+    budget::{Budget, BudgetSettings},
+    prompts::Template,
+    provider::{Completion, CompletionRequest, Usage},
+    ConsensusSettings,
+};
+
+/// Never approves the answer, and reports 150 tokens for every call.
+fn spend(request: &CompletionRequest) -> Option<Completion> {
+    let text = match request.template {
+        Template::Draft => "The first version.",
+        Template::Refine => "Another version.",
+        _ => OBJECTION,
+    };
+    Some(Completion { usage: Some(Usage { prompt_tokens: 100, completion_tokens: 50 }), ..reply(text) })
+}
+
+#[actix::test]
+async fn question_stops_within_its_token_budget() {
+    // Drafting and evaluating spend 300 tokens, so a second round would overrun 500.
+    let budget = BudgetSettings { question: Budget { tokens: Some(500), ..Budget::default() }, ..BudgetSettings::default() };
+    let system = panel(ConsensusSettings { budget, ..ConsensusSettings::default() }, vec![("Spender", actor("Spender", spend))]);
+
+    let result = system.ask("How much will it cost?").await.expect("the panel should answer within its budget");
+
+    assert!(!result.consensus_reached);
+    assert!(result.budget_exceeded.is_some());
+    assert_eq!(result.rounds.len(), 1);
+    assert_eq!(result.answer, "The first version.");
+    assert_eq!(result.usage.total.prompt_tokens + result.usage.total.completion_tokens, 300);
+}
@@ -0,0 +1,39 @@
+//! When the panel runs out of rounds without agreeing, the chair rules on the version of the answer the user gets.
+
+#![cfg(feature = "actix")]
+
+mod common;
+
+use common::{actor, panel, reply, OBJECTION};
+use llm_consensus::{
+    messages::ActorRole,
+    prompts::Template,
+    provider::{Completion, CompletionRequest},
+    ConsensusSettings,
+};
+
+/// Drafts one version and refines it into another, never approving either, and as chair rules for the first.
+fn deliberate(request: &CompletionRequest) -> Option<Completion> {
+    Some(reply(match request.template {
+        Template::Draft => "The first version.",
+        Template::Refine => "The second version.",
+        Template::Summarize => "Say it differently.",
+        Template::Decide => "1\nThe first version put it best.",
+        _ => OBJECTION,
+    }))
+}
+
+#[actix::test]
+async fn chair_rules_without_consensus() {
+    let system = panel(
+        ConsensusSettings { max_rounds: 2, ..ConsensusSettings::default() },
+        vec![("Member", actor("Member", deliberate)), ("Chair", actor("Chair", deliberate).with_role(ActorRole::Chair))],
+    );
+
+    let result = system.ask("How should it be put?").await.expect("the chair should rule");
+
+    assert!(!result.consensus_reached);
+    assert_eq!(result.version(1), Some("The second version."));
+    assert_eq!(result.answer, "The first version.");
+    assert_eq!(result.ruling.as_deref(), Some("The first version put it best."));
+}
@@ -0,0 +1,60 @@
+//! A stand-in for a model that follows a script, for running a whole panel through a given deliberation.
+
+// Each test binary builds this module for itself and uses only some of it.
+#![allow(dead_code)]
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use llm_consensus::{
+    personas::Persona,
+    provider::{Completion, CompletionRequest, LlmProvider, ProviderError},
+    usage::Pricing,
+    ConsensusSettings, ConsensusSystem, LlmActor,
+};
+
+/// An evaluation approving the answer.
+pub const APPROVAL: &str = r#"{"verdict": "Good", "reasoning": "It answers the question."}"#;
+/// An evaluation asking for the answer to be refined.
+pub const OBJECTION: &str = r#"{"verdict": "NeedsRefinement", "reasoning": "It could be better."}"#;
+
+/// A completion of `text`, without tool calls or a token count.
+pub fn reply(text: impl Into<String>) -> Completion {
+    Completion { text: text.into(), usage: None, backend: None, tool_calls: Vec::new() }
+}
+
+/// Answers each request with what its script makes of it. A request the script gives nothing for is never answered,
+/// as if the model had stalled.
+struct Scripted<F>(F);
+
+#[async_trait]
+impl<F> LlmProvider for Scripted<F>
+where
+    F: Fn(&CompletionRequest) -> Option<Completion> + Send + Sync,
+{
+    async fn complete(&self, request: &CompletionRequest) -> Result<Completion, ProviderError> {
+        match (self.0)(request) {
+            Some(completion) => Ok(completion),
+            None => std::future::pending().await,
+        }
+    }
+
+    fn supports_tools(&self) -> bool {
+        true
+    }
+}
+
+/// An actor named `name` whose model follows the `script`.
+pub fn actor(name: &str, script: impl Fn(&CompletionRequest) -> Option<Completion> + Send + Sync + 'static) -> LlmActor {
+    LlmActor::new(name.to_string(), Persona::default(), Arc::new(Scripted(script)))
+}
+
+/// A panel of the actors, each of weight 1, deliberating under the `settings`.
+pub fn panel(settings: ConsensusSettings, actors: Vec<(&str, LlmActor)>) -> ConsensusSystem {
+    let system = ConsensusSystem::new();
+    for (name, actor) in actors {
+        system.register(name.to_string(), actor, 1.0, Pricing::default());
+    }
+    system.configure(settings);
+    system
+}
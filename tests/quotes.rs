@@ -0,0 +1,61 @@
+//! Questions and answers holding quotes, such as code, reach the models and come back in the result unchanged.
+
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use futures::executor::block_on;
+use llm_consensus::{
+    engine::{Engine, Panelist},
+    personas::Persona,
+    prompts::Template,
+    provider::{Completion, CompletionRequest, LlmProvider, ProviderError},
+    result::Feedback,
+    ConsensusSettings,
+};
+
+const QUESTION: &str = r#"Why does `println!("{}", "a \"quoted\" word")` print "a "quoted" word"?"#;
+const ANSWER: &str = r#"The format string "{}" is filled with the argument, whose \" escapes become plain " characters."#;
+const REASONING: &str = r#"It explains how "{}", "a" and "quoted" end up in the output."#;
+
+/// Drafts [ANSWER] and approves it with [REASONING], without escaping its quotes as models sometimes do, and
+/// records the prompts it is sent. How each provider puts a prompt into its request body is tested alongside them.
+#[derive(Default)]
+struct Recorder {
+    prompts: Mutex<Vec<(Template, String)>>,
+}
+
+#[async_trait]
+impl LlmProvider for Recorder {
+    async fn complete(&self, request: &CompletionRequest) -> Result<Completion, ProviderError> {
+        self.prompts.lock().unwrap().push((request.template, request.prompt.clone()));
+        let text = match request.template {
+            Template::BinaryEvaluation => format!(r#"{{"verdict": "Good", "reasoning": "{}"}}"#, REASONING),
+            _ => ANSWER.to_string(),
+        };
+        Ok(Completion { text, usage: None, backend: None, tool_calls: Vec::new() })
+    }
+}
+
+#[test]
+fn quoted_question_survives_the_round_trip() {
+    let recorder = Arc::new(Recorder::default());
+    let persona = Persona { domain: "Rust".to_string(), expertise: vec!["Formatting".to_string()], ..Persona::default() };
+    let engine = Engine::new(ConsensusSettings::default())
+        .with_panelist(Panelist::new("Rustacean".to_string(), persona, recorder.clone()));
+
+    let result = block_on(engine.ask(QUESTION)).expect("the panel should answer");
+
+    assert_eq!(result.question, QUESTION);
+    assert_eq!(result.answer, ANSWER);
+    assert!(result.consensus_reached);
+    let evaluation = &result.rounds[0].evaluations[0];
+    assert_eq!(evaluation.feedback, Feedback::Good);
+    assert_eq!(evaluation.reasoning, REASONING);
+
+    let prompts = recorder.prompts.lock().unwrap();
+    let (_, draft) = prompts.iter().find(|(template, _)| *template == Template::Draft).expect("the answer should be drafted");
+    assert!(draft.contains(QUESTION), "the draft prompt lost the question's quotes: {}", draft);
+    let (_, evaluation) = prompts.iter().find(|(template, _)| *template == Template::BinaryEvaluation).expect("the answer should be evaluated");
+    assert!(evaluation.contains(QUESTION), "the evaluation prompt lost the question's quotes: {}", evaluation);
+    assert!(evaluation.contains(ANSWER), "the evaluation prompt lost the answer's quotes: {}", evaluation);
+}
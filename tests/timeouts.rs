@@ -0,0 +1,32 @@
+//! A question whose actor never answers is sent to it again, then fails once the stage runs out of time.
+
+#![cfg(feature = "actix")]
+
+mod common;
+
+use common::{actor, panel, reply};
+use llm_consensus::{
+    prompts::Template,
+    provider::{Completion, CompletionRequest},
+    strategy::{Stage, StageTimeouts},
+    AskError, ConsensusError, ConsensusSettings,
+};
+
+/// Drafts an answer, then never comes back with its evaluation of it.
+fn stall(request: &CompletionRequest) -> Option<Completion> {
+    (request.template == Template::Draft).then(|| reply("A draft."))
+}
+
+#[actix::test]
+async fn stalled_evaluation_times_out() {
+    let timeouts = StageTimeouts { evaluation_secs: 1, redispatches: 1, ..StageTimeouts::default() };
+    let system = panel(ConsensusSettings { timeouts, ..ConsensusSettings::default() }, vec![("Sleeper", actor("Sleeper", stall))]);
+
+    let result = system.ask("Will you answer?").await;
+
+    assert!(
+        matches!(result, Err(AskError::Failed(ConsensusError::Timeout { stage: Stage::Evaluating }))),
+        "expected a timeout while evaluating, got {:?}",
+        result,
+    );
+}
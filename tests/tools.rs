@@ -0,0 +1,36 @@
+//! An actor that calls a tool instead of answering gets back what the tool returned, and answers with it.
+
+#![cfg(feature = "actix")]
+
+mod common;
+
+use common::{actor, panel, reply, APPROVAL};
+use llm_consensus::{
+    prompts::Template,
+    provider::{Completion, CompletionRequest, ToolCall},
+    tools::Tool,
+    ConsensusSettings,
+};
+use serde_json::json;
+
+/// Drafts by calling the calculator, then answers with what it returned, and approves the answer.
+fn reckon(request: &CompletionRequest) -> Option<Completion> {
+    Some(match (request.template, request.tool_rounds.as_slice()) {
+        (Template::Draft, []) => {
+            let call = ToolCall { id: "call_0".to_string(), name: "calculator".to_string(), arguments: json!({"expression": "6 * 7"}) };
+            Completion { tool_calls: vec![call], ..reply("") }
+        },
+        (Template::Draft, [round]) => reply(format!("By the calculator, {}.", round.outputs[0].content)),
+        _ => reply(APPROVAL),
+    })
+}
+
+#[actix::test]
+async fn tool_output_reaches_the_model() {
+    let system = panel(ConsensusSettings::default(), vec![("Reckoner", actor("Reckoner", reckon).with_tools(vec![Tool::Calculator]))]);
+
+    let result = system.ask("What is 6 times 7?").await.expect("the panel should answer");
+
+    assert_eq!(result.answer, "By the calculator, 6 * 7 = 42.");
+    assert!(result.consensus_reached);
+}